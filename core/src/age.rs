@@ -0,0 +1,625 @@
+use crate::crypto::{check_cancelled, replace_with, Cancelled, KeyLen};
+use crate::entropy;
+use crate::error::GuardXError;
+use crate::kdf::{self, Kdf};
+use crate::format;
+use crate::throttle;
+use anyhow::{Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use ring::{hkdf, hmac};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+// age v1 (https://age-encryption.org/v1), so files can be opened with `age -d`: either a single scrypt
+// recipient with the GuardX key as the passphrase, or X25519 recipients (public keys):
+//   header  = "age-encryption.org/v1\n" | stanzas "-> <tag> <args>\n<wrapped file key>\n" | "--- <mac>\n"
+//   payload = nonce [16] | ChaCha20-Poly1305 chunks of 64 KiB, nonce = counter [11] | last flag [1]
+// Header values are unpadded base64; the MAC covers the header up to and including "---".
+pub const MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+const SCRYPT_LABEL: &[u8] = b"age-encryption.org/v1/scrypt";
+// The work factor `age` itself picks for new files (about a second on a laptop)
+const WORK_FACTOR: u8 = 18;
+// Refuse anything costlier than `age` would decrypt by default; scrypt memory doubles every step
+const MAX_WORK_FACTOR: u8 = 22;
+const FILE_KEY_LEN: usize = 16;
+const PAYLOAD_NONCE_LEN: usize = 16;
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+// Longest header line we accept; real ones stay well under 100 bytes
+const MAX_LINE_LEN: u64 = 1024;
+const MAX_HEADER_LEN: usize = 64 * 1024;
+const X25519_LABEL: &[u8] = b"age-encryption.org/v1/X25519";
+const IDENTITY_HRP: &str = "age-secret-key-";
+const IDENTITY_PREFIX: &str = "AGE-SECRET-KEY-1";
+const RECIPIENT_HRP: &str = "age";
+
+pub fn is_age(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; MAGIC.len()];
+    let n = format::read_full(&mut File::open(path)?, &mut magic)?;
+    Ok(n == magic.len() && magic == MAGIC)
+}
+
+fn malformed(what: &str) -> anyhow::Error {
+    GuardXError::Format(format!("Malformed age header: {}", what)).into()
+}
+
+fn hkdf_expand(ikm: &[u8], salt: &[u8], info: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let mut out = Zeroizing::new([0u8; 32]);
+    hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(ikm)
+        .expand(&[info], KeyLen(32))
+        .and_then(|okm| okm.fill(&mut out[..]))
+        .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+    Ok(out)
+}
+
+fn chacha(key: &[u8]) -> Result<LessSafeKey> {
+    let unbound = UnboundKey::new(&CHACHA20_POLY1305, key).map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+    Ok(LessSafeKey::new(unbound))
+}
+
+fn wrap_key(passphrase: &str, salt: &[u8; 16], work_factor: u8) -> Result<LessSafeKey> {
+    let params = scrypt::Params::new(work_factor, 8, 1, 32).map_err(|e| anyhow::anyhow!("scrypt error: {}", e))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt::scrypt(passphrase.as_bytes(), &[SCRYPT_LABEL, &salt[..]].concat(), &params, &mut key[..])
+        .map_err(|e| anyhow::anyhow!("scrypt error: {}", e))?;
+    chacha(&key[..])
+}
+
+fn header_mac(file_key: &[u8], header: &[u8]) -> Result<hmac::Tag> {
+    let mac_key = hkdf_expand(file_key, &[], b"header")?;
+    Ok(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &mac_key[..]), header))
+}
+
+fn payload_key(file_key: &[u8], nonce: &[u8]) -> Result<LessSafeKey> {
+    chacha(&hkdf_expand(file_key, nonce, b"payload")?[..])
+}
+
+fn chunk_nonce(counter: u64, last: bool) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+// One recipient's copy of the file key: "-> <tag> <args>" and the unpadded base64 body, wrapped at 64 columns
+struct Stanza {
+    tag: String,
+    args: Vec<String>,
+    body: Vec<u8>,
+}
+
+impl Stanza {
+    fn to_text(&self) -> String {
+        let mut text = format!("-> {}", self.tag);
+        for arg in &self.args {
+            text.push(' ');
+            text.push_str(arg);
+        }
+        text.push('\n');
+        let body = encode(&self.body);
+        let mut rest = body.as_str();
+        // The last body line is always shorter than 64 columns, so a full one is followed by an empty one
+        loop {
+            let (line, tail) = rest.split_at(rest.len().min(64));
+            text.push_str(line);
+            text.push('\n');
+            if line.len() < 64 {
+                return text;
+            }
+            rest = tail;
+        }
+    }
+}
+
+fn seal_file_key(wrap_key: &LessSafeKey, file_key: &[u8]) -> Result<Vec<u8>> {
+    let mut wrapped = file_key.to_vec();
+    wrap_key.seal_in_place_append_tag(Nonce::assume_unique_for_key([0u8; 12]), Aad::empty(), &mut wrapped)
+        .map_err(|e| anyhow::anyhow!("Encryption error: {:?}", e))?;
+    Ok(wrapped)
+}
+
+fn open_file_key(wrap_key: &LessSafeKey, body: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+    if body.len() != FILE_KEY_LEN + TAG_LEN {
+        return None;
+    }
+    let mut file_key = Zeroizing::new(body.to_vec());
+    let len = wrap_key.open_in_place(Nonce::assume_unique_for_key([0u8; 12]), Aad::empty(), &mut file_key[..]).ok()?.len();
+    file_key.truncate(len);
+    Some(file_key)
+}
+
+fn new_file_key() -> Result<Zeroizing<[u8; FILE_KEY_LEN]>> {
+    let mut file_key = Zeroizing::new([0u8; FILE_KEY_LEN]);
+    entropy::fill(&mut file_key[..])?;
+    Ok(file_key)
+}
+
+/// Encrypts `reader` into `writer` as an age v1 file whose only recipient is `passphrase`, which
+/// `age -d` can open.
+///
+/// ```
+/// use guardx_core::age::{decrypt_stream, encrypt_stream};
+///
+/// let mut sealed = Vec::new();
+/// encrypt_stream(&mut &b"attack at dawn"[..], &mut sealed, "correct horse")?;
+/// assert!(sealed.starts_with(b"age-encryption.org/v1\n-> scrypt "));
+///
+/// let mut opened = Vec::new();
+/// decrypt_stream(&mut &sealed[..], &mut opened, "correct horse")?;
+/// assert_eq!(opened, b"attack at dawn");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn encrypt_stream<R: Read, W: Write>(reader: &mut R, writer: &mut W, passphrase: &str) -> Result<()> {
+    let file_key = new_file_key()?;
+    let mut salt = [0u8; 16];
+    entropy::fill(&mut salt)?;
+    let stanza = Stanza {
+        tag: "scrypt".to_string(),
+        args: vec![encode(&salt), WORK_FACTOR.to_string()],
+        body: seal_file_key(&wrap_key(passphrase, &salt, WORK_FACTOR)?, &file_key[..])?,
+    };
+    seal(reader, writer, &file_key[..], &[stanza])
+}
+
+/// Encrypts `reader` into `writer` as an age v1 file that only the identities behind `recipients`
+/// can open. Encrypting needs no secret at all.
+///
+/// ```
+/// use guardx_core::age::{decrypt_stream, encrypt_stream_to, Identity};
+///
+/// let identity = Identity::generate()?;
+/// let mut sealed = Vec::new();
+/// encrypt_stream_to(&mut &b"attack at dawn"[..], &mut sealed, &[identity.recipient()])?;
+///
+/// let mut opened = Vec::new();
+/// decrypt_stream(&mut &sealed[..], &mut opened, &identity.to_secret_string())?;
+/// assert_eq!(opened, b"attack at dawn");
+/// assert!(decrypt_stream(&mut &sealed[..], &mut Vec::new(), "correct horse").is_err());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn encrypt_stream_to<R: Read, W: Write>(reader: &mut R, writer: &mut W, recipients: &[Recipient]) -> Result<()> {
+    if recipients.is_empty() {
+        return Err(anyhow::anyhow!("No recipients to encrypt to"));
+    }
+    let file_key = new_file_key()?;
+    let stanzas = recipients.iter().map(|r| r.wrap(&file_key[..])).collect::<Result<Vec<_>>>()?;
+    seal(reader, writer, &file_key[..], &stanzas)
+}
+
+// Writes the header for `stanzas` and the payload under `file_key`
+fn seal<R: Read, W: Write>(reader: &mut R, writer: &mut W, file_key: &[u8], stanzas: &[Stanza]) -> Result<()> {
+    let mut nonce = [0u8; PAYLOAD_NONCE_LEN];
+    entropy::fill(&mut nonce)?;
+    let mut header = MAGIC.to_vec();
+    for stanza in stanzas {
+        header.extend_from_slice(stanza.to_text().as_bytes());
+    }
+    header.extend_from_slice(b"---");
+    let mac = header_mac(file_key, &header)?;
+    writer.write_all(&header)?;
+    writer.write_all(format!(" {}\n", encode(mac.as_ref())).as_bytes())?;
+    writer.write_all(&nonce)?;
+
+    // Each chunk is only sealed once the next read shows whether it is the last one
+    let key = payload_key(file_key, &nonce)?;
+    let mut chunk = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    let mut next = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    let mut len = format::read_full(reader, &mut chunk)?;
+    throttle::pace(len);
+    let mut counter = 0u64;
+    loop {
+        check_cancelled()?;
+        let next_len = if len == CHUNK_SIZE { format::read_full(reader, &mut next)? } else { 0 };
+        throttle::pace(next_len);
+        let last = next_len == 0;
+        let mut sealed = chunk[..len].to_vec();
+        key.seal_in_place_append_tag(chunk_nonce(counter, last), Aad::empty(), &mut sealed)
+            .map_err(|e| anyhow::anyhow!("Encryption error: {:?}", e))?;
+        writer.write_all(&sealed)?;
+        if last {
+            return Ok(());
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+        counter += 1;
+    }
+}
+
+// One header line without its newline; `age` headers are ASCII, and anything that is not ends up malformed
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut line = Vec::new();
+    reader.by_ref().take(MAX_LINE_LEN).read_until(b'\n', &mut line)?;
+    if line.pop() != Some(b'\n') {
+        return Err(malformed("line too long or file truncated"));
+    }
+    String::from_utf8(line).map_err(|_| malformed("not ASCII"))
+}
+
+// Reads the stanzas and the MAC line; `header` gets the bytes the MAC covers
+fn read_header<R: BufRead>(reader: &mut R) -> Result<(Vec<Stanza>, Vec<u8>, Vec<u8>)> {
+    let first = read_line(reader)?;
+    if first.as_bytes() != &MAGIC[..MAGIC.len() - 1] {
+        if first.starts_with("-----BEGIN AGE ENCRYPTED FILE-----") {
+            return Err(GuardXError::Format("ASCII-armored age files are not supported; decrypt with `age -d` or re-encrypt without -a".to_string()).into());
+        }
+        return Err(GuardXError::Format("Not an age encrypted file".to_string()).into());
+    }
+    let mut header = MAGIC.to_vec();
+    let mut stanzas = Vec::new();
+    loop {
+        if header.len() > MAX_HEADER_LEN {
+            return Err(malformed("header too long"));
+        }
+        let line = read_line(reader)?;
+        if let Some(mac) = line.strip_prefix("--- ") {
+            header.extend_from_slice(b"---");
+            return Ok((stanzas, decode(mac)?, header));
+        }
+        let Some(rest) = line.strip_prefix("-> ") else { return Err(malformed("expected a recipient stanza or the MAC line")) };
+        let mut args: Vec<String> = rest.split(' ').map(str::to_string).collect();
+        if args.iter().any(|a| a.is_empty()) {
+            return Err(malformed("empty stanza argument"));
+        }
+        let tag = args.remove(0);
+        header.extend_from_slice(line.as_bytes());
+        header.push(b'\n');
+        let mut body = String::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.len() > 64 {
+                return Err(malformed("stanza body line longer than 64 columns"));
+            }
+            header.extend_from_slice(line.as_bytes());
+            header.push(b'\n');
+            body.push_str(&line);
+            if line.len() < 64 {
+                break;
+            }
+            if header.len() > MAX_HEADER_LEN {
+                return Err(malformed("header too long"));
+            }
+        }
+        stanzas.push(Stanza { tag, args, body: decode(&body)? });
+    }
+}
+
+// A key is either a passphrase or, when it holds "AGE-SECRET-KEY-1" lines (like an age identity file does),
+// the identities to try; '#' comment lines are skipped
+fn parse_identities(key: &str) -> Result<Option<Vec<Identity>>> {
+    if !key.lines().any(|l| l.trim().starts_with(IDENTITY_PREFIX)) {
+        return Ok(None);
+    }
+    let identities = key.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::parse)
+        .collect::<Result<Vec<Identity>>>()?;
+    Ok(Some(identities))
+}
+
+fn unwrap_scrypt(stanza: &Stanza, passphrase: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let [salt, work_factor] = &stanza.args[..] else { return Err(malformed("scrypt stanza needs a salt and a work factor")) };
+    let salt: [u8; 16] = decode(salt)?.try_into().map_err(|_| malformed("salt must be 16 bytes"))?;
+    let work_factor: u8 = work_factor.parse().ok()
+        .filter(|w| *w > 0 && !work_factor.starts_with('0'))
+        .ok_or_else(|| malformed("invalid work factor"))?;
+    if work_factor > MAX_WORK_FACTOR {
+        return Err(GuardXError::Format(format!("age work factor {} is above the limit of {}", work_factor, MAX_WORK_FACTOR)).into());
+    }
+    kdf::check(Some(Kdf::Scrypt { log_n: work_factor, r: 8, p: 1 }))?;
+    open_file_key(&wrap_key(passphrase, &salt, work_factor)?, &stanza.body).ok_or_else(|| GuardXError::Authentication.into())
+}
+
+// Reads and authenticates the header; returns the file key, leaving `reader` at the payload nonce
+fn open_header<R: BufRead>(reader: &mut R, key: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let (stanzas, mac, header) = read_header(reader)?;
+    let scrypt = stanzas.iter().any(|s| s.tag == "scrypt");
+    if scrypt && stanzas.len() != 1 {
+        return Err(GuardXError::Format("An scrypt age file must have exactly one recipient".to_string()).into());
+    }
+    let file_key = match parse_identities(key)? {
+        None if scrypt => unwrap_scrypt(&stanzas[0], key)?,
+        None => return Err(anyhow::Error::new(GuardXError::Authentication)
+            .context("This age file is encrypted to public keys; decrypt it with a matching identity (AGE-SECRET-KEY-1...)")),
+        Some(_) if scrypt => return Err(anyhow::Error::new(GuardXError::Authentication)
+            .context("This age file is encrypted with a passphrase, not to an identity")),
+        Some(identities) => {
+            let mut found = None;
+            for stanza in stanzas.iter().filter(|s| s.tag == "X25519") {
+                if let Some(file_key) = identities.iter().find_map(|i| i.unwrap(stanza).transpose()).transpose()? {
+                    found = Some(file_key);
+                    break;
+                }
+            }
+            found.ok_or(GuardXError::Authentication)?
+        }
+    };
+    let mac_key = hkdf_expand(&file_key, &[], b"header")?;
+    hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, &mac_key[..]), &header, &mac).map_err(|_| GuardXError::Authentication)?;
+    Ok(file_key)
+}
+
+// Secret half of an X25519 key pair, written like age writes it ("AGE-SECRET-KEY-1..."), so `age -d -i`
+// accepts GuardX identities and the other way round
+pub struct Identity(StaticSecret);
+
+// Public half ("age1..."), the only thing a machine that encrypts needs
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Recipient([u8; 32]);
+
+impl Identity {
+    pub fn generate() -> Result<Self> {
+        let mut secret = Zeroizing::new([0u8; 32]);
+        entropy::fill(&mut secret[..])?;
+        Ok(Identity(StaticSecret::from(*secret)))
+    }
+
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0).to_bytes())
+    }
+
+    pub fn to_secret_string(&self) -> Zeroizing<String> {
+        Zeroizing::new(bech32::encode(IDENTITY_HRP, self.0.as_bytes()).to_uppercase())
+    }
+
+    // None when the stanza was made for a different identity
+    fn unwrap(&self, stanza: &Stanza) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let [share] = &stanza.args[..] else { return Err(malformed("X25519 stanza needs one share")) };
+        let share: [u8; 32] = decode(share)?.try_into().map_err(|_| malformed("X25519 share must be 32 bytes"))?;
+        let shared = self.0.diffie_hellman(&PublicKey::from(share));
+        if !shared.was_contributory() {
+            return Err(malformed("X25519 share is a low-order point"));
+        }
+        let salt = [&share[..], &self.recipient().0[..]].concat();
+        let wrap_key = chacha(&hkdf_expand(shared.as_bytes(), &salt, X25519_LABEL)?[..])?;
+        Ok(open_file_key(&wrap_key, &stanza.body))
+    }
+}
+
+impl std::str::FromStr for Identity {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        if text != text.to_uppercase() {
+            return Err(anyhow::anyhow!("Not an age identity (expected AGE-SECRET-KEY-1...)"));
+        }
+        let secret = Zeroizing::new(bech32::decode(&text.to_lowercase(), IDENTITY_HRP)
+            .context("Not an age identity (expected AGE-SECRET-KEY-1...)")?);
+        let secret: [u8; 32] = secret[..].try_into().map_err(|_| anyhow::anyhow!("age identity must hold 32 bytes"))?;
+        Ok(Identity(StaticSecret::from(secret)))
+    }
+}
+
+impl Recipient {
+    fn wrap(&self, file_key: &[u8]) -> Result<Stanza> {
+        let ephemeral = Identity::generate()?;
+        let share = ephemeral.recipient().0;
+        let shared = ephemeral.0.diffie_hellman(&PublicKey::from(self.0));
+        if !shared.was_contributory() {
+            return Err(anyhow::anyhow!("Invalid recipient {}", self));
+        }
+        let salt = [&share[..], &self.0[..]].concat();
+        let wrap_key = chacha(&hkdf_expand(shared.as_bytes(), &salt, X25519_LABEL)?[..])?;
+        Ok(Stanza { tag: "X25519".to_string(), args: vec![encode(&share)], body: seal_file_key(&wrap_key, file_key)? })
+    }
+}
+
+impl std::fmt::Display for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&bech32::encode(RECIPIENT_HRP, &self.0))
+    }
+}
+
+impl std::str::FromStr for Recipient {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let key = bech32::decode(text, RECIPIENT_HRP).with_context(|| format!("Not an age public key (expected age1...): {}", text))?;
+        let key = key.try_into().map_err(|_| anyhow::anyhow!("age public key must hold 32 bytes: {}", text))?;
+        Ok(Recipient(key))
+    }
+}
+
+// Streaming plaintext of an age file; every chunk is authenticated before any of it is returned
+pub struct Decryptor<R: BufRead> {
+    reader: R,
+    key: LessSafeKey,
+    counter: u64,
+    plain: Zeroizing<Vec<u8>>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Decryptor<R> {
+    pub fn new(mut reader: R, key: &str) -> Result<Self> {
+        let file_key = open_header(&mut reader, key)?;
+        let mut nonce = [0u8; PAYLOAD_NONCE_LEN];
+        reader.read_exact(&mut nonce).context(GuardXError::Format("age file is truncated".to_string()))?;
+        let key = payload_key(&file_key, &nonce)?;
+        Ok(Decryptor { reader, key, counter: 0, plain: Zeroizing::new(Vec::new()), pos: 0, done: false })
+    }
+
+    fn load(&mut self) -> Result<()> {
+        check_cancelled()?;
+        let mut data = Zeroizing::new(vec![0u8; CHUNK_SIZE + TAG_LEN]);
+        let len = format::read_full(&mut self.reader, &mut data)?;
+        // Only a full chunk can be followed by more; a full final chunk is allowed too
+        let last = len < data.len() || self.reader.fill_buf()?.is_empty();
+        if len < TAG_LEN || (last && len == TAG_LEN && self.counter > 0) {
+            return Err(GuardXError::Format("age file is truncated or corrupted".to_string()).into());
+        }
+        data.truncate(len);
+        let plain_len = self.key
+            .open_in_place(chunk_nonce(self.counter, last), Aad::empty(), &mut data[..])
+            .map_err(|_| GuardXError::Authentication)?
+            .len();
+        data.truncate(plain_len);
+        self.plain = data;
+        self.pos = 0;
+        self.counter += 1;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Decryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.load().map_err(|e| match e.downcast::<GuardXError>() {
+                Ok(failure) => io::Error::other(failure),
+                Err(e) => match e.downcast::<Cancelled>() {
+                    Ok(cancelled) => io::Error::other(cancelled),
+                    Err(e) => io::Error::other(e),
+                },
+            })?;
+        }
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// `key` is a passphrase or one or more identities, see `parse_identities`
+pub fn decrypt_stream<R: Read, W: Write>(reader: &mut R, writer: &mut W, key: &str) -> Result<()> {
+    let mut decryptor = Decryptor::new(BufReader::new(reader), key)?;
+    io::copy(&mut decryptor, writer)?;
+    Ok(())
+}
+
+pub fn encrypt_file(path: &Path, passphrase: &str) -> Result<()> {
+    replace_with(path, |w| encrypt_stream(&mut BufReader::new(File::open(path)?), w, passphrase))
+}
+
+pub fn encrypt_file_to(path: &Path, recipients: &[Recipient]) -> Result<()> {
+    replace_with(path, |w| encrypt_stream_to(&mut BufReader::new(File::open(path)?), w, recipients))
+}
+
+pub fn decrypt_file(path: &Path, key: &str) -> Result<()> {
+    replace_with(path, |w| decrypt_stream(&mut File::open(path)?, w, key))
+}
+
+pub fn verify_file(path: &Path, key: &str) -> Result<()> {
+    decrypt_stream(&mut File::open(path)?, &mut io::sink(), key)
+}
+
+// Streams the old plaintext straight into a new passphrase age file; the file is only replaced once it all went through
+pub fn rekey_file(path: &Path, old: &str, new: &str) -> Result<()> {
+    let mut decryptor = Decryptor::new(BufReader::new(File::open(path)?), old)?;
+    replace_with(path, move |w| encrypt_stream(&mut decryptor, w, new))?;
+    Ok(())
+}
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard base64 without padding, as age writes it
+fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |acc, (i, b)| acc | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..=group.len() {
+            out.push(ALPHABET[((bits >> (18 - 6 * i)) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+// Rejects padding and non-canonical trailing bits, like age does
+fn decode(text: &str) -> Result<Vec<u8>> {
+    if text.len() % 4 == 1 {
+        return Err(malformed("invalid base64"));
+    }
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for group in text.as_bytes().chunks(4) {
+        let mut bits = 0u32;
+        for (i, c) in group.iter().enumerate() {
+            let value = ALPHABET.iter().position(|a| a == c).ok_or_else(|| malformed("invalid base64"))?;
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        let bytes = group.len() - 1;
+        if bits & (0xFF_FFFF >> (8 * bytes)) != 0 {
+            return Err(malformed("non-canonical base64"));
+        }
+        out.extend((0..bytes).map(|i| (bits >> (16 - 8 * i)) as u8));
+    }
+    Ok(out)
+}
+
+// BIP 173 bech32, which age uses for its keys (without the 90 character limit)
+mod bech32 {
+    use anyhow::Result;
+
+    const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+    fn polymod(values: &[u8]) -> u32 {
+        const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+        values.iter().fold(1u32, |chk, v| {
+            let top = chk >> 25;
+            let chk = ((chk & 0x1ffffff) << 5) ^ *v as u32;
+            (0..5).filter(|i| (top >> i) & 1 == 1).fold(chk, |chk, i| chk ^ GENERATOR[i])
+        })
+    }
+
+    fn hrp_expand(hrp: &str) -> Vec<u8> {
+        let mut out: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+        out.push(0);
+        out.extend(hrp.bytes().map(|b| b & 31));
+        out
+    }
+
+    // Regroups bits, e.g. bytes into 5-bit groups and back; padding is only allowed when widening
+    fn convert(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+        let (mut acc, mut bits, mut out) = (0u32, 0u32, Vec::new());
+        for value in data {
+            acc = (acc << from) | *value as u32;
+            bits += from;
+            while bits >= to {
+                bits -= to;
+                out.push(((acc >> bits) & ((1 << to) - 1)) as u8);
+            }
+        }
+        if pad && bits > 0 {
+            out.push(((acc << (to - bits)) & ((1 << to) - 1)) as u8);
+        } else if !pad && (bits >= from || (acc << (to - bits)) & ((1 << to) - 1) != 0) {
+            return None;
+        }
+        Some(out)
+    }
+
+    pub fn encode(hrp: &str, data: &[u8]) -> String {
+        let data = convert(data, 8, 5, true).unwrap_or_default();
+        let mut values = hrp_expand(hrp);
+        values.extend(&data);
+        values.extend([0u8; 6]);
+        let checksum = polymod(&values) ^ 1;
+        let mut out = format!("{}1", hrp);
+        out.extend(data.iter().map(|v| CHARSET[*v as usize] as char));
+        out.extend((0..6).map(|i| CHARSET[((checksum >> (5 * (5 - i))) & 31) as usize] as char));
+        out
+    }
+
+    // Lowercase input only; the caller handles age's uppercase identities
+    pub fn decode(text: &str, hrp: &str) -> Result<Vec<u8>> {
+        let rest = text.strip_prefix(hrp).and_then(|r| r.strip_prefix('1')).ok_or_else(|| anyhow::anyhow!("wrong prefix"))?;
+        let values = rest.bytes()
+            .map(|c| CHARSET.iter().position(|a| *a == c).map(|v| v as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| anyhow::anyhow!("invalid character"))?;
+        if values.len() < 6 {
+            return Err(anyhow::anyhow!("too short"));
+        }
+        let mut check = hrp_expand(hrp);
+        check.extend(&values);
+        if polymod(&check) != 1 {
+            return Err(anyhow::anyhow!("bad checksum"));
+        }
+        convert(&values[..values.len() - 6], 5, 8, false).ok_or_else(|| anyhow::anyhow!("invalid padding"))
+    }
+}
@@ -0,0 +1,498 @@
+use crate::age::Recipient;
+use crate::crypto::{preferred_cipher, Cipher, OutputFormat};
+use crate::filter::{self, Filter};
+use crate::kdf::{self, Family, Kdf};
+use crate::keyring;
+use crate::oplog;
+use crate::schedule::Schedule;
+use crate::throttle;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Config {
+    // How keys are stretched for new files: "argon2id" (the default), "scrypt" or "pbkdf2", optionally
+    // with parameters as `Kdf::parse` reads them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<String>,
+    // Weakest key stretching accepted when opening files: "none" (the default), "pbkdf2", "scrypt" or "argon2id"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_kdf: Option<String>,
+    // How much goes into the log files: "off", "error", "warn", "info" (the default), "debug" or "trace"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+    // Hash-chain the history log so `guardx audit verify` can tell whether it was changed or cut short
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub audit: bool,
+    // Refuse a key in GUARDX_KEY, so scripts on this machine must use --key-file or a prompt
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub forbid_env_key: bool,
+    // Gitignore-style patterns for files no folder's operations touch, e.g. ["node_modules/", ".git/", "*.iso"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    // When set, only files matching one of these patterns are touched in any folder
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // Folders whose subfolders the TUI lists instead of the home directory's; `--root` sets them, and a
+    // profile's own roots take their place
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<PathBuf>,
+    // Folders in the TUI's bookmark popup ('), in the order they were bookmarked with b
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<PathBuf>,
+    // Keep mounted drives out of the folder list; only the encrypted folders on them are offered
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_drives: bool,
+    // Also index files, not just folders, for the TUI's fuzzy finder (Ctrl+P)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub finder_files: bool,
+    // Letters typed in the TUI's folder and file lists jump to the first matching name instead of running
+    // their commands, which the palette (:) still offers; j in Settings switches it for the session
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub type_ahead: bool,
+    // Language of the TUI, one of LANGUAGES; the locale (LC_ALL, LC_MESSAGES, LANG) picks it when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    // Seconds an outcome stays up as a toast in the TUI, 5 by default; 0 leaves them in the status bar only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toast_seconds: Option<u64>,
+    // Seconds before the clipboard is emptied after Y copied decrypted text in the TUI or a key was pasted into
+    // it, 30 by default; 0 keeps it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard_clear_seconds: Option<u64>,
+    // SFTP folders listed after the local ones in the TUI, e.g. "sftp://me@nas/home/me/documents"
+    #[serde(default, rename = "sftp", skip_serializing_if = "Vec::is_empty")]
+    pub sftp_roots: Vec<String>,
+    // Most `guardx daemon` and `guardx watch` read per second to encrypt, like "20M" or "512K"; no cap when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_limit: Option<String>,
+    // Niceness, 0 to 19, that `guardx daemon` and `guardx watch` lower themselves to; Unix only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_nice: Option<i32>,
+    // Profile used when none is picked with --profile or in Settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    #[serde(default, rename = "profile", skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<Profile>,
+    #[serde(default, rename = "vault", skip_serializing_if = "Vec::is_empty")]
+    pub vaults: Vec<VaultConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct VaultConfig {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cipher: Option<String>,
+    // "age" writes age v1 files that the `age` tool can open; GuardX's own format otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    // Public keys ("age1...") or identity names; when set, files are encrypted to these instead of the key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+    // Store files under random names with the real name sealed in the header
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypt_names: bool,
+    // Keep encrypted SHA-256 checksums of the plaintext and check them after decrypting
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub checksums: bool,
+    // Colour label shown in the folder list; labelled folders are grouped by colour
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<Label>,
+    // Position among the pinned folders, which are listed first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<u32>,
+    // Archived folders are hidden from the list and not checked in the background; retired ones are read-only
+    #[serde(default, skip_serializing_if = "Lifecycle::is_active")]
+    pub lifecycle: Lifecycle,
+    // When the folder was retired; it can be deleted once RETIREMENT_GRACE has passed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retired_at: Option<i64>,
+    // Where a copy of the encrypted folder is kept, for `verify-backup` and B in the TUI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+    // Folder that goes into the duress slot when this folder is archived into a container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoy: Option<PathBuf>,
+    // Where `guardx push` and `guardx pull` keep a copy of the folder, e.g. "s3://bucket/prefix"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    // Cron expression for when `guardx daemon` encrypts the folder, e.g. "0 18 * * 1-5"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    // Patterns added after the global `exclude` ones for this folder only; `!pattern` takes a file back
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+}
+
+// A named set of folders and defaults, e.g. "work" and "personal", of which one is active at a time
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Profile {
+    pub name: String,
+    // Folders whose subfolders are listed in the TUI instead of the home directory's; registered vaults
+    // outside them are left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<PathBuf>,
+    // Cipher for folders without one of their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cipher: Option<String>,
+    // Patterns added after the global ones while the profile is active
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // One of THEME_PRESETS, or the name of a file in the themes directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+}
+
+// Themes built into the TUI
+pub const THEME_PRESETS: [&str; 6] = ["dark", "light", "solarized", "dracula", "gruvbox", "high-contrast"];
+
+// Languages the TUI has message catalogs for
+pub const LANGUAGES: [&str; 2] = ["en", "fa"];
+
+// The profile picked with --profile or in Settings, for the whole process; None falls back to `default_profile`
+static SELECTED_PROFILE: Mutex<Option<String>> = Mutex::new(None);
+
+// Takes effect at the next `Config::load`
+pub fn select_profile(name: Option<String>) {
+    *SELECTED_PROFILE.lock().unwrap_or_else(|e| e.into_inner()) = name;
+}
+
+// Seconds a retired folder is kept read-only before it may be deleted
+pub const RETIREMENT_GRACE: i64 = 30 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Lifecycle {
+    #[default]
+    Active,
+    Archived,
+    Retired,
+}
+
+impl Lifecycle {
+    pub fn is_active(&self) -> bool {
+        *self == Lifecycle::Active
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lifecycle::Active => "active",
+            Lifecycle::Archived => "archived",
+            Lifecycle::Retired => "retired",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "active" => Some(Lifecycle::Active),
+            "archived" => Some(Lifecycle::Archived),
+            "retired" => Some(Lifecycle::Retired),
+            _ => None,
+        }
+    }
+
+    // Active -> archived -> retired -> active
+    pub fn next(self) -> Self {
+        match self {
+            Lifecycle::Active => Lifecycle::Archived,
+            Lifecycle::Archived => Lifecycle::Retired,
+            Lifecycle::Retired => Lifecycle::Active,
+        }
+    }
+}
+
+// Listed in the order labelled folders are grouped in
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Label {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Magenta,
+}
+
+impl Label {
+    // Cycles through the colours and back to no label
+    pub fn next(label: Option<Label>) -> Option<Label> {
+        match label {
+            None => Some(Label::Red),
+            Some(Label::Red) => Some(Label::Yellow),
+            Some(Label::Yellow) => Some(Label::Green),
+            Some(Label::Green) => Some(Label::Blue),
+            Some(Label::Blue) => Some(Label::Magenta),
+            Some(Label::Magenta) => None,
+        }
+    }
+}
+
+impl Config {
+    pub fn file() -> Result<PathBuf> {
+        let dir = dirs::config_dir().context("Could not find config directory")?.join("guardx");
+        Ok(dir.join("config.toml"))
+    }
+
+    // Where the TUI looks for `<name>.toml` theme files
+    pub fn themes_dir() -> Result<PathBuf> {
+        Ok(dirs::config_dir().context("Could not find config directory")?.join("guardx").join("themes"))
+    }
+
+    // Also applies the KDF settings, the exclude patterns and audit mode, which hold for the whole process
+    pub fn load() -> Result<Self> {
+        let path = Self::file()?;
+        let config: Config = if path.exists() {
+            let data = fs::read_to_string(&path)?;
+            toml::from_str(&data).with_context(|| format!("Malformed config: {:?}", path))?
+        } else {
+            Config::default()
+        };
+        config.check_profiles().with_context(|| format!("Malformed config: {:?}", path))?;
+        if let Some(language) = config.language.as_deref().filter(|language| !LANGUAGES.contains(language)) {
+            return Err(anyhow::anyhow!("Unknown language {:?} in {:?}; use {}", language, path, LANGUAGES.join(", ")));
+        }
+        config.apply_kdf().with_context(|| format!("Malformed config: {:?}", path))?;
+        config.background_rate().with_context(|| format!("Malformed config: {:?}", path))?;
+        if let Some(nice) = config.background_nice.filter(|nice| !(0..=19).contains(nice)) {
+            return Err(anyhow::anyhow!("background_nice must be from 0 to 19, not {}, in {:?}", nice, path));
+        }
+        config.apply_filters();
+        oplog::set_audit(config.audit);
+        Ok(config)
+    }
+
+    fn check_profiles(&self) -> Result<()> {
+        for profile in &self.profiles {
+            if let Some(name) = profile.cipher.as_deref().filter(|name| Cipher::from_name(name).is_none()) {
+                return Err(anyhow::anyhow!("Unknown cipher {:?} in profile {:?}", name, profile.name));
+            }
+            if let Some(theme) = profile.theme.as_deref().filter(|theme| !THEME_PRESETS.contains(theme)) {
+                if !Self::themes_dir()?.join(format!("{}.toml", theme)).is_file() {
+                    return Err(anyhow::anyhow!("Unknown theme {:?} in profile {:?}; use {} or a file in the themes directory", theme, profile.name, THEME_PRESETS.join(", ")));
+                }
+            }
+        }
+        match self.profile_name() {
+            Some(name) if self.profile().is_none() => {
+                let known: Vec<&str> = self.profiles.iter().map(|p| p.name.as_str()).collect();
+                Err(anyhow::anyhow!("Unknown profile {:?}; config.toml has {}", name, if known.is_empty() { "none".to_string() } else { known.join(", ") }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn profile_name(&self) -> Option<String> {
+        SELECTED_PROFILE.lock().unwrap_or_else(|e| e.into_inner()).clone().or_else(|| self.default_profile.clone())
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        let name = self.profile_name()?;
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    // `background_limit` in bytes per second, 0 when unset. K, M and G are powers of 1024, and "B", "iB"
+    // and "/s" may follow them.
+    pub fn background_rate(&self) -> Result<u64> {
+        let Some(text) = self.background_limit.as_deref() else { return Ok(0) };
+        let invalid = || anyhow::anyhow!("Invalid background_limit {:?}; use a rate like \"20M\" or \"512K\"", text);
+        let rate = text.trim().trim_end_matches("/s");
+        let rate = rate.strip_suffix("iB").or_else(|| rate.strip_suffix('B')).unwrap_or(rate);
+        let (number, scale) = match rate.chars().last().map(|c| c.to_ascii_uppercase()) {
+            Some('K') => (&rate[..rate.len() - 1], 1u64 << 10),
+            Some('M') => (&rate[..rate.len() - 1], 1 << 20),
+            Some('G') => (&rate[..rate.len() - 1], 1 << 30),
+            _ => (rate, 1),
+        };
+        let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+        if !number.is_finite() || number < 1.0 / scale as f64 {
+            return Err(invalid());
+        }
+        Ok((number * scale as f64) as u64)
+    }
+
+    // For `guardx daemon` and `guardx watch`: caps their reads at `background_limit` and lowers their
+    // priority to `background_nice`, for the rest of the process
+    pub fn apply_background(&self) -> Result<()> {
+        throttle::set_limit(self.background_rate()?);
+        if let Some(nice) = self.background_nice {
+            throttle::lower_priority(nice).with_context(|| format!("Could not lower the priority to background_nice {}", nice))?;
+        }
+        Ok(())
+    }
+
+    // The active profile's cipher, or else the fastest one on this machine
+    pub fn default_cipher(&self) -> Cipher {
+        self.profile().and_then(|p| p.cipher.as_deref()).and_then(Cipher::from_name).unwrap_or_else(preferred_cipher)
+    }
+
+    fn apply_kdf(&self) -> Result<()> {
+        kdf::set_kdf(match &self.kdf {
+            Some(spec) => Kdf::parse(spec)?,
+            None => Kdf::DEFAULT,
+        });
+        kdf::set_minimum(match &self.min_kdf {
+            Some(name) => Family::from_name(name).with_context(|| format!("Unknown min_kdf {:?}; use none, pbkdf2, scrypt or argon2id", name))?,
+            None => Family::None,
+        });
+        Ok(())
+    }
+
+    fn apply_filters(&self) {
+        let folders = self.vaults.iter()
+            .filter(|v| !v.exclude.is_empty() || !v.include.is_empty())
+            .map(|v| (v.path.clone(), self.filter(&v.path)))
+            .collect();
+        let (exclude, include) = self.global_patterns();
+        filter::configure(Filter::new(&exclude, &include), folders);
+    }
+
+    // The global patterns followed by the active profile's
+    fn global_patterns(&self) -> (Vec<String>, Vec<String>) {
+        let (mut exclude, mut include) = (self.exclude.clone(), self.include.clone());
+        if let Some(profile) = self.profile() {
+            exclude.extend(profile.exclude.iter().cloned());
+            include.extend(profile.include.iter().cloned());
+        }
+        (exclude, include)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::file()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn vault(&self, path: &Path) -> Option<&VaultConfig> {
+        self.vaults.iter().find(|v| v.path == path)
+    }
+
+    // The global and profile patterns followed by the folder's own
+    pub fn filter(&self, path: &Path) -> Filter {
+        let (mut exclude, mut include) = self.global_patterns();
+        if let Some(vault) = self.vault(path) {
+            exclude.extend(vault.exclude.iter().cloned());
+            include.extend(vault.include.iter().cloned());
+        }
+        Filter::new(&exclude, &include)
+    }
+
+    // Adds a folder to the bookmarks or removes it; true when it is bookmarked now
+    pub fn toggle_bookmark(&mut self, path: &Path) -> bool {
+        match self.bookmarks.iter().position(|b| b == path) {
+            Some(i) => {
+                self.bookmarks.remove(i);
+                false
+            }
+            None => {
+                self.bookmarks.push(path.to_path_buf());
+                true
+            }
+        }
+    }
+
+    pub fn vault_mut(&mut self, path: &Path) -> &mut VaultConfig {
+        if let Some(idx) = self.vaults.iter().position(|v| v.path == path) {
+            return &mut self.vaults[idx];
+        }
+        self.vaults.push(VaultConfig { path: path.to_path_buf(), ..Default::default() });
+        self.vaults.last_mut().unwrap()
+    }
+
+    // Per-vault override, if one is set and names a cipher we know
+    pub fn cipher_for(&self, path: &Path) -> Option<Cipher> {
+        self.vault(path)?.cipher.as_deref().and_then(Cipher::from_name)
+    }
+
+    // What new files of the folder are encrypted with: its own cipher, the profile's, or the fastest here
+    pub fn cipher(&self, path: &Path) -> Cipher {
+        self.cipher_for(path).unwrap_or_else(|| self.default_cipher())
+    }
+
+    pub fn output_format(&self, path: &Path) -> OutputFormat {
+        self.vault(path).and_then(|v| v.format.as_deref()).and_then(OutputFormat::from_name).unwrap_or(OutputFormat::GuardX)
+    }
+
+    pub fn recipients(&self, path: &Path) -> Result<Vec<Recipient>> {
+        self.vault(path).map_or(&[][..], |v| &v.recipients[..]).iter().map(|r| keyring::resolve(r)).collect()
+    }
+
+    pub fn encrypt_names(&self, path: &Path) -> bool {
+        self.vault(path).is_some_and(|v| v.encrypt_names)
+    }
+
+    pub fn checksums(&self, path: &Path) -> bool {
+        self.vault(path).is_some_and(|v| v.checksums)
+    }
+
+    pub fn label(&self, path: &Path) -> Option<Label> {
+        self.vault(path)?.label
+    }
+
+    pub fn pin(&self, path: &Path) -> Option<u32> {
+        self.vault(path)?.pin
+    }
+
+    pub fn backup(&self, path: &Path) -> Option<&Path> {
+        self.vault(path)?.backup.as_deref()
+    }
+
+    pub fn remote(&self, path: &Path) -> Option<&str> {
+        self.vault(path)?.remote.as_deref()
+    }
+
+    pub fn decoy(&self, path: &Path) -> Option<&Path> {
+        self.vault(path)?.decoy.as_deref()
+    }
+
+    // Folders with a schedule, with it parsed; fails on the first malformed one
+    pub fn schedules(&self) -> Result<Vec<(PathBuf, Schedule)>> {
+        self.vaults.iter()
+            .filter_map(|v| Some((v, v.schedule.as_deref()?)))
+            .map(|(v, spec)| Ok((v.path.clone(), Schedule::parse(spec).with_context(|| format!("Schedule of {:?}", v.path))?)))
+            .collect()
+    }
+
+    pub fn lifecycle(&self, path: &Path) -> Lifecycle {
+        self.vault(path).map_or(Lifecycle::Active, |v| v.lifecycle)
+    }
+
+    // Moves a folder to `state`; retiring starts the grace period, leaving retirement ends it
+    pub fn set_lifecycle(&mut self, path: &Path, state: Lifecycle, now: i64) {
+        let vault = self.vault_mut(path);
+        if state == Lifecycle::Retired && vault.lifecycle != Lifecycle::Retired {
+            vault.retired_at = Some(now);
+        } else if state != Lifecycle::Retired {
+            vault.retired_at = None;
+        }
+        vault.lifecycle = state;
+    }
+
+    // Refuses anything that would write to a retired folder
+    pub fn check_writable(&self, path: &Path) -> Result<()> {
+        if self.lifecycle(path) == Lifecycle::Retired {
+            return Err(anyhow::anyhow!("{:?} is retired and read-only; make it active again to change it", path));
+        }
+        Ok(())
+    }
+
+    // A retired folder may only be deleted once its grace period is over; other folders any time
+    pub fn check_deletable(&self, path: &Path, now: i64) -> Result<()> {
+        let Some(retired_at) = self.vault(path).filter(|v| v.lifecycle == Lifecycle::Retired).and_then(|v| v.retired_at) else {
+            return Ok(());
+        };
+        let left = retired_at + RETIREMENT_GRACE - now;
+        if left > 0 {
+            return Err(anyhow::anyhow!("{:?} is retired; it can be deleted in {} days", path, (left + 86_399) / 86_400));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1239 @@
+use crate::age;
+use crate::entropy;
+use crate::error::{self, GuardXError};
+use crate::kdf::{self, Kdf};
+use crate::throttle;
+use crate::format::{self, corrupted, truncated, Header, EXT_KDF, EXT_NAME, EXT_SEALED_MAP, MAGIC, MAP_ENTRY_LEN, NONCE_LEN, REC_CHUNK, REC_FINAL, REC_MAP, TAG_LEN};
+use anyhow::{Context, Result};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, CHACHA20_POLY1305};
+use ring::{hkdf, hmac, pbkdf2};
+use std::fs::{self, read, write, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::sync::mpsc;
+use std::thread;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+// Nonce plus AEAD tag: anything shorter cannot be a file we produced
+pub const MIN_CIPHERTEXT_LEN: usize = 12 + 16;
+
+const CHUNK_KEY_INFO: &[u8] = b"guardx chunk key";
+const MAP_KEY_INFO: &[u8] = b"guardx chunk map";
+const MAP_TAG_INFO: &[u8] = b"guardx map tag";
+const NAME_KEY_INFO: &[u8] = b"guardx file name";
+const EXT_NAME_AAD: &[u8] = b"guardx name";
+const MANIFEST_HASH_INFO: &[u8] = b"guardx manifest hash";
+const VERIFIER_ITERATIONS: u32 = 100_000;
+
+// Salted PBKDF2 digest that lets the lock screen check a key without keeping it in memory
+pub struct KeyVerifier {
+    salt: [u8; 16],
+    hash: [u8; 32],
+}
+
+impl KeyVerifier {
+    pub fn new(key: &str) -> Result<Self> {
+        let mut salt = [0u8; 16];
+        entropy::fill(&mut salt)?;
+        let mut hash = [0u8; 32];
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, Self::iterations(), &salt, key.as_bytes(), &mut hash);
+        Ok(KeyVerifier { salt, hash })
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        pbkdf2::verify(pbkdf2::PBKDF2_HMAC_SHA256, Self::iterations(), &self.salt, key.as_bytes(), &self.hash).is_ok()
+    }
+
+    fn iterations() -> NonZeroU32 {
+        NonZeroU32::new(VERIFIER_ITERATIONS).unwrap()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Cipher {
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub const ALL: [Cipher; 2] = [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305];
+
+    pub fn id(self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => format::CIPHER_AES_256_GCM,
+            Cipher::ChaCha20Poly1305 => format::CIPHER_CHACHA20_POLY1305,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self> {
+        Cipher::ALL.into_iter().find(|c| c.id() == id)
+            .ok_or_else(|| GuardXError::Format(format!("Unsupported cipher id {}", id)).into())
+    }
+
+    // Name used in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "aes-256-gcm",
+            Cipher::ChaCha20Poly1305 => "chacha20-poly1305",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Cipher::ALL.into_iter().find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "AES-256-GCM",
+            Cipher::ChaCha20Poly1305 => "ChaCha20-Poly1305",
+        }
+    }
+
+    fn algorithm(self) -> &'static aead::Algorithm {
+        match self {
+            Cipher::Aes256Gcm => &AES_256_GCM,
+            Cipher::ChaCha20Poly1305 => &CHACHA20_POLY1305,
+        }
+    }
+}
+
+// File format new files are written in. age files can be opened by the `age` tool with the key as the
+// passphrase, but do not support delta re-encryption or sealed names.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    GuardX,
+    Age,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 2] = [OutputFormat::GuardX, OutputFormat::Age];
+
+    // Name used in the config file and the event protocol
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::GuardX => "guardx",
+            OutputFormat::Age => "age",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        OutputFormat::ALL.into_iter().find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            OutputFormat::GuardX => "GuardX",
+            OutputFormat::Age => "age v1 (scrypt)",
+        }
+    }
+}
+
+// AES-GCM is only fast with hardware AES and carry-less multiply; without them ChaCha20 wins
+pub fn aes_accelerated() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let accelerated = std::is_x86_feature_detected!("aes") && std::is_x86_feature_detected!("pclmulqdq");
+    #[cfg(target_arch = "aarch64")]
+    let accelerated = std::arch::is_aarch64_feature_detected!("aes") && std::arch::is_aarch64_feature_detected!("pmull");
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    let accelerated = false;
+    accelerated
+}
+
+// Cipher for new files when neither the user nor the vault picked one; detected once per run
+pub fn preferred_cipher() -> Cipher {
+    static PREFERRED: OnceLock<Cipher> = OnceLock::new();
+    *PREFERRED.get_or_init(|| if aes_accelerated() { Cipher::Aes256Gcm } else { Cipher::ChaCha20Poly1305 })
+}
+
+pub struct DeltaStats {
+    pub total: u64,
+    pub reused: u64,
+}
+
+// Key material is padded/truncated to 32 bytes and wiped when the buffer is dropped
+fn derive_key(key: &str) -> Zeroizing<Vec<u8>> {
+    let mut key_bytes = Zeroizing::new(vec![0u8; 32]);
+    let input_bytes = key.as_bytes();
+    key_bytes[..input_bytes.len().min(32)].copy_from_slice(&input_bytes[..input_bytes.len().min(32)]);
+    key_bytes
+}
+
+// The key stretched as `kdf` says, or as it is for files and manifests from before key stretching.
+// Either way the `min_kdf` policy is checked first.
+fn input_key(key: &str, kdf: Option<Kdf>, salt: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    kdf::check(kdf)?;
+    match kdf {
+        Some(kdf) => Ok(Zeroizing::new(kdf::stretch_cached(kdf, key, salt)?.to_vec())),
+        None => Ok(derive_key(key)),
+    }
+}
+
+// Key for the keyed BLAKE3 file hashes of a vault manifest, salted per manifest
+pub fn manifest_hash_key(key: &str, salt: &[u8], kdf: Option<Kdf>) -> Result<Zeroizing<[u8; 32]>> {
+    let ikm = input_key(key, kdf, salt)?;
+    let mut hash_key = Zeroizing::new([0u8; 32]);
+    hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(&ikm)
+        .expand(&[MANIFEST_HASH_INFO], KeyLen(32))
+        .and_then(|okm| okm.fill(&mut hash_key[..]))
+        .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+    Ok(hash_key)
+}
+
+pub(crate) struct KeyLen(pub(crate) usize);
+
+impl hkdf::KeyType for KeyLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+// Per-file subkeys, salted with the file id so no two files share a chunk key
+struct FileKeys {
+    aead: LessSafeKey,
+    mac: hmac::Key,
+    tag: hmac::Key,
+    name: LessSafeKey,
+}
+
+impl FileKeys {
+    fn derive(key: &str, header: &Header) -> Result<Self> {
+        let cipher = Cipher::from_id(header.cipher)?;
+        let ikm = match header.extensions.iter().find(|(kind, _)| *kind == EXT_KDF) {
+            Some((_, params)) => {
+                let (kdf, salt) = Kdf::from_bytes(params)?;
+                input_key(key, Some(kdf), &salt)?
+            }
+            None => input_key(key, None, &[])?,
+        };
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &header.file_id).extract(&ikm);
+        let mut chunk_key = Zeroizing::new([0u8; 32]);
+        let mut map_key = Zeroizing::new([0u8; 32]);
+        let mut tag_key = Zeroizing::new([0u8; 32]);
+        let mut name_key = Zeroizing::new([0u8; 32]);
+        prk.expand(&[CHUNK_KEY_INFO], KeyLen(32))
+            .and_then(|okm| okm.fill(&mut chunk_key[..]))
+            .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+        prk.expand(&[MAP_KEY_INFO], KeyLen(32))
+            .and_then(|okm| okm.fill(&mut map_key[..]))
+            .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+        prk.expand(&[MAP_TAG_INFO], KeyLen(32))
+            .and_then(|okm| okm.fill(&mut tag_key[..]))
+            .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+        prk.expand(&[NAME_KEY_INFO], KeyLen(32))
+            .and_then(|okm| okm.fill(&mut name_key[..]))
+            .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+
+        let unbound_key = UnboundKey::new(cipher.algorithm(), &chunk_key[..])
+            .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+        let name_key = UnboundKey::new(cipher.algorithm(), &name_key[..])
+            .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+        Ok(FileKeys {
+            aead: LessSafeKey::new(unbound_key),
+            mac: hmac::Key::new(hmac::HMAC_SHA256, &map_key[..]),
+            tag: hmac::Key::new(hmac::HMAC_SHA256, &tag_key[..]),
+            name: LessSafeKey::new(name_key),
+        })
+    }
+
+    fn seal_name(&self, name: &str) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        entropy::fill(&mut nonce)?;
+        let mut sealed = name.as_bytes().to_vec();
+        self.name.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(EXT_NAME_AAD), &mut sealed)
+            .map_err(|e| anyhow::anyhow!("Encryption error: {:?}", e))?;
+        Ok([&nonce[..], &sealed].concat())
+    }
+
+    fn open_name(&self, sealed: &[u8]) -> Result<String> {
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(GuardXError::Format("Malformed file name in header".to_string()).into());
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        let mut data = sealed.to_vec();
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|e| anyhow::anyhow!("Nonce error: {:?}", e))?;
+        let name = self.name.open_in_place(nonce, Aad::from(EXT_NAME_AAD), &mut data).map_err(|_| GuardXError::Authentication)?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| GuardXError::Format("Malformed file name in header".to_string()))?;
+        // The name is authenticated, but is still checked so a file can never decrypt outside its folder
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(GuardXError::Format(format!("Invalid file name in header: {:?}", name)).into());
+        }
+        Ok(name)
+    }
+
+    // Keyed so the map reveals nothing about the plaintext, indexed so entries cannot be reordered
+    fn chunk_mac(&self, index: u64, chunk: &[u8]) -> [u8; MAP_ENTRY_LEN] {
+        let mut ctx = hmac::Context::with_key(&self.mac);
+        ctx.update(&index.to_le_bytes());
+        ctx.update(chunk);
+        let mut out = [0u8; MAP_ENTRY_LEN];
+        out.copy_from_slice(ctx.sign().as_ref());
+        out
+    }
+
+    // Binds the map to the header and the revision, so entries and chunks of different versions cannot be mixed
+    fn map_tag(&self, header_bytes: &[u8], entries: &[[u8; MAP_ENTRY_LEN]], revision: u64) -> [u8; MAP_ENTRY_LEN] {
+        let mut out = [0u8; MAP_ENTRY_LEN];
+        out.copy_from_slice(hmac::sign(&self.tag, &map_tag_input(header_bytes, entries, revision)).as_ref());
+        out
+    }
+
+    // Fails unless the map is sealed with this header and key
+    fn check_map(&self, header_bytes: &[u8], map: &Map) -> Result<()> {
+        let (revision, tag) = map.seal.ok_or_else(corrupted)?;
+        hmac::verify(&self.tag, &map_tag_input(header_bytes, &map.entries, revision), &tag).map_err(|_| GuardXError::Authentication)?;
+        Ok(())
+    }
+}
+
+fn map_tag_input(header_bytes: &[u8], entries: &[[u8; MAP_ENTRY_LEN]], revision: u64) -> Vec<u8> {
+    let mut input = header_bytes.to_vec();
+    input.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    entries.iter().for_each(|entry| input.extend_from_slice(entry));
+    input.extend_from_slice(&revision.to_le_bytes());
+    input
+}
+
+// Offset, kind and total length of one chunk record
+type Record = (u64, u8, u64);
+
+// The keyed chunk hashes at the end of a file, with the revision and tag of a sealed map
+#[derive(Default)]
+struct Map {
+    entries: Vec<[u8; MAP_ENTRY_LEN]>,
+    seal: Option<(u64, [u8; MAP_ENTRY_LEN])>,
+}
+
+fn sealed_map(header: &Header) -> bool {
+    header.extensions.iter().any(|(kind, _)| *kind == EXT_SEALED_MAP)
+}
+
+// Reads what follows REC_MAP; `count` is checked against what the caller expects before the entries are read
+fn read_map<R: Read>(reader: &mut R, sealed: bool, expected: Option<usize>) -> Result<Map> {
+    let mut count = [0u8; 8];
+    reader.read_exact(&mut count).context(truncated())?;
+    let count = u64::from_le_bytes(count);
+    if expected.is_some_and(|n| n as u64 != count) {
+        return Err(GuardXError::Authentication.into());
+    }
+    let mut map = Map::default();
+    for _ in 0..count {
+        let mut entry = [0u8; MAP_ENTRY_LEN];
+        reader.read_exact(&mut entry).context(truncated())?;
+        map.entries.push(entry);
+    }
+    if sealed {
+        let mut revision = [0u8; 8];
+        let mut tag = [0u8; MAP_ENTRY_LEN];
+        reader.read_exact(&mut revision).context(truncated())?;
+        reader.read_exact(&mut tag).context(truncated())?;
+        map.seal = Some((u64::from_le_bytes(revision), tag));
+    }
+    Ok(map)
+}
+
+// Ciphertext records of an existing chunked file that may be copied instead of re-encrypted
+struct Previous {
+    file: BufReader<File>,
+    records: Vec<Record>,
+    map: Vec<[u8; MAP_ENTRY_LEN]>,
+    revision: u64,
+}
+
+struct Plain {
+    kind: u8,
+    data: Zeroizing<Vec<u8>>,
+}
+
+enum Sealed {
+    Fresh { kind: u8, nonce: [u8; NONCE_LEN], data: Vec<u8> },
+    Reuse(usize),
+}
+
+// Splits input into chunks, looking one chunk ahead so the last one can be flagged as final
+struct ChunkReader<'a, R> {
+    reader: &'a mut R,
+    current: Zeroizing<Vec<u8>>,
+    next: Zeroizing<Vec<u8>>,
+    current_len: usize,
+    done: bool,
+}
+
+impl<'a, R: Read> ChunkReader<'a, R> {
+    fn new(reader: &'a mut R, chunk_size: usize) -> Result<Self> {
+        let mut current = Zeroizing::new(vec![0u8; chunk_size]);
+        let current_len = format::read_full(reader, &mut current)?;
+        throttle::pace(current_len);
+        Ok(ChunkReader { reader, current, next: Zeroizing::new(vec![0u8; chunk_size]), current_len, done: false })
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<'_, R> {
+    type Item = Result<Plain>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Err(e) = check_cancelled() {
+            self.done = true;
+            return Some(Err(e));
+        }
+        // A full chunk is only the last one if nothing follows it
+        let next_len = if self.current_len == self.current.len() {
+            match format::read_full(self.reader, &mut self.next) {
+                Ok(n) => {
+                    throttle::pace(n);
+                    n
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        } else {
+            0
+        };
+        let kind = if next_len == 0 { REC_FINAL } else { REC_CHUNK };
+        let data = Zeroizing::new(self.current[..self.current_len].to_vec());
+        if kind == REC_FINAL {
+            self.done = true;
+        } else {
+            std::mem::swap(&mut self.current, &mut self.next);
+            self.current_len = next_len;
+        }
+        Some(Ok(Plain { kind, data }))
+    }
+}
+
+struct Sealer<'a> {
+    keys: &'a FileKeys,
+    header_bytes: &'a [u8],
+    records: &'a [Record],
+    map: &'a [[u8; MAP_ENTRY_LEN]],
+}
+
+impl Sealer<'_> {
+    fn hash(&self, index: u64, plain: Plain) -> (Plain, [u8; MAP_ENTRY_LEN]) {
+        let mac = self.keys.chunk_mac(index, &plain.data);
+        (plain, mac)
+    }
+
+    fn seal(&self, index: u64, (plain, mac): (Plain, [u8; MAP_ENTRY_LEN])) -> Result<(Sealed, [u8; MAP_ENTRY_LEN])> {
+        let i = index as usize;
+        if i < self.map.len() && self.map[i] == mac && self.records[i].1 == plain.kind {
+            return Ok((Sealed::Reuse(i), mac));
+        }
+
+        let mut nonce = [0u8; NONCE_LEN];
+        entropy::fill(&mut nonce)?;
+        let mut data = plain.data;
+        self.keys.aead.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(format::chunk_aad(self.header_bytes, index, plain.kind)),
+            &mut *data,
+        )
+        .map_err(|e| anyhow::anyhow!("Encryption error: {:?}", e))?;
+        Ok((Sealed::Fresh { kind: plain.kind, nonce, data: std::mem::take(&mut *data) }, mac))
+    }
+}
+
+struct Sink<'a, W> {
+    writer: &'a mut W,
+    keys: &'a FileKeys,
+    header_bytes: &'a [u8],
+    // Where the map is sealed, the revision it is sealed with
+    revision: Option<u64>,
+    previous: Option<&'a mut BufReader<File>>,
+    records: &'a [Record],
+    map: Vec<[u8; MAP_ENTRY_LEN]>,
+    stats: DeltaStats,
+}
+
+impl<W: Write> Sink<'_, W> {
+    fn write(&mut self, (sealed, mac): (Sealed, [u8; MAP_ENTRY_LEN])) -> Result<()> {
+        match sealed {
+            Sealed::Fresh { kind, nonce, data } => {
+                self.writer.write_all(&[kind])?;
+                self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+                self.writer.write_all(&nonce)?;
+                self.writer.write_all(&data)?;
+            }
+            Sealed::Reuse(index) => {
+                let file = self.previous.as_mut().context("No previous file to reuse chunks from")?;
+                let (offset, _, len) = self.records[index];
+                file.seek(SeekFrom::Start(offset))?;
+                let mut record = vec![0u8; len as usize];
+                file.read_exact(&mut record)?;
+                self.writer.write_all(&record)?;
+                self.stats.reused += 1;
+            }
+        }
+        self.map.push(mac);
+        self.stats.total += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<DeltaStats> {
+        self.writer.write_all(&[REC_MAP])?;
+        self.writer.write_all(&(self.map.len() as u64).to_le_bytes())?;
+        for entry in &self.map {
+            self.writer.write_all(entry)?;
+        }
+        if let Some(revision) = self.revision {
+            self.writer.write_all(&revision.to_le_bytes())?;
+            self.writer.write_all(&self.keys.map_tag(self.header_bytes, &self.map, revision))?;
+        }
+        Ok(self.stats)
+    }
+}
+
+// Chunks allowed in flight between pipeline stages; 0 runs read, encrypt and write on one thread
+static PIPELINE_DEPTH: AtomicUsize = AtomicUsize::new(8);
+
+pub fn set_pipeline_depth(chunks: usize) {
+    PIPELINE_DEPTH.store(chunks, Ordering::Relaxed);
+}
+
+// Browsers give wasm32 no threads, so the core always runs the sequential path there
+pub fn pipeline_depth() -> usize {
+    if cfg!(target_arch = "wasm32") {
+        return 0;
+    }
+    PIPELINE_DEPTH.load(Ordering::Relaxed)
+}
+
+// Set when an operation runs out of time. Chunk loops check it between chunks, so the partial output is
+// dropped by replace_with and every file is left either fully written or untouched.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::Relaxed);
+}
+
+pub fn check_cancelled() -> Result<()> {
+    if CANCELLED.load(Ordering::Relaxed) {
+        return Err(Cancelled.into());
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Operation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl Cancelled {
+    // Also looks inside io::Error, which is how DecryptingReader reports it
+    pub fn of(e: &anyhow::Error) -> bool {
+        e.chain().any(|cause| cause.is::<Cancelled>() || cause.downcast_ref::<io::Error>().and_then(|e| e.get_ref()).is_some_and(|e| e.is::<Cancelled>()))
+    }
+}
+
+// Header for a new file, stretched with the current KDF
+fn new_header(file_id: [u8; 16], cipher: Cipher) -> Result<Header> {
+    let (kdf, salt) = kdf::for_new_files()?;
+    let mut header = Header::new(file_id, cipher.id());
+    header.extensions.push((EXT_KDF, kdf.to_bytes(&salt)));
+    header.extensions.push((EXT_SEALED_MAP, Vec::new()));
+    Ok(header)
+}
+
+fn encrypt_chunks<R: Read + Send, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &str,
+    header: &Header,
+    previous: Option<Previous>,
+) -> Result<DeltaStats> {
+    let keys = FileKeys::derive(key, header)?;
+    let header_bytes = header.to_bytes();
+    writer.write_all(&header_bytes)?;
+
+    let (mut prev_file, records, prev_map, revision) = match previous {
+        Some(prev) => (Some(prev.file), prev.records, prev.map, prev.revision + 1),
+        None => (None, Vec::new(), Vec::new(), 0),
+    };
+    let sealer = Sealer { keys: &keys, header_bytes: &header_bytes, records: &records, map: &prev_map };
+    let mut sink = Sink {
+        writer,
+        keys: &keys,
+        header_bytes: &header_bytes,
+        revision: sealed_map(header).then_some(revision),
+        previous: prev_file.as_mut(),
+        records: &records,
+        map: Vec::new(),
+        stats: DeltaStats { total: 0, reused: 0 },
+    };
+    let chunks = ChunkReader::new(reader, header.chunk_size as usize)?;
+
+    let depth = pipeline_depth();
+    if depth == 0 {
+        for (index, plain) in chunks.enumerate() {
+            let hashed = sealer.hash(index as u64, plain?);
+            sink.write(sealer.seal(index as u64, hashed)?)?;
+        }
+        return sink.finish();
+    }
+
+    // Read and hash the next chunk while encrypting the current one and writing the previous one
+    let slots = (depth / 2).max(1);
+    let sealer = &sealer;
+    thread::scope(|scope| -> Result<()> {
+        let (plain_tx, plain_rx) = mpsc::sync_channel(slots);
+        let (sealed_tx, sealed_rx) = mpsc::sync_channel(slots);
+        scope.spawn(move || {
+            for (index, plain) in chunks.enumerate() {
+                let plain = plain.map(|p| sealer.hash(index as u64, p));
+                let failed = plain.is_err();
+                if plain_tx.send(plain).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        scope.spawn(move || {
+            for (index, plain) in plain_rx.iter().enumerate() {
+                let sealed = plain.and_then(|p| sealer.seal(index as u64, p));
+                let failed = sealed.is_err();
+                if sealed_tx.send(sealed).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        for sealed in sealed_rx {
+            sink.write(sealed?)?;
+        }
+        Ok(())
+    })?;
+    sink.finish()
+}
+
+/// Encrypts everything `reader` yields into the chunked format.
+///
+/// ```
+/// use guardx_core::crypto::{decrypt_stream, encrypt_stream, Cipher};
+///
+/// let mut sealed = Vec::new();
+/// encrypt_stream(&mut &b"attack at dawn"[..], &mut sealed, "correct horse", Cipher::ChaCha20Poly1305)?;
+///
+/// let mut opened = Vec::new();
+/// decrypt_stream(&mut &sealed[..], &mut opened, "correct horse")?;
+/// assert_eq!(opened, b"attack at dawn");
+/// assert!(decrypt_stream(&mut &sealed[..], &mut Vec::new(), "wrong key").is_err());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn encrypt_stream<R: Read + Send, W: Write>(reader: &mut R, writer: &mut W, key: &str, cipher: Cipher) -> Result<()> {
+    let mut file_id = [0u8; 16];
+    entropy::fill(&mut file_id)?;
+    encrypt_chunks(reader, writer, key, &new_header(file_id, cipher)?, None)?;
+    Ok(())
+}
+
+pub fn decrypt_stream<R: Read, W: Write>(reader: &mut R, writer: &mut W, key: &str) -> Result<()> {
+    let mut magic = [0u8; 6];
+    reader.read_exact(&mut magic).context(GuardXError::Format("File too short to be encrypted".to_string()))?;
+    if &magic != MAGIC {
+        return Err(GuardXError::Format("Not a GuardX encrypted stream".to_string()).into());
+    }
+    let header = Header::read_after_magic(reader)?;
+    let header_bytes = header.to_bytes();
+    let keys = FileKeys::derive(key, &header)?;
+    let max_len = header.chunk_size as usize + TAG_LEN;
+    let mut data = Zeroizing::new(Vec::with_capacity(max_len));
+    let mut index = 0u64;
+    // Keyed hashes of the chunks read, checked against a sealed map once the last one is in
+    let sealed = sealed_map(&header);
+    let mut macs = Vec::new();
+
+    loop {
+        check_cancelled()?;
+        let mut head = [0u8; 5];
+        reader.read_exact(&mut head).context(truncated())?;
+        let kind = head[0];
+        let len = u32::from_le_bytes([head[1], head[2], head[3], head[4]]) as usize;
+        if (kind != REC_CHUNK && kind != REC_FINAL) || !(TAG_LEN..=max_len).contains(&len) {
+            return Err(corrupted().into());
+        }
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        reader.read_exact(&mut nonce_bytes).context(truncated())?;
+        data.resize(len, 0);
+        reader.read_exact(&mut data[..]).context(truncated())?;
+
+        let plaintext = keys.aead
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce_bytes),
+                Aad::from(format::chunk_aad(&header_bytes, index, kind)),
+                &mut data[..],
+            )
+            .map_err(|_| GuardXError::Authentication)?;
+        if sealed {
+            macs.push(keys.chunk_mac(index, plaintext));
+        }
+        writer.write_all(plaintext)?;
+
+        if kind == REC_FINAL {
+            break;
+        }
+        index += 1;
+    }
+    if sealed {
+        let mut kind = [0u8; 1];
+        reader.read_exact(&mut kind).context(truncated())?;
+        if kind[0] != REC_MAP {
+            return Err(corrupted().into());
+        }
+        let map = read_map(reader, true, Some(macs.len()))?;
+        keys.check_map(&header_bytes, &map)?;
+        if map.entries != macs {
+            return Err(GuardXError::Authentication.into());
+        }
+    }
+    Ok(())
+}
+
+// Random access to an encrypted file's plaintext. Only the chunks a read touches are decrypted and
+// authenticated, so truncation is only caught once the final chunk has been read. Where the map is sealed,
+// it is checked up front and each chunk against its entry.
+pub struct DecryptingReader {
+    file: BufReader<File>,
+    end: u64,
+    header_bytes: Vec<u8>,
+    keys: FileKeys,
+    chunk_size: u64,
+    records: Vec<Record>,
+    map: Option<Vec<[u8; MAP_ENTRY_LEN]>>,
+    size: u64,
+    pos: u64,
+    chunk: Option<(u64, Zeroizing<Vec<u8>>)>,
+}
+
+impl DecryptingReader {
+    pub fn open(path: &Path, key: &str) -> Result<Self> {
+        Self::open_at(path, key, 0)
+    }
+
+    // A stream that starts `start` bytes into the file, as the second slot of a container does
+    pub fn open_at(path: &Path, key: &str, start: u64) -> Result<Self> {
+        let mut file = BufReader::new(open_file(path)?);
+        file.seek(SeekFrom::Start(start))?;
+        let mut magic = [0u8; 6];
+        if format::read_full(&mut file, &mut magic)? < magic.len() || &magic != MAGIC {
+            return Err(GuardXError::Format("Not a GuardX encrypted stream".to_string()).into());
+        }
+        let header = Header::read_after_magic(&mut file)?;
+        let (records, map, end) = scan_records(&mut file, &header, start)?;
+        let chunk_size = header.chunk_size as u64;
+        let overhead = (format::RECORD_OVERHEAD + TAG_LEN) as u64;
+        // Seeking relies on every record but the last holding exactly one full chunk
+        let last = match records.split_last() {
+            Some((&(_, REC_FINAL, last), full)) if full.iter().all(|&(_, kind, len)| kind == REC_CHUNK && len == overhead + chunk_size) => last,
+            _ => return Err(corrupted().into()),
+        };
+        if !(overhead..=overhead + chunk_size).contains(&last) {
+            return Err(corrupted().into());
+        }
+        let size = (records.len() as u64 - 1) * chunk_size + last - overhead;
+        let keys = FileKeys::derive(key, &header)?;
+        let header_bytes = header.to_bytes();
+        let map = match sealed_map(&header) {
+            true if map.entries.len() != records.len() => return Err(corrupted().into()),
+            true => {
+                keys.check_map(&header_bytes, &map)?;
+                Some(map.entries)
+            }
+            false => None,
+        };
+        Ok(DecryptingReader {
+            file,
+            end,
+            keys,
+            header_bytes,
+            chunk_size,
+            records,
+            map,
+            size,
+            pos: 0,
+            chunk: None,
+        })
+    }
+
+    // Plaintext length as recorded by the chunk layout
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // File offset just past the stream, where anything stored after it begins
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    fn load(&mut self, index: u64) -> Result<&[u8]> {
+        if self.chunk.as_ref().is_none_or(|(loaded, _)| *loaded != index) {
+            check_cancelled()?;
+            self.chunk = None;
+            let (offset, kind, len) = self.records[index as usize];
+            self.file.seek(SeekFrom::Start(offset + 5))?;
+            let mut nonce = [0u8; NONCE_LEN];
+            self.file.read_exact(&mut nonce).context(truncated())?;
+            let mut data = Zeroizing::new(vec![0u8; len as usize - format::RECORD_OVERHEAD]);
+            self.file.read_exact(&mut data[..]).context(truncated())?;
+            let plain_len = self.keys.aead
+                .open_in_place(
+                    Nonce::assume_unique_for_key(nonce),
+                    Aad::from(format::chunk_aad(&self.header_bytes, index, kind)),
+                    &mut data[..],
+                )
+                .map_err(|_| GuardXError::Authentication)?
+                .len();
+            data.truncate(plain_len);
+            if self.map.as_ref().is_some_and(|map| map[index as usize] != self.keys.chunk_mac(index, &data)) {
+                return Err(GuardXError::Authentication.into());
+            }
+            self.chunk = Some((index, data));
+        }
+        Ok(self.chunk.as_ref().map(|(_, data)| &data[..]).unwrap_or_default())
+    }
+}
+
+impl Read for DecryptingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let (index, start) = (self.pos / self.chunk_size, (self.pos % self.chunk_size) as usize);
+        let chunk = self.load(index).map_err(|e| match e.downcast::<GuardXError>() {
+            Ok(failure) => io::Error::other(failure),
+            Err(e) => match e.downcast::<Cancelled>() {
+                Ok(cancelled) => io::Error::other(cancelled),
+                Err(e) => io::Error::other(e),
+            },
+        })?;
+        let n = buf.len().min(chunk.len().saturating_sub(start));
+        if n == 0 {
+            return Err(io::Error::other(corrupted()));
+        }
+        buf[..n].copy_from_slice(&chunk[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for DecryptingReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.size.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the file"))?;
+        Ok(self.pos)
+    }
+}
+
+pub fn temp_path(dest: &Path) -> PathBuf {
+    let name = dest.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    dest.with_file_name(format!(".{}.gxtmp", name))
+}
+
+// Writes to a temporary sibling and only renames it over `dest` once `write` succeeded
+pub fn replace_with<T, F>(dest: &Path, write: F) -> Result<T>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<T>,
+{
+    let tmp = temp_path(dest);
+    let result: Result<T> = (|| {
+        let mut writer = BufWriter::new(File::create(&tmp).map_err(|e| error::io(e, dest))?);
+        let value = write(&mut writer)?;
+        writer.flush().map_err(|e| error::io(e, dest))?;
+        writer.get_ref().sync_all().map_err(|e| error::io(e, dest))?;
+        Ok(value)
+    })();
+    match result {
+        Ok(value) => {
+            fs::rename(&tmp, dest).map_err(|e| error::io(e, dest))?;
+            Ok(value)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            // The disk fills up while `write` runs, so the error does not know which file it was writing
+            Err(match GuardXError::of(&e) {
+                Some(GuardXError::DiskFull(path)) if path.as_os_str().is_empty() => e.context(GuardXError::DiskFull(dest.to_path_buf())),
+                _ => e,
+            })
+        }
+    }
+}
+
+// Opening a file to read it is where permissions and vanished files show up; the error names the file
+fn open_file(path: &Path) -> Result<File> {
+    File::open(path).map_err(|e| error::io(e, path))
+}
+
+// Files at least this large are mapped rather than read when encrypting them
+const MAP_THRESHOLD: u64 = 256 * 1024 * 1024;
+
+// A file being encrypted. Mapped, each chunk is copied once, out of the page cache, instead of into a read
+// buffer and then into the chunk; that is worth it for files of gigabytes. Small files, platforms without
+// the check below and files that cannot be mapped are read through BufReader.
+enum Source {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    Mapped(io::Cursor<memmap2::Mmap>),
+    Buffered(BufReader<File>),
+}
+
+impl Source {
+    fn open(path: &Path) -> Result<Source> {
+        let file = open_file(path)?;
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        if let Some(map) = map(&file) {
+            return Ok(Source::Mapped(io::Cursor::new(map)));
+        }
+        Ok(Source::Buffered(BufReader::new(file)))
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            Source::Mapped(map) => map.read(buf),
+            Source::Buffered(file) => file.read(buf),
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn map(file: &File) -> Option<memmap2::Mmap> {
+    let meta = file.metadata().ok()?;
+    if !meta.is_file() || meta.len() < MAP_THRESHOLD || !local_filesystem(file) {
+        return None;
+    }
+    // The mapping is only read, through the Cursor that owns it. A file cut short while it is mapped makes
+    // the read fault instead of return early; on a local filesystem only another program on this machine
+    // can do that, and only while it is being encrypted.
+    let map = unsafe { memmap2::Mmap::map(file) }.ok()?;
+    let _ = map.advise(memmap2::Advice::Sequential);
+    Some(map)
+}
+
+// On network and FUSE filesystems a file can change or vanish on another machine, and a dropped connection
+// faults the mapping too
+#[cfg(target_os = "linux")]
+fn local_filesystem(file: &File) -> bool {
+    use std::os::fd::AsRawFd;
+    // NFS, SMB, CIFS, SMB2, FUSE, 9p, Ceph and AFS
+    const REMOTE: [u32; 8] = [0x6969, 0x517b, 0xff53_4d42, 0xfe53_4d42, 0x6573_5546, 0x0102_1997, 0x00c3_6400, 0x5346_414f];
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    let found = unsafe { libc::fstatfs(file.as_raw_fd(), &mut stat) } == 0;
+    found && !REMOTE.contains(&(stat.f_type as u32))
+}
+
+#[cfg(target_os = "macos")]
+fn local_filesystem(file: &File) -> bool {
+    use std::os::fd::AsRawFd;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    let found = unsafe { libc::fstatfs(file.as_raw_fd(), &mut stat) } == 0;
+    found && stat.f_flags & libc::MNT_LOCAL as u32 != 0
+}
+
+pub fn is_chunked(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; 6];
+    let n = format::read_full(&mut open_file(path)?, &mut magic)?;
+    Ok(n == magic.len() && &magic == MAGIC)
+}
+
+/// Encrypts the file at `path` in place; the original is only replaced once encryption succeeded.
+///
+/// ```
+/// use guardx_core::crypto::{decrypt_file, encrypt_file, is_chunked, preferred_cipher};
+///
+/// # let path = std::env::temp_dir().join(format!("guardx-doc-file-{}", std::process::id()));
+/// std::fs::write(&path, "meet at noon")?;
+/// encrypt_file(&path, "correct horse", preferred_cipher())?;
+/// assert!(is_chunked(&path)?);
+///
+/// decrypt_file(&path, "correct horse")?;
+/// assert_eq!(std::fs::read_to_string(&path)?, "meet at noon");
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn encrypt_file(path: &Path, key: &str, cipher: Cipher) -> Result<()> {
+    encrypt_to(path, path, key, cipher)
+}
+
+pub fn encrypt_to(src: &Path, dest: &Path, key: &str, cipher: Cipher) -> Result<()> {
+    replace_with(dest, |w| encrypt_stream(&mut Source::open(src)?, w, key, cipher))
+}
+
+// Encrypts the file at `path` under a random name and seals its real name into the header; returns the new path
+pub fn encrypt_hiding_name(path: &Path, key: &str, cipher: Cipher) -> Result<PathBuf> {
+    let dest = encrypt_hiding_name_into(path, path.parent().unwrap_or(Path::new("")), key, cipher)?;
+    fs::remove_file(path).map_err(|e| error::io(e, path))?;
+    Ok(dest)
+}
+
+// The same, but into `dir`, leaving the file at `path` as it is
+pub fn encrypt_hiding_name_into(path: &Path, dir: &Path, key: &str, cipher: Cipher) -> Result<PathBuf> {
+    let name = path.file_name().and_then(|n| n.to_str()).with_context(|| format!("File name is not valid UTF-8: {:?}", path))?;
+    let mut file_id = [0u8; 16];
+    entropy::fill(&mut file_id)?;
+    let mut header = new_header(file_id, cipher)?;
+    let sealed = FileKeys::derive(key, &header)?.seal_name(name)?;
+    if sealed.len() > u16::MAX as usize / 2 {
+        return Err(anyhow::anyhow!("File name is too long to encrypt: {:?}", path));
+    }
+    header.extensions.push((EXT_NAME, sealed));
+    let dest = dir.join(file_id.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    replace_with(&dest, |w| encrypt_chunks(&mut Source::open(path)?, w, key, &header, None))?;
+    Ok(dest)
+}
+
+// The real name sealed into an encrypted file's header, or None when the file keeps its own name
+pub fn original_name(path: &Path, key: &str) -> Result<Option<String>> {
+    let Some(header) = read_header(path)? else { return Ok(None) };
+    sealed_name(&header, key)
+}
+
+// What a chunked file's header and record layout show without the key: the cipher, how the key is
+// stretched, whether the real name is sealed in, and how many chunks there are. None for other files.
+pub struct HeaderInfo {
+    pub version: u8,
+    pub cipher: Cipher,
+    pub chunk_size: u32,
+    pub file_id: [u8; 16],
+    // None for files written before key stretching
+    pub kdf: Option<Kdf>,
+    pub name_sealed: bool,
+    pub chunks: usize,
+    pub chunk_map: bool,
+    // How many times the file was re-encrypted in place; None for files from before sealed maps
+    pub revision: Option<u64>,
+}
+
+pub fn header_info(path: &Path) -> Result<Option<HeaderInfo>> {
+    let mut file = BufReader::new(open_file(path)?);
+    let Some(header) = header_from(&mut file)? else { return Ok(None) };
+    let kdf = match header.extensions.iter().find(|(kind, _)| *kind == EXT_KDF) {
+        Some((_, params)) => Some(Kdf::from_bytes(params)?.0),
+        None => None,
+    };
+    let (records, map, _) = scan_records(&mut file, &header, 0)?;
+    Ok(Some(HeaderInfo {
+        version: header.version,
+        cipher: Cipher::from_id(header.cipher)?,
+        chunk_size: header.chunk_size,
+        file_id: header.file_id,
+        kdf,
+        name_sealed: header.extensions.iter().any(|(kind, _)| *kind == EXT_NAME),
+        chunks: records.len(),
+        chunk_map: !map.entries.is_empty() && map.entries.len() == records.len(),
+        revision: map.seal.map(|(revision, _)| revision),
+    }))
+}
+
+// The same for an encrypted file read from elsewhere, such as an SFTP server; only its header is read
+pub fn original_name_in<R: Read>(reader: &mut R, key: &str) -> Result<Option<String>> {
+    let Some(header) = header_from(reader)? else { return Ok(None) };
+    sealed_name(&header, key)
+}
+
+// None for anything that is not a chunked GuardX file
+fn read_header(path: &Path) -> Result<Option<Header>> {
+    header_from(&mut BufReader::new(open_file(path)?))
+}
+
+fn header_from<R: Read>(reader: &mut R) -> Result<Option<Header>> {
+    let mut magic = [0u8; 6];
+    if format::read_full(reader, &mut magic)? < magic.len() || &magic != MAGIC {
+        return Ok(None);
+    }
+    Ok(Some(Header::read_after_magic(reader)?))
+}
+
+fn sealed_name(header: &Header, key: &str) -> Result<Option<String>> {
+    match header.extensions.iter().find(|(kind, _)| *kind == EXT_NAME) {
+        Some((_, sealed)) => Ok(Some(FileKeys::derive(key, header)?.open_name(sealed)?)),
+        None => Ok(None),
+    }
+}
+
+// Moves a file from `old_key` to `new_key` in one streaming pass: each chunk is authenticated with the
+// old key and sealed again with the new one, and the file is only replaced once all of it went through.
+// The file id is kept, so a name hidden under it stays valid; the sealed name is carried over. age files stay age files.
+pub fn rekey_file(path: &Path, old_key: &str, new_key: &str, cipher: Cipher) -> Result<()> {
+    if age::is_age(path)? {
+        return age::rekey_file(path, old_key, new_key);
+    }
+    let Some(old_header) = read_header(path)? else {
+        let plain = open_legacy(path, old_key)?;
+        return replace_with(path, |w| encrypt_stream(&mut &plain[..], w, new_key, cipher));
+    };
+    let mut header = new_header(old_header.file_id, cipher)?;
+    if let Some(name) = sealed_name(&old_header, old_key)? {
+        let sealed = FileKeys::derive(new_key, &header)?.seal_name(&name)?;
+        header.extensions.push((EXT_NAME, sealed));
+    }
+    // Moved into the closure so the source is closed before the rename, which Windows insists on
+    let mut reader = DecryptingReader::open(path, old_key)?;
+    replace_with(path, move |w| encrypt_chunks(&mut reader, w, new_key, &header, None).map(drop))
+}
+
+// Files with a sealed name come back under that name; the random-named file is removed afterwards
+pub fn decrypt_file(path: &Path, key: &str) -> Result<()> {
+    if age::is_age(path)? {
+        return age::decrypt_file(path, key);
+    }
+    if !is_chunked(path)? {
+        return decrypt_legacy(path, key);
+    }
+    let dest = match original_name(path, key)? {
+        Some(name) => path.with_file_name(name),
+        None => path.to_path_buf(),
+    };
+    if dest != path && dest.exists() {
+        return Err(anyhow::anyhow!("{:?} already exists", dest));
+    }
+    replace_with(&dest, |w| decrypt_stream(&mut BufReader::new(open_file(path)?), w, key))?;
+    if dest != path {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// Writes the plaintext of any encrypted file to `dest`, leaving the encrypted file as it is
+pub fn decrypt_to(path: &Path, dest: &Path, key: &str) -> Result<()> {
+    if age::is_age(path)? {
+        return replace_with(dest, |w| age::decrypt_stream(&mut open_file(path)?, w, key));
+    }
+    if !is_chunked(path)? {
+        let plain = open_legacy(path, key)?;
+        return replace_with(dest, |w| Ok(w.write_all(&plain)?));
+    }
+    replace_with(dest, |w| decrypt_stream(&mut BufReader::new(open_file(path)?), w, key))
+}
+
+// Authenticates every chunk of an encrypted file (a whole legacy file, or an age file) without writing anything
+pub fn verify_file(path: &Path, key: &str) -> Result<()> {
+    if age::is_age(path)? {
+        return age::verify_file(path, key);
+    }
+    if !is_chunked(path)? {
+        return open_legacy(path, key).map(drop);
+    }
+    decrypt_stream(&mut BufReader::new(open_file(path)?), &mut io::sink(), key)
+}
+
+// A chunked file's id and its keyed chunk hashes
+pub type ChunkMap = ([u8; 16], Vec<[u8; MAP_ENTRY_LEN]>);
+
+// Read from the record heads and the map at the end without touching the ciphertext. None for files
+// without a complete map: legacy and age files.
+pub fn chunk_map(path: &Path) -> Result<Option<ChunkMap>> {
+    let mut file = BufReader::new(open_file(path)?);
+    let mut magic = [0u8; 6];
+    if format::read_full(&mut file, &mut magic)? < magic.len() || &magic != MAGIC {
+        return Ok(None);
+    }
+    let header = Header::read_after_magic(&mut file)?;
+    let (records, map, _) = scan_records(&mut file, &header, 0)?;
+    Ok((!map.entries.is_empty() && map.entries.len() == records.len()).then_some((header.file_id, map.entries)))
+}
+
+// Every chunk record in order, plus the chunk map if one follows them and the offset where the stream ends
+fn scan_records(file: &mut BufReader<File>, header: &Header, start: u64) -> Result<(Vec<Record>, Map, u64)> {
+    let mut records = Vec::new();
+    let mut map = Map::default();
+    let mut pos = start + header.to_bytes().len() as u64;
+    loop {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut head = [0u8; 5];
+        if format::read_full(file, &mut head)? < head.len() {
+            break;
+        }
+        let kind = head[0];
+        if kind == REC_MAP {
+            file.seek(SeekFrom::Start(pos + 1))?;
+            map = read_map(file, sealed_map(header), None)?;
+            pos += 1 + 8 + (map.entries.len() * MAP_ENTRY_LEN) as u64;
+            if map.seal.is_some() {
+                pos += 8 + MAP_ENTRY_LEN as u64;
+            }
+            break;
+        }
+        if kind != REC_CHUNK && kind != REC_FINAL {
+            break;
+        }
+        let len = u32::from_le_bytes([head[1], head[2], head[3], head[4]]) as u64;
+        let total = format::RECORD_OVERHEAD as u64 + len;
+        records.push((pos, kind, total));
+        pos += total;
+    }
+    Ok((records, map, pos))
+}
+
+// Re-encrypts `plain` into the existing encrypted file, copying every chunk whose keyed hash is unchanged.
+// The file keeps its cipher; `cipher` only applies when the old file has to be replaced wholesale.
+pub fn reencrypt_delta(plain: &Path, encrypted: &Path, key: &str, cipher: Cipher) -> Result<DeltaStats> {
+    let mut file = BufReader::new(open_file(encrypted)?);
+    let mut magic = [0u8; 6];
+    if format::read_full(&mut file, &mut magic)? < magic.len() || &magic != MAGIC {
+        // Legacy files have no chunk map, so there is nothing to reuse
+        drop(file);
+        encrypt_to(plain, encrypted, key, cipher)?;
+        return Ok(DeltaStats { total: 0, reused: 0 });
+    }
+    let mut header = Header::read_after_magic(&mut file)?;
+    let (records, map, _) = scan_records(&mut file, &header, 0)?;
+    let revision = map.seal.map_or(0, |(revision, _)| revision);
+    // Only a complete map sealed with this key is trusted to say which chunks are unchanged
+    let trusted = sealed_map(&header) && map.entries.len() == records.len() && FileKeys::derive(key, &header)?.check_map(&header.to_bytes(), &map).is_ok();
+    let entries = if trusted { map.entries } else { Vec::new() };
+    // Files from before sealed maps get one; that changes the header every chunk is bound to, so this
+    // pass re-encrypts all of them
+    if !sealed_map(&header) {
+        header.extensions.push((EXT_SEALED_MAP, Vec::new()));
+    }
+
+    let previous = Previous { file, records, map: entries, revision };
+    replace_with(encrypted, move |w| {
+        encrypt_chunks(&mut Source::open(plain)?, w, key, &header, Some(previous))
+    })
+}
+
+fn open_legacy(path: &Path, key: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let encrypted_data = read(path)?;
+    if encrypted_data.len() < MIN_CIPHERTEXT_LEN {
+        return Err(GuardXError::Format("File too short to be encrypted".to_string()).into());
+    }
+    let (nonce_bytes, ciphertext) = encrypted_data.split_at(12);
+
+    let key_bytes = input_key(key, None, &[])?;
+    let unbound_key = UnboundKey::new(&AES_256_GCM, &key_bytes)
+        .map_err(|e| anyhow::anyhow!("Key error: {:?}", e))?;
+    let key = LessSafeKey::new(unbound_key);
+    let mut data = Zeroizing::new(ciphertext.to_vec());
+    let plain_len = key
+        .open_in_place(
+            Nonce::try_assume_unique_for_key(nonce_bytes)
+                .map_err(|e| anyhow::anyhow!("Nonce error: {:?}", e))?,
+            Aad::empty(),
+            &mut data,
+        )
+        .map_err(|_| GuardXError::Authentication)?
+        .len();
+    data.truncate(plain_len);
+    Ok(data)
+}
+
+fn decrypt_legacy(path: &Path, key: &str) -> Result<()> {
+    write(path, open_legacy(path, key)?)?;
+    Ok(())
+}
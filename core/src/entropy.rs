@@ -0,0 +1,86 @@
+use anyhow::Result;
+use ring::rand::{SecureRandom, SystemRandom};
+
+// Every nonce, salt, file id and timestamp GuardX writes comes through here. Builds with the
+// `deterministic` feature can replace them with a seeded DRBG and a fixed clock so that an
+// encrypt/verify/restore run produces byte-identical artifacts; normal builds cannot.
+#[cfg(feature = "deterministic")]
+mod seeded {
+    use ring::hmac;
+    use std::sync::{Mutex, OnceLock};
+
+    pub const SEED_VAR: &str = "GUARDX_TEST_SEED";
+    pub const TIME_VAR: &str = "GUARDX_TEST_TIME";
+
+    // HMAC-SHA256 in counter mode; predictable by design, for tests only
+    pub struct Drbg {
+        key: hmac::Key,
+        counter: u64,
+    }
+
+    impl Drbg {
+        fn fill(&mut self, buf: &mut [u8]) {
+            for block in buf.chunks_mut(32) {
+                let tag = hmac::sign(&self.key, &self.counter.to_le_bytes());
+                block.copy_from_slice(&tag.as_ref()[..block.len()]);
+                self.counter += 1;
+            }
+        }
+    }
+
+    fn drbg() -> Option<&'static Mutex<Drbg>> {
+        static DRBG: OnceLock<Option<Mutex<Drbg>>> = OnceLock::new();
+        DRBG.get_or_init(|| {
+            let seed = std::env::var(SEED_VAR).ok()?;
+            let key = hmac::Key::new(hmac::HMAC_SHA256, seed.as_bytes());
+            Some(Mutex::new(Drbg { key, counter: 0 }))
+        })
+        .as_ref()
+    }
+
+    pub fn fill(buf: &mut [u8]) -> bool {
+        match drbg() {
+            Some(drbg) => {
+                drbg.lock().unwrap_or_else(|e| e.into_inner()).fill(buf);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn timestamp() -> Option<i64> {
+        std::env::var(TIME_VAR).ok()?.parse().ok()
+    }
+}
+
+pub fn fill(buf: &mut [u8]) -> Result<()> {
+    #[cfg(feature = "deterministic")]
+    {
+        if seeded::fill(buf) {
+            return Ok(());
+        }
+    }
+    SystemRandom::new().fill(buf)
+        .map_err(|e| anyhow::anyhow!("RNG error: {:?}", e))
+}
+
+pub fn timestamp() -> i64 {
+    #[cfg(feature = "deterministic")]
+    {
+        if let Some(ts) = seeded::timestamp() {
+            return ts;
+        }
+    }
+    chrono::Utc::now().timestamp()
+}
+
+// Lets `guardx` warn loudly if a deterministic build is actually running seeded
+pub fn is_seeded() -> bool {
+    #[cfg(feature = "deterministic")]
+    {
+        if std::env::var_os(seeded::SEED_VAR).is_some() {
+            return true;
+        }
+    }
+    false
+}
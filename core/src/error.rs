@@ -0,0 +1,55 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Causes of a failure that callers branch on: the CLI turns them into exit codes, and the TUI picks its
+// message and whether to offer a retry. Everything else stays a plain anyhow message. Attach them with
+// `.context(..)` or `.into()`, or wrap I/O errors with `io`, so `GuardXError::of` finds them.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum GuardXError {
+    // The AEAD tag did not verify: the key is wrong or the data was altered
+    #[error("Decryption failed: wrong key or damaged data")]
+    Authentication,
+    // Not a GuardX file, an unsupported version, or a damaged header or record layout
+    #[error("{0}")]
+    Format(String),
+    // The path is empty when the I/O error did not say which file it was about
+    #[error("Permission denied: {}", .0.display())]
+    PermissionDenied(PathBuf),
+    #[error("Not enough disk space to write {}", .0.display())]
+    DiskFull(PathBuf),
+    #[error("{} does not exist", .0.display())]
+    NotFound(PathBuf),
+}
+
+impl GuardXError {
+    // Also looks inside io::Errors, which is how failures cross Read implementations, and sorts plain
+    // I/O errors anywhere in the chain by their kind
+    pub fn of(e: &anyhow::Error) -> Option<GuardXError> {
+        let attached = e.downcast_ref::<GuardXError>().or_else(|| {
+            e.chain().find_map(|cause| cause.downcast_ref::<GuardXError>().or_else(|| cause.downcast_ref::<io::Error>()?.get_ref()?.downcast_ref::<GuardXError>()))
+        });
+        match attached {
+            Some(found) => Some(found.clone()),
+            None => e.chain().find_map(|cause| Self::from_io(cause.downcast_ref::<io::Error>()?, Path::new(""))),
+        }
+    }
+
+    // The variant for an I/O error about `path`, if it is one of the kinds callers branch on
+    pub fn from_io(e: &io::Error, path: &Path) -> Option<GuardXError> {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => Some(GuardXError::PermissionDenied(path.to_path_buf())),
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => Some(GuardXError::DiskFull(path.to_path_buf())),
+            io::ErrorKind::NotFound => Some(GuardXError::NotFound(path.to_path_buf())),
+            _ => None,
+        }
+    }
+}
+
+// An I/O error about `path`, carrying its variant when it has one so the message names the file
+pub fn io(e: io::Error, path: &Path) -> anyhow::Error {
+    match GuardXError::from_io(&e, path) {
+        Some(variant) => anyhow::Error::new(e).context(variant),
+        None => anyhow::Error::new(e).context(format!("{}", path.display())),
+    }
+}
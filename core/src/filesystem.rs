@@ -0,0 +1,1057 @@
+use crate::config::{Config, Label, Lifecycle};
+use crate::age::{self, Recipient};
+use crate::entropy;
+use crate::error::{self, GuardXError};
+use crate::filter;
+use crate::crypto::{self, encrypt_file, encrypt_hiding_name, encrypt_hiding_name_into, encrypt_to, decrypt_file, is_chunked, rekey_file, verify_file, Cipher, OutputFormat};
+use crate::journal::{self, Interrupted, Operation};
+use crate::manifest::{Checksums, Manifest, CHECKSUMS_NAME, MANIFEST_NAME, SIGNATURES_NAME};
+use crate::registry::Registry;
+use crate::storage::{Kind, Local, Storage};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::uring;
+use anyhow::{Result, Context};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use dirs::home_dir;
+use zeroize::Zeroizing;
+
+// Enough to put a destructive operation back the way it was
+pub enum Undo {
+    Trashed { path: PathBuf, folder: bool },
+    // Also covers moves: undoing puts `to` back at `from`
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+impl Undo {
+    pub fn describe(&self) -> String {
+        match self {
+            Undo::Trashed { path, .. } => format!("restore {}", display_name(path)),
+            Undo::Renamed { from, to } => format!("rename {} back to {}", display_name(to), display_name(from)),
+        }
+    }
+}
+
+// The drive under a folder went away part way through encrypting or decrypting it; what was left is in
+// the journal, for `resume` once the drive is back
+#[derive(Debug)]
+pub struct Offline {
+    pub dir: PathBuf,
+    pub operation: Operation,
+    pub done: usize,
+    pub total: usize,
+}
+
+impl std::fmt::Display for Offline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} went offline after {} of {} files; the {} is finished once it is back", display_name(&self.dir), self.done, self.total, self.operation.name())
+    }
+}
+
+impl std::error::Error for Offline {}
+
+pub struct FileSystem {
+    // What the folders are listed, read, written, renamed and deleted through
+    pub storage: Box<dyn Storage>,
+    pub dirs: Vec<PathBuf>,
+    // Where `dirs` were listed from; folders are grouped by root, and registered vaults elsewhere come last
+    pub roots: Vec<PathBuf>,
+    pub config: Config,
+    encrypted: Vec<bool>,
+    // Archived folders are kept out of `dirs` unless `show_archived` is on
+    archived: Vec<PathBuf>,
+    pub show_archived: bool,
+    // Listed folders whose drive is unmounted; they stay listed so they can pick up where they left off
+    offline: BTreeSet<PathBuf>,
+    // Mounted drives added to `roots` while they are plugged in; never saved
+    drives: Vec<PathBuf>,
+    // Tree view: subfolders listed under an expanded folder, with how many levels down they are
+    nested: HashMap<PathBuf, usize>,
+    expanded: BTreeSet<PathBuf>,
+    // The subfolders of each folder that was ever expanded, as read then, for the per-subtree counts
+    subfolders: HashMap<PathBuf, Vec<(PathBuf, bool)>>,
+}
+
+impl FileSystem {
+    pub fn new() -> Result<Self> {
+        Self::with_storage(Box::new(Local))
+    }
+
+    pub fn with_storage(storage: Box<dyn Storage>) -> Result<Self> {
+        let config = Config::load()?;
+        let roots = Self::roots(&config)?;
+        let mut dirs = Vec::new();
+        for root in &roots {
+            // A root on a drive that is not mounted is just empty for now
+            if !storage.exists(root) {
+                continue;
+            }
+            dirs.extend(storage.list(root)?.into_iter().filter(|e| e.kind == Kind::Folder).map(|e| e.path));
+        }
+        let profiled = config.profile().is_some_and(|p| !p.roots.is_empty());
+        if let Ok(registry) = Registry::load() {
+            for vault in registry.vaults {
+                let in_profile = !profiled || roots.iter().any(|root| vault.path.starts_with(root));
+                if in_profile && storage.exists(&vault.path) && !dirs.contains(&vault.path) {
+                    dirs.push(vault.path);
+                }
+            }
+        }
+        let (archived, dirs): (Vec<_>, Vec<_>) = dirs.into_iter().partition(|d| config.lifecycle(d) == Lifecycle::Archived);
+        let encrypted = dirs.iter().map(|d| storage.exists(&d.join(MANIFEST_NAME))).collect();
+        let mut fs = FileSystem { storage, dirs, roots, config, encrypted, archived, show_archived: false, offline: BTreeSet::new(), drives: Vec::new(), nested: HashMap::new(), expanded: BTreeSet::new(), subfolders: HashMap::new() };
+        fs.arrange();
+        Ok(fs)
+    }
+
+    // The active profile's roots, or else the configured ones, or else the home directory
+    fn roots(config: &Config) -> Result<Vec<PathBuf>> {
+        match config.profile().filter(|p| !p.roots.is_empty()) {
+            Some(profile) => Ok(profile.roots.clone()),
+            None if !config.roots.is_empty() => Ok(config.roots.clone()),
+            None => Ok(vec![home_dir().context("Could not find home directory")?]),
+        }
+    }
+
+    // Index of the root a folder was listed from; `roots.len()` for folders from anywhere else
+    pub fn group(&self, dir: &Path) -> usize {
+        self.roots.iter().position(|root| dir.parent() == Some(root.as_path()))
+            .or_else(|| self.roots.iter().position(|root| dir.starts_with(root)))
+            .unwrap_or(self.roots.len())
+    }
+
+    pub fn group_name(&self, group: usize) -> String {
+        self.roots.get(group).map_or("Elsewhere".to_string(), |root| root.display().to_string())
+    }
+
+    pub fn toggle_show_archived(&mut self) {
+        self.show_archived = !self.show_archived;
+        if self.show_archived {
+            for dir in self.archived.drain(..) {
+                self.encrypted.push(self.storage.exists(&dir.join(MANIFEST_NAME)));
+                self.dirs.push(dir);
+            }
+        } else {
+            let mut i = 0;
+            while i < self.dirs.len() {
+                if self.config.lifecycle(&self.dirs[i]) == Lifecycle::Archived {
+                    self.archived.push(self.dirs.remove(i));
+                    self.encrypted.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.arrange();
+    }
+
+    // Folders from a removable drive, listed until it is unmounted and never written to the registry;
+    // returns how many were not listed yet
+    pub fn attach(&mut self, dirs: &[PathBuf]) -> usize {
+        let mut added = 0;
+        for dir in dirs {
+            if self.dirs.contains(dir) || self.archived.contains(dir) {
+                continue;
+            }
+            if self.config.lifecycle(dir) == Lifecycle::Archived && !self.show_archived {
+                self.archived.push(dir.clone());
+            } else {
+                self.dirs.push(dir.clone());
+                self.encrypted.push(self.storage.exists(&dir.join(MANIFEST_NAME)));
+            }
+            added += 1;
+        }
+        self.arrange();
+        added
+    }
+
+    // A mounted drive as one more root, listing its folders and the encrypted folders further down until it
+    // is unmounted; a drive that is itself encrypted, or inside a root already, is listed like `attach` does.
+    // Returns how many folders were not listed yet.
+    pub fn add_drive(&mut self, mount: &Path, vaults: &[PathBuf]) -> usize {
+        if mount.join(MANIFEST_NAME).is_file() || self.roots.iter().any(|root| mount.starts_with(root)) {
+            return self.attach(vaults);
+        }
+        if !self.drives.iter().any(|d| d == mount) {
+            self.drives.push(mount.to_path_buf());
+            self.roots.push(mount.to_path_buf());
+        }
+        let mut dirs: Vec<PathBuf> = self.storage.list(mount)
+            .map(|entries| entries.into_iter().filter(|e| e.kind == Kind::Folder && !e.link && !system_folder(&e.name())).map(|e| e.path).collect())
+            .unwrap_or_default();
+        dirs.extend(vaults.iter().cloned());
+        self.attach(&dirs)
+    }
+
+    // Drops an unmounted drive's root and the folders on it that were not encrypted and have nothing to
+    // finish; encrypted ones stay listed offline. Call `go_offline` first.
+    pub fn remove_drive(&mut self, mount: &Path) {
+        let Some(i) = self.drives.iter().position(|d| d == mount) else { return };
+        let unfinished = journal::load().unwrap_or_default();
+        let keep = |dir: &PathBuf, encrypted: bool| !dir.starts_with(mount) || encrypted || unfinished.iter().any(|e| e.dir == *dir);
+        let order: Vec<(PathBuf, bool)> = self.dirs.drain(..).zip(self.encrypted.drain(..)).filter(|(dir, encrypted)| keep(dir, *encrypted)).collect();
+        (self.dirs, self.encrypted) = order.into_iter().unzip();
+        self.archived.retain(|dir| keep(dir, true));
+        self.offline.retain(|dir| self.dirs.contains(dir) || self.archived.contains(dir));
+        if !self.dirs.iter().chain(&self.archived).any(|dir| dir.starts_with(mount)) {
+            self.drives.remove(i);
+            self.roots.retain(|root| root != mount);
+            self.arrange();
+        }
+    }
+
+    // Marks the listed folders on an unmounted drive offline; returns them
+    pub fn go_offline(&mut self, mount: &Path) -> Vec<PathBuf> {
+        let gone: Vec<PathBuf> = self.dirs.iter().chain(&self.archived).filter(|d| d.starts_with(mount)).cloned().collect();
+        self.offline.extend(gone.iter().cloned());
+        gone
+    }
+
+    // The folders on a drive that is mounted again; returns the ones that were offline
+    pub fn come_online(&mut self, mount: &Path) -> Vec<PathBuf> {
+        let back: Vec<PathBuf> = self.offline.iter().filter(|d| d.starts_with(mount)).cloned().collect();
+        self.offline.retain(|d| !d.starts_with(mount));
+        for dir in &back {
+            if let Some(i) = self.dirs.iter().position(|d| d == dir) {
+                self.encrypted[i] = self.storage.exists(&dir.join(MANIFEST_NAME));
+            }
+        }
+        back
+    }
+
+    pub fn is_offline(&self, index: usize) -> bool {
+        self.dirs.get(index).is_some_and(|d| self.offline.contains(d))
+    }
+
+    fn check_online(&self, index: usize) -> Result<()> {
+        if self.is_offline(index) {
+            return Err(anyhow::anyhow!("{} is offline; plug its drive back in", display_name(&self.dirs[index])));
+        }
+        Ok(())
+    }
+
+    // Finishes the journaled operation on a folder whose drive is back, with that folder's settings
+    pub fn resume(&self, entry: &Interrupted, key: &str) -> Result<()> {
+        let dir = &entry.dir;
+        self.config.check_writable(dir)?;
+        let cipher = self.config.cipher(dir);
+        resume(entry, key, cipher, self.config.output_format(dir), self.config.encrypt_names(dir))
+    }
+
+    // Active -> archived -> retired -> active; an archived folder leaves the list unless archived ones are shown
+    pub fn cycle_lifecycle(&mut self, index: usize) -> Result<Lifecycle> {
+        let dir = self.dirs.get(index).context("Invalid directory index")?.clone();
+        let state = self.config.lifecycle(&dir).next();
+        self.config.set_lifecycle(&dir, state, entropy::timestamp());
+        self.config.save()?;
+        if state == Lifecycle::Archived && !self.show_archived {
+            self.dirs.remove(index);
+            self.encrypted.remove(index);
+            self.archived.push(dir);
+        }
+        Ok(state)
+    }
+
+    // Pinned folders first in their pinned order, then labelled ones grouped by colour, then the rest by name;
+    // the subfolders of an expanded folder follow it by name
+    pub fn arrange(&mut self) {
+        let mut order: Vec<(PathBuf, bool)> = self.dirs.drain(..).zip(self.encrypted.drain(..)).collect();
+        order.sort_by_cached_key(|(dir, _)| {
+            let top = self.top_level(dir);
+            let below: Vec<String> = dir.strip_prefix(top).unwrap_or(Path::new("")).components().map(|c| c.as_os_str().to_string_lossy().to_lowercase()).collect();
+            (self.config.pin(top).unwrap_or(u32::MAX), self.group(top), self.config.label(top).is_none(), self.config.label(top), display_name(top).to_lowercase(), below)
+        });
+        (self.dirs, self.encrypted) = order.into_iter().unzip();
+    }
+
+    // The listed folder a subfolder in the tree view was expanded from, or the folder itself
+    fn top_level<'a>(&self, dir: &'a Path) -> &'a Path {
+        let mut top = dir;
+        while self.nested.contains_key(top) {
+            match top.parent() {
+                Some(parent) => top = parent,
+                None => break,
+            }
+        }
+        top
+    }
+
+    // Levels below its top-level folder; 0 for folders listed on their own
+    pub fn depth(&self, index: usize) -> usize {
+        self.dirs.get(index).and_then(|d| self.nested.get(d)).copied().unwrap_or(0)
+    }
+
+    pub fn is_expanded(&self, index: usize) -> bool {
+        self.dirs.get(index).is_some_and(|d| self.expanded.contains(d))
+    }
+
+    // None until the folder has been expanded once
+    pub fn has_subfolders(&self, index: usize) -> Option<bool> {
+        self.dirs.get(index).and_then(|d| self.subfolders.get(d)).map(|subs| !subs.is_empty())
+    }
+
+    // The listed folder a subfolder was expanded from
+    pub fn parent_index(&self, index: usize) -> Option<usize> {
+        let dir = self.dirs.get(index).filter(|d| self.nested.contains_key(*d))?;
+        self.dirs.iter().position(|d| Some(d.as_path()) == dir.parent())
+    }
+
+    // Lists a folder's subfolders under it, read afresh each time; returns how many there are
+    pub fn expand(&mut self, index: usize) -> Result<usize> {
+        let dir = self.dirs.get(index).context("Invalid directory index")?.clone();
+        self.check_online(index)?;
+        let mut subs: Vec<PathBuf> = self.storage.list(&dir)?
+            .into_iter()
+            .filter(|e| e.kind == Kind::Folder && !e.link)
+            .map(|e| e.path)
+            .collect();
+        subs.sort();
+        let depth = self.depth(index) + 1;
+        for sub in &subs {
+            if self.dirs.contains(sub) || self.archived.contains(sub) {
+                continue;
+            }
+            self.nested.insert(sub.clone(), depth);
+            if self.config.lifecycle(sub) == Lifecycle::Archived && !self.show_archived {
+                self.archived.push(sub.clone());
+            } else {
+                self.dirs.push(sub.clone());
+                self.encrypted.push(self.storage.exists(&sub.join(MANIFEST_NAME)));
+            }
+        }
+        let count = subs.len();
+        self.subfolders.insert(dir.clone(), subs.into_iter().map(|sub| {
+            let encrypted = self.storage.exists(&sub.join(MANIFEST_NAME));
+            (sub, encrypted)
+        }).collect());
+        self.expanded.insert(dir);
+        self.arrange();
+        Ok(count)
+    }
+
+    // Takes a folder's listed subfolders, and theirs, out of the list
+    pub fn collapse(&mut self, index: usize) {
+        let Some(dir) = self.dirs.get(index).cloned() else { return };
+        self.expanded.retain(|d| !d.starts_with(&dir));
+        let below = |d: &PathBuf| *d != dir && d.starts_with(&dir);
+        let order: Vec<(PathBuf, bool)> = self.dirs.drain(..).zip(self.encrypted.drain(..)).filter(|(d, _)| !(below(d) && self.nested.contains_key(d))).collect();
+        (self.dirs, self.encrypted) = order.into_iter().unzip();
+        self.archived.retain(|d| !(below(d) && self.nested.contains_key(d)));
+        self.nested.retain(|d, _| !below(d));
+    }
+
+    // Back to the flat list
+    pub fn collapse_all(&mut self) {
+        let order: Vec<(PathBuf, bool)> = self.dirs.drain(..).zip(self.encrypted.drain(..)).filter(|(d, _)| !self.nested.contains_key(d)).collect();
+        (self.dirs, self.encrypted) = order.into_iter().unzip();
+        self.archived.retain(|d| !self.nested.contains_key(d));
+        self.nested.clear();
+        self.expanded.clear();
+    }
+
+    // Encrypted folders and all folders below a folder, as far as its subtree has been expanded
+    pub fn subtree_counts(&self, dir: &Path) -> (usize, usize) {
+        let Some(subs) = self.subfolders.get(dir) else { return (0, 0) };
+        let mut counts = (0, 0);
+        for (sub, encrypted) in subs {
+            // Listed folders know when they were encrypted or decrypted since
+            let encrypted = self.dirs.iter().position(|d| d == sub).map_or(*encrypted, |i| self.encrypted[i]);
+            let (below_encrypted, below_total) = self.subtree_counts(sub);
+            counts.0 += below_encrypted + encrypted as usize;
+            counts.1 += below_total + 1;
+        }
+        counts
+    }
+
+    // Pins an unpinned folder at the end of the pinned ones, or unpins it
+    pub fn toggle_pin(&mut self, index: usize) -> Result<()> {
+        let dir = self.dirs.get(index).context("Invalid directory index")?.clone();
+        let next = self.dirs.iter().filter_map(|d| self.config.pin(d)).max().map_or(0, |p| p + 1);
+        let vault = self.config.vault_mut(&dir);
+        vault.pin = if vault.pin.is_some() { None } else { Some(next) };
+        self.config.save()?;
+        self.arrange();
+        Ok(())
+    }
+
+    // Swaps a pinned folder with its pinned neighbour above (`up`) or below
+    pub fn move_pinned(&mut self, index: usize, up: bool) -> Result<()> {
+        let dir = self.dirs.get(index).context("Invalid directory index")?.clone();
+        let pin = self.config.pin(&dir).context("Only pinned folders can be moved; pin it with P")?;
+        let neighbour = if up { index.checked_sub(1) } else { Some(index + 1) };
+        let Some(other) = neighbour.and_then(|i| self.dirs.get(i)).filter(|d| self.config.pin(d).is_some()).cloned() else {
+            return Ok(());
+        };
+        let other_pin = self.config.pin(&other).unwrap_or(pin);
+        self.config.vault_mut(&other).pin = Some(pin);
+        self.config.vault_mut(&dir).pin = Some(other_pin);
+        self.config.save()?;
+        self.arrange();
+        Ok(())
+    }
+
+    pub fn cycle_label(&mut self, index: usize) -> Result<()> {
+        let dir = self.dirs.get(index).context("Invalid directory index")?.clone();
+        let vault = self.config.vault_mut(&dir);
+        vault.label = Label::next(vault.label);
+        self.config.save()?;
+        self.arrange();
+        Ok(())
+    }
+
+    pub fn cipher_for(&self, index: usize) -> Cipher {
+        self.dirs.get(index).map_or_else(|| self.config.default_cipher(), |dir| self.config.cipher(dir))
+    }
+
+    pub fn has_recipients(&self, index: usize) -> bool {
+        self.dirs.get(index).and_then(|dir| self.config.vault(dir)).is_some_and(|v| !v.recipients.is_empty())
+    }
+
+    pub fn get_files(&self, index: usize) -> Result<Vec<String>, anyhow::Error> {
+        if index >= self.dirs.len() {
+            return Err(anyhow::anyhow!("Invalid directory index"));
+        }
+        let dir = &self.dirs[index];
+        Ok(self.storage.list(dir)
+            .with_context(|| format!("Failed to read directory: {:?}", dir))?
+            .iter()
+            .map(|e| e.name())
+            .collect())
+    }
+
+    pub fn encrypt_dir(&self, index: usize, key: &str) -> Result<()> {
+        self.encrypt_job(index, key)?.run(&mut |_, _, _| {})
+    }
+
+    pub fn decrypt_dir(&self, index: usize, key: &str) -> Result<()> {
+        self.decrypt_job(index, key)?.run(&mut |_, _, _| {})
+    }
+
+    // Checks the folder can be encrypted and takes its settings, for running elsewhere
+    pub fn encrypt_job(&self, index: usize, key: &str) -> Result<Job> {
+        if index >= self.dirs.len() {
+            return Err(anyhow::anyhow!("Invalid directory index"));
+        }
+        self.check_online(index)?;
+        let dir = &self.dirs[index];
+        self.config.check_writable(dir)?;
+        Ok(Job::Encrypt {
+            dir: dir.clone(),
+            key: Zeroizing::new(key.to_string()),
+            recipients: self.config.recipients(dir)?,
+            cipher: self.cipher_for(index),
+            format: self.config.output_format(dir),
+            hide_names: self.config.encrypt_names(dir),
+            checksums: self.config.checksums(dir),
+        })
+    }
+
+    pub fn decrypt_job(&self, index: usize, key: &str) -> Result<Job> {
+        if index >= self.dirs.len() {
+            return Err(anyhow::anyhow!("Invalid directory index"));
+        }
+        self.check_online(index)?;
+        self.config.check_writable(&self.dirs[index])?;
+        Ok(Job::Decrypt { dir: self.dirs[index].clone(), key: Zeroizing::new(key.to_string()) })
+    }
+
+    pub fn rekey_dir(&self, index: usize, old_key: &str, new_key: &str) -> Result<()> {
+        if index >= self.dirs.len() {
+            return Err(anyhow::anyhow!("Invalid directory index"));
+        }
+        self.check_online(index)?;
+        self.config.check_writable(&self.dirs[index])?;
+        rekey_folder_with(&self.dirs[index], old_key, new_key, self.cipher_for(index), &mut |_, _, _| {})
+    }
+
+    // In the first root; `name` may be a nested path such as "projects/2025/reports", whose first folder is
+    // the one listed
+    pub fn create_folder(&mut self, name: &str) -> Result<PathBuf> {
+        let relative = check_new_path(name)?;
+        let root = self.roots.first().context("No folder to create it in")?.clone();
+        let new_path = root.join(&relative);
+        if self.storage.exists(&new_path) {
+            return Err(anyhow::anyhow!("{} already exists", new_path.display()));
+        }
+        self.storage.create_dir(&new_path)?;
+        let top = root.join(relative.iter().next().context("No folder name given")?);
+        if !self.dirs.contains(&top) {
+            self.dirs.push(top);
+            self.encrypted.push(false);
+        }
+        Ok(new_path)
+    }
+
+    // An empty file in the folder, or in subfolders of it made on the way ("notes/todo.txt")
+    pub fn create_file(&self, index: usize, name: &str) -> Result<PathBuf> {
+        if index >= self.dirs.len() {
+            return Err(anyhow::anyhow!("Invalid directory index"));
+        }
+        self.check_online(index)?;
+        self.config.check_writable(&self.dirs[index])?;
+        let path = self.dirs[index].join(check_new_path(name)?);
+        self.storage.write(&path, &mut std::io::empty())?;
+        Ok(path)
+    }
+
+    pub fn remove_dir(&mut self, index: usize, permanent: bool) -> Result<Option<Undo>> {
+        if index >= self.dirs.len() {
+            return Err(anyhow::anyhow!("Invalid directory index"));
+        }
+        self.config.check_deletable(&self.dirs[index], entropy::timestamp())?;
+        self.collapse(index);
+        let undo = self.storage.delete(&self.dirs[index], permanent)?.map(|path| Undo::Trashed { path, folder: true });
+        self.dirs.remove(index);
+        self.encrypted.remove(index);
+        Ok(undo)
+    }
+
+    pub fn remove_file(&self, index: usize, name: &str, permanent: bool) -> Result<Option<Undo>> {
+        let dir = self.dirs.get(index).context("Invalid directory index")?;
+        self.config.check_writable(dir)?;
+        let undo = self.storage.delete(&dir.join(name), permanent)?;
+        Ok(undo.map(|path| Undo::Trashed { path, folder: false }))
+    }
+
+    pub fn undo(&mut self, op: &Undo) -> Result<()> {
+        match op {
+            Undo::Trashed { path, folder } => {
+                self.storage.restore(path)?;
+                if *folder && !self.dirs.contains(path) {
+                    self.dirs.push(path.clone());
+                    self.encrypted.push(self.storage.exists(&path.join(MANIFEST_NAME)));
+                }
+            }
+            Undo::Renamed { from, to } => move_path(self.storage.as_ref(), to, from)?,
+        }
+        Ok(())
+    }
+
+    pub fn rename_file(&self, index: usize, from: &str, to: &str) -> Result<Undo> {
+        if index >= self.dirs.len() {
+            return Err(anyhow::anyhow!("Invalid directory index"));
+        }
+        let to = to.trim();
+        if to.is_empty() || to == "." || to == ".." || to.contains(['/', '\\']) {
+            return Err(anyhow::anyhow!("Invalid file name: '{}'", to));
+        }
+        if to == MANIFEST_NAME || to == CHECKSUMS_NAME || to == SIGNATURES_NAME || to.ends_with(".gxtmp") {
+            return Err(anyhow::anyhow!("'{}' is reserved by GuardX", to));
+        }
+        let dir = &self.dirs[index];
+        self.config.check_writable(dir)?;
+        let (from, to) = (dir.join(from), dir.join(to));
+        move_path(self.storage.as_ref(), &from, &to)?;
+        Ok(Undo::Renamed { from, to })
+    }
+
+    // Copies a file into another listed folder, or moves it there (undone like a rename); an existing file is
+    // never overwritten. Nothing goes into an encrypted folder or is moved out of one this way, which would
+    // leave its manifest out of date; `encrypt_copy` puts a file into one.
+    pub fn transfer_file(&self, index: usize, name: &str, target: usize, keep: bool) -> Result<Option<Undo>> {
+        let (from, to) = self.transfer_paths(index, name, target)?;
+        if self.is_encrypted(target) {
+            return Err(anyhow::anyhow!("{} is an encrypted folder; encrypt a copy into it with e instead", display_name(&self.dirs[target])));
+        }
+        if !keep && self.is_encrypted(index) {
+            return Err(anyhow::anyhow!("{} is an encrypted folder; copy files out of it instead", display_name(&self.dirs[index])));
+        }
+        if !keep {
+            self.config.check_writable(&self.dirs[index])?;
+            move_path(self.storage.as_ref(), &from, &to)?;
+            return Ok(Some(Undo::Renamed { from, to }));
+        }
+        self.storage.write(&to, &mut self.storage.read(&from)?)?;
+        Ok(None)
+    }
+
+    // Encrypts a file from one folder into another the way that folder's watch would: to its recipients, or with
+    // its cipher, format and hidden names, bringing its checksums and manifest up to date. The plaintext is
+    // never written to the other folder.
+    pub fn encrypt_copy(&self, index: usize, name: &str, target: usize, key: &str) -> Result<PathBuf> {
+        let (from, to) = self.transfer_paths(index, name, target)?;
+        if is_chunked(&from)? || age::is_age(&from)? {
+            return Err(anyhow::anyhow!("{} is encrypted already", name));
+        }
+        let dir = &self.dirs[target];
+        let recipients = self.config.recipients(dir)?;
+        if recipients.is_empty() && key.is_empty() {
+            return Err(anyhow::anyhow!("Enter a key first (k)"));
+        }
+        let vault = dir.join(MANIFEST_NAME).is_file();
+        if vault && recipients.is_empty() {
+            Manifest::load(dir)?.check_key(key)?;
+        }
+        let cipher = self.config.cipher(dir);
+        let open = || std::fs::File::open(&from).map(std::io::BufReader::new).map_err(|e| error::io(e, &from));
+        let written = if !recipients.is_empty() {
+            crypto::replace_with(&to, |w| age::encrypt_stream_to(&mut open()?, w, &recipients)).map(|()| to)
+        } else if self.config.output_format(dir) == OutputFormat::Age {
+            crypto::replace_with(&to, |w| age::encrypt_stream(&mut open()?, w, key)).map(|()| to)
+        } else if self.config.encrypt_names(dir) {
+            encrypt_hiding_name_into(&from, dir, key, cipher)
+        } else {
+            encrypt_to(&from, &to, key, cipher).map(|()| to)
+        }?;
+        if recipients.is_empty() && self.config.checksums(dir) {
+            let mut sums = Checksums::load(dir, key)?.map_or_else(|| Checksums::build(&[]), Ok)?;
+            sums.files.extend(Checksums::build(std::slice::from_ref(&from))?.files);
+            sums.save(dir, key, cipher)?;
+        }
+        if vault {
+            let manifest = if recipients.is_empty() { Manifest::build_hashed(dir, key) } else { Manifest::build(dir) };
+            manifest.and_then(|m| m.save(dir)).context("Could not update the manifest")?;
+        }
+        Ok(written)
+    }
+
+    // The file and where it would go in the other folder, which must be online, writable and free of that name
+    fn transfer_paths(&self, index: usize, name: &str, target: usize) -> Result<(PathBuf, PathBuf)> {
+        let (Some(dir), Some(other)) = (self.dirs.get(index), self.dirs.get(target)) else {
+            return Err(anyhow::anyhow!("Invalid directory index"));
+        };
+        if dir == other {
+            return Err(anyhow::anyhow!("Both panes show {}; pick another folder for one of them", display_name(dir)));
+        }
+        self.check_online(index)?;
+        self.check_online(target)?;
+        self.config.check_writable(other)?;
+        let (from, to) = (dir.join(name), other.join(name));
+        if self.storage.exists(&to) {
+            return Err(anyhow::anyhow!("{} already exists in {}", name, display_name(other)));
+        }
+        Ok((from, to))
+    }
+
+    pub fn mark_encrypted(&mut self, index: usize, encrypted: bool) {
+        if index < self.encrypted.len() {
+            self.encrypted[index] = encrypted;
+        }
+    }
+
+    pub fn is_encrypted(&self, index: usize) -> bool {
+        index < self.encrypted.len() && self.encrypted[index]
+    }
+}
+
+// Encrypting or decrypting one folder, with everything it needs taken from the FileSystem up front, so it
+// can run on a worker thread while the folder list keeps changing
+pub enum Job {
+    Encrypt { dir: PathBuf, key: Zeroizing<String>, recipients: Vec<Recipient>, cipher: Cipher, format: OutputFormat, hide_names: bool, checksums: bool },
+    Decrypt { dir: PathBuf, key: Zeroizing<String> },
+}
+
+impl Job {
+    pub fn dir(&self) -> &Path {
+        match self {
+            Job::Encrypt { dir, .. } | Job::Decrypt { dir, .. } => dir,
+        }
+    }
+
+    // What `run` would do; only encrypting is planned. Recipient folders keep their names and have no checksums.
+    pub fn plan(&self) -> Result<Plan> {
+        match self {
+            Job::Encrypt { dir, recipients, hide_names, checksums, .. } => plan_encrypt(dir, *hide_names && recipients.is_empty(), *checksums && recipients.is_empty()),
+            Job::Decrypt { .. } => Err(anyhow::anyhow!("Only encrypting can be planned")),
+        }
+    }
+
+    // Recipient folders are encrypted to public keys, which needs no key
+    pub fn run(&self, progress: &mut dyn FnMut(usize, usize, &Path)) -> Result<()> {
+        let operation = match self {
+            Job::Encrypt { .. } => Operation::Encrypt,
+            Job::Decrypt { .. } => Operation::Decrypt,
+        };
+        tracing::info!("Starting the {} of {:?}", operation.name(), self.dir());
+        let result = match self {
+            Job::Encrypt { dir, recipients, .. } if !recipients.is_empty() => encrypt_folder_to(dir, recipients, progress),
+            Job::Encrypt { dir, key, cipher, format, hide_names, checksums, .. } => encrypt_folder_with(dir, key, *cipher, *format, *hide_names, *checksums, progress),
+            Job::Decrypt { dir, key } => decrypt_folder_with(dir, key, progress),
+        };
+        match &result {
+            Ok(()) => tracing::info!("Finished the {} of {:?}", operation.name(), self.dir()),
+            Err(e) => tracing::error!("The {} of {:?} failed: {:#}", operation.name(), self.dir(), e),
+        }
+        result
+    }
+}
+
+// With the `io-uring` feature on Linux, runs of small files are encrypted in batches through io_uring
+// (see uring.rs). On by default; `guardx bench` turns it off to compare.
+static IO_URING: AtomicBool = AtomicBool::new(true);
+
+pub fn set_io_uring(on: bool) {
+    IO_URING.store(on, Ordering::Relaxed);
+}
+
+// Whether folders would be encrypted through io_uring here: built in, and allowed by the kernel
+pub fn io_uring_available() -> bool {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    let available = uring::Ring::new().is_some();
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    let available = false;
+    available
+}
+
+// Encrypts every file directly inside `dir` and records them in the vault manifest
+pub fn encrypt_folder(dir: &Path, key: &str, cipher: Cipher) -> Result<()> {
+    encrypt_folder_with(dir, key, cipher, OutputFormat::GuardX, false, false, &mut |_, _, _| {})
+}
+
+// `progress` is called with (files done, total, file) before each file is processed. With `hide_names`
+// the files end up under random names, so the manifest lists those rather than the real ones. With
+// `checksums` the plaintext of every file is hashed first and the hashes are stored encrypted in the
+// vault, for `decrypt_folder_with` to check. With `OutputFormat::Age` every file becomes an age file
+// (the manifest and checksums keep GuardX's format), which rules out hidden names.
+pub fn encrypt_folder_with(dir: &Path, key: &str, cipher: Cipher, format: OutputFormat, hide_names: bool, checksums: bool, progress: &mut dyn FnMut(usize, usize, &Path)) -> Result<()> {
+    if format == OutputFormat::Age && hide_names {
+        return Err(anyhow::anyhow!("File names cannot be encrypted in age files; turn off name encryption or use the GuardX format"));
+    }
+    let files = files_in(dir)?;
+    if checksums {
+        Checksums::build(&files)?.save(dir, key, cipher)?;
+    }
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    let mut ring = (format == OutputFormat::GuardX && !hide_names && IO_URING.load(Ordering::Relaxed)).then(uring::Ring::new).flatten();
+    let mut i = 0;
+    while i < files.len() {
+        progress(i, files.len(), &files[i]);
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(ring) = ring.as_mut() {
+            let batch = uring::leading_small(&files[i..]);
+            if !batch.is_empty() {
+                uring::encrypt_batch(ring, &batch, key, cipher).map_err(|e| interrupted(dir, Operation::Encrypt, &files[i..], files.len(), e))?;
+                i += batch.len();
+                continue;
+            }
+        }
+        encrypt_one(&files[i], key, cipher, format, hide_names).map_err(|e| interrupted(dir, Operation::Encrypt, &files[i..], files.len(), e))?;
+        i += 1;
+    }
+    Manifest::build_hashed(dir, key).and_then(|m| m.save(dir)).map_err(|e| interrupted(dir, Operation::Encrypt, &[], files.len(), e))
+}
+
+// What encrypting a folder would do, worked out without changing anything, for `guardx encrypt --dry-run`
+// and the summary the TUI asks to confirm before it starts
+pub struct Plan {
+    // Files that would be encrypted, with their sizes
+    pub process: Vec<(PathBuf, u64)>,
+    // What would be left as it is, with why
+    pub skipped: Vec<(PathBuf, String)>,
+    // Files among `process` that are already encrypted and would be encrypted a second time
+    pub already_encrypted: Vec<PathBuf>,
+    // Whether the encrypted files would get random names
+    pub renames: bool,
+    pub checksums: bool,
+}
+
+// A modest rate for reading, encrypting and replacing files on an ordinary disk; the estimate is a guide
+const ESTIMATED_RATE: f64 = 100.0 * 1024.0 * 1024.0;
+
+impl Plan {
+    pub fn total_size(&self) -> u64 {
+        self.process.iter().map(|(_, size)| size).sum()
+    }
+
+    // Checksums read every file once more before it is encrypted
+    pub fn estimate(&self) -> Duration {
+        let passes = if self.checksums { 2.0 } else { 1.0 };
+        Duration::from_secs_f64(self.total_size() as f64 * passes / ESTIMATED_RATE)
+    }
+}
+
+pub fn plan_encrypt(dir: &Path, hide_names: bool, checksums: bool) -> Result<Plan> {
+    let mut process = Vec::new();
+    let mut already_encrypted = Vec::new();
+    for path in files_in(dir)? {
+        if is_chunked(&path)? || age::is_age(&path)? {
+            already_encrypted.push(path.clone());
+        }
+        let size = std::fs::metadata(&path)?.len();
+        process.push((path, size));
+    }
+    let filter = filter::for_dir(dir);
+    let mut skipped = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_reserved(&path) || process.iter().any(|(p, _)| *p == path) {
+            continue;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let reason = if filter.excludes(&name, path.is_dir()) {
+            "excluded by a pattern in config.toml"
+        } else if path.is_dir() {
+            "a folder; only files directly inside are encrypted"
+        } else if name.ends_with(".gxtmp") {
+            "left over from an interrupted operation"
+        } else {
+            "not a regular file"
+        };
+        skipped.push((path, reason.to_string()));
+    }
+    process.sort();
+    skipped.sort();
+    Ok(Plan { process, skipped, already_encrypted, renames: hide_names, checksums })
+}
+
+pub fn encrypt_one(path: &Path, key: &str, cipher: Cipher, format: OutputFormat, hide_names: bool) -> Result<()> {
+    if format == OutputFormat::Age {
+        age::encrypt_file(path, key)
+    } else if hide_names {
+        encrypt_hiding_name(path, key, cipher).map(drop)
+    } else {
+        encrypt_file(path, key, cipher)
+    }
+}
+
+// When the folder itself can no longer be read (its drive was removed), `remaining` goes into the journal
+// and the error becomes Offline. A full disk journals `remaining` too, so the operation can be finished
+// once there is space, and stays GuardXError::DiskFull. Otherwise the error is about the file and is passed on.
+fn interrupted(dir: &Path, operation: Operation, remaining: &[PathBuf], total: usize, e: anyhow::Error) -> anyhow::Error {
+    let full = matches!(GuardXError::of(&e), Some(GuardXError::DiskFull(_)));
+    if std::fs::read_dir(dir).is_ok() && !full {
+        return e;
+    }
+    let entry = Interrupted { dir: dir.to_path_buf(), operation, remaining: remaining.to_vec(), total, at: entropy::timestamp() };
+    tracing::warn!("Journaling the {} of {:?} with {} of {} files left: {:#}", operation.name(), dir, remaining.len(), total, e);
+    if let Err(journal) = journal::record(entry) {
+        let what = if full { "ran out of disk space" } else { "went offline" };
+        return e.context(format!("{} {}, and what was left could not be journaled: {:#}", display_name(dir), what, journal));
+    }
+    if full {
+        return e.context(format!("The {} of {} stopped after {} of {} files", operation.name(), display_name(dir), total - remaining.len(), total));
+    }
+    Offline { dir: dir.to_path_buf(), operation, done: total - remaining.len(), total }.into()
+}
+
+// Files reached before the drive went away are already in (or already out of) an encrypted format, which
+// is how they are told apart from the rest. Legacy files count as plaintext here.
+pub fn resume(entry: &Interrupted, key: &str, cipher: Cipher, format: OutputFormat, hide_names: bool) -> Result<()> {
+    let dir = &entry.dir;
+    if !dir.is_dir() {
+        return Err(anyhow::anyhow!("{} is still offline", display_name(dir)));
+    }
+    tracing::info!("Resuming the {} of {:?}", entry.operation.name(), dir);
+    let encrypting = entry.operation == Operation::Encrypt;
+    let mut pending = Vec::new();
+    for path in entry.remaining.iter().filter(|p| p.is_file()) {
+        if is_chunked(path)? || age::is_age(path)? {
+            if !encrypting {
+                pending.push(path.clone());
+            }
+        } else if encrypting {
+            pending.push(path.clone());
+        }
+    }
+    if encrypting {
+        // A different key would leave the folder split between two keys
+        let mut encrypted = files_in(dir)?.into_iter().filter(|p| !pending.contains(p));
+        if let Some(sample) = encrypted.next() {
+            verify_file(&sample, key).context("This is not the key the folder was being encrypted with")?;
+        }
+        for (i, path) in pending.iter().enumerate() {
+            encrypt_one(path, key, cipher, format, hide_names).map_err(|e| interrupted(dir, Operation::Encrypt, &pending[i..], entry.total, e))?;
+        }
+        Manifest::build_hashed(dir, key).and_then(|m| m.save(dir)).map_err(|e| interrupted(dir, Operation::Encrypt, &[], entry.total, e))?;
+    } else {
+        if dir.join(MANIFEST_NAME).is_file() {
+            Manifest::load(dir)?.check_key(key)?;
+        }
+        let checksums = Checksums::load(dir, key)?;
+        for (i, path) in pending.iter().enumerate() {
+            decrypt_file(path, key).map_err(|e| interrupted(dir, Operation::Decrypt, &pending[i..], entry.total, e))?;
+        }
+        finish_decrypt(dir, checksums.as_ref()).map_err(|e| interrupted(dir, Operation::Decrypt, &[], entry.total, e))?;
+    }
+    journal::clear(dir)
+}
+
+// Recipient mode: every file becomes an age file that only the identities behind `recipients` can open,
+// so no key is needed. The manifest therefore carries no keyed hashes, and checksums are not kept.
+pub fn encrypt_folder_to(dir: &Path, recipients: &[Recipient], progress: &mut dyn FnMut(usize, usize, &Path)) -> Result<()> {
+    if recipients.is_empty() {
+        return Err(anyhow::anyhow!("No recipients to encrypt to"));
+    }
+    let files = files_in(dir)?;
+    for (i, path) in files.iter().enumerate() {
+        progress(i, files.len(), path);
+        age::encrypt_file_to(path, recipients)?;
+    }
+    Manifest::build(dir)?.save(dir)?;
+    Ok(())
+}
+
+pub fn decrypt_folder(dir: &Path, key: &str) -> Result<()> {
+    decrypt_folder_with(dir, key, &mut |_, _, _| {})
+}
+
+// Fails after decrypting if the vault has checksums and any file does not match them
+pub fn decrypt_folder_with(dir: &Path, key: &str, progress: &mut dyn FnMut(usize, usize, &Path)) -> Result<()> {
+    let checksums = Checksums::load(dir, key)?;
+    let files = files_in(dir)?;
+    for (i, path) in files.iter().enumerate() {
+        progress(i, files.len(), path);
+        decrypt_file(path, key).map_err(|e| interrupted(dir, Operation::Decrypt, &files[i..], files.len(), e))?;
+    }
+    finish_decrypt(dir, checksums.as_ref()).map_err(|e| interrupted(dir, Operation::Decrypt, &[], files.len(), e))
+}
+
+fn finish_decrypt(dir: &Path, checksums: Option<&Checksums>) -> Result<()> {
+    let mismatches = match checksums {
+        Some(checksums) => checksums.mismatches(dir)?,
+        None => Vec::new(),
+    };
+    // Signatures cover the ciphertext, which is gone now
+    for name in [MANIFEST_NAME, CHECKSUMS_NAME, SIGNATURES_NAME] {
+        let path = dir.join(name);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    if !mismatches.is_empty() {
+        return Err(anyhow::anyhow!("{} files do not match their checksums after decryption: {}", mismatches.len(), mismatches.join(", ")));
+    }
+    Ok(())
+}
+
+// Moves every file of an encrypted folder (checksums included) from `old_key` to `new_key`, one file at a
+// time. Each file is replaced atomically, so a failure part way leaves some files on the new key and the
+// rest on the old one; the error says how many.
+pub fn rekey_folder_with(dir: &Path, old_key: &str, new_key: &str, cipher: Cipher, progress: &mut dyn FnMut(usize, usize, &Path)) -> Result<()> {
+    if !dir.join(MANIFEST_NAME).is_file() {
+        return Err(anyhow::anyhow!("{:?} is not an encrypted folder", dir));
+    }
+    Manifest::load(dir)?.check_key(old_key)?;
+    let mut files = files_in(dir)?;
+    if dir.join(CHECKSUMS_NAME).is_file() {
+        files.insert(0, dir.join(CHECKSUMS_NAME));
+    }
+    let mut result = Ok(());
+    for (i, path) in files.iter().enumerate() {
+        progress(i, files.len(), path);
+        if let Err(e) = rekey_file(path, old_key, new_key, cipher) {
+            // Nothing changed yet, so the manifest stays as it was
+            if i == 0 {
+                return Err(e);
+            }
+            result = Err(e.context(format!("Re-keyed {} of {} files; {} and the files after it still use the old key", i, files.len(), display_name(path))));
+            break;
+        }
+    }
+    // Hashes cover the stored bytes, so the manifest is right for whichever files moved
+    Manifest::build_hashed(dir, new_key)?.save(dir)?;
+    result
+}
+
+// Authenticates every file of an encrypted folder with `key`; nothing is written. Each file gets its own
+// outcome, so one damaged file does not hide the state of the others.
+pub fn verify_folder(dir: &Path, key: &str) -> Result<Vec<(PathBuf, Result<()>)>> {
+    if !dir.join(MANIFEST_NAME).is_file() {
+        return Err(anyhow::anyhow!("{:?} is not an encrypted folder", dir));
+    }
+    Ok(files_in(dir)?.into_iter().map(|path| {
+        let result = verify_file(&path, key);
+        (path, result)
+    }).collect())
+}
+
+// Refuses to overwrite, and keeps a vault's manifest pointing at the file's new name
+fn move_path(storage: &dyn Storage, from: &Path, to: &Path) -> Result<()> {
+    // A case-only rename finds the file itself at the target on case-insensitive filesystems
+    let case_change = from.parent() == to.parent()
+        && from.file_name().map(|n| n.to_string_lossy().to_lowercase()) == to.file_name().map(|n| n.to_string_lossy().to_lowercase());
+    if storage.exists(to) && !case_change {
+        return Err(anyhow::anyhow!("{} already exists", display_name(to)));
+    }
+    storage.rename(from, to)?;
+    if let (Some(dir), Some(old), Some(new)) = (from.parent(), from.file_name(), to.file_name()) {
+        if from.parent() == to.parent() && dir.join(MANIFEST_NAME).is_file() {
+            let mut manifest = Manifest::load(dir)?;
+            let (old, new) = (old.to_string_lossy(), new.to_string_lossy());
+            if let Some(entry) = manifest.files.iter_mut().find(|e| e.path == old) {
+                entry.path = new.to_string();
+                manifest.save(dir)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Names Windows refuses for a file or folder, whatever the extension
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// A relative path of names to create, "projects/2025/reports" or a single name, checked as a whole before
+// anything is made: no absolute paths or `..`, nothing GuardX keeps for itself, and nothing the platform
+// refuses. `\` separates names too on Windows.
+pub fn check_new_path(path: &str) -> Result<PathBuf> {
+    let separators: &[char] = if cfg!(windows) { &['/', '\\'] } else { &['/'] };
+    let mut checked = PathBuf::new();
+    for name in path.trim().split(separators) {
+        check_name(name)?;
+        checked.push(name);
+    }
+    Ok(checked)
+}
+
+fn check_name(name: &str) -> Result<()> {
+    let invalid = |why: String| Err(anyhow::anyhow!("Invalid name {:?}: {}", name, why));
+    if name.is_empty() || name == "." || name == ".." {
+        return invalid("names cannot be empty, . or ..".to_string());
+    }
+    if name.len() > 255 {
+        return invalid("longer than 255 bytes".to_string());
+    }
+    if let Some(c) = name.chars().find(|&c| c == '\0' || (cfg!(windows) && (c.is_control() || "<>:\"|?*".contains(c)))) {
+        return invalid(format!("{:?} is not allowed in names", c));
+    }
+    if name == MANIFEST_NAME || name == CHECKSUMS_NAME || name == SIGNATURES_NAME || name.ends_with(".gxtmp") {
+        return invalid("reserved by GuardX".to_string());
+    }
+    if cfg!(windows) {
+        if name.ends_with(['.', ' ']) {
+            return invalid("Windows does not allow names ending in a dot or space".to_string());
+        }
+        let stem = name.split('.').next().unwrap_or(name).trim_end();
+        if WINDOWS_RESERVED.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+            return invalid("reserved by Windows".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Moves to the OS recycle bin unless a permanent delete was asked for; only trashed paths can be undone
+pub fn remove_path(path: &Path, permanent: bool) -> Result<Option<Undo>> {
+    Ok(Local.delete(path, permanent)?.map(|path| Undo::Trashed { path, folder: false }))
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.display().to_string())
+}
+
+// What operating systems keep at the top of a drive: .Trashes, .Spotlight-V100, $RECYCLE.BIN and the like
+fn system_folder(name: &str) -> bool {
+    name.starts_with('.') || name.starts_with('$') || name == "System Volume Information" || name == "lost+found"
+}
+
+// Collected up front because each file is replaced through a temporary sibling while we iterate
+pub fn files_in(dir: &Path) -> Result<Vec<PathBuf>> {
+    let filter = filter::for_dir(dir);
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| error::io(e, dir))? {
+        let path = entry?.path();
+        let partial = path.extension().is_some_and(|ext| ext == "gxtmp");
+        if path.is_file() && !is_reserved(&path) && !partial && !filter.excludes_file(dir, &path) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn is_reserved(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == MANIFEST_NAME || n == CHECKSUMS_NAME || n == SIGNATURES_NAME)
+}
@@ -0,0 +1,211 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+// Which files in a folder GuardX leaves alone, from gitignore-style patterns. Patterns match the path
+// relative to the folder: one without a slash matches a file or folder of that name at any depth, a
+// trailing '/' only matches folders, and `*`, `?`, `[a-z]` and `**` work as in .gitignore. The last
+// pattern that matches decides, so `!pattern` takes a file back; anything inside an excluded folder stays
+// excluded. When there are include patterns, only files matching one of them are touched at all.
+#[derive(Clone, Default, Debug)]
+pub struct Filter {
+    excludes: Vec<Rule>,
+    includes: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    // Matched against the whole relative path rather than the last name in it
+    anchored: bool,
+}
+
+impl Filter {
+    // Blank lines and lines starting with '#' are ignored, as in .gitignore
+    pub fn new(excludes: &[String], includes: &[String]) -> Self {
+        Filter {
+            excludes: excludes.iter().filter_map(|p| Rule::parse(p)).collect(),
+            includes: includes.iter().filter_map(|p| Rule::parse(p)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.excludes.is_empty() && self.includes.is_empty()
+    }
+
+    // `rel` is relative to the folder, with '/' between names
+    pub fn excludes(&self, rel: &str, is_dir: bool) -> bool {
+        let names: Vec<&str> = rel.split('/').filter(|n| !n.is_empty()).collect();
+        if (1..names.len()).any(|end| self.excluded_here(&names[..end].join("/"), true)) {
+            return true;
+        }
+        if self.excluded_here(rel, is_dir) {
+            return true;
+        }
+        !is_dir && !self.includes.is_empty() && !self.includes.iter().any(|rule| rule.matches(rel, false))
+    }
+
+    // A file directly inside the folder
+    pub fn excludes_file(&self, dir: &Path, path: &Path) -> bool {
+        match path.strip_prefix(dir) {
+            Ok(rel) => self.excludes(&rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"), false),
+            Err(_) => false,
+        }
+    }
+
+    fn excluded_here(&self, rel: &str, is_dir: bool) -> bool {
+        let mut excluded = false;
+        for rule in &self.excludes {
+            if rule.matches(rel, is_dir) {
+                excluded = !rule.negated;
+            }
+        }
+        excluded
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        (!pattern.is_empty()).then_some(Rule { pattern, negated, dir_only, anchored })
+    }
+
+    fn matches(&self, rel: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let text = if self.anchored { rel } else { rel.rsplit('/').next().unwrap_or(rel) };
+        glob(self.pattern.as_bytes(), text.as_bytes())
+    }
+}
+
+// `*` and `?` stay within one name, `**` crosses folders, and `**/` also matches no folder at all
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            (0..=text.len()).any(|i| glob(rest, &text[i..])) || (rest.first() == Some(&b'/') && glob(&rest[1..], text))
+        }
+        Some(b'*') => {
+            for i in 0..=text.len() {
+                if glob(&pattern[1..], &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => text.first().is_some_and(|&c| c != b'/') && glob(&pattern[1..], &text[1..]),
+        Some(b'[') => {
+            let Some(&c) = text.first() else { return false };
+            match class(&pattern[1..], c) {
+                Some((hit, len)) => hit && glob(&pattern[1 + len..], &text[1..]),
+                // No closing ']', so the '[' is just a character
+                None => c == b'[' && glob(&pattern[1..], &text[1..]),
+            }
+        }
+        Some(b'\\') if pattern.len() > 1 => text.first() == Some(&pattern[1]) && glob(&pattern[2..], &text[1..]),
+        Some(&p) => text.first() == Some(&p) && glob(&pattern[1..], &text[1..]),
+    }
+}
+
+// The class just past a '[': whether `c` is in it, and how many bytes it takes up including the ']'
+fn class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let (negated, start) = match pattern.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut found = false;
+    let mut i = start;
+    while i < pattern.len() {
+        // A ']' straight after the '[' is part of the class
+        if pattern[i] == b']' && i > start {
+            return Some((found != negated && c != b'/', i + 1));
+        }
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&hi| hi != b']') {
+            found |= (pattern[i]..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= pattern[i] == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+// The global filter, then each folder with patterns of its own along with the global ones
+type Configured = (Filter, Vec<(PathBuf, Filter)>);
+
+// Set from config.toml by `Config::load`, like the KDF settings
+static CONFIGURED: RwLock<Option<Configured>> = RwLock::new(None);
+
+pub fn configure(global: Filter, folders: Vec<(PathBuf, Filter)>) {
+    *CONFIGURED.write().unwrap_or_else(|e| e.into_inner()) = Some((global, folders));
+}
+
+// What applies to `dir`; nothing is excluded until `configure` has run
+pub fn for_dir(dir: &Path) -> Filter {
+    match &*CONFIGURED.read().unwrap_or_else(|e| e.into_inner()) {
+        Some((global, folders)) => folders.iter().find(|(path, _)| path == dir).map_or_else(|| global.clone(), |(_, filter)| filter.clone()),
+        None => Filter::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(excludes: &[&str], includes: &[&str]) -> Filter {
+        let owned = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        Filter::new(&owned(excludes), &owned(includes))
+    }
+
+    #[test]
+    fn the_last_matching_exclude_decides() {
+        let f = filter(&["*.log", "!keep.log", "# a comment", ""], &[]);
+        assert!(f.excludes("debug.log", false));
+        assert!(!f.excludes("keep.log", false));
+        assert!(!f.excludes("notes.txt", false));
+        // Taken back and excluded again
+        let f = filter(&["*.log", "!keep.log", "keep*"], &[]);
+        assert!(f.excludes("keep.log", false));
+    }
+
+    #[test]
+    fn nothing_inside_an_excluded_folder_is_taken_back() {
+        let f = filter(&["build/", "!build/keep.txt"], &[]);
+        assert!(f.excludes("build", true));
+        assert!(f.excludes("build/keep.txt", false));
+        assert!(f.excludes("src/build/out.o", false));
+        // A trailing '/' only matches folders
+        assert!(!f.excludes("build", false));
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let f = filter(&["secret.*"], &["*.txt", "docs/**"]);
+        assert!(!f.excludes("notes.txt", false));
+        assert!(!f.excludes("docs/a/b.pdf", false));
+        assert!(f.excludes("photo.jpg", false));
+        assert!(f.excludes("secret.txt", false));
+        // Includes only pick files; folders are still walked into
+        assert!(!f.excludes("photos", true));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::error::GuardXError;
+use anyhow::{Context, Result};
+use std::io::Read;
+
+pub fn truncated() -> GuardXError {
+    GuardXError::Format("Encrypted file is truncated".to_string())
+}
+
+pub fn corrupted() -> GuardXError {
+    GuardXError::Format("Encrypted file is truncated or corrupted".to_string())
+}
+
+// File layout (v2):
+//   header  = MAGIC | version u8 | cipher u8 | chunk_size u32 | file_id [16] | ext_len u16 | ext
+//   records = type u8 | len u32 | nonce [12] | ciphertext+tag   (one per chunk, last one is REC_FINAL)
+//   map     = REC_MAP | count u64 | keyed chunk hashes [32; count] | revision u64 | tag [32]
+// Every chunk record except the final one has the same size, so chunk i always starts at the same
+// offset and unchanged chunks keep identical bytes across re-encryptions. The revision and tag are only
+// there in files whose header carries EXT_SEALED_MAP.
+pub const MAGIC: &[u8; 6] = b"GUARDX";
+pub const VERSION: u8 = 2;
+pub const CIPHER_AES_256_GCM: u8 = 1;
+pub const CIPHER_CHACHA20_POLY1305: u8 = 2;
+pub const DEFAULT_CHUNK_SIZE: u32 = 1024 * 1024;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+pub const MAP_ENTRY_LEN: usize = 32;
+
+// Header extension holding the file's real name, sealed with the per-file name key: nonce [12] | ciphertext+tag.
+// Files that carry it are stored under a random name.
+pub const EXT_NAME: u8 = 1;
+// Header extension naming how the key was stretched before the per-file HKDF, as `kdf::Kdf::to_bytes`
+// writes it. Files without it were written before key stretching and use the key as it is.
+pub const EXT_KDF: u8 = 2;
+// Empty header extension saying the map ends in a revision and a keyed tag over the map, and that readers
+// check each chunk against its map entry. Re-encryption copies unchanged chunks verbatim, so without it a
+// chunk from an earlier version of the file would still authenticate in its place. Being in the header, it
+// is in every chunk's associated data and cannot be stripped.
+pub const EXT_SEALED_MAP: u8 = 3;
+
+pub const REC_CHUNK: u8 = 0;
+pub const REC_FINAL: u8 = 1;
+pub const REC_MAP: u8 = 2;
+
+// type + len + nonce in front of every chunk's ciphertext
+pub const RECORD_OVERHEAD: usize = 1 + 4 + NONCE_LEN;
+
+#[derive(Clone)]
+pub struct Header {
+    pub version: u8,
+    pub cipher: u8,
+    pub chunk_size: u32,
+    pub file_id: [u8; 16],
+    pub extensions: Vec<(u8, Vec<u8>)>,
+}
+
+impl Header {
+    pub fn new(file_id: [u8; 16], cipher: u8) -> Self {
+        Header {
+            version: VERSION,
+            cipher,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            file_id,
+            extensions: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ext = Vec::new();
+        for (kind, value) in &self.extensions {
+            ext.push(*kind);
+            ext.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            ext.extend_from_slice(value);
+        }
+        let mut out = MAGIC.to_vec();
+        out.push(self.version);
+        out.push(self.cipher);
+        out.extend_from_slice(&self.chunk_size.to_le_bytes());
+        out.extend_from_slice(&self.file_id);
+        out.extend_from_slice(&(ext.len() as u16).to_le_bytes());
+        out.extend_from_slice(&ext);
+        out
+    }
+
+    // Reads the header that follows MAGIC; the caller has already consumed and checked the magic
+    pub fn read_after_magic<R: Read>(r: &mut R) -> Result<Self> {
+        let mut fixed = [0u8; 1 + 1 + 4 + 16 + 2];
+        r.read_exact(&mut fixed).context(truncated())?;
+        let version = fixed[0];
+        if version != VERSION {
+            return Err(GuardXError::Format(format!("Unsupported file format version {}", version)).into());
+        }
+        let cipher = fixed[1];
+        let chunk_size = u32::from_le_bytes([fixed[2], fixed[3], fixed[4], fixed[5]]);
+        if chunk_size == 0 {
+            return Err(GuardXError::Format("Invalid chunk size in header".to_string()).into());
+        }
+        let mut file_id = [0u8; 16];
+        file_id.copy_from_slice(&fixed[6..22]);
+        let ext_len = u16::from_le_bytes([fixed[22], fixed[23]]) as usize;
+        let mut ext = vec![0u8; ext_len];
+        r.read_exact(&mut ext).context(truncated())?;
+
+        let mut extensions = Vec::new();
+        let mut pos = 0;
+        while pos < ext.len() {
+            if pos + 3 > ext.len() {
+                return Err(GuardXError::Format("Malformed header extension".to_string()).into());
+            }
+            let kind = ext[pos];
+            let len = u16::from_le_bytes([ext[pos + 1], ext[pos + 2]]) as usize;
+            pos += 3;
+            if pos + len > ext.len() {
+                return Err(GuardXError::Format("Malformed header extension".to_string()).into());
+            }
+            extensions.push((kind, ext[pos..pos + len].to_vec()));
+            pos += len;
+        }
+        Ok(Header { version, cipher, chunk_size, file_id, extensions })
+    }
+}
+
+// Associated data binds each chunk to this file's header, its position, and whether it is the last one
+pub fn chunk_aad(header_bytes: &[u8], index: u64, kind: u8) -> Vec<u8> {
+    let mut aad = header_bytes.to_vec();
+    aad.extend_from_slice(&index.to_le_bytes());
+    aad.push(kind);
+    aad
+}
+
+// Fills `buf` as far as the reader allows; a short count means end of input
+pub fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
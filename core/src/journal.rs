@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Folders whose drive went away while they were being encrypted or decrypted, with the files that were
+// not reached, kept in the data directory until the drive is back and the operation is finished. Files
+// are replaced atomically, so each one is either done or untouched.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Encrypt,
+    Decrypt,
+}
+
+impl Operation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Encrypt => "encryption",
+            Operation::Decrypt => "decryption",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Interrupted {
+    pub dir: PathBuf,
+    pub operation: Operation,
+    pub remaining: Vec<PathBuf>,
+    pub total: usize,
+    pub at: i64,
+}
+
+pub fn file() -> Result<PathBuf> {
+    let dir = dirs::data_dir().context("Could not find data directory")?.join("guardx");
+    Ok(dir.join("interrupted.json"))
+}
+
+pub fn load() -> Result<Vec<Interrupted>> {
+    let path = file()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path)?;
+    serde_json::from_str(&data).with_context(|| format!("Malformed journal: {:?}", path))
+}
+
+fn save(entries: &[Interrupted]) -> Result<()> {
+    let path = file()?;
+    if entries.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(entries)?)?;
+    Ok(())
+}
+
+// Replaces any entry for the same folder
+pub fn record(entry: Interrupted) -> Result<()> {
+    let mut entries = load()?;
+    entries.retain(|e| e.dir != entry.dir);
+    entries.push(entry);
+    save(&entries)
+}
+
+pub fn clear(dir: &Path) -> Result<()> {
+    let mut entries = load()?;
+    let before = entries.len();
+    entries.retain(|e| e.dir != dir);
+    if entries.len() == before {
+        return Ok(());
+    }
+    save(&entries)
+}
+
+pub fn find(dir: &Path) -> Result<Option<Interrupted>> {
+    Ok(load()?.into_iter().find(|e| e.dir == dir))
+}
@@ -0,0 +1,362 @@
+use crate::entropy;
+use crate::error::GuardXError;
+use anyhow::{Context, Result};
+use ring::pbkdf2;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::sync::{Mutex, RwLock};
+use zeroize::Zeroizing;
+
+// Stretches a typed key before the per-file HKDF, so every guess at a key costs an attacker the same work
+// it costs us. The algorithm, its parameters and the salt travel in each file's header (and in a keyed
+// manifest), so files written with any of them keep opening whatever new files use. Files from before
+// key stretching carry none and use the key as it is; `min_kdf` in config.toml can refuse those, and
+// anything else below the chosen family, when files come from someone else.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kdf {
+    Argon2id { memory_kib: u32, passes: u32, lanes: u32 },
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    // PBKDF2-HMAC-SHA256, for platforms without the memory for the other two
+    Pbkdf2 { iterations: u32 },
+}
+
+// Ordered from weakest to strongest for the `min_kdf` policy
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Family {
+    None,
+    Pbkdf2,
+    Scrypt,
+    Argon2id,
+}
+
+impl Family {
+    pub const ALL: [Family; 4] = [Family::None, Family::Pbkdf2, Family::Scrypt, Family::Argon2id];
+
+    // Name used in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Family::None => "none",
+            Family::Pbkdf2 => "pbkdf2",
+            Family::Scrypt => "scrypt",
+            Family::Argon2id => "argon2id",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Family::ALL.into_iter().find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Family::None => "no key stretching",
+            Family::Pbkdf2 => "PBKDF2-HMAC-SHA256",
+            Family::Scrypt => "scrypt",
+            Family::Argon2id => "Argon2id",
+        }
+    }
+}
+
+const ID_ARGON2ID: u8 = 1;
+const ID_SCRYPT: u8 = 2;
+const ID_PBKDF2: u8 = 3;
+const SALT_LEN: usize = 16;
+
+// Upper bounds for parameters read from files, so a crafted header cannot ask for unbounded memory or time.
+// Either memory-hard family may ask for at most 1 GiB; for scrypt that is 128 * r * N bytes.
+const MAX_MEMORY: u64 = 1024 * 1024 * 1024;
+const MAX_ARGON2_MEMORY_KIB: u32 = (MAX_MEMORY / 1024) as u32;
+const MAX_ARGON2_PASSES: u32 = 16;
+const MAX_LANES: u32 = 16;
+const MAX_SCRYPT_LOG_N: u8 = 22;
+const MAX_SCRYPT_R: u32 = 32;
+const MAX_PBKDF2_ITERATIONS: u32 = 100_000_000;
+
+impl Kdf {
+    // What new files use unless config.toml picks something else
+    pub const DEFAULT: Kdf = Kdf::Argon2id { memory_kib: 64 * 1024, passes: 3, lanes: 1 };
+
+    pub fn family(self) -> Family {
+        match self {
+            Kdf::Argon2id { .. } => Family::Argon2id,
+            Kdf::Scrypt { .. } => Family::Scrypt,
+            Kdf::Pbkdf2 { .. } => Family::Pbkdf2,
+        }
+    }
+
+    // Recommended parameters for a family; None for Family::None
+    pub fn recommended(family: Family) -> Option<Kdf> {
+        match family {
+            Family::None => None,
+            Family::Pbkdf2 => Some(Kdf::Pbkdf2 { iterations: 600_000 }),
+            Family::Scrypt => Some(Kdf::Scrypt { log_n: 17, r: 8, p: 1 }),
+            Family::Argon2id => Some(Kdf::DEFAULT),
+        }
+    }
+
+    // Parameters below current OWASP guidance; files still open, with a warning
+    pub fn weakness(self) -> Option<String> {
+        let weak = match self {
+            Kdf::Argon2id { memory_kib, passes, .. } => memory_kib < 19 * 1024 || passes < 2,
+            Kdf::Scrypt { log_n, r, .. } => log_n < 17 || r < 8,
+            Kdf::Pbkdf2 { iterations } => iterations < 600_000,
+        };
+        weak.then(|| format!("{} is weaker than recommended ({})", self, Kdf::recommended(self.family()).map_or_else(String::new, |k| k.to_string())))
+    }
+
+    fn check_bounds(self) -> Result<()> {
+        let valid = match self {
+            Kdf::Argon2id { memory_kib, passes, lanes } => {
+                (1..=MAX_LANES).contains(&lanes) && (8 * lanes..=MAX_ARGON2_MEMORY_KIB).contains(&memory_kib) && (1..=MAX_ARGON2_PASSES).contains(&passes)
+            }
+            Kdf::Scrypt { log_n, r, p } => {
+                (1..=MAX_SCRYPT_LOG_N).contains(&log_n) && (1..=MAX_SCRYPT_R).contains(&r) && (1..=MAX_LANES).contains(&p) && 128 * r as u64 * (1u64 << log_n) <= MAX_MEMORY
+            }
+            Kdf::Pbkdf2 { iterations } => (1..=MAX_PBKDF2_ITERATIONS).contains(&iterations),
+        };
+        if !valid {
+            return Err(GuardXError::Format(format!("Key stretching parameters out of range: {}", self)).into());
+        }
+        Ok(())
+    }
+
+    // kdf id u8 | parameters (u32 LE each; scrypt's log N is a u8) | salt [16]
+    pub fn to_bytes(self, salt: &[u8; SALT_LEN]) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Kdf::Argon2id { memory_kib, passes, lanes } => {
+                out.push(ID_ARGON2ID);
+                for value in [memory_kib, passes, lanes] {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Kdf::Scrypt { log_n, r, p } => {
+                out.push(ID_SCRYPT);
+                out.push(log_n);
+                out.extend_from_slice(&r.to_le_bytes());
+                out.extend_from_slice(&p.to_le_bytes());
+            }
+            Kdf::Pbkdf2 { iterations } => {
+                out.push(ID_PBKDF2);
+                out.extend_from_slice(&iterations.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(salt);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Kdf, [u8; SALT_LEN])> {
+        let malformed = || GuardXError::Format("Malformed key stretching parameters in header".to_string());
+        let (&id, rest) = bytes.split_first().ok_or_else(malformed)?;
+        let param_len = match id {
+            ID_ARGON2ID => 12,
+            ID_SCRYPT => 9,
+            ID_PBKDF2 => 4,
+            _ => return Err(GuardXError::Format(format!("Unsupported key stretching id {}", id)).into()),
+        };
+        if rest.len() != param_len + SALT_LEN {
+            return Err(malformed().into());
+        }
+        let (params, salt) = rest.split_at(param_len);
+        let u32_at = |at: usize| u32::from_le_bytes([params[at], params[at + 1], params[at + 2], params[at + 3]]);
+        let kdf = match id {
+            ID_ARGON2ID => Kdf::Argon2id { memory_kib: u32_at(0), passes: u32_at(4), lanes: u32_at(8) },
+            ID_SCRYPT => Kdf::Scrypt { log_n: params[0], r: u32_at(1), p: u32_at(5) },
+            _ => Kdf::Pbkdf2 { iterations: u32_at(0) },
+        };
+        kdf.check_bounds()?;
+        Ok((kdf, salt.try_into().map_err(|_| malformed())?))
+    }
+
+    // Accepts a family name for its recommended parameters, or one with some of them overridden:
+    // "argon2id:m=19456,t=2,p=1" (memory in KiB), "scrypt:logn=15,r=8,p=1", "pbkdf2:i=600000"
+    pub fn parse(spec: &str) -> Result<Kdf> {
+        let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+        let family = Family::from_name(name.trim()).filter(|f| *f != Family::None)
+            .with_context(|| format!("Unknown KDF {:?}; use argon2id, scrypt or pbkdf2", name.trim()))?;
+        let mut kdf = Kdf::recommended(family).expect("every family but None has recommended parameters");
+        for param in params.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (field, value) = param.split_once('=').with_context(|| format!("Expected name=value in KDF parameters, got {:?}", param))?;
+            let value: u32 = value.trim().parse().with_context(|| format!("Invalid number in KDF parameter {:?}", param))?;
+            match (&mut kdf, field.trim()) {
+                (Kdf::Argon2id { memory_kib, .. }, "m") => *memory_kib = value,
+                (Kdf::Argon2id { passes, .. }, "t") => *passes = value,
+                (Kdf::Argon2id { lanes, .. }, "p") => *lanes = value,
+                (Kdf::Scrypt { log_n, .. }, "logn") => *log_n = u8::try_from(value).unwrap_or(u8::MAX),
+                (Kdf::Scrypt { r, .. }, "r") => *r = value,
+                (Kdf::Scrypt { p, .. }, "p") => *p = value,
+                (Kdf::Pbkdf2 { iterations }, "i") => *iterations = value,
+                (_, field) => return Err(anyhow::anyhow!("{} has no parameter {:?}", family.name(), field)),
+            }
+        }
+        kdf.check_bounds()?;
+        Ok(kdf)
+    }
+
+    pub fn stretch(self, key: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+        let mut out = Zeroizing::new([0u8; 32]);
+        match self {
+            Kdf::Argon2id { memory_kib, passes, lanes } => {
+                let params = argon2::Params::new(memory_kib, passes, lanes, Some(out.len())).map_err(|e| anyhow::anyhow!("Argon2 error: {}", e))?;
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(key.as_bytes(), salt, &mut out[..])
+                    .map_err(|e| anyhow::anyhow!("Argon2 error: {}", e))?;
+            }
+            Kdf::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(log_n, r, p, out.len()).map_err(|e| anyhow::anyhow!("scrypt error: {}", e))?;
+                scrypt::scrypt(key.as_bytes(), salt, &params, &mut out[..]).map_err(|e| anyhow::anyhow!("scrypt error: {}", e))?;
+            }
+            Kdf::Pbkdf2 { iterations } => {
+                let iterations = NonZeroU32::new(iterations).context("PBKDF2 needs at least one iteration")?;
+                pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, key.as_bytes(), &mut out[..]);
+            }
+        }
+        Ok(out)
+    }
+}
+
+// The same form `parse` reads, with every parameter spelled out
+impl fmt::Display for Kdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kdf::Argon2id { memory_kib, passes, lanes } => write!(f, "argon2id:m={},t={},p={}", memory_kib, passes, lanes),
+            Kdf::Scrypt { log_n, r, p } => write!(f, "scrypt:logn={},r={},p={}", log_n, r, p),
+            Kdf::Pbkdf2 { iterations } => write!(f, "pbkdf2:i={}", iterations),
+        }
+    }
+}
+
+static CURRENT: RwLock<Kdf> = RwLock::new(Kdf::DEFAULT);
+static MINIMUM: RwLock<Family> = RwLock::new(Family::None);
+// Salt shared by the files this process writes, so a folder costs one stretch rather than one per file;
+// each file's keys still differ through its file id
+static SALT: Mutex<Option<[u8; SALT_LEN]>> = Mutex::new(None);
+// Stretched keys, found again by parameters, salt and a fingerprint of the key; wiped by `forget_derived_keys`.
+// At most MAX_DERIVED are kept, the oldest dropped first, so files from many salts cannot grow it without end.
+struct Derived {
+    kdf: Kdf,
+    salt: Vec<u8>,
+    fingerprint: Zeroizing<[u8; 32]>,
+    stretched: Zeroizing<[u8; 32]>,
+}
+
+static DERIVED: Mutex<Vec<Derived>> = Mutex::new(Vec::new());
+const MAX_DERIVED: usize = 16;
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static WARNED: Mutex<Vec<Kdf>> = Mutex::new(Vec::new());
+
+// What new files and manifests are stretched with
+pub fn set_kdf(kdf: Kdf) {
+    *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = kdf;
+}
+
+pub fn current() -> Kdf {
+    *CURRENT.read().unwrap_or_else(|e| e.into_inner())
+}
+
+// Files stretched with a weaker family than this are refused rather than opened
+pub fn set_minimum(family: Family) {
+    *MINIMUM.write().unwrap_or_else(|e| e.into_inner()) = family;
+}
+
+pub fn minimum() -> Family {
+    *MINIMUM.read().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(crate) fn for_new_files() -> Result<(Kdf, [u8; SALT_LEN])> {
+    let mut shared = SALT.lock().unwrap_or_else(|e| e.into_inner());
+    let salt = match *shared {
+        Some(salt) => salt,
+        None => {
+            let mut fresh = [0u8; SALT_LEN];
+            entropy::fill(&mut fresh)?;
+            *shared.insert(fresh)
+        }
+    };
+    Ok((current(), salt))
+}
+
+// Refuses what is below the minimum and queues a warning, once per parameter set, for weak parameters
+pub(crate) fn check(kdf: Option<Kdf>) -> Result<()> {
+    let family = kdf.map_or(Family::None, Kdf::family);
+    let minimum = minimum();
+    if family < minimum {
+        return Err(anyhow::anyhow!("This file uses {}, below the minimum set in config.toml ({})", family.label(), minimum.label()));
+    }
+    if let Some(kdf) = kdf {
+        if let Some(warning) = kdf.weakness() {
+            let mut warned = WARNED.lock().unwrap_or_else(|e| e.into_inner());
+            if !warned.contains(&kdf) {
+                warned.push(kdf);
+                WARNINGS.lock().unwrap_or_else(|e| e.into_inner()).push(warning);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Weak parameters met since the last call, for the caller to show
+pub fn take_warnings() -> Vec<String> {
+    std::mem::take(&mut *WARNINGS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+pub(crate) fn stretch_cached(kdf: Kdf, key: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let fingerprint = Zeroizing::new(blake3::derive_key("guardx kdf cache v1", key.as_bytes()));
+    let found = DERIVED.lock().unwrap_or_else(|e| e.into_inner()).iter()
+        .find(|d| d.kdf == kdf && d.salt == salt && d.fingerprint[..] == fingerprint[..])
+        .map(|d| d.stretched.clone());
+    if let Some(stretched) = found {
+        return Ok(stretched);
+    }
+    let stretched = kdf.stretch(key, salt)?;
+    let mut derived = DERIVED.lock().unwrap_or_else(|e| e.into_inner());
+    if derived.len() >= MAX_DERIVED {
+        derived.remove(0);
+    }
+    derived.push(Derived { kdf, salt: salt.to_vec(), fingerprint, stretched: stretched.clone() });
+    Ok(stretched)
+}
+
+// Wipes the stretched keys kept for reuse; call when the key itself is forgotten
+pub fn forget_derived_keys() {
+    DERIVED.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_above_a_gibibyte_is_refused() {
+        assert!(Kdf::parse("argon2id:m=1048576").is_ok());
+        assert!(Kdf::parse("argon2id:m=1048577").is_err());
+        // 128 * 8 * 2^20 is exactly 1 GiB; twice that, by N or by r, is not
+        assert!(Kdf::parse("scrypt:logn=20,r=8").is_ok());
+        assert!(Kdf::parse("scrypt:logn=21,r=8").is_err());
+        assert!(Kdf::parse("scrypt:logn=20,r=16").is_err());
+        assert!(Kdf::Scrypt { log_n: 22, r: 32, p: 1 }.check_bounds().is_err());
+        assert!(Kdf::Argon2id { memory_kib: 4 * 1024 * 1024, passes: 1, lanes: 1 }.check_bounds().is_err());
+    }
+
+    #[test]
+    fn headers_asking_for_too_much_memory_are_refused() {
+        let salt = [7u8; SALT_LEN];
+        for kdf in [Kdf::Argon2id { memory_kib: 2 * 1024 * 1024, passes: 1, lanes: 1 }, Kdf::Scrypt { log_n: 22, r: 8, p: 1 }] {
+            let e = Kdf::from_bytes(&kdf.to_bytes(&salt)).unwrap_err();
+            assert!(matches!(GuardXError::of(&e), Some(GuardXError::Format(_))), "{}", kdf);
+        }
+        let within = Kdf::Scrypt { log_n: 20, r: 8, p: 1 };
+        assert_eq!(Kdf::from_bytes(&within.to_bytes(&salt)).unwrap(), (within, salt));
+    }
+
+    #[test]
+    fn the_stretched_key_cache_is_bounded() {
+        let kdf = Kdf::Pbkdf2 { iterations: 1 };
+        for salt in 0..MAX_DERIVED as u8 * 2 {
+            stretch_cached(kdf, "correct horse", &[salt; SALT_LEN]).unwrap();
+            assert!(DERIVED.lock().unwrap().len() <= MAX_DERIVED);
+        }
+        // The newest are the ones kept
+        let last = [MAX_DERIVED as u8 * 2 - 1; SALT_LEN];
+        assert!(DERIVED.lock().unwrap().iter().any(|d| d.salt == last));
+    }
+}
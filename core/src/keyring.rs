@@ -0,0 +1,88 @@
+use crate::age::{Identity, Recipient};
+use crate::entropy;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+// Identities for recipient mode, one age identity file per name, so `age -d -i <file>` can use them too.
+// A machine that only encrypts needs the public keys, never these files.
+pub fn dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir().context("Could not find config directory")?.join("guardx").join("identities"))
+}
+
+fn file(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(anyhow::anyhow!("Identity names may only use letters, digits, '-' and '_': {:?}", name));
+    }
+    Ok(dir()?.join(format!("{}.txt", name)))
+}
+
+// Creates a new identity; refuses to replace an existing one, whose files could not be opened again
+pub fn generate(name: &str) -> Result<(PathBuf, Identity)> {
+    let path = file(name)?;
+    fs::create_dir_all(dir()?)?;
+    let identity = Identity::generate()?;
+    let created = chrono::DateTime::from_timestamp(entropy::timestamp(), 0).unwrap_or_default();
+    let contents = Zeroizing::new(format!(
+        "# created: {}\n# public key: {}\n{}\n",
+        created.to_rfc3339(),
+        identity.recipient(),
+        &*identity.to_secret_string()
+    ));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut out = options.open(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => anyhow::anyhow!("An identity named '{}' already exists", name),
+        _ => e.into(),
+    })?;
+    out.write_all(contents.as_bytes())?;
+    out.sync_all()?;
+    Ok((path, identity))
+}
+
+// Every identity with its name, sorted by name
+pub fn list() -> Result<Vec<(String, Identity)>> {
+    let dir = dir()?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut identities = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_stem().filter(|_| path.extension().is_some_and(|e| e == "txt")) else { continue };
+        let contents = Zeroizing::new(fs::read_to_string(&path)?);
+        let secret = contents.lines().map(str::trim).find(|l| !l.is_empty() && !l.starts_with('#'))
+            .with_context(|| format!("No identity in {:?}", path))?;
+        identities.push((name.to_string_lossy().to_string(), secret.parse().with_context(|| format!("Malformed identity in {:?}", path))?));
+    }
+    identities.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(identities)
+}
+
+// All identities as one key, which the age decryptor tries in turn; None when there are none
+pub fn all_as_key() -> Result<Option<Zeroizing<String>>> {
+    let identities = list()?;
+    if identities.is_empty() {
+        return Ok(None);
+    }
+    let mut key = Zeroizing::new(String::new());
+    for (_, identity) in &identities {
+        key.push_str(&identity.to_secret_string());
+        key.push('\n');
+    }
+    Ok(Some(key))
+}
+
+// An "age1..." public key, or the name of an identity on this machine
+pub fn resolve(recipient: &str) -> Result<Recipient> {
+    if recipient.starts_with("age1") {
+        return recipient.parse();
+    }
+    list()?.into_iter().find(|(name, _)| name == recipient)
+        .map(|(_, identity)| identity.recipient())
+        .with_context(|| format!("'{}' is neither an age public key nor the name of an identity", recipient))
+}
@@ -0,0 +1,59 @@
+//! The core of GuardX with no terminal in it: the encrypted file format and its key stretching, age files,
+//! folder manifests and signatures, and, with the `native` feature, encrypting whole folders on disk along
+//! with the config, vault registry and journal that go with that. The `GuardX` crate and its
+//! binary are built on this one; a GUI or a script can link it on its own.
+//!
+//! Encrypting a folder and checking it against its manifest:
+//!
+//! ```
+//! use guardx_core::crypto::{encrypt_file, preferred_cipher};
+//! use guardx_core::manifest::Manifest;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! # let vault = std::env::temp_dir().join(format!("guardx-core-doc-vault-{}", std::process::id()));
+//! # std::fs::create_dir_all(&vault)?;
+//! std::fs::write(vault.join("notes.txt"), "meet at noon")?;
+//! encrypt_file(&vault.join("notes.txt"), "correct horse", preferred_cipher())?;
+//! Manifest::build(&vault)?.save(&vault)?;
+//!
+//! let manifest = Manifest::load(&vault)?;
+//! assert!(manifest.is_supported());
+//! assert!(manifest.validate(&vault)?.is_clean());
+//! # std::fs::remove_dir_all(&vault)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Anything that fails with a known cause carries an [`error::GuardXError`] in its `anyhow::Error`;
+//! `GuardXError::of` finds it.
+
+pub mod age;
+pub mod crypto;
+pub mod entropy;
+pub mod error;
+pub mod filter;
+pub mod format;
+pub mod kdf;
+pub mod manifest;
+pub mod throttle;
+
+#[cfg(feature = "native")]
+pub mod config;
+#[cfg(feature = "native")]
+pub mod filesystem;
+#[cfg(feature = "native")]
+pub mod journal;
+#[cfg(feature = "native")]
+pub mod keyring;
+#[cfg(feature = "native")]
+pub mod oplog;
+#[cfg(feature = "native")]
+pub mod registry;
+#[cfg(feature = "native")]
+pub mod schedule;
+#[cfg(feature = "native")]
+pub mod signing;
+#[cfg(feature = "native")]
+pub mod storage;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod uring;
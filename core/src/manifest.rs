@@ -0,0 +1,427 @@
+use crate::age;
+use crate::crypto::{decrypt_stream, encrypt_stream, is_chunked, manifest_hash_key, replace_with, Cipher, MIN_CIPHERTEXT_LEN};
+use crate::entropy;
+use crate::error::GuardXError;
+use crate::filter::{self, Filter};
+use crate::kdf::{self, Kdf};
+use anyhow::{Context, Result};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+pub const MANIFEST_NAME: &str = ".guardx-manifest.json";
+pub const CHECKSUMS_NAME: &str = ".guardx-checksums";
+// Ed25519 signatures over the encrypted files; written after the manifest, so it never lists them
+pub const SIGNATURES_NAME: &str = ".guardx-signatures.json";
+// v3 added the optional `hashing` section, v4 the key stretching of its hash key
+pub const FORMAT_VERSION: u32 = 4;
+pub const SUPPORTED_VERSIONS: &[u32] = &[1, 2, 3, 4];
+
+const KEY_CHECK: &[u8] = b"guardx manifest key check";
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub created: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hashing: Option<Hashing>,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    // Hex digest of the file as stored (encrypted), in the manifest's hashing algorithm
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+// Keyed BLAKE3 is what GuardX writes. Plain SHA-256 manifests are still checked, and are upgraded by
+// `upgrade_hashes` once they verify cleanly.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum HashAlgorithm {
+    #[serde(rename = "sha256")]
+    Sha256,
+    #[serde(rename = "blake3-keyed")]
+    Blake3Keyed,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Hashing {
+    pub algorithm: HashAlgorithm,
+    // Keyed hashes only: the salt of the hash key, and a hash that tells a wrong key from damaged files
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    salt: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    check: String,
+    // How the key was stretched before the hash key was derived, as `Kdf::parse` reads it; empty in
+    // manifests from before key stretching
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    kdf: String,
+}
+
+#[derive(Default)]
+pub struct Validation {
+    pub missing: Vec<String>,
+    pub extraneous: Vec<String>,
+    pub damaged: Vec<String>,
+}
+
+impl Validation {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.extraneous.is_empty() && self.damaged.is_empty()
+    }
+}
+
+impl Manifest {
+    // Describes the encrypted files directly inside `dir`, matching what `encrypt_dir` touches
+    pub fn build(dir: &Path) -> Result<Self> {
+        let filter = filter::for_dir(dir);
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let excluded = name != CHECKSUMS_NAME && filter.excludes(&name, false);
+            if entry.path().is_file() && name != MANIFEST_NAME && name != SIGNATURES_NAME && !excluded {
+                files.push(ManifestEntry { path: name, size: entry.metadata()?.len(), hash: None });
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest { format_version: FORMAT_VERSION, created: entropy::timestamp(), hashing: None, files })
+    }
+
+    // Like `build`, plus a keyed BLAKE3 hash of every file so `verify_hashes` can find damage without decrypting
+    pub fn build_hashed(dir: &Path, key: &str) -> Result<Self> {
+        let mut manifest = Self::build(dir)?;
+        manifest.rehash(dir, key)?;
+        Ok(manifest)
+    }
+
+    fn rehash(&mut self, dir: &Path, key: &str) -> Result<()> {
+        let mut salt = [0u8; 16];
+        entropy::fill(&mut salt)?;
+        let kdf = kdf::current();
+        let hash_key = manifest_hash_key(key, &salt, Some(kdf))?;
+        for entry in &mut self.files {
+            entry.hash = Some(hash_file(&dir.join(&entry.path), HashAlgorithm::Blake3Keyed, &hash_key)?);
+        }
+        let check = to_hex(blake3::keyed_hash(&hash_key, KEY_CHECK).as_bytes());
+        self.hashing = Some(Hashing { algorithm: HashAlgorithm::Blake3Keyed, salt: to_hex(&salt), check, kdf: kdf.to_string() });
+        self.format_version = FORMAT_VERSION;
+        Ok(())
+    }
+
+    // Hashes every file listed in the manifest and reports those whose bytes changed, on top of what
+    // `validate` finds. Much faster than decrypting, but only as fresh as the manifest.
+    pub fn verify_hashes(&self, dir: &Path, key: &str) -> Result<Validation> {
+        let hashing = self.hashing.as_ref().context("This manifest has no file hashes; run a full verify instead")?;
+        let hash_key = self.hash_key(key)?;
+        let mut report = self.validate(dir)?;
+        for entry in &self.files {
+            if report.missing.contains(&entry.path) || report.damaged.contains(&entry.path) {
+                continue;
+            }
+            let expected = entry.hash.as_deref().ok_or_else(|| GuardXError::Format(format!("Manifest has no hash for {}", entry.path)))?;
+            if hash_file(&dir.join(&entry.path), hashing.algorithm, &hash_key)? != expected {
+                report.damaged.push(entry.path.clone());
+            }
+        }
+        Ok(report)
+    }
+
+    // A cheap pass for when a vault is opened: sizes and headers of every file, plus the hashes of up to
+    // `sample` files picked at random. Keyed hashes are only sampled with the right key.
+    pub fn quick_check(&self, dir: &Path, key: Option<&str>, sample: usize) -> Result<Validation> {
+        let mut report = self.validate(dir)?;
+        let mut intact = Vec::new();
+        for entry in &self.files {
+            if report.missing.contains(&entry.path) || report.damaged.contains(&entry.path) {
+                continue;
+            }
+            // v1 vaults hold legacy files, which have no header
+            let path = dir.join(&entry.path);
+            if self.format_version >= 2 && !is_chunked(&path)? && !age::is_age(&path)? {
+                report.damaged.push(entry.path.clone());
+            } else {
+                intact.push(entry);
+            }
+        }
+        let Some(hashing) = &self.hashing else { return Ok(report) };
+        let hash_key = match (hashing.algorithm, key) {
+            (HashAlgorithm::Blake3Keyed, None) => return Ok(report),
+            (_, key) => match self.hash_key(key.unwrap_or_default()) {
+                Err(e) if matches!(GuardXError::of(&e), Some(GuardXError::Authentication)) => return Ok(report),
+                result => result?,
+            },
+        };
+        // Partial Fisher-Yates: the first `sample` entries end up a random pick
+        let sample = sample.min(intact.len());
+        for i in 0..sample {
+            let mut random = [0u8; 8];
+            entropy::fill(&mut random)?;
+            let j = i + (u64::from_le_bytes(random) % (intact.len() - i) as u64) as usize;
+            intact.swap(i, j);
+        }
+        for entry in &intact[..sample] {
+            let Some(expected) = entry.hash.as_deref() else { continue };
+            if hash_file(&dir.join(&entry.path), hashing.algorithm, &hash_key)? != expected {
+                report.damaged.push(entry.path.clone());
+            }
+        }
+        Ok(report)
+    }
+
+    // Fails with GuardXError::Authentication when the manifest's keyed hashes were made with another key;
+    // manifests without keyed hashes cannot tell and always pass
+    pub fn check_key(&self, key: &str) -> Result<()> {
+        self.hash_key(key).map(drop)
+    }
+
+    fn hash_key(&self, key: &str) -> Result<Zeroizing<[u8; 32]>> {
+        match &self.hashing {
+            Some(hashing) if hashing.algorithm == HashAlgorithm::Blake3Keyed => {
+                let salt = from_hex(&hashing.salt).ok_or_else(|| GuardXError::Format("Malformed manifest hash salt".to_string()))?;
+                let kdf = match hashing.kdf.as_str() {
+                    "" => None,
+                    spec => Some(Kdf::parse(spec).map_err(|e| GuardXError::Format(format!("Malformed manifest key stretching: {:#}", e)))?),
+                };
+                let hash_key = manifest_hash_key(key, &salt, kdf)?;
+                if to_hex(blake3::keyed_hash(&hash_key, KEY_CHECK).as_bytes()) != hashing.check {
+                    return Err(GuardXError::Authentication.into());
+                }
+                Ok(hash_key)
+            }
+            _ => Ok(Zeroizing::new([0u8; 32])),
+        }
+    }
+
+    // Replaces SHA-256 hashes with keyed BLAKE3 ones; call only after `verify_hashes` came back clean
+    pub fn upgrade_hashes(&mut self, dir: &Path, key: &str) -> Result<bool> {
+        if self.hashing.as_ref().is_none_or(|h| h.algorithm != HashAlgorithm::Sha256) {
+            return Ok(false);
+        }
+        self.rehash(dir, key)?;
+        Ok(true)
+    }
+
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_NAME);
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("No manifest found at {:?}", path))?;
+        serde_json::from_str(&data).with_context(|| GuardXError::Format(format!("Malformed manifest: {:?}", path)))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::write(dir.join(MANIFEST_NAME), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_VERSIONS.contains(&self.format_version)
+    }
+
+    /// Compares the tree under `dir` against the manifest without reading any file contents.
+    ///
+    /// ```
+    /// use guardx_core::manifest::Manifest;
+    ///
+    /// # let vault = std::env::temp_dir().join(format!("guardx-doc-validate-{}", std::process::id()));
+    /// # std::fs::create_dir_all(&vault)?;
+    /// std::fs::write(vault.join("a.bin"), [0u8; 64])?;
+    /// let manifest = Manifest::build(&vault)?;
+    ///
+    /// std::fs::write(vault.join("a.bin"), [0u8; 10])?;
+    /// std::fs::write(vault.join("stray.txt"), "not from this vault")?;
+    /// let report = manifest.validate(&vault)?;
+    /// assert_eq!(report.damaged, ["a.bin"]);
+    /// assert_eq!(report.extraneous, ["stray.txt"]);
+    /// # std::fs::remove_dir_all(&vault)?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn validate(&self, dir: &Path) -> Result<Validation> {
+        let mut report = Validation::default();
+        let mut present = Vec::new();
+        // Excluded files were never part of the vault, so they are not extraneous either
+        walk(dir, dir, &filter::for_dir(dir), &mut present)?;
+
+        for entry in &self.files {
+            match fs::metadata(dir.join(&entry.path)) {
+                Ok(meta) if !meta.is_file() => report.missing.push(entry.path.clone()),
+                Ok(meta) => {
+                    if meta.len() != entry.size || (meta.len() as usize) < MIN_CIPHERTEXT_LEN {
+                        report.damaged.push(entry.path.clone());
+                    }
+                }
+                Err(_) => report.missing.push(entry.path.clone()),
+            }
+        }
+        report.extraneous = present.into_iter()
+            .filter(|p| p != MANIFEST_NAME && p != SIGNATURES_NAME && !self.files.iter().any(|e| &e.path == p))
+            .collect();
+        Ok(report)
+    }
+}
+
+// SHA-256 of every file's plaintext, taken before a folder is encrypted and kept inside the vault as
+// an encrypted file, so decryption can prove each file came back exactly as it went in
+#[derive(Serialize, Deserialize)]
+pub struct Checksums {
+    pub algorithm: HashAlgorithm,
+    // Real file names, even when the vault stores files under random ones
+    pub files: BTreeMap<String, String>,
+}
+
+impl Checksums {
+    pub fn build(files: &[PathBuf]) -> Result<Self> {
+        let mut checksums = BTreeMap::new();
+        for path in files {
+            let name = path.file_name().context("Invalid file name")?.to_string_lossy().to_string();
+            checksums.insert(name, sha256_file(path)?);
+        }
+        Ok(Checksums { algorithm: HashAlgorithm::Sha256, files: checksums })
+    }
+
+    pub fn save(&self, dir: &Path, key: &str, cipher: Cipher) -> Result<()> {
+        let data = Zeroizing::new(serde_json::to_vec(self)?);
+        replace_with(&dir.join(CHECKSUMS_NAME), |w| encrypt_stream(&mut &data[..], w, key, cipher))
+    }
+
+    // None when the vault was encrypted without checksums
+    pub fn load(dir: &Path, key: &str) -> Result<Option<Self>> {
+        let path = dir.join(CHECKSUMS_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut data = Zeroizing::new(Vec::new());
+        decrypt_stream(&mut io::BufReader::new(fs::File::open(&path)?), &mut *data, key)
+            .with_context(|| format!("Could not decrypt the checksums in {:?}", path))?;
+        let checksums: Self = serde_json::from_slice(&data).with_context(|| GuardXError::Format(format!("Malformed checksums: {:?}", path)))?;
+        if checksums.algorithm != HashAlgorithm::Sha256 {
+            return Err(GuardXError::Format(format!("Unsupported checksum algorithm in {:?}", path)).into());
+        }
+        Ok(Some(checksums))
+    }
+
+    // Files under `dir` that are missing or whose plaintext no longer matches
+    pub fn mismatches(&self, dir: &Path) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+        for (name, expected) in &self.files {
+            let path = dir.join(name);
+            if !path.is_file() {
+                problems.push(format!("{} (missing)", name));
+            } else if &sha256_file(&path)? != expected {
+                problems.push(name.clone());
+            }
+        }
+        Ok(problems)
+    }
+}
+
+fn hash_file(path: &Path, algorithm: HashAlgorithm, hash_key: &[u8; 32]) -> Result<String> {
+    match algorithm {
+        HashAlgorithm::Blake3Keyed => {
+            let mut file = fs::File::open(path)?;
+            let mut hasher = blake3::Hasher::new_keyed(hash_key);
+            io::copy(&mut file, &mut hasher)?;
+            Ok(to_hex(hasher.finalize().as_bytes()))
+        }
+        HashAlgorithm::Sha256 => sha256_file(path),
+    }
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        context.update(&buf[..n]);
+    }
+    Ok(to_hex(context.finish().as_ref()))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn walk(root: &Path, dir: &Path, filter: &Filter, out: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read directory: {:?}", dir))? {
+        let path = entry?.path();
+        let Ok(rel) = path.strip_prefix(root) else { continue };
+        let rel = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        if filter.excludes(&rel, path.is_dir()) && rel != CHECKSUMS_NAME {
+            continue;
+        }
+        if path.is_dir() {
+            walk(root, &path, filter, out)?;
+        } else {
+            out.push(rel);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("guardx-manifest-test-{}", std::process::id())).join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("notes.gx"), "ciphertext").unwrap();
+        dir
+    }
+
+    fn is_authentication(e: &anyhow::Error) -> bool {
+        matches!(GuardXError::of(e), Some(GuardXError::Authentication))
+    }
+
+    #[test]
+    fn another_key_does_not_match_the_keyed_hashes() {
+        let dir = scratch("mismatch");
+        Manifest::build_hashed(&dir, "correct horse").unwrap().save(&dir).unwrap();
+        let manifest = Manifest::load(&dir).unwrap();
+        manifest.check_key("correct horse").unwrap();
+        assert!(is_authentication(&manifest.check_key("battery staple").unwrap_err()));
+        assert!(manifest.verify_hashes(&dir, "battery staple").err().is_some_and(|e| is_authentication(&e)));
+        // The quick check leaves the hashes out rather than failing
+        assert!(manifest.quick_check(&dir, Some("battery staple"), 1).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn an_edited_key_check_is_a_mismatch_and_a_bad_salt_is_malformed() {
+        let dir = scratch("edited");
+        let mut manifest = Manifest::build_hashed(&dir, "correct horse").unwrap();
+        manifest.hashing.as_mut().unwrap().check = to_hex(&[0; 32]);
+        assert!(is_authentication(&manifest.check_key("correct horse").unwrap_err()));
+        manifest.hashing.as_mut().unwrap().salt = "zz".to_string();
+        assert!(matches!(GuardXError::of(&manifest.check_key("correct horse").unwrap_err()), Some(GuardXError::Format(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_manifest_without_keyed_hashes_takes_any_key() {
+        let dir = scratch("unkeyed");
+        let manifest = Manifest::build(&dir).unwrap();
+        manifest.check_key("anything").unwrap();
+        manifest.check_key("").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,346 @@
+use crate::age;
+use crate::entropy;
+use crate::error::GuardXError;
+use crate::signing::{self, SigningKey, Verdict};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use zeroize::Zeroizing;
+
+// Persistent record of significant operations (encrypting, decrypting, re-keying, verifying, deleting)
+// with the note the user attached, one JSON object per line in the data directory, so the intent behind
+// an operation can still be looked up months later. Only ever appended to.
+pub const MAX_NOTE_LEN: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LogEntry {
+    pub time: i64,
+    pub operation: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    // In audit mode: the entry's place in the chain and the hash of the line before it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev: Option<String>,
+}
+
+impl LogEntry {
+    pub fn new(operation: impl Into<String>, path: Option<&Path>, ok: bool, note: Option<&str>) -> Self {
+        LogEntry {
+            time: entropy::timestamp(),
+            operation: operation.into(),
+            path: path.map(Path::to_path_buf),
+            ok,
+            note: note.map(str::to_string),
+            seq: None,
+            prev: None,
+        }
+    }
+}
+
+pub fn file() -> Result<PathBuf> {
+    let dir = dirs::data_dir().context("Could not find data directory")?.join("guardx");
+    Ok(dir.join("history.jsonl"))
+}
+
+pub fn append(entry: &LogEntry) -> Result<()> {
+    let path = file()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut entry = entry.clone();
+    let audited = AUDIT.load(Ordering::Relaxed);
+    if audited {
+        let (seq, prev) = chain_end(&path)?;
+        entry.seq = Some(seq);
+        entry.prev = Some(prev);
+    }
+    let mut line = serde_json::to_string(&entry)?;
+    let hash = line_hash(line.as_bytes());
+    line.push('\n');
+    fs::OpenOptions::new().create(true).append(true).open(&path)?.write_all(line.as_bytes())?;
+    if audited {
+        let head = Head { seq: entry.seq.unwrap_or_default(), hash };
+        fs::write(head_file()?, serde_json::to_string(&head)?).context("Could not write the audit head")?;
+    }
+    Ok(())
+}
+
+// Audit mode (`audit = true` in config.toml) chains the log for compliance: each entry carries its place in
+// the chain and the BLAKE3 hash of the line before it, and the first one the hash of everything logged
+// before audit mode was turned on. `history.head` keeps the place and hash of the last line, so cutting
+// entries off the end shows too. Whoever can write both files can forge a new chain, so `guardx audit
+// verify` prints the head hash for keeping somewhere else.
+static AUDIT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_audit(on: bool) {
+    AUDIT.store(on, Ordering::Relaxed);
+}
+
+#[derive(Serialize, Deserialize)]
+struct Head {
+    seq: u64,
+    hash: String,
+}
+
+fn head_file() -> Result<PathBuf> {
+    Ok(file()?.with_file_name("history.head"))
+}
+
+fn line_hash(line: &[u8]) -> String {
+    blake3::hash(line).to_hex().to_string()
+}
+
+// Where the next chained entry goes: after the last line if it is chained, otherwise a new chain
+fn chain_end(path: &Path) -> Result<(u64, String)> {
+    let data = if path.exists() { fs::read(path)? } else { Vec::new() };
+    let last = data.split(|&b| b == b'\n').rfind(|line| !line.is_empty());
+    match last.and_then(|line| Some((serde_json::from_slice::<LogEntry>(line).ok()?.seq?, line))) {
+        Some((seq, line)) => Ok((seq + 1, line_hash(line))),
+        None => Ok((0, line_hash(&data))),
+    }
+}
+
+// What `guardx audit verify` found. Lines are numbered from 1.
+pub struct AuditReport {
+    // Entries from before audit mode, which nothing protects
+    pub unchained: usize,
+    pub chained: usize,
+    // Hash of the last chained line
+    pub head: Option<String>,
+    pub problems: Vec<String>,
+}
+
+// Whether a chained line still hashes to `head`, a head printed by an earlier verify. A log rewritten
+// from before that point, however consistent, no longer does.
+pub fn chain_contains(head: &str) -> Result<bool> {
+    let path = file()?;
+    let data = if path.exists() { fs::read(&path)? } else { Vec::new() };
+    Ok(data.split(|&b| b == b'\n')
+        .filter(|line| serde_json::from_slice::<LogEntry>(line).ok().is_some_and(|e| e.seq.is_some()))
+        .any(|line| line_hash(line).eq_ignore_ascii_case(head.trim())))
+}
+
+// Follows the chain through the whole log and checks its end against `history.head`
+pub fn verify_audit() -> Result<AuditReport> {
+    let path = file()?;
+    let data = if path.exists() { fs::read(&path)? } else { Vec::new() };
+    let mut report = AuditReport { unchained: 0, chained: 0, head: None, problems: Vec::new() };
+    // Place and hash of the previous line, once the chain has started
+    let mut previous: Option<(u64, String)> = None;
+    let mut offset = 0;
+    for (n, line) in data.split(|&b| b == b'\n').enumerate() {
+        let start = offset;
+        offset += line.len() + 1;
+        if line.is_empty() {
+            continue;
+        }
+        let number = n + 1;
+        let entry: Option<LogEntry> = serde_json::from_slice(line).ok();
+        let Some((seq, prev)) = entry.as_ref().and_then(|e| Some((e.seq?, e.prev.clone()?))) else {
+            if previous.is_some() {
+                report.problems.push(format!("line {} is not part of the chain; it was changed or added outside audit mode", number));
+            } else {
+                report.unchained += 1;
+            }
+            continue;
+        };
+        let expected = match &previous {
+            // Audit mode was turned on here, or on again after being off
+            _ if seq == 0 => line_hash(&data[..start]),
+            Some((last, hash)) if seq == last + 1 => hash.clone(),
+            Some((last, _)) => {
+                report.problems.push(format!("line {} is entry {} of the chain, but {} came before it; entries were removed or reordered", number, seq, last));
+                String::new()
+            }
+            None => {
+                report.problems.push(format!("line {} is entry {} of the chain, but the entries before it are gone", number, seq));
+                String::new()
+            }
+        };
+        if !expected.is_empty() && prev != expected {
+            report.problems.push(format!("line {} does not follow from the line before it; one of them was changed", number));
+        }
+        report.chained += 1;
+        previous = Some((seq, line_hash(line)));
+    }
+    report.head = previous.as_ref().map(|(_, hash)| hash.clone());
+    let head_path = head_file()?;
+    let head: Option<Head> = match fs::read_to_string(&head_path) {
+        Ok(text) => Some(serde_json::from_str(&text).with_context(|| GuardXError::Format(format!("Malformed audit head: {:?}", head_path)))?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Could not read {:?}", head_path)),
+    };
+    match (head, &previous) {
+        (Some(head), Some((seq, hash))) if head.seq != *seq || head.hash != *hash => {
+            report.problems.push(format!("the log ends at entry {} of the chain, but entry {} was the last one written; entries were cut off or changed", seq, head.seq));
+        }
+        (Some(head), None) => report.problems.push(format!("{} chained entries were written, but none are left in the log", head.seq + 1)),
+        (None, Some(_)) => report.problems.push(format!("{:?} is missing, so entries cut off the end would not show", head_path)),
+        _ => {}
+    }
+    Ok(report)
+}
+
+// Oldest first; a line that cannot be parsed (say, cut short by a crash) is skipped rather than hiding the rest
+pub fn load() -> Result<Vec<LogEntry>> {
+    let path = file()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path)?;
+    Ok(data.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+// Part of the log handed to someone else, with the filter that picked it so the recipient can see
+// what was asked for. Written as an age file with a passphrase, so `age -d` opens it too, and signed
+// (the ciphertext) with a signing key.
+#[derive(Serialize, Deserialize)]
+pub struct Export {
+    pub exported: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault: Option<PathBuf>,
+    pub entries: Vec<LogEntry>,
+}
+
+impl Export {
+    // Entries from `since` to `until` (both inclusive) about `vault` or anything inside it
+    pub fn select(entries: Vec<LogEntry>, since: Option<i64>, until: Option<i64>, vault: Option<&Path>) -> Self {
+        let entries = entries.into_iter()
+            .filter(|e| since.is_none_or(|t| e.time >= t) && until.is_none_or(|t| e.time <= t))
+            .filter(|e| vault.is_none_or(|v| e.path.as_deref().is_some_and(|p| p.starts_with(v))))
+            .collect();
+        Export { exported: entropy::timestamp(), since, until, vault: vault.map(Path::to_path_buf), entries }
+    }
+
+    pub fn write(&self, dest: &Path, password: &str, key: &SigningKey) -> Result<()> {
+        let data = Zeroizing::new(serde_json::to_vec_pretty(self)?);
+        let mut out = fs::OpenOptions::new().write(true).create_new(true).open(dest)
+            .with_context(|| format!("Could not create {:?}", dest))?;
+        age::encrypt_stream(&mut &data[..], &mut out, password)?;
+        out.sync_all()?;
+        signing::sign_file(dest, key)
+    }
+
+    // Checks the signature before anything is decrypted; only a valid signature from a trusted key passes
+    pub fn open(path: &Path, password: &str, trusted: &[String]) -> Result<(String, Self)> {
+        let signer = match signing::verify_file(path, trusted)? {
+            Verdict::Valid { signer } => signer,
+            Verdict::UnknownSigner { signer } => {
+                return Err(anyhow::Error::new(GuardXError::Authentication).context(format!("Signed by an untrusted key {}", signer)))
+            }
+            Verdict::Invalid => return Err(anyhow::Error::new(GuardXError::Authentication).context("The signature does not match; the export was changed")),
+            Verdict::Unsigned => return Err(anyhow::Error::new(GuardXError::Authentication).context("The export is not signed")),
+        };
+        let mut data = Zeroizing::new(Vec::new());
+        age::decrypt_stream(&mut BufReader::new(fs::File::open(path)?), &mut *data, password)?;
+        let export = serde_json::from_slice(&data).with_context(|| GuardXError::Format(format!("Malformed history export: {:?}", path)))?;
+        Ok((signer, export))
+    }
+}
+
+// One line of at most MAX_NOTE_LEN characters; None when nothing is left
+pub fn clean_note(note: &str) -> Option<String> {
+    let note: String = note.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(MAX_NOTE_LEN).collect();
+    (!note.is_empty()).then_some(note)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // The log lives in the data directory, so the tests get one of their own and take turns with it
+    static LOG: Mutex<()> = Mutex::new(());
+
+    fn fresh() -> std::sync::MutexGuard<'static, ()> {
+        let lock = LOG.lock().unwrap_or_else(|e| e.into_inner());
+        let home = std::env::temp_dir().join(format!("guardx-oplog-test-{}", std::process::id()));
+        std::env::set_var("HOME", &home);
+        std::env::set_var("XDG_DATA_HOME", home.join("data"));
+        let _ = fs::remove_file(file().unwrap());
+        let _ = fs::remove_file(head_file().unwrap());
+        lock
+    }
+
+    fn log(operations: &[&str], audit: bool) {
+        set_audit(audit);
+        for operation in operations {
+            append(&LogEntry::new(*operation, None, true, None)).unwrap();
+        }
+        set_audit(false);
+    }
+
+    // Rewrites the log's lines with `change`
+    fn tamper(change: impl FnOnce(&mut Vec<String>)) {
+        let mut lines: Vec<String> = fs::read_to_string(file().unwrap()).unwrap().lines().map(str::to_string).collect();
+        change(&mut lines);
+        fs::write(file().unwrap(), lines.iter().map(|line| format!("{}\n", line)).collect::<String>()).unwrap();
+    }
+
+    fn problems() -> String {
+        verify_audit().unwrap().problems.join("\n")
+    }
+
+    #[test]
+    fn an_edited_line_breaks_the_chain_after_it() {
+        let _lock = fresh();
+        log(&["encrypt", "decrypt", "rekey"], true);
+        let head = verify_audit().unwrap().head.unwrap();
+        tamper(|lines| lines[1] = lines[1].replace("decrypt", "verify"));
+        assert!(problems().contains("line 3 does not follow from the line before it"), "{}", problems());
+        // The edit is in the middle, so the last line, and the head kept elsewhere, still match
+        assert!(chain_contains(&head).unwrap());
+    }
+
+    #[test]
+    fn a_deleted_middle_line_shows() {
+        let _lock = fresh();
+        log(&["encrypt", "decrypt", "rekey"], true);
+        tamper(|lines| {
+            lines.remove(1);
+        });
+        assert!(problems().contains("line 2 is entry 2 of the chain, but 0 came before it"), "{}", problems());
+    }
+
+    #[test]
+    fn a_truncated_tail_does_not_match_the_head() {
+        let _lock = fresh();
+        log(&["encrypt", "decrypt", "rekey"], true);
+        let head = verify_audit().unwrap().head.unwrap();
+        assert!(chain_contains(&head).unwrap());
+        tamper(|lines| {
+            lines.pop();
+        });
+        // The chain that is left is consistent; only history.head and the printed head give it away
+        let report = verify_audit().unwrap();
+        assert_eq!(report.chained, 2);
+        assert!(problems().contains("the log ends at entry 1 of the chain, but entry 2 was the last one written"), "{}", problems());
+        assert!(!chain_contains(&head).unwrap());
+    }
+
+    #[test]
+    fn the_chain_starts_from_a_hash_of_everything_before_it() {
+        let _lock = fresh();
+        log(&["encrypt", "decrypt"], false);
+        log(&["rekey", "verify"], true);
+        let report = verify_audit().unwrap();
+        assert_eq!((report.unchained, report.chained), (2, 2));
+        assert!(report.problems.is_empty(), "{}", report.problems.join("\n"));
+
+        // Nothing protects the entries from before audit mode one by one, but changing them shows
+        tamper(|lines| lines[0] = lines[0].replace("encrypt", "verify"));
+        assert!(problems().contains("line 3 does not follow from the line before it"), "{}", problems());
+    }
+}
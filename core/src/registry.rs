@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Registry {
+    pub vaults: Vec<VaultEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VaultEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub format_version: u32,
+    pub registered: i64,
+}
+
+impl Registry {
+    fn file() -> Result<PathBuf> {
+        let dir = dirs::data_dir().context("Could not find data directory")?.join("guardx");
+        Ok(dir.join("vaults.json"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::file()?;
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+        let data = fs::read_to_string(&path)?;
+        serde_json::from_str(&data).with_context(|| format!("Malformed vault registry: {:?}", path))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::file()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn register(&mut self, entry: VaultEntry) {
+        self.vaults.retain(|v| v.path != entry.path);
+        self.vaults.push(entry);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::age;
+use crate::config::Config;
+use crate::crypto::is_chunked;
+use crate::filesystem::{encrypt_folder_to, encrypt_folder_with, encrypt_one, files_in};
+use crate::manifest::{Checksums, Manifest, MANIFEST_NAME};
+use anyhow::{Context, Result};
+use chrono::{Datelike, Local, TimeZone, Timelike};
+use std::path::Path;
+
+// When `guardx daemon` encrypts a folder, as a cron expression in the folder's `schedule`: five fields for
+// minute (0-59), hour (0-23), day of month (1-31), month (1-12) and day of week (0-7, Sunday is 0 or 7).
+// Each field is `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a list of those separated by
+// commas. As in cron, when both day fields are restricted a day matching either one counts. `@hourly`,
+// `@daily`, `@weekly` and `@monthly` stand for the usual expressions. Times are local.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+// A schedule that matches nothing within this long is treated as never due (say, February 30th)
+const HORIZON_DAYS: i64 = 5 * 366;
+
+impl Schedule {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let expanded = match spec.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow::anyhow!("Expected five fields (minute hour day month weekday) in schedule {:?}", spec));
+        };
+        let field = |text: &str, min: u32, max: u32, name: &str| {
+            parse_field(text, min, max).with_context(|| format!("Invalid {} {:?} in schedule {:?}", name, text, spec))
+        };
+        let weekdays = field(weekday, 0, 7, "day of week")?;
+        Ok(Schedule {
+            minutes: field(minute, 0, 59, "minute")?,
+            hours: field(hour, 0, 23, "hour")? as u32,
+            days: field(day, 1, 31, "day of month")? as u32,
+            months: field(month, 1, 12, "month")? as u16,
+            // Sunday may be written as 7
+            weekdays: ((weekdays | (weekdays >> 7)) & 0x7f) as u8,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    // The first matching minute after the timestamp `after`, as a timestamp
+    pub fn next_after(&self, after: i64) -> Option<i64> {
+        let mut t = (after.div_euclid(60) + 1) * 60;
+        let end = after + HORIZON_DAYS * 24 * 60 * 60;
+        while t <= end {
+            let local = Local.timestamp_opt(t, 0).earliest()?;
+            let matches_day = match (self.any_day, self.any_weekday) {
+                (true, true) => true,
+                (false, true) => bit(self.days as u64, local.day()),
+                (true, false) => bit(self.weekdays as u64, local.weekday().num_days_from_sunday()),
+                (false, false) => bit(self.days as u64, local.day()) || bit(self.weekdays as u64, local.weekday().num_days_from_sunday()),
+            };
+            if !bit(self.months as u64, local.month()) || !matches_day {
+                // On to the next local midnight
+                t += ((24 * 60 - (local.hour() * 60 + local.minute())) * 60) as i64;
+            } else if !bit(self.hours as u64, local.hour()) {
+                t += ((60 - local.minute()) * 60) as i64;
+            } else if !bit(self.minutes, local.minute()) {
+                t += 60;
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+fn bit(set: u64, n: u32) -> bool {
+    set & (1 << n) != 0
+}
+
+fn parse_field(text: &str, min: u32, max: u32) -> Result<u64> {
+    let mut set = 0u64;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0).context("the step must be a positive number")?),
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((from, to)) => (from.parse()?, to.parse()?),
+                // `5/15` means from 5 to the end in steps of 15
+                None if step > 1 => (range.parse()?, max),
+                None => {
+                    let n = range.parse()?;
+                    (n, n)
+                }
+            },
+        };
+        if from < min || to > max || from > to {
+            return Err(anyhow::anyhow!("values must be between {} and {}", min, max));
+        }
+        for n in (from..=to).step_by(step as usize) {
+            set |= 1 << n;
+        }
+    }
+    Ok(set)
+}
+
+// Encrypts what is still plaintext in `dir` with the folder's settings: all of it when the folder is not
+// encrypted yet, otherwise only the files added to it since. Returns how many files were encrypted. The key
+// is only needed for folders without recipients.
+pub fn encrypt_scheduled(dir: &Path, key: &str, config: &Config) -> Result<usize> {
+    if !dir.is_dir() {
+        return Err(anyhow::anyhow!("{:?} is not reachable", dir));
+    }
+    config.check_writable(dir)?;
+    let recipients = config.recipients(dir)?;
+    if recipients.is_empty() && key.is_empty() {
+        return Err(anyhow::anyhow!("{:?} has no recipients, so it needs the key", dir));
+    }
+    let cipher = config.cipher(dir);
+    let format = config.output_format(dir);
+    let hide_names = config.encrypt_names(dir);
+    if !dir.join(MANIFEST_NAME).is_file() {
+        let count = files_in(dir)?.len();
+        if !recipients.is_empty() {
+            encrypt_folder_to(dir, &recipients, &mut |_, _, _| {})?;
+        } else {
+            encrypt_folder_with(dir, key, cipher, format, hide_names, config.checksums(dir), &mut |_, _, _| {})?;
+        }
+        return Ok(count);
+    }
+
+    let manifest = Manifest::load(dir)?;
+    if recipients.is_empty() {
+        manifest.check_key(key)?;
+    }
+    let mut plain = Vec::new();
+    for path in files_in(dir)? {
+        if !is_chunked(&path)? && !age::is_age(&path)? {
+            plain.push(path);
+        }
+    }
+    if plain.is_empty() {
+        return Ok(0);
+    }
+    if !recipients.is_empty() {
+        for path in &plain {
+            age::encrypt_file_to(path, &recipients)?;
+        }
+        Manifest::build(dir)?.save(dir)?;
+    } else {
+        if config.checksums(dir) {
+            let mut sums = match Checksums::load(dir, key)? {
+                Some(sums) => sums,
+                None => Checksums::build(&[])?,
+            };
+            sums.files.extend(Checksums::build(&plain)?.files);
+            sums.save(dir, key, cipher)?;
+        }
+        for path in &plain {
+            encrypt_one(path, key, cipher, format, hide_names)?;
+        }
+        Manifest::build_hashed(dir, key)?.save(dir)?;
+    }
+    Ok(plain.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A local time as a timestamp; none of these fall on a daylight saving change
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> i64 {
+        Local.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap().timestamp()
+    }
+
+    fn next(spec: &str, after: i64) -> Option<i64> {
+        Schedule::parse(spec).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn the_next_run_is_the_first_matching_minute_after() {
+        assert_eq!(next("*/15 * * * *", at(2024, 3, 1, 10, 7)), Some(at(2024, 3, 1, 10, 15)));
+        // Strictly after, so a run at 10:15 is not due again until 10:30
+        assert_eq!(next("*/15 * * * *", at(2024, 3, 1, 10, 15)), Some(at(2024, 3, 1, 10, 30)));
+        assert_eq!(next("*/15 * * * *", at(2024, 3, 1, 10, 15) + 30), Some(at(2024, 3, 1, 10, 30)));
+        assert_eq!(next("@daily", at(2024, 3, 1, 23, 59)), Some(at(2024, 3, 2, 0, 0)));
+        assert_eq!(next("@monthly", at(2024, 1, 31, 12, 0)), Some(at(2024, 2, 1, 0, 0)));
+        // 2024-03-01 is a Friday
+        assert_eq!(next("30 9 * * 1-5", at(2024, 3, 1, 10, 0)), Some(at(2024, 3, 4, 9, 30)));
+        assert_eq!(next("0 12 * * 7", at(2024, 3, 1, 0, 0)), Some(at(2024, 3, 3, 12, 0)));
+        assert_eq!(next("5/20 8-9 * * *", at(2024, 3, 1, 8, 50)), Some(at(2024, 3, 1, 9, 5)));
+    }
+
+    #[test]
+    fn with_both_day_fields_set_either_one_counts() {
+        // The next Friday comes before the 13th
+        assert_eq!(next("0 0 13 * 5", at(2024, 3, 1, 12, 0)), Some(at(2024, 3, 8, 0, 0)));
+        assert_eq!(next("0 0 2 * 5", at(2024, 3, 1, 12, 0)), Some(at(2024, 3, 2, 0, 0)));
+    }
+
+    #[test]
+    fn a_day_that_never_comes_is_never_due() {
+        assert_eq!(next("0 0 30 2 *", at(2024, 3, 1, 0, 0)), None);
+        // February 29th does come
+        assert_eq!(next("0 0 29 2 *", at(2024, 3, 1, 0, 0)), Some(at(2028, 2, 29, 0, 0)));
+    }
+
+    #[test]
+    fn malformed_schedules_are_refused() {
+        for spec in ["61 * * * *", "* * *", "*/0 * * * *", "* * 0 * *", "* * * * 8", "10-5 * * * *", "@yearly"] {
+            assert!(Schedule::parse(spec).is_err(), "{}", spec);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use crate::entropy;
+use crate::error::GuardXError;
+use crate::manifest::{from_hex, to_hex, MANIFEST_NAME, SIGNATURES_NAME};
+use anyhow::{Context, Result};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+// Ed25519 signatures over encrypted files, so a backup can be shown to come from one of our keys without
+// decrypting it. What is signed is SIGN_CONTEXT | name length u16 | file name | BLAKE3 of the stored bytes,
+// so swapping two signed files is caught as well. Files of an encrypted folder are signed in the folder's
+// SIGNATURES_NAME; a file anywhere else gets a detached `<file>.gxsig` next to it.
+pub const SIGNATURE_EXTENSION: &str = "gxsig";
+const SIGN_CONTEXT: &[u8] = b"guardx ed25519 signature v1";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Signature {
+    // Hex Ed25519 public key of the signer
+    pub signer: String,
+    pub hash: String,
+    pub signature: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct FolderSignatures {
+    files: BTreeMap<String, Signature>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Valid { signer: String },
+    // Signed, but by a key that is not trusted
+    UnknownSigner { signer: String },
+    // The file changed since it was signed, or the signature is forged
+    Invalid,
+    Unsigned,
+}
+
+// Signing keys, one 32-byte Ed25519 seed per name. Only the machine that signs needs them; checking a
+// signature needs the public key alone.
+pub fn dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir().context("Could not find config directory")?.join("guardx").join("signing"))
+}
+
+fn file(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(anyhow::anyhow!("Signing key names may only use letters, digits, '-' and '_': {:?}", name));
+    }
+    Ok(dir()?.join(format!("{}.key", name)))
+}
+
+pub struct SigningKey(Ed25519KeyPair);
+
+impl SigningKey {
+    fn from_seed(seed: &[u8]) -> Result<Self> {
+        Ed25519KeyPair::from_seed_unchecked(seed).map(SigningKey).map_err(|_| anyhow::anyhow!("Invalid signing key"))
+    }
+
+    pub fn public_key(&self) -> String {
+        to_hex(self.0.public_key().as_ref())
+    }
+
+    pub fn sign(&self, path: &Path, name: &str) -> Result<Signature> {
+        let hash = hash_file(path)?;
+        let signature = self.0.sign(&message(name, &hash));
+        Ok(Signature { signer: self.public_key(), hash: to_hex(&hash), signature: to_hex(signature.as_ref()) })
+    }
+}
+
+// Refuses to replace an existing key, whose signatures could then no longer be made
+pub fn generate(name: &str) -> Result<(PathBuf, SigningKey)> {
+    let path = file(name)?;
+    fs::create_dir_all(dir()?)?;
+    let mut seed = Zeroizing::new([0u8; 32]);
+    entropy::fill(&mut seed[..])?;
+    let key = SigningKey::from_seed(&seed[..])?;
+    let contents = Zeroizing::new(format!("# public key: {}\n{}\n", key.public_key(), to_hex(&seed[..])));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut out = options.open(&path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => anyhow::anyhow!("A signing key named '{}' already exists", name),
+        _ => e.into(),
+    })?;
+    out.write_all(contents.as_bytes())?;
+    out.sync_all()?;
+    Ok((path, key))
+}
+
+// Every signing key with its name, sorted by name
+pub fn list() -> Result<Vec<(String, SigningKey)>> {
+    let dir = dir()?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut keys = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_stem().filter(|_| path.extension().is_some_and(|e| e == "key")) else { continue };
+        let contents = Zeroizing::new(fs::read_to_string(&path)?);
+        let seed = contents.lines().map(str::trim).find(|l| !l.is_empty() && !l.starts_with('#'))
+            .and_then(|l| from_hex(l).map(Zeroizing::new))
+            .with_context(|| format!("Malformed signing key in {:?}", path))?;
+        keys.push((name.to_string_lossy().to_string(), SigningKey::from_seed(&seed)?));
+    }
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(keys)
+}
+
+// The named key, or the only one when no name is given
+pub fn load(name: Option<&str>) -> Result<SigningKey> {
+    let mut keys = list()?;
+    match name {
+        Some(name) => keys.into_iter().find(|(n, _)| n == name).map(|(_, key)| key)
+            .with_context(|| format!("No signing key named '{}'", name)),
+        None if keys.len() == 1 => Ok(keys.remove(0).1),
+        None if keys.is_empty() => Err(anyhow::anyhow!("No signing key; create one with `guardx keygen --signing <name>`")),
+        None => Err(anyhow::anyhow!("There are {} signing keys; pick one by name", keys.len())),
+    }
+}
+
+// Public keys of this machine's signing keys, which are trusted without being named
+pub fn own_public_keys() -> Result<Vec<String>> {
+    Ok(list()?.into_iter().map(|(_, key)| key.public_key()).collect())
+}
+
+// Signs one file; inside an encrypted folder its entry in the folder's signatures is replaced
+pub fn sign_file(path: &Path, key: &SigningKey) -> Result<()> {
+    let (dir, name) = split(path)?;
+    if dir.join(MANIFEST_NAME).is_file() {
+        let mut signatures = load_folder(dir)?;
+        signatures.files.insert(name.clone(), key.sign(path, &name)?);
+        return save_folder(dir, &signatures);
+    }
+    let signature = key.sign(path, &name)?;
+    fs::write(detached_path(path), serde_json::to_string_pretty(&signature)?)?;
+    Ok(())
+}
+
+// Signs every file of an encrypted folder, replacing any earlier signatures
+pub fn sign_folder(dir: &Path, files: &[PathBuf], key: &SigningKey) -> Result<()> {
+    let mut signatures = FolderSignatures::default();
+    for path in files {
+        let (_, name) = split(path)?;
+        signatures.files.insert(name.clone(), key.sign(path, &name)?);
+    }
+    save_folder(dir, &signatures)
+}
+
+pub fn verify_file(path: &Path, trusted: &[String]) -> Result<Verdict> {
+    let (dir, name) = split(path)?;
+    let signature = if dir.join(MANIFEST_NAME).is_file() {
+        load_folder(dir)?.files.remove(&name)
+    } else {
+        let detached = detached_path(path);
+        match fs::read_to_string(&detached) {
+            Ok(data) => Some(serde_json::from_str(&data).with_context(|| GuardXError::Format(format!("Malformed signature: {:?}", detached)))?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    match signature {
+        Some(signature) => check(path, &name, &signature, trusted),
+        None => Ok(Verdict::Unsigned),
+    }
+}
+
+// A verdict for every file, plus signatures whose file is gone (reported as Invalid)
+pub fn verify_folder(dir: &Path, files: &[PathBuf], trusted: &[String]) -> Result<Vec<(String, Verdict)>> {
+    let mut signatures = load_folder(dir)?;
+    let mut verdicts = Vec::new();
+    for path in files {
+        let (_, name) = split(path)?;
+        let verdict = match signatures.files.remove(&name) {
+            Some(signature) => check(path, &name, &signature, trusted)?,
+            None => Verdict::Unsigned,
+        };
+        verdicts.push((name, verdict));
+    }
+    verdicts.extend(signatures.files.into_keys().map(|name| (name, Verdict::Invalid)));
+    Ok(verdicts)
+}
+
+fn check(path: &Path, name: &str, signature: &Signature, trusted: &[String]) -> Result<Verdict> {
+    let (Some(public_key), Some(bytes)) = (from_hex(&signature.signer), from_hex(&signature.signature)) else {
+        return Err(GuardXError::Format(format!("Malformed signature for {}", name)).into());
+    };
+    let hash = hash_file(path)?;
+    if to_hex(&hash) != signature.hash || UnparsedPublicKey::new(&ED25519, public_key).verify(&message(name, &hash), &bytes).is_err() {
+        return Ok(Verdict::Invalid);
+    }
+    if trusted.iter().any(|t| t.eq_ignore_ascii_case(&signature.signer)) {
+        Ok(Verdict::Valid { signer: signature.signer.clone() })
+    } else {
+        Ok(Verdict::UnknownSigner { signer: signature.signer.clone() })
+    }
+}
+
+pub fn detached_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}.{}", name, SIGNATURE_EXTENSION))
+}
+
+fn split(path: &Path) -> Result<(&Path, String)> {
+    let name = path.file_name().with_context(|| format!("Invalid file name: {:?}", path))?.to_string_lossy().to_string();
+    Ok((path.parent().unwrap_or(Path::new(".")), name))
+}
+
+fn message(name: &str, hash: &[u8; 32]) -> Vec<u8> {
+    let mut message = SIGN_CONTEXT.to_vec();
+    message.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    message.extend_from_slice(name.as_bytes());
+    message.extend_from_slice(hash);
+    message
+}
+
+fn hash_file(path: &Path) -> Result<[u8; 32]> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(*hasher.finalize().as_bytes())
+}
+
+fn load_folder(dir: &Path) -> Result<FolderSignatures> {
+    let path = dir.join(SIGNATURES_NAME);
+    match fs::read_to_string(&path) {
+        Ok(data) => serde_json::from_str(&data).with_context(|| GuardXError::Format(format!("Malformed signatures: {:?}", path))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(FolderSignatures::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save_folder(dir: &Path, signatures: &FolderSignatures) -> Result<()> {
+    fs::write(dir.join(SIGNATURES_NAME), serde_json::to_string_pretty(signatures)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("guardx-signing-test-{}", std::process::id())).join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn signature(path: &Path) -> Signature {
+        serde_json::from_str(&fs::read_to_string(detached_path(path)).unwrap()).unwrap()
+    }
+
+    fn rewrite(path: &Path, change: impl FnOnce(&mut Signature)) {
+        let mut signature = signature(path);
+        change(&mut signature);
+        fs::write(detached_path(path), serde_json::to_string(&signature).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn only_a_trusted_key_is_valid() {
+        let dir = scratch("trust");
+        let path = dir.join("backup.gx");
+        fs::write(&path, "ciphertext").unwrap();
+        let key = SigningKey::from_seed(&[1; 32]).unwrap();
+        let other = SigningKey::from_seed(&[2; 32]).unwrap();
+        sign_file(&path, &key).unwrap();
+
+        assert_eq!(verify_file(&path, &[key.public_key().to_uppercase()]).unwrap(), Verdict::Valid { signer: key.public_key() });
+        assert_eq!(verify_file(&path, &[other.public_key()]).unwrap(), Verdict::UnknownSigner { signer: key.public_key() });
+        assert_eq!(verify_file(&path, &[]).unwrap(), Verdict::UnknownSigner { signer: key.public_key() });
+        // Claiming a trusted signer does not make someone else's signature valid
+        rewrite(&path, |s| s.signer = other.public_key());
+        assert_eq!(verify_file(&path, &[other.public_key()]).unwrap(), Verdict::Invalid);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_changed_file_or_signature_is_invalid() {
+        let dir = scratch("bad");
+        let path = dir.join("backup.gx");
+        fs::write(&path, "ciphertext").unwrap();
+        let key = SigningKey::from_seed(&[1; 32]).unwrap();
+        let trusted = [key.public_key()];
+        sign_file(&path, &key).unwrap();
+        let signed = signature(&path);
+
+        fs::write(&path, "ciphertexT").unwrap();
+        assert_eq!(verify_file(&path, &trusted).unwrap(), Verdict::Invalid);
+        // The stored hash updated to match the change does not help, the signature is over the old one
+        rewrite(&path, |s| s.hash = to_hex(&hash_file(&path).unwrap()));
+        assert_eq!(verify_file(&path, &trusted).unwrap(), Verdict::Invalid);
+
+        fs::write(&path, "ciphertext").unwrap();
+        rewrite(&path, |s| *s = signed.clone());
+        assert!(matches!(verify_file(&path, &trusted).unwrap(), Verdict::Valid { .. }));
+        // The first digit of the signature changed
+        rewrite(&path, |s| s.signature = format!("{}{}", if s.signature.starts_with('0') { '1' } else { '0' }, &s.signature[1..]));
+        assert_eq!(verify_file(&path, &trusted).unwrap(), Verdict::Invalid);
+        rewrite(&path, |s| s.signature = "not hex".to_string());
+        assert!(matches!(GuardXError::of(&verify_file(&path, &trusted).unwrap_err()), Some(GuardXError::Format(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn swapped_files_in_a_folder_are_invalid() {
+        let dir = scratch("folder");
+        fs::write(dir.join(MANIFEST_NAME), "{}").unwrap();
+        let (a, b) = (dir.join("a.gx"), dir.join("b.gx"));
+        fs::write(&a, "first").unwrap();
+        fs::write(&b, "second").unwrap();
+        let key = SigningKey::from_seed(&[1; 32]).unwrap();
+        let trusted = [key.public_key()];
+        sign_folder(&dir, &[a.clone(), b.clone()], &key).unwrap();
+        assert!(verify_folder(&dir, &[a.clone(), b.clone()], &trusted).unwrap().iter().all(|(_, v)| matches!(v, Verdict::Valid { .. })));
+
+        fs::rename(&a, dir.join("tmp")).unwrap();
+        fs::rename(&b, &a).unwrap();
+        fs::rename(dir.join("tmp"), &b).unwrap();
+        assert!(verify_folder(&dir, &[a.clone(), b.clone()], &trusted).unwrap().iter().all(|(_, v)| *v == Verdict::Invalid));
+        fs::remove_file(&b).unwrap();
+        assert_eq!(verify_folder(&dir, &[a], &trusted).unwrap()[1], ("b.gx".to_string(), Verdict::Invalid));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use crate::error;
+use anyhow::Result;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+// Where FileSystem's folders are kept. Listing, reading, writing, renaming and deleting go through this, so
+// an S3 bucket, an SFTP server or an archive can stand in for the local disk; `Local` is the disk. Paths
+// are the backend's own, laid out like local ones. Encrypting and decrypting whole folders still works on
+// local paths.
+pub trait Storage: Send + Sync {
+    // What is directly in `dir`
+    fn list(&self, dir: &Path) -> Result<Vec<Entry>>;
+    fn read(&self, path: &Path) -> Result<Box<dyn Read + '_>>;
+    // A new file, made with the folders it is in; an existing one is never overwritten, and nothing is left
+    // behind when the write fails part way
+    fn write(&self, path: &Path, data: &mut dyn Read) -> Result<u64>;
+    fn create_dir(&self, path: &Path) -> Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+    // Into the trash unless `permanent`; returns the path to restore when the trash can give it back
+    fn delete(&self, path: &Path, permanent: bool) -> Result<Option<PathBuf>>;
+    fn restore(&self, path: &Path) -> Result<()>;
+    fn exists(&self, path: &Path) -> bool;
+
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        let mut text = String::new();
+        self.read(path)?.read_to_string(&mut text).map_err(|e| error::io(e, path))?;
+        Ok(text)
+    }
+}
+
+pub struct Entry {
+    pub path: PathBuf,
+    pub kind: Kind,
+    // A symlink, with `kind` saying what it points at
+    pub link: bool,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Kind {
+    File,
+    Folder,
+    Other,
+}
+
+impl Entry {
+    pub fn name(&self) -> String {
+        self.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+    }
+}
+
+pub struct Local;
+
+impl Storage for Local {
+    fn list(&self, dir: &Path) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|e| error::io(e, dir))?.flatten() {
+            let path = entry.path();
+            // The type comes with the directory entry on most systems, so only symlinks cost a stat
+            let (kind, link) = match entry.file_type() {
+                Ok(t) if t.is_symlink() => (kind_of(&path), true),
+                Ok(t) if t.is_dir() => (Kind::Folder, false),
+                Ok(t) if t.is_file() => (Kind::File, false),
+                _ => (Kind::Other, false),
+            };
+            entries.push(Entry { path, kind, link });
+        }
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> Result<Box<dyn Read + '_>> {
+        let file = File::open(path).map_err(|e| error::io(e, path))?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+
+    fn write(&self, path: &Path, data: &mut dyn Read) -> Result<u64> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| error::io(e, parent))?;
+        }
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => anyhow::anyhow!("{} already exists", display_name(path)),
+            _ => error::io(e, path),
+        })?;
+        io::copy(data, &mut file).map_err(|e| {
+            let _ = fs::remove_file(path);
+            error::io(e, path)
+        })
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        fs::create_dir_all(path).map_err(|e| error::io(e, path))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        fs::rename(from, to).map_err(|e| error::io(e, from))
+    }
+
+    fn delete(&self, path: &Path, permanent: bool) -> Result<Option<PathBuf>> {
+        if !permanent {
+            // The trash records the path with its parent resolved, so that is what we look for when restoring
+            let original = match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => fs::canonicalize(parent)?.join(name),
+                _ => path.to_path_buf(),
+            };
+            trash::delete(path).map_err(|e| anyhow::anyhow!("Could not move to trash: {}", e))?;
+            return Ok(TRASH_RESTORE.then_some(original));
+        }
+        if path.is_dir() {
+            fs::remove_dir_all(path).map_err(|e| error::io(e, path))?;
+        } else {
+            fs::remove_file(path).map_err(|e| error::io(e, path))?;
+        }
+        Ok(None)
+    }
+
+    fn restore(&self, path: &Path) -> Result<()> {
+        restore_from_trash(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.symlink_metadata().is_ok()
+    }
+}
+
+fn kind_of(path: &Path) -> Kind {
+    if path.is_dir() {
+        Kind::Folder
+    } else if path.is_file() {
+        Kind::File
+    } else {
+        Kind::Other
+    }
+}
+
+// The trash crate can only list and restore items on Windows and freedesktop systems
+const TRASH_RESTORE: bool = cfg!(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+));
+
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+fn restore_from_trash(path: &Path) -> Result<()> {
+    let item = trash::os_limited::list()
+        .map_err(|e| anyhow::anyhow!("Could not read trash: {}", e))?
+        .into_iter()
+        .filter(|item| item.original_path() == path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| anyhow::anyhow!("{} is no longer in the trash", display_name(path)))?;
+    trash::os_limited::restore_all([item]).map_err(|e| match e {
+        trash::Error::RestoreCollision { .. } => anyhow::anyhow!("{} already exists", display_name(path)),
+        e => anyhow::anyhow!("Could not restore from trash: {}", e),
+    })
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+)))]
+fn restore_from_trash(_path: &Path) -> Result<()> {
+    Err(anyhow::anyhow!("Restoring from trash is not supported on this platform"))
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.display().to_string())
+}
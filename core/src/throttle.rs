@@ -0,0 +1,51 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// A cap on how fast files are read to be encrypted, which `guardx daemon` and `guardx watch` set from
+// `background_limit` so a scheduled run or a big drop into a watched folder leaves the disk to whoever is
+// using the machine. Each chunk read waits until the chunks before it are paid for. 0, as everywhere
+// else, reads at full speed.
+static LIMIT: AtomicU64 = AtomicU64::new(0);
+// When the budget is free again; shared, so pipeline threads and concurrent files split one budget
+static NEXT: Mutex<Option<Instant>> = Mutex::new(None);
+
+pub fn set_limit(bytes_per_second: u64) {
+    LIMIT.store(bytes_per_second, Ordering::Relaxed);
+}
+
+pub fn limit() -> u64 {
+    LIMIT.load(Ordering::Relaxed)
+}
+
+// Called with what was just read; waits for the reads before it and books this one
+pub fn pace(bytes: usize) {
+    let limit = limit();
+    if limit == 0 || bytes == 0 {
+        return;
+    }
+    let now = Instant::now();
+    let start = {
+        let mut next = NEXT.lock().unwrap_or_else(|e| e.into_inner());
+        let start = next.filter(|at| *at > now).unwrap_or(now);
+        *next = Some(start + Duration::from_secs_f64(bytes as f64 / limit as f64));
+        start
+    };
+    std::thread::sleep(start - now);
+}
+
+// Lowers this process's CPU priority to `nice`, 0 to 19. Linux's I/O schedulers give its reads and
+// writes the matching priority too, unless it was set with ionice.
+#[cfg(unix)]
+pub fn lower_priority(nice: i32) -> Result<()> {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority(_nice: i32) -> Result<()> {
+    Err(anyhow::anyhow!("Lowering the priority is only supported on Unix"))
+}
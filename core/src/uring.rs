@@ -0,0 +1,225 @@
+use crate::crypto::{check_cancelled, encrypt_file, encrypt_stream, temp_path, Cipher};
+use crate::error;
+use anyhow::Result;
+use io_uring::{opcode, squeue, types, IoUring, Probe};
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+// Files up to this size are encrypted in batches, each read whole and encrypted in memory
+pub const SMALL_FILE: u64 = 64 * 1024;
+// Files per batch; with SMALL_FILE that bounds a batch to a few MiB of plaintext and ciphertext
+pub const BATCH: usize = 256;
+const RING_ENTRIES: u32 = 256;
+const AT_FDCWD: types::Fd = types::Fd(libc::AT_FDCWD);
+
+// Encrypting a folder of tiny files spends its time in system calls: opening, reading, closing, creating the
+// temporary copy, writing, syncing, closing and renaming, for every file. This submits each of those steps
+// for a whole batch of files at once, so a batch costs a handful of calls and the syncs run side by side.
+pub struct Ring {
+    ring: IoUring,
+}
+
+impl Ring {
+    // None where io_uring is missing, blocked (as seccomp does in many containers) or too old to rename,
+    // which needs Linux 5.11; the folder is then encrypted a file at a time as usual
+    pub fn new() -> Option<Ring> {
+        let ring = IoUring::new(RING_ENTRIES).ok()?;
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe).ok()?;
+        let codes = [opcode::OpenAt::CODE, opcode::Read::CODE, opcode::Write::CODE, opcode::Fsync::CODE, opcode::Close::CODE, opcode::RenameAt::CODE];
+        codes.iter().all(|code| probe.is_supported(*code)).then_some(Ring { ring })
+    }
+
+    // Runs every entry and returns each one's result, in order. Entries point into memory the caller
+    // owns; that stays valid because this only returns once all of them have completed.
+    fn run(&mut self, entries: Vec<squeue::Entry>) -> Result<Vec<i32>> {
+        let mut results = vec![0; entries.len()];
+        let per_submit = self.ring.params().sq_entries() as usize;
+        for (start, group) in entries.chunks(per_submit).enumerate().map(|(i, group)| (i * per_submit, group)) {
+            for (i, entry) in group.iter().enumerate() {
+                let entry = entry.clone().user_data((start + i) as u64);
+                // The queue has room for the whole group, since each group is drained before the next
+                unsafe { self.ring.submission().push(&entry) }.map_err(|_| anyhow::anyhow!("The io_uring submission queue is full"))?;
+            }
+            let mut done = 0;
+            while done < group.len() {
+                // io_uring_enter only fails before taking the entries, or while waiting, which is retried
+                match self.ring.submit_and_wait(group.len() - done) {
+                    Ok(_) => {}
+                    Err(e) if matches!(e.raw_os_error(), Some(libc::EINTR) | Some(libc::EAGAIN) | Some(libc::EBUSY)) => {}
+                    Err(e) => return Err(e.into()),
+                }
+                for completion in self.ring.completion() {
+                    results[completion.user_data() as usize] = completion.result();
+                    done += 1;
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn close(&mut self, fds: &[i32]) -> Result<Vec<i32>> {
+        self.run(fds.iter().map(|fd| opcode::Close::new(types::Fd(*fd)).build()).collect())
+    }
+
+    // Each file whole, or None for one that grew past the size it was picked with
+    fn read_all(&mut self, files: &[(PathBuf, u64)]) -> Result<Vec<Option<Zeroizing<Vec<u8>>>>> {
+        let names = files.iter().map(|(path, _)| c_path(path)).collect::<Result<Vec<_>>>()?;
+        let opened = self.run(names.iter().map(|name| opcode::OpenAt::new(AT_FDCWD, name.as_ptr()).flags(libc::O_RDONLY | libc::O_CLOEXEC).build()).collect())?;
+        let fds: Vec<i32> = opened.iter().copied().filter(|fd| *fd >= 0).collect();
+        if let Some((i, res)) = opened.iter().enumerate().find(|(_, res)| **res < 0) {
+            self.close(&fds)?;
+            return Err(error::io(io::Error::from_raw_os_error(-res), &files[i].0));
+        }
+        // One byte more than expected, so a file that grew is noticed instead of cut short
+        let mut buffers: Vec<Zeroizing<Vec<u8>>> = files.iter().map(|(_, size)| Zeroizing::new(vec![0u8; *size as usize + 1])).collect();
+        let mut filled = vec![0usize; files.len()];
+        let mut pending: Vec<usize> = (0..files.len()).collect();
+        let mut failure = None;
+        while !pending.is_empty() && failure.is_none() {
+            let reads = pending
+                .iter()
+                .map(|&i| {
+                    let rest = &mut buffers[i][filled[i]..];
+                    opcode::Read::new(types::Fd(fds[i]), rest.as_mut_ptr(), rest.len() as u32).offset(filled[i] as u64).build()
+                })
+                .collect();
+            let results = self.run(reads)?;
+            let mut still = Vec::new();
+            for (&i, &res) in pending.iter().zip(&results) {
+                if res < 0 {
+                    failure = Some(error::io(io::Error::from_raw_os_error(-res), &files[i].0));
+                } else if res > 0 {
+                    filled[i] += res as usize;
+                    if filled[i] < buffers[i].len() {
+                        still.push(i);
+                    }
+                }
+            }
+            pending = still;
+        }
+        self.close(&fds)?;
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        Ok(buffers
+            .into_iter()
+            .zip(filled)
+            .map(|(mut data, len)| {
+                (len < data.len()).then(|| {
+                    data.truncate(len);
+                    data
+                })
+            })
+            .collect())
+    }
+
+    // Does what crypto::replace_with does for each file: writes a temporary sibling, syncs it and renames
+    // it over the file. Nothing is renamed unless every copy was written, and temporary copies are removed
+    // on failure, so each file ends up either replaced or untouched.
+    fn replace_all(&mut self, files: &[&Path], contents: &[Vec<u8>]) -> Result<()> {
+        let temps: Vec<PathBuf> = files.iter().map(|path| temp_path(path)).collect();
+        let temp_names = temps.iter().map(|path| c_path(path)).collect::<Result<Vec<_>>>()?;
+        let names = files.iter().map(|path| c_path(path)).collect::<Result<Vec<_>>>()?;
+        let create = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC;
+        let opened = self.run(temp_names.iter().map(|name| opcode::OpenAt::new(AT_FDCWD, name.as_ptr()).flags(create).mode(0o666).build()).collect())?;
+        let fds: Vec<i32> = opened.iter().copied().filter(|fd| *fd >= 0).collect();
+        let result = match opened.iter().position(|res| *res < 0) {
+            Some(i) => Err(error::io(io::Error::from_raw_os_error(-opened[i]), files[i])),
+            None => self.write_all(&fds, files, contents),
+        };
+        let closed = self.close(&fds)?;
+        let result = result.and_then(|()| match closed.iter().position(|res| *res < 0) {
+            Some(i) => Err(error::io(io::Error::from_raw_os_error(-closed[i]), files[i])),
+            None => Ok(()),
+        });
+        if let Err(e) = result {
+            for temp in &temps {
+                let _ = std::fs::remove_file(temp);
+            }
+            return Err(e);
+        }
+        let renamed = self.run(temp_names.iter().zip(&names).map(|(from, to)| opcode::RenameAt::new(AT_FDCWD, from.as_ptr(), AT_FDCWD, to.as_ptr()).build()).collect())?;
+        if let Some(i) = renamed.iter().position(|res| *res < 0) {
+            for (temp, _) in temps.iter().zip(&renamed).filter(|(_, res)| **res < 0) {
+                let _ = std::fs::remove_file(temp);
+            }
+            return Err(error::io(io::Error::from_raw_os_error(-renamed[i]), files[i]));
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self, fds: &[i32], files: &[&Path], contents: &[Vec<u8>]) -> Result<()> {
+        let mut written = vec![0usize; files.len()];
+        let mut pending: Vec<usize> = (0..files.len()).filter(|&i| !contents[i].is_empty()).collect();
+        while !pending.is_empty() {
+            let writes = pending
+                .iter()
+                .map(|&i| {
+                    let rest = &contents[i][written[i]..];
+                    opcode::Write::new(types::Fd(fds[i]), rest.as_ptr(), rest.len() as u32).offset(written[i] as u64).build()
+                })
+                .collect();
+            let results = self.run(writes)?;
+            let mut still = Vec::new();
+            for (&i, &res) in pending.iter().zip(&results) {
+                if res <= 0 {
+                    let e = if res == 0 { io::ErrorKind::WriteZero.into() } else { io::Error::from_raw_os_error(-res) };
+                    return Err(error::io(e, files[i]));
+                }
+                written[i] += res as usize;
+                if written[i] < contents[i].len() {
+                    still.push(i);
+                }
+            }
+            pending = still;
+        }
+        let synced = self.run(fds.iter().map(|fd| opcode::Fsync::new(types::Fd(*fd)).build()).collect())?;
+        match synced.iter().position(|res| *res < 0) {
+            Some(i) => Err(error::io(io::Error::from_raw_os_error(-synced[i]), files[i])),
+            None => Ok(()),
+        }
+    }
+}
+
+// Sizes of the small files `files` starts with, at most BATCH of them; empty when the first is not small
+pub fn leading_small(files: &[PathBuf]) -> Vec<(PathBuf, u64)> {
+    files
+        .iter()
+        .take(BATCH)
+        .map_while(|path| std::fs::metadata(path).ok().filter(|m| m.is_file() && m.len() <= SMALL_FILE).map(|m| (path.clone(), m.len())))
+        .collect()
+}
+
+// Encrypts each file in place into the chunked format, as crypto::encrypt_file does. A file that grew
+// since it was picked is encrypted on its own afterwards.
+pub fn encrypt_batch(ring: &mut Ring, files: &[(PathBuf, u64)], key: &str, cipher: Cipher) -> Result<()> {
+    check_cancelled()?;
+    let plain = ring.read_all(files)?;
+    let mut batch = Vec::with_capacity(files.len());
+    let mut sealed = Vec::with_capacity(files.len());
+    let mut grown = Vec::new();
+    for ((path, _), data) in files.iter().zip(plain) {
+        match data {
+            Some(data) => {
+                let mut out = Vec::with_capacity(data.len() + 256);
+                encrypt_stream(&mut &data[..], &mut out, key, cipher)?;
+                batch.push(path.as_path());
+                sealed.push(out);
+            }
+            None => grown.push(path),
+        }
+    }
+    ring.replace_all(&batch, &sealed)?;
+    for path in grown {
+        encrypt_file(path, key, cipher)?;
+    }
+    Ok(())
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| anyhow::anyhow!("Path contains a NUL byte: {:?}", path))
+}
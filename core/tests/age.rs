@@ -0,0 +1,69 @@
+use guardx_core::age::{decrypt_stream, encrypt_stream_to, Identity, Recipient};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const PLAIN: &[u8] = b"meet at noon, bring the keys";
+
+// Whether the reference `age` tool is installed; CI installs it and sets GUARDX_REQUIRE_AGE, so there a
+// missing tool fails instead of skipping the test. Passphrase files are left out: `age -p` only reads the
+// passphrase from a terminal.
+fn reference_installed() -> bool {
+    let found = ["age", "age-keygen"].iter().all(|tool| Command::new(tool).arg("--version").output().is_ok_and(|out| out.status.success()));
+    assert!(found || std::env::var_os("GUARDX_REQUIRE_AGE").is_none(), "age and age-keygen are not installed");
+    if !found {
+        eprintln!("skipped: the reference age tool is not installed");
+    }
+    found
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("guardx-age-tests-{}", std::process::id())).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn age_opens_a_file_encrypted_to_its_key() {
+    if !reference_installed() {
+        return;
+    }
+    let dir = scratch("to-age");
+    let keys = dir.join("key.txt");
+    assert!(Command::new("age-keygen").arg("-o").arg(&keys).output().unwrap().status.success());
+    // age-keygen writes "# public key: age1..." above the secret key
+    let written = fs::read_to_string(&keys).unwrap();
+    let recipient: Recipient = written.lines().find_map(|line| line.strip_prefix("# public key: ")).unwrap().parse().unwrap();
+
+    let sealed = dir.join("sealed.age");
+    let mut out = Vec::new();
+    encrypt_stream_to(&mut &PLAIN[..], &mut out, &[recipient]).unwrap();
+    fs::write(&sealed, out).unwrap();
+    let opened = Command::new("age").arg("-d").arg("-i").arg(&keys).arg(&sealed).output().unwrap();
+    assert!(opened.status.success(), "{}", String::from_utf8_lossy(&opened.stderr));
+    assert_eq!(opened.stdout, PLAIN);
+
+    // The identity age-keygen wrote parses here too and names the same recipient
+    let identity: Identity = written.lines().find(|line| line.starts_with("AGE-SECRET-KEY-1")).unwrap().parse().unwrap();
+    assert_eq!(identity.recipient(), recipient);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_file_age_encrypted_to_our_key_opens() {
+    if !reference_installed() {
+        return;
+    }
+    let identity = Identity::generate().unwrap();
+    let mut age = Command::new("age").arg("-r").arg(identity.recipient().to_string()).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    age.stdin.take().unwrap().write_all(PLAIN).unwrap();
+    let sealed = age.wait_with_output().unwrap();
+    assert!(sealed.status.success());
+
+    let mut opened = Vec::new();
+    decrypt_stream(&mut &sealed.stdout[..], &mut opened, &identity.to_secret_string()).unwrap();
+    assert_eq!(opened, PLAIN);
+    assert!(decrypt_stream(&mut &sealed.stdout[..], &mut Vec::new(), &Identity::generate().unwrap().to_secret_string()).is_err());
+}
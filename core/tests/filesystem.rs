@@ -0,0 +1,214 @@
+use guardx_core::config::Config;
+use guardx_core::crypto::{encrypt_to, header_info, is_chunked, reencrypt_delta, verify_file, Cipher, DecryptingReader};
+use guardx_core::error::GuardXError;
+use guardx_core::filesystem::{verify_folder, FileSystem};
+use guardx_core::format::{Header, DEFAULT_CHUNK_SIZE, MAGIC, RECORD_OVERHEAD, TAG_LEN};
+use guardx_core::manifest::MANIFEST_NAME;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const KEY: &str = "correct horse battery staple";
+
+// FileSystem reads config.toml, the vault registry and the journal from the user's directories, so the test
+// process gets a home of its own whose config lists one root. Tests run in parallel, each in a folder of
+// its own under that root.
+fn root() -> &'static Path {
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let home = std::env::temp_dir().join(format!("guardx-core-tests-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+        let root = home.join("vaults");
+        fs::create_dir_all(&root).unwrap();
+        std::env::set_var("HOME", &home);
+        std::env::set_var("XDG_CONFIG_HOME", home.join("config"));
+        std::env::set_var("XDG_DATA_HOME", home.join("data"));
+        let config = Config { roots: vec![root.clone()], ..Default::default() };
+        config.save().unwrap();
+        root
+    })
+}
+
+// A folder under the root with these files, and a FileSystem listing it
+fn vault(name: &str, files: &[(&str, &[u8])]) -> (FileSystem, usize, PathBuf) {
+    let dir = root().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        fs::write(dir.join(file), contents).unwrap();
+    }
+    let fs = FileSystem::new().unwrap();
+    let index = fs.dirs.iter().position(|d| *d == dir).expect("the folder is listed");
+    (fs, index, dir)
+}
+
+fn sample() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("notes.txt", b"meet at noon".to_vec()),
+        ("empty.txt", Vec::new()),
+        // Several chunks, with a partial one at the end
+        ("photo.bin", (0..3_000_000u32).map(|i| (i * 7 % 251) as u8).collect()),
+    ]
+}
+
+fn borrowed<'a>(files: &'a [(&'static str, Vec<u8>)]) -> Vec<(&'static str, &'a [u8])> {
+    files.iter().map(|(name, data)| (*name, data.as_slice())).collect()
+}
+
+#[test]
+fn encrypt_then_decrypt_gives_back_every_byte() {
+    let files = sample();
+    let (fs, index, dir) = vault("round-trip", &borrowed(&files));
+
+    fs.encrypt_dir(index, KEY).unwrap();
+    assert!(dir.join(MANIFEST_NAME).is_file());
+    for (name, data) in &files {
+        let path = dir.join(name);
+        assert!(is_chunked(&path).unwrap(), "{} is encrypted", name);
+        assert_ne!(&fs::read(&path).unwrap(), data);
+    }
+
+    fs.decrypt_dir(index, KEY).unwrap();
+    assert!(!dir.join(MANIFEST_NAME).exists());
+    for (name, data) in &files {
+        assert_eq!(&fs::read(dir.join(name)).unwrap(), data, "{} round-trips", name);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wrong_key_is_refused_and_changes_nothing() {
+    let files = sample();
+    let (fs, index, dir) = vault("wrong-key", &borrowed(&files));
+    fs.encrypt_dir(index, KEY).unwrap();
+    let sealed: Vec<Vec<u8>> = files.iter().map(|(name, _)| fs::read(dir.join(name)).unwrap()).collect();
+
+    let e = fs.decrypt_dir(index, "not the key").unwrap_err();
+    assert!(matches!(GuardXError::of(&e), Some(GuardXError::Authentication)), "{:#}", e);
+    for ((name, _), sealed) in files.iter().zip(&sealed) {
+        assert_eq!(&fs::read(dir.join(name)).unwrap(), sealed, "{} is untouched", name);
+    }
+    assert!(verify_folder(&dir, "not the key").unwrap().iter().all(|(_, result)| result.is_err()));
+
+    fs.decrypt_dir(index, KEY).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn verify_singles_out_the_damaged_file() {
+    let files = sample();
+    let (fs, index, dir) = vault("verify", &borrowed(&files));
+    fs.encrypt_dir(index, KEY).unwrap();
+    assert!(verify_folder(&dir, KEY).unwrap().iter().all(|(_, result)| result.is_ok()));
+
+    let damaged = dir.join("photo.bin");
+    let mut bytes = fs::read(&damaged).unwrap();
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0x01;
+    fs::write(&damaged, bytes).unwrap();
+
+    for (path, result) in verify_folder(&dir, KEY).unwrap() {
+        assert_eq!(result.is_err(), path == damaged, "{}", path.display());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_damaged_file_stops_decryption_without_losing_any_file() {
+    let files = sample();
+    let (fs, index, dir) = vault("partial", &borrowed(&files));
+    fs.encrypt_dir(index, KEY).unwrap();
+
+    let damaged = dir.join("notes.txt");
+    let mut bytes = fs::read(&damaged).unwrap();
+    // Inside the ciphertext or the sealed chunk map at the end; either fails authentication
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0x01;
+    fs::write(&damaged, &bytes).unwrap();
+
+    assert!(fs.decrypt_dir(index, KEY).is_err());
+    // Files are replaced atomically: each one is decrypted or still encrypted, never half of either, and
+    // no temporary file is left
+    assert_eq!(fs::read(&damaged).unwrap(), bytes);
+    for (name, data) in files.iter().filter(|(name, _)| *name != "notes.txt") {
+        let path = dir.join(name);
+        assert!(is_chunked(&path).unwrap() || &fs::read(&path).unwrap() == data, "{} is whole", name);
+    }
+    assert!(fs::read_dir(&dir).unwrap().flatten().all(|e| e.path().extension().is_none_or(|ext| ext != "gxtmp")));
+    assert!(dir.join(MANIFEST_NAME).is_file());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_chunk_from_an_earlier_version_does_not_authenticate() {
+    let dir = root().join("splice");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let (plain, encrypted) = (dir.join("photo.bin"), dir.join("photo.bin.gx"));
+    let mut data = sample().remove(2).1;
+    fs::write(&plain, &data).unwrap();
+    encrypt_to(&plain, &encrypted, KEY, Cipher::ChaCha20Poly1305).unwrap();
+    let first = fs::read(&encrypted).unwrap();
+
+    // Only the middle chunk changes, so the other two are copied as they are
+    let chunk = DEFAULT_CHUNK_SIZE as usize;
+    data[chunk + 10] ^= 0xff;
+    fs::write(&plain, &data).unwrap();
+    let stats = reencrypt_delta(&plain, &encrypted, KEY, Cipher::ChaCha20Poly1305).unwrap();
+    assert_eq!((stats.total, stats.reused), (3, 2));
+    verify_file(&encrypted, KEY).unwrap();
+    assert_eq!(header_info(&encrypted).unwrap().unwrap().revision, Some(1));
+
+    // The old middle chunk has the same header, index and length, so its AEAD tag still checks out in
+    // the new version; only the sealed map gives it away
+    let header_len = Header::read_after_magic(&mut &first[MAGIC.len()..]).unwrap().to_bytes().len();
+    let record = RECORD_OVERHEAD + chunk + TAG_LEN;
+    let at = header_len + record;
+    let mut second = fs::read(&encrypted).unwrap();
+    assert_ne!(second[at..at + record], first[at..at + record]);
+    second[at..at + record].copy_from_slice(&first[at..at + record]);
+    fs::write(&encrypted, &second).unwrap();
+
+    let e = verify_file(&encrypted, KEY).unwrap_err();
+    assert!(matches!(GuardXError::of(&e), Some(GuardXError::Authentication)), "{:#}", e);
+    let mut reader = DecryptingReader::open(&encrypted, KEY).unwrap();
+    reader.seek(SeekFrom::Start(chunk as u64)).unwrap();
+    assert!(reader.read(&mut [0u8; 16]).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// The DRBG is seeded once per process and every test in it draws from it, so each run is a process of its
+// own that runs only this test and prints a digest of the encrypted folder
+#[cfg(feature = "deterministic")]
+#[test]
+fn the_same_seed_encrypts_a_tree_to_the_same_bytes() {
+    const NAME: &str = "the_same_seed_encrypts_a_tree_to_the_same_bytes";
+    if std::env::var_os("GUARDX_TEST_SEED").is_some() {
+        let files = sample();
+        let (fs, index, dir) = vault("seeded", &borrowed(&files));
+        fs.encrypt_dir(index, KEY).unwrap();
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+        paths.sort();
+        let mut digest = blake3::Hasher::new();
+        for path in paths {
+            digest.update(path.file_name().unwrap().as_encoded_bytes());
+            digest.update(&fs::read(&path).unwrap());
+        }
+        println!("digest {}", digest.finalize().to_hex());
+        return;
+    }
+    let run = || {
+        let out = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", NAME, "--nocapture", "--test-threads=1"])
+            .env("GUARDX_TEST_SEED", "the same tree")
+            .env("GUARDX_TEST_TIME", "1700000000")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+        assert!(out.status.success(), "{}{}", stdout, String::from_utf8_lossy(&out.stderr));
+        // libtest may print the test's name on the same line first
+        stdout.lines().find_map(|line| line.split_once("digest ").map(|(_, digest)| digest.to_string())).expect("the run prints a digest")
+    };
+    assert_eq!(run(), run());
+}
@@ -0,0 +1,79 @@
+#ifndef GUARDX_H
+#define GUARDX_H
+
+/* Generated by cbindgen from ffi/src/lib.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define GUARDX_OK 0
+
+#define GUARDX_ERR_ARGUMENT 1
+
+#define GUARDX_ERR_FAILED 2
+
+#define GUARDX_ERR_MISMATCH 3
+
+#define GUARDX_ERR_PANIC 4
+
+/*
+ Bytes owned by GuardX; release with guardx_buffer_free.
+ */
+typedef struct GuardxBuffer {
+  uint8_t *data;
+  size_t len;
+} GuardxBuffer;
+
+/*
+ Encrypts the file at `path` in place with the preferred cipher for this CPU.
+
+ # Safety
+ `path` and `key` must be NUL-terminated strings.
+ */
+int32_t guardx_encrypt_file(const char *path, const char *key);
+
+/*
+ Decrypts the file at `path` in place.
+
+ # Safety
+ `path` and `key` must be NUL-terminated strings.
+ */
+int32_t guardx_decrypt_file(const char *path, const char *key);
+
+/*
+ Decrypts `len` bytes of an encrypted file's contents into `out`, which is only written on success.
+
+ # Safety
+ `data` must point to `len` readable bytes, `key` must be a NUL-terminated string and `out` must be writable.
+ */
+int32_t guardx_decrypt_stream(const uint8_t *data,
+                              size_t len,
+                              const char *key,
+                              struct GuardxBuffer *out);
+
+/*
+ Checks the vault at `dir` against its manifest. Returns GUARDX_ERR_MISMATCH when files are
+ missing, damaged, or extraneous; guardx_last_error() then lists them.
+
+ # Safety
+ `dir` must be a NUL-terminated string.
+ */
+int32_t guardx_verify(const char *dir);
+
+/*
+ Wipes and frees a buffer filled by GuardX; the buffer is left empty.
+
+ # Safety
+ `buf` must be null or point to a buffer GuardX filled that has not been freed yet.
+ */
+void guardx_buffer_free(struct GuardxBuffer *buf);
+
+/*
+ Message for the last failed call on this thread, or null. Valid until the next call.
+ */
+const char *guardx_last_error(void);
+
+#endif  /* GUARDX_H */
@@ -0,0 +1,145 @@
+// C ABI over the guardx core so other frontends read and write exactly the same format.
+// Every call returns a GUARDX_* status; on failure guardx_last_error() describes what went wrong.
+use guardx::crypto::{decrypt_file, decrypt_stream, encrypt_file, preferred_cipher};
+use guardx::manifest::Manifest;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use zeroize::Zeroize;
+
+pub const GUARDX_OK: i32 = 0;
+pub const GUARDX_ERR_ARGUMENT: i32 = 1;
+pub const GUARDX_ERR_FAILED: i32 = 2;
+pub const GUARDX_ERR_MISMATCH: i32 = 3;
+pub const GUARDX_ERR_PANIC: i32 = 4;
+
+/// Bytes owned by GuardX; release with guardx_buffer_free.
+#[repr(C)]
+pub struct GuardxBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+type Failure = (i32, String);
+
+fn guard<F: FnOnce() -> Result<(), Failure>>(f: F) -> i32 {
+    let (code, message) = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => {
+            LAST_ERROR.with(|e| *e.borrow_mut() = None);
+            return GUARDX_OK;
+        }
+        Ok(Err(failure)) => failure,
+        Err(_) => (GUARDX_ERR_PANIC, "GuardX panicked".to_string()),
+    };
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+    code
+}
+
+fn failed(e: anyhow::Error) -> Failure {
+    (GUARDX_ERR_FAILED, format!("{:#}", e))
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err((GUARDX_ERR_ARGUMENT, format!("{} is null", name)));
+    }
+    CStr::from_ptr(ptr).to_str().map_err(|_| (GUARDX_ERR_ARGUMENT, format!("{} is not valid UTF-8", name)))
+}
+
+/// Encrypts the file at `path` in place with the preferred cipher for this CPU.
+///
+/// # Safety
+/// `path` and `key` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn guardx_encrypt_file(path: *const c_char, key: *const c_char) -> i32 {
+    guard(|| {
+        let (path, key) = (str_arg(path, "path")?, str_arg(key, "key")?);
+        encrypt_file(Path::new(path), key, preferred_cipher()).map_err(failed)
+    })
+}
+
+/// Decrypts the file at `path` in place.
+///
+/// # Safety
+/// `path` and `key` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn guardx_decrypt_file(path: *const c_char, key: *const c_char) -> i32 {
+    guard(|| {
+        let (path, key) = (str_arg(path, "path")?, str_arg(key, "key")?);
+        decrypt_file(Path::new(path), key).map_err(failed)
+    })
+}
+
+/// Decrypts `len` bytes of an encrypted file's contents into `out`, which is only written on success.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, `key` must be a NUL-terminated string and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn guardx_decrypt_stream(data: *const u8, len: usize, key: *const c_char, out: *mut GuardxBuffer) -> i32 {
+    guard(|| {
+        if data.is_null() || out.is_null() {
+            return Err((GUARDX_ERR_ARGUMENT, "data and out must not be null".to_string()));
+        }
+        let key = str_arg(key, "key")?;
+        let mut input = std::slice::from_raw_parts(data, len);
+        let mut plain = Vec::new();
+        if let Err(e) = decrypt_stream(&mut input, &mut plain, key) {
+            plain.zeroize();
+            return Err(failed(e));
+        }
+        let len = plain.len();
+        *out = GuardxBuffer { data: Box::into_raw(plain.into_boxed_slice()) as *mut u8, len };
+        Ok(())
+    })
+}
+
+/// Checks the vault at `dir` against its manifest. Returns GUARDX_ERR_MISMATCH when files are
+/// missing, damaged, or extraneous; guardx_last_error() then lists them.
+///
+/// # Safety
+/// `dir` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn guardx_verify(dir: *const c_char) -> i32 {
+    guard(|| {
+        let dir = Path::new(str_arg(dir, "dir")?);
+        let manifest = Manifest::load(dir).map_err(failed)?;
+        if !manifest.is_supported() {
+            return Err((GUARDX_ERR_FAILED, format!("Unsupported manifest version {}", manifest.format_version)));
+        }
+        let report = manifest.validate(dir).map_err(failed)?;
+        if report.is_clean() {
+            return Ok(());
+        }
+        let mut problems = Vec::new();
+        problems.extend(report.missing.iter().map(|p| format!("missing: {}", p)));
+        problems.extend(report.damaged.iter().map(|p| format!("damaged: {}", p)));
+        problems.extend(report.extraneous.iter().map(|p| format!("extraneous: {}", p)));
+        Err((GUARDX_ERR_MISMATCH, problems.join("\n")))
+    })
+}
+
+/// Wipes and frees a buffer filled by GuardX; the buffer is left empty.
+///
+/// # Safety
+/// `buf` must be null or point to a buffer GuardX filled that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn guardx_buffer_free(buf: *mut GuardxBuffer) {
+    if buf.is_null() || (*buf).data.is_null() {
+        return;
+    }
+    let mut owned = Box::from_raw(std::ptr::slice_from_raw_parts_mut((*buf).data, (*buf).len));
+    owned.zeroize();
+    *buf = GuardxBuffer { data: std::ptr::null_mut(), len: 0 };
+}
+
+/// Message for the last failed call on this thread, or null. Valid until the next call.
+#[no_mangle]
+pub extern "C" fn guardx_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(std::ptr::null(), |m| m.as_ptr()))
+}
@@ -0,0 +1,82 @@
+use guardx_ffi::*;
+use std::ffi::{CStr, CString};
+use std::path::PathBuf;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("guardx-ffi-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn c(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+fn last_error() -> String {
+    let ptr = guardx_last_error();
+    assert!(!ptr.is_null());
+    unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+}
+
+#[test]
+fn encrypt_file_round_trips_through_decrypt_stream() {
+    let dir = scratch("stream");
+    let path = dir.join("notes.txt");
+    std::fs::write(&path, "meet at noon").unwrap();
+    let (cpath, key) = (c(path.to_str().unwrap()), c("correct horse"));
+
+    assert_eq!(unsafe { guardx_encrypt_file(cpath.as_ptr(), key.as_ptr()) }, GUARDX_OK);
+    let sealed = std::fs::read(&path).unwrap();
+    assert_ne!(sealed, b"meet at noon");
+
+    let mut out = GuardxBuffer { data: std::ptr::null_mut(), len: 0 };
+    assert_eq!(unsafe { guardx_decrypt_stream(sealed.as_ptr(), sealed.len(), key.as_ptr(), &mut out) }, GUARDX_OK);
+    assert_eq!(unsafe { std::slice::from_raw_parts(out.data, out.len) }, b"meet at noon");
+    unsafe { guardx_buffer_free(&mut out) };
+    assert!(out.data.is_null());
+
+    assert_eq!(unsafe { guardx_decrypt_file(cpath.as_ptr(), key.as_ptr()) }, GUARDX_OK);
+    assert_eq!(std::fs::read(&path).unwrap(), b"meet at noon");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wrong_key_fails_and_leaves_output_untouched() {
+    let dir = scratch("wrong-key");
+    let path = dir.join("a.bin");
+    std::fs::write(&path, [7u8; 4096]).unwrap();
+    let cpath = c(path.to_str().unwrap());
+    assert_eq!(unsafe { guardx_encrypt_file(cpath.as_ptr(), c("right").as_ptr()) }, GUARDX_OK);
+
+    let sealed = std::fs::read(&path).unwrap();
+    let mut out = GuardxBuffer { data: std::ptr::null_mut(), len: 0 };
+    assert_eq!(unsafe { guardx_decrypt_stream(sealed.as_ptr(), sealed.len(), c("wrong").as_ptr(), &mut out) }, GUARDX_ERR_FAILED);
+    assert!(out.data.is_null());
+    assert!(!last_error().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn verify_reports_damaged_and_extraneous_files() {
+    let dir = scratch("verify");
+    std::fs::write(dir.join("a.bin"), [0u8; 64]).unwrap();
+    guardx::manifest::Manifest::build(&dir).unwrap().save(&dir).unwrap();
+    let cdir = c(dir.to_str().unwrap());
+    assert_eq!(unsafe { guardx_verify(cdir.as_ptr()) }, GUARDX_OK);
+    assert!(guardx_last_error().is_null());
+
+    std::fs::write(dir.join("a.bin"), [0u8; 10]).unwrap();
+    std::fs::write(dir.join("stray.txt"), "x").unwrap();
+    assert_eq!(unsafe { guardx_verify(cdir.as_ptr()) }, GUARDX_ERR_MISMATCH);
+    assert_eq!(last_error(), "damaged: a.bin\nextraneous: stray.txt");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn null_arguments_are_rejected() {
+    assert_eq!(unsafe { guardx_encrypt_file(std::ptr::null(), c("k").as_ptr()) }, GUARDX_ERR_ARGUMENT);
+    assert_eq!(last_error(), "path is null");
+    assert_eq!(unsafe { guardx_decrypt_stream(std::ptr::null(), 0, c("k").as_ptr(), std::ptr::null_mut()) }, GUARDX_ERR_ARGUMENT);
+    unsafe { guardx_buffer_free(std::ptr::null_mut()) };
+}
@@ -0,0 +1,153 @@
+// Python bindings over the guardx core, so pipelines read and write exactly what the TUI does.
+// Build with `maturin develop` (or `maturin build`) from this directory.
+use guardx::age;
+use guardx::crypto::{decrypt_file, encrypt_file, preferred_cipher, Cipher, OutputFormat};
+use guardx::filesystem::{decrypt_folder, encrypt_folder_with};
+use guardx::manifest::Manifest;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use std::path::{Path, PathBuf};
+
+create_exception!(guardx, GuardXError, PyException);
+
+fn err(e: anyhow::Error) -> PyErr {
+    GuardXError::new_err(format!("{:#}", e))
+}
+
+fn cipher(name: Option<&str>) -> PyResult<Cipher> {
+    match name {
+        None => Ok(preferred_cipher()),
+        Some(name) => Cipher::from_name(name).ok_or_else(|| {
+            let known: Vec<_> = Cipher::ALL.iter().map(|c| c.name()).collect();
+            GuardXError::new_err(format!("Unknown cipher '{}', expected one of: {}", name, known.join(", ")))
+        }),
+    }
+}
+
+fn output_format(name: &str) -> PyResult<OutputFormat> {
+    OutputFormat::from_name(name).ok_or_else(|| {
+        let known: Vec<_> = OutputFormat::ALL.iter().map(|f| f.name()).collect();
+        GuardXError::new_err(format!("Unknown format '{}', expected one of: {}", name, known.join(", ")))
+    })
+}
+
+/// Encrypts a file in place. `cipher` is "aes-256-gcm" or "chacha20-poly1305"; the default depends on the CPU.
+/// `format="age"` writes an age v1 file that `age -d` opens with the key as passphrase (the cipher is then ignored).
+#[pyfunction]
+#[pyo3(signature = (path, key, cipher=None, format="guardx"))]
+fn encrypt(py: Python<'_>, path: PathBuf, key: &str, cipher: Option<&str>, format: &str) -> PyResult<()> {
+    let cipher = self::cipher(cipher)?;
+    match output_format(format)? {
+        OutputFormat::Age => py.allow_threads(|| age::encrypt_file(&path, key)).map_err(err),
+        OutputFormat::GuardX => py.allow_threads(|| encrypt_file(&path, key, cipher)).map_err(err),
+    }
+}
+
+/// Decrypts a file in place.
+#[pyfunction]
+fn decrypt(py: Python<'_>, path: PathBuf, key: &str) -> PyResult<()> {
+    py.allow_threads(|| decrypt_file(&path, key)).map_err(err)
+}
+
+/// Encrypts every file directly inside `path`, writes the vault manifest, and returns the vault.
+/// With `encrypt_names=True` files are stored under random names; decrypting restores the real ones.
+/// With `checksums=True` the plaintext hashes are kept (encrypted) and `Vault.decrypt` checks them.
+/// With `format="age"` every file is written as an age v1 file.
+#[pyfunction]
+#[pyo3(name = "encrypt_folder", signature = (path, key, cipher=None, encrypt_names=false, checksums=false, format="guardx"))]
+fn create_vault(py: Python<'_>, path: PathBuf, key: &str, cipher: Option<&str>, encrypt_names: bool, checksums: bool, format: &str) -> PyResult<Vault> {
+    let cipher = self::cipher(cipher)?;
+    let format = output_format(format)?;
+    py.allow_threads(|| encrypt_folder_with(&path, key, cipher, format, encrypt_names, checksums, &mut |_, _, _| {})).map_err(err)?;
+    Vault::open(path)
+}
+
+/// Result of `Vault.verify()`; truthy when the folder matches its manifest.
+#[pyclass(module = "guardx", frozen)]
+struct VerifyReport {
+    #[pyo3(get)]
+    missing: Vec<String>,
+    #[pyo3(get)]
+    damaged: Vec<String>,
+    #[pyo3(get)]
+    extraneous: Vec<String>,
+}
+
+#[pymethods]
+impl VerifyReport {
+    #[getter]
+    fn clean(&self) -> bool {
+        self.missing.is_empty() && self.damaged.is_empty() && self.extraneous.is_empty()
+    }
+
+    fn __bool__(&self) -> bool {
+        self.clean()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("VerifyReport(missing={:?}, damaged={:?}, extraneous={:?})", self.missing, self.damaged, self.extraneous)
+    }
+}
+
+/// An encrypted folder with a GuardX manifest.
+#[pyclass(module = "guardx", frozen)]
+struct Vault {
+    path: PathBuf,
+    manifest: Manifest,
+}
+
+#[pymethods]
+impl Vault {
+    #[staticmethod]
+    fn open(path: PathBuf) -> PyResult<Self> {
+        let manifest = Manifest::load(&path).map_err(err)?;
+        if !manifest.is_supported() {
+            return Err(GuardXError::new_err(format!("Unsupported manifest version {}", manifest.format_version)));
+        }
+        Ok(Vault { path, manifest })
+    }
+
+    #[getter]
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[getter]
+    fn format_version(&self) -> u32 {
+        self.manifest.format_version
+    }
+
+    /// (name, encrypted size) for every file the manifest lists.
+    #[getter]
+    fn files(&self) -> Vec<(String, u64)> {
+        self.manifest.files.iter().map(|e| (e.path.clone(), e.size)).collect()
+    }
+
+    /// Checks the folder against the manifest without reading file contents or needing the key.
+    fn verify(&self) -> PyResult<VerifyReport> {
+        let report = self.manifest.validate(&self.path).map_err(err)?;
+        Ok(VerifyReport { missing: report.missing, damaged: report.damaged, extraneous: report.extraneous })
+    }
+
+    /// Decrypts every file in the vault and removes the manifest; the folder is no longer a vault afterwards.
+    fn decrypt(&self, py: Python<'_>, key: &str) -> PyResult<()> {
+        py.allow_threads(|| decrypt_folder(&self.path, key)).map_err(err)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Vault({:?}, {} files)", self.path, self.manifest.files.len())
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "guardx")]
+fn guardx_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(encrypt, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt, m)?)?;
+    m.add_function(wrap_pyfunction!(create_vault, m)?)?;
+    m.add_class::<Vault>()?;
+    m.add_class::<VerifyReport>()?;
+    m.add("GuardXError", m.py().get_type::<GuardXError>())?;
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use crate::config::Lifecycle;
+use crate::crypto::{self, chunk_map};
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+// Checks that a backup copy of an encrypted folder (on a mounted drive or network share, or made by
+// `mirror`) still matches the folder, without restoring it. Chunked files are compared by the keyed
+// chunk hashes at their end, so a matching file costs a few bytes per chunk to read and needs no key.
+// Other files are compared byte by byte. With a key, `deep` also decrypts every matching backup file
+// to nowhere, which proves its ciphertext is intact and not just its chunk map.
+pub enum Drift {
+    // In the folder but not in the backup
+    Missing,
+    // Only in the backup, e.g. deleted from the folder since
+    Extra,
+    // Some chunks differ (`Some((differing, total))`), or the whole file when it has no chunk map
+    Changed(Option<(usize, usize)>),
+    // The chunk maps match but the backup copy does not decrypt
+    Unreadable(String),
+}
+
+impl Drift {
+    pub fn describe(&self) -> String {
+        match self {
+            Drift::Missing => "missing from the backup".to_string(),
+            Drift::Extra => "only in the backup".to_string(),
+            Drift::Changed(Some((differing, total))) => format!("{} of {} chunks differ", differing, total),
+            Drift::Changed(None) => "differs".to_string(),
+            Drift::Unreadable(e) => format!("backup copy does not decrypt: {}", e),
+        }
+    }
+}
+
+// How closely a backup has to match. An active folder changes all the time, so a file only in the backup is
+// left over from before and only noted. Archived and retired folders are kept cold: nothing in them is meant
+// to change, so their backup has to be an exact copy and a file only in the backup is drift as well.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Policy {
+    #[default]
+    Hot,
+    Cold,
+}
+
+impl Policy {
+    pub fn of(lifecycle: Lifecycle) -> Policy {
+        if lifecycle.is_active() { Policy::Hot } else { Policy::Cold }
+    }
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub policy: Policy,
+    pub matching: Vec<String>,
+    pub drift: Vec<(String, Drift)>,
+}
+
+impl Report {
+    // Whether `drift` fails the check under this report's policy
+    pub fn fails(&self, drift: &Drift) -> bool {
+        self.policy == Policy::Cold || !matches!(drift, Drift::Extra)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.drift.iter().filter(|(_, drift)| self.fails(drift)).count()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+pub fn compare(vault: &Path, backup: &Path, deep: Option<&str>, policy: Policy) -> Result<Report> {
+    if !backup.is_dir() {
+        return Err(anyhow::anyhow!("Backup folder {:?} cannot be read; is the drive mounted?", backup));
+    }
+    let local = names(vault)?;
+    let mut report = Report { policy, ..Default::default() };
+    for name in &local {
+        let (ours, theirs) = (vault.join(name), backup.join(name));
+        let drift = if !theirs.is_file() {
+            Some(Drift::Missing)
+        } else {
+            compare_file(&ours, &theirs).with_context(|| format!("Could not compare {}", name))?
+        };
+        let drift = match (drift, deep) {
+            (None, Some(key)) => crypto::verify_file(&theirs, key).err().map(|e| Drift::Unreadable(format!("{:#}", e))),
+            (drift, _) => drift,
+        };
+        match drift {
+            Some(drift) => report.drift.push((name.clone(), drift)),
+            None => report.matching.push(name.clone()),
+        }
+    }
+    for name in names(backup)? {
+        if !local.contains(&name) {
+            report.drift.push((name, Drift::Extra));
+        }
+    }
+    Ok(report)
+}
+
+// Every file directly in `dir`, manifest and checksums included, since a restore needs them too
+fn names(dir: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read directory: {:?}", dir))? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_none_or(|ext| ext != "gxtmp") {
+            names.push(path.file_name().context("Invalid file name")?.to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn compare_file(ours: &Path, theirs: &Path) -> Result<Option<Drift>> {
+    if let (Some((our_id, our_map)), Some((their_id, their_map))) = (chunk_map(ours)?, chunk_map(theirs)?) {
+        let total = our_map.len().max(their_map.len());
+        // Chunk hashes are keyed per file, so a file encrypted afresh since the backup differs in every chunk
+        let differing = if our_id != their_id {
+            total
+        } else {
+            total - our_map.iter().zip(&their_map).filter(|(a, b)| a == b).count()
+        };
+        let same_size = fs::metadata(ours)?.len() == fs::metadata(theirs)?.len();
+        return Ok((differing > 0 || !same_size).then_some(Drift::Changed(Some((differing, total)))));
+    }
+    if fs::metadata(ours)?.len() != fs::metadata(theirs)?.len() || hash(ours)? != hash(theirs)? {
+        return Ok(Some(Drift::Changed(None)));
+    }
+    Ok(None)
+}
+
+fn hash(path: &Path) -> Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize())
+}
@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+// Daily unlock without typing the key. On macOS the key is kept in the Keychain with an access control
+// that requires Touch ID (or the login password) to read it. On Windows it is encrypted with a key derived
+// from a signature by a Windows Hello credential, which the TPM only makes after Windows Hello confirms
+// the user. `file()` marks that a key is stored; on Windows it also holds the encrypted key. Other
+// platforms have neither.
+pub fn file() -> Result<PathBuf> {
+    Ok(dirs::config_dir().context("Could not find config directory")?.join("guardx").join("platform-unlock"))
+}
+
+pub fn name() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "Touch ID"
+    } else if cfg!(windows) {
+        "Windows Hello"
+    } else {
+        "Touch ID or Windows Hello"
+    }
+}
+
+pub fn is_stored() -> bool {
+    file().is_ok_and(|path| path.is_file())
+}
+
+// Replaces any stored key; asks for Touch ID or Windows Hello on the way on Windows
+pub fn store(key: &str) -> Result<()> {
+    if let Some(parent) = file()?.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    imp::store(key)
+}
+
+// Blocks while the system asks the user to confirm
+pub fn release() -> Result<Zeroizing<String>> {
+    if !is_stored() {
+        return Err(anyhow::anyhow!("No key is stored for {}", name()));
+    }
+    let key = imp::release()?;
+    std::str::from_utf8(&key).map(|key| Zeroizing::new(key.to_string())).ok().context("The stored key is not text")
+}
+
+pub fn forget() -> Result<()> {
+    imp::forget()?;
+    match fs::remove_file(file()?) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use super::file;
+    use anyhow::{Context, Result};
+    use security_framework::passwords::{delete_generic_password_options, generic_password, set_generic_password_options, AccessControlOptions, PasswordOptions};
+    use std::fs;
+    use zeroize::Zeroizing;
+
+    const SERVICE: &str = "GuardX";
+    const ACCOUNT: &str = "encryption key";
+    // errSecItemNotFound
+    const NOT_FOUND: i32 = -25300;
+
+    fn query() -> PasswordOptions {
+        PasswordOptions::new_generic_password(SERVICE, ACCOUNT)
+    }
+
+    pub fn store(key: &str) -> Result<()> {
+        forget()?;
+        let mut options = query();
+        options.set_access_control_options(AccessControlOptions::USER_PRESENCE);
+        set_generic_password_options(key.as_bytes(), options).context("Could not store the key in the Keychain")?;
+        fs::write(file()?, "keychain\n")?;
+        Ok(())
+    }
+
+    pub fn release() -> Result<Zeroizing<Vec<u8>>> {
+        generic_password(query()).map(Zeroizing::new).context("Touch ID did not release the key")
+    }
+
+    pub fn forget() -> Result<()> {
+        match delete_generic_password_options(query()) {
+            Err(e) if e.code() != NOT_FOUND => Err(e).context("Could not remove the key from the Keychain"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::file;
+    use crate::entropy;
+    use crate::error::GuardXError;
+    use anyhow::{Context, Result};
+    use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+    use std::fs;
+    use windows::core::{Array, HSTRING};
+    use windows::Security::Credentials::{KeyCredential, KeyCredentialCreationOption, KeyCredentialManager, KeyCredentialStatus};
+    use windows::Security::Cryptography::CryptographicBuffer;
+    use zeroize::Zeroizing;
+
+    const CREDENTIAL: &str = "GuardX";
+    // Signed to derive the wrapping key; the credential's RSA signatures are deterministic
+    const CHALLENGE: &[u8] = b"guardx windows hello challenge v1";
+    const KEY_CONTEXT: &str = "guardx windows hello key v1";
+
+    fn wrapping_key(credential: &KeyCredential) -> Result<LessSafeKey> {
+        let challenge = CryptographicBuffer::CreateFromByteArray(CHALLENGE)?;
+        let signed = credential.RequestSignAsync(&challenge)?.get()?;
+        if signed.Status()? != KeyCredentialStatus::Success {
+            return Err(anyhow::anyhow!("Windows Hello did not confirm"));
+        }
+        let mut signature = Array::<u8>::new();
+        CryptographicBuffer::CopyToByteArray(&signed.Result()?, &mut signature)?;
+        let key = Zeroizing::new(blake3::derive_key(KEY_CONTEXT, &signature));
+        Ok(LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key[..]).map_err(|_| anyhow::anyhow!("Invalid wrapping key"))?))
+    }
+
+    pub fn store(key: &str) -> Result<()> {
+        if !KeyCredentialManager::IsSupportedAsync()?.get()? {
+            return Err(anyhow::anyhow!("Windows Hello is not set up on this machine"));
+        }
+        let created = KeyCredentialManager::RequestCreateAsync(&HSTRING::from(CREDENTIAL), KeyCredentialCreationOption::ReplaceExisting)?.get()?;
+        if created.Status()? != KeyCredentialStatus::Success {
+            return Err(anyhow::anyhow!("Windows Hello did not create a credential"));
+        }
+        let wrap = wrapping_key(&created.Credential()?)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        entropy::fill(&mut nonce)?;
+        let mut sealed = Zeroizing::new(key.as_bytes().to_vec());
+        wrap.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut *sealed)
+            .map_err(|_| anyhow::anyhow!("Could not encrypt the key"))?;
+        let mut contents = nonce.to_vec();
+        contents.extend_from_slice(&sealed);
+        fs::write(file()?, contents)?;
+        Ok(())
+    }
+
+    pub fn release() -> Result<Zeroizing<Vec<u8>>> {
+        let opened = KeyCredentialManager::OpenAsync(&HSTRING::from(CREDENTIAL))?.get()?;
+        if opened.Status()? != KeyCredentialStatus::Success {
+            return Err(anyhow::anyhow!("The Windows Hello credential is gone; store the key again"));
+        }
+        let wrap = wrapping_key(&opened.Credential()?)?;
+        let path = file()?;
+        let contents = fs::read(&path)?;
+        if contents.len() < NONCE_LEN {
+            return Err(GuardXError::Format(format!("Malformed stored key: {:?}", path)).into());
+        }
+        let (nonce, sealed) = contents.split_at(NONCE_LEN);
+        let mut key = Zeroizing::new(sealed.to_vec());
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| GuardXError::Format("Malformed nonce".to_string()))?;
+        let len = wrap.open_in_place(nonce, Aad::empty(), &mut key).map_err(|_| GuardXError::Authentication)
+            .context("The stored key does not open")?.len();
+        key.truncate(len);
+        Ok(key)
+    }
+
+    pub fn forget() -> Result<()> {
+        // Nothing to delete is fine
+        let _ = KeyCredentialManager::DeleteAsync(&HSTRING::from(CREDENTIAL))?.get();
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod imp {
+    use anyhow::Result;
+    use zeroize::Zeroizing;
+
+    fn unsupported() -> anyhow::Error {
+        anyhow::anyhow!("Unlocking with Touch ID or Windows Hello is only available on macOS and Windows")
+    }
+
+    pub fn store(_key: &str) -> Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn release() -> Result<Zeroizing<Vec<u8>>> {
+        Err(unsupported())
+    }
+
+    pub fn forget() -> Result<()> {
+        Ok(())
+    }
+}
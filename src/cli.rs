@@ -0,0 +1,1755 @@
+use guardx::age;
+use guardx::backup;
+use guardx::config::{Config, Lifecycle};
+use guardx::crypto::{self, aes_accelerated, encrypt_to, original_name, pipeline_depth, preferred_cipher, reencrypt_delta, set_pipeline_depth, temp_path, verify_file, Cancelled, Cipher, OutputFormat};
+use guardx::entropy;
+use guardx::format;
+use guardx::filesystem::{decrypt_folder, encrypt_folder_to, encrypt_folder_with, files_in, io_uring_available, plan_encrypt, rekey_folder_with, remove_path, set_io_uring, Plan};
+use guardx::error::GuardXError;
+use guardx::hardware::{self, Token};
+use guardx::kdf;
+use guardx::keyring;
+use guardx::lockall;
+use guardx::manifest::{Manifest, Validation, MANIFEST_NAME};
+use guardx::oplog::{self, clean_note, Export, LogEntry};
+use guardx::redteam;
+use guardx::remote;
+use guardx::schedule::encrypt_scheduled;
+use guardx::registry::{Registry, VaultEntry};
+use guardx::signing::{self, Verdict};
+use guardx::watch::Guard;
+use guardx::webdav;
+use anyhow::{Context, Result};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use serde_json::json;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use std::io::{BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+use crate::doctor::Outcome;
+use crate::ui::{human_size, rough_duration};
+
+#[derive(Parser)]
+#[command(name = "guardx", version, about = "Manage and encrypt folders from the terminal")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Answer yes to every confirmation
+    #[arg(long, short = 'y', global = true)]
+    pub yes: bool,
+    /// Never read from the terminal; fail on any prompt that has no answer
+    #[arg(long, global = true)]
+    pub no_input: bool,
+    /// TOML file of prompt answers, e.g. `key = "..."` or `continue_on_error = true`
+    #[arg(long, global = true, value_name = "FILE")]
+    pub answers: Option<PathBuf>,
+    /// Folder whose subfolders the TUI lists instead of the home directory's; repeat for several. Remembered in config.toml
+    #[arg(long = "root", value_name = "DIR")]
+    pub roots: Vec<PathBuf>,
+    /// Profile from config.toml to use instead of `default_profile`
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// File holding the key on its own, e.g. for backup scripts; readable only by you
+    #[arg(long, global = true, value_name = "FILE")]
+    pub key_file: Option<PathBuf>,
+    /// Stop at the first file that fails instead of asking whether to continue
+    #[arg(long, global = true)]
+    pub fail_fast: bool,
+    /// Give up on the command after this long, e.g. `90`, `30s`, `5m` or `2h`
+    #[arg(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    pub timeout: Option<Duration>,
+    /// Note kept with the operation in the history log, e.g. "before sending the laptop for repair"
+    #[arg(long, global = true, value_name = "TEXT")]
+    pub note: Option<String>,
+    /// Print one JSON object per line instead of text, ending with a "result" line that has the exit code
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+// Exit codes are a contract with scripts: never renumber them, only add new ones
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_PARTIAL: i32 = 1;
+pub const EXIT_WRONG_KEY: i32 = 2;
+pub const EXIT_FORMAT: i32 = 3;
+pub const EXIT_FAILED: i32 = 4;
+pub const EXIT_USAGE: i32 = 5;
+pub const EXIT_TIMEOUT: i32 = 6;
+
+#[derive(Debug)]
+struct Partial {
+    failed: usize,
+    total: usize,
+}
+
+impl std::fmt::Display for Partial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} files failed", self.failed, self.total)
+    }
+}
+
+impl std::error::Error for Partial {}
+
+#[derive(Debug)]
+struct Unanswered(String);
+
+impl std::fmt::Display for Unanswered {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Unanswered {}
+
+#[derive(Debug)]
+struct TimedOut {
+    limit: Duration,
+    incomplete: usize,
+}
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timed out after {}s with {} files not finished", self.limit.as_secs(), self.incomplete)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+// With --json, every line a command prints is a JSON object instead, so scripts need not parse messages
+// meant for people: "file" lines for what happened to each file, "message" lines for the rest, and lines
+// of their own for listings. The last line is the "result". Prompts and progress still go to stderr as text.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+fn json_output() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+// A line of output: `text`, or `value` with --json
+fn emit(text: impl std::fmt::Display, value: serde_json::Value) {
+    if json_output() {
+        println!("{}", value);
+    } else {
+        println!("{}", text);
+    }
+}
+
+// Summaries and advice; with --json the level comes from the mark the text starts with
+fn say(text: impl Into<String>) {
+    let text = text.into();
+    let (level, message) = [("[OK] ", "ok"), ("[X] ", "error"), ("[!] ", "warning")].iter()
+        .find_map(|(mark, level)| Some((*level, text.strip_prefix(mark)?)))
+        .unwrap_or(("info", text.as_str()));
+    emit(&text, json!({ "type": "message", "level": level, "text": message }));
+}
+
+#[derive(Clone, Copy)]
+enum Status {
+    Ok,
+    Failed,
+    Warning,
+}
+
+// "[OK] name" or "[X] name: why"; `took` is how long the file itself took, where that was measured
+fn file_status(status: Status, file: &str, detail: Option<&str>, took: Option<Duration>) {
+    let (mark, name) = match status {
+        Status::Ok => ("[OK]", "ok"),
+        Status::Failed => ("[X]", "failed"),
+        Status::Warning => ("[!]", "warning"),
+    };
+    let text = match detail {
+        Some(detail) => format!("{} {}: {}", mark, file, detail),
+        None => format!("{} {}", mark, file),
+    };
+    emit(text, json!({ "type": "file", "file": file, "status": name, "detail": detail, "secs": took.map(|t| t.as_secs_f64()) }));
+}
+
+// Files a manifest check found missing, damaged or extraneous
+fn print_validation(report: &Validation, extraneous: bool) {
+    for file in &report.missing {
+        emit(format!("[X] missing: {}", file), json!({ "type": "file", "file": file, "status": "missing" }));
+    }
+    for file in &report.damaged {
+        emit(format!("[X] damaged: {}", file), json!({ "type": "file", "file": file, "status": "damaged" }));
+    }
+    if extraneous {
+        for file in &report.extraneous {
+            emit(format!("[!] extraneous: {}", file), json!({ "type": "file", "file": file, "status": "extraneous" }));
+        }
+    }
+}
+
+// --root: saved as `roots` in config.toml, so later launches list the same folders
+pub fn remember_roots(roots: &[PathBuf]) -> Result<()> {
+    let roots = roots.iter()
+        .map(|root| root.canonicalize().ok().filter(|r| r.is_dir()).with_context(|| format!("{:?} is not a folder", root)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut config = Config::load()?;
+    config.roots = roots;
+    config.save()
+}
+
+pub fn exit_code(e: &anyhow::Error) -> i32 {
+    if e.downcast_ref::<Partial>().is_some() {
+        return EXIT_PARTIAL;
+    }
+    if e.downcast_ref::<Unanswered>().is_some() {
+        return EXIT_USAGE;
+    }
+    if e.downcast_ref::<TimedOut>().is_some() {
+        return EXIT_TIMEOUT;
+    }
+    match GuardXError::of(e) {
+        Some(GuardXError::Authentication) => EXIT_WRONG_KEY,
+        Some(GuardXError::Format(_)) => EXIT_FORMAT,
+        Some(_) | None => EXIT_FAILED,
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Validate an extracted encrypted tree and register it as a vault
+    Import {
+        path: PathBuf,
+        /// Name shown for the vault (defaults to the folder name)
+        #[arg(long)]
+        name: Option<String>,
+        /// Register even when files are missing or damaged
+        #[arg(long)]
+        force: bool,
+    },
+    /// Keep an encrypted copy of a folder, re-encrypting only the chunks that changed
+    Mirror {
+        source: PathBuf,
+        dest: PathBuf,
+    },
+    /// Check that every file of an encrypted folder (or a single encrypted file) decrypts, without writing anything
+    Verify {
+        path: PathBuf,
+        /// Compare each file with the hash in the folder's manifest instead of decrypting it
+        #[arg(long)]
+        quick: bool,
+    },
+    /// Check that a backup copy of an encrypted folder still matches it, by chunk hashes and without restoring anything
+    VerifyBackup {
+        path: PathBuf,
+        /// Defaults to the folder's `backup` in config.toml
+        backup: Option<PathBuf>,
+        /// Also decrypt every matching backup file (to nowhere) to prove its ciphertext is intact; needs the key
+        #[arg(long)]
+        deep: bool,
+    },
+    /// Create an X25519 identity for recipient mode (or an Ed25519 signing key) and print its public key
+    Keygen {
+        /// Name to refer to the identity by, e.g. in `--to` or a folder's `recipients`
+        name: String,
+        /// Create a key for `sign` instead of an identity
+        #[arg(long)]
+        signing: bool,
+    },
+    /// List this machine's identities and signing keys and their public keys
+    Keys,
+    /// Sign every file of an encrypted folder (or a single encrypted file) with an Ed25519 signing key
+    Sign {
+        path: PathBuf,
+        /// Signing key to use; may be left out when there is only one
+        #[arg(long)]
+        key: Option<String>,
+    },
+    /// Check the signatures of an encrypted folder or file; no key is needed
+    VerifySignature {
+        path: PathBuf,
+        /// Hex public key to trust besides this machine's own signing keys; repeat for several
+        #[arg(long = "signer", value_name = "PUBLIC_KEY")]
+        signers: Vec<String>,
+    },
+    /// Encrypt a folder (or a single file) to public keys; no key or identity is needed
+    Encrypt {
+        #[arg(required_unless_present = "stdin")]
+        path: Option<PathBuf>,
+        /// Public key (age1...) or identity name; repeat for several. Defaults to the folder's recipients in config.toml
+        #[arg(long = "to", value_name = "RECIPIENT")]
+        to: Vec<String>,
+        /// List the files that would be encrypted or skipped, with their total size and an estimated time, and change nothing
+        #[arg(long)]
+        dry_run: bool,
+        /// Encrypt what is piped in and write it to stdout: to the --to recipients, or else with the key
+        #[arg(long, conflicts_with_all = ["path", "dry_run"])]
+        stdin: bool,
+    },
+    /// Decrypt what is piped in and write the plaintext to stdout; folders are decrypted in the TUI
+    Decrypt {
+        #[arg(long, required = true)]
+        stdin: bool,
+    },
+    /// Measure both ciphers, key stretching as configured, the read/encrypt/write pipeline, whole-folder encryption and io_uring batching
+    Bench {
+        /// Size of the synthetic test file in MiB
+        #[arg(long, default_value_t = 256)]
+        size_mb: usize,
+        /// Number of files in the synthetic folder, from 4 KiB to 1 MiB each; the io_uring test uses ten times as many tiny ones
+        #[arg(long, default_value_t = 500)]
+        files: usize,
+    },
+    /// Mark a folder active, archived (hidden from the folder list) or retired (read-only, then deleted)
+    Lifecycle {
+        path: PathBuf,
+        #[arg(value_parser = ["active", "archived", "retired"])]
+        state: String,
+    },
+    /// Permanently delete retired folders whose grace period is over
+    PurgeRetired,
+    /// Set the decoy folder that a duress key opens when this folder is archived into a container
+    Decoy {
+        path: PathBuf,
+        #[arg(required_unless_present = "clear")]
+        decoy: Option<PathBuf>,
+        /// Archive the folder without a decoy again
+        #[arg(long, conflicts_with = "decoy")]
+        clear: bool,
+    },
+    /// List the operations recorded in the history log, with their notes
+    History {
+        /// Show only the most recent N entries
+        #[arg(long, value_name = "N")]
+        last: Option<usize>,
+    },
+    /// Lock every running GuardX at once: keys are purged, working sessions closed and previews dropped
+    LockAll,
+    /// Bind the key to a YubiKey (HMAC-SHA1 challenge-response); without options, show the enrolled slot and connected YubiKeys
+    HardwareKey {
+        /// Enroll this challenge-response slot (1 or 2) of the connected YubiKey
+        #[arg(long, conflicts_with = "remove")]
+        slot: Option<u8>,
+        /// Re-encrypt an encrypted folder from the key alone to the key bound to the YubiKey
+        #[arg(long, value_name = "FOLDER", conflicts_with_all = ["unbind", "remove"])]
+        bind: Option<PathBuf>,
+        /// Re-encrypt a folder back to the key alone, e.g. before removing the YubiKey
+        #[arg(long, value_name = "FOLDER", conflicts_with = "remove")]
+        unbind: Option<PathBuf>,
+        /// Stop using the YubiKey; unbind its folders first or they no longer open
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Export history log entries as a password-protected (age) file, signed with a signing key
+    ExportHistory {
+        dest: PathBuf,
+        /// First day to include (YYYY-MM-DD, local time)
+        #[arg(long)]
+        since: Option<String>,
+        /// Last day to include (YYYY-MM-DD, local time)
+        #[arg(long)]
+        until: Option<String>,
+        /// Only operations on this folder or anything inside it
+        #[arg(long)]
+        vault: Option<PathBuf>,
+        /// Signing key to sign the export with; may be left out when there is only one
+        #[arg(long = "signing-key", value_name = "NAME")]
+        signing_key: Option<String>,
+    },
+    /// Check the signature of an exported history log, then decrypt it and list its entries
+    VerifyExport {
+        path: PathBuf,
+        /// Hex public key of the exporter, if it is not one of this machine's signing keys; repeat for several
+        #[arg(long = "signer", value_name = "PUBLIC_KEY")]
+        signers: Vec<String>,
+    },
+    /// Work with the hash-chained history log of audit mode (`audit = true` in config.toml)
+    Audit {
+        #[command(subcommand)]
+        action: AuditAction,
+    },
+    /// Show an encrypted folder's files decrypted, read-only, at MOUNTPOINT until it is unmounted (Linux and macOS)
+    Mount {
+        folder: PathBuf,
+        mountpoint: PathBuf,
+    },
+    /// Encrypt plaintext files as they are dropped into an encrypted folder, until interrupted
+    Watch {
+        dir: PathBuf,
+    },
+    /// Encrypt the folders that have a `schedule` in config.toml whenever it comes due, until interrupted
+    Daemon,
+    /// Rehearse a wrong key, damaged files and an interrupted run on copies of a few files in a throwaway sandbox
+    RedTeam {
+        folder: PathBuf,
+        /// How many files to copy into the sandbox, picked at random
+        #[arg(long, default_value_t = 3)]
+        sample: usize,
+    },
+    /// Send an encrypted folder's changed files to remote storage; only ciphertext ever leaves the machine
+    Push {
+        path: PathBuf,
+        /// Where to keep the copy, e.g. s3://bucket/prefix; defaults to the folder's `remote` in config.toml
+        url: Option<String>,
+    },
+    /// Fetch a folder's changed files back from remote storage into PATH
+    Pull {
+        path: PathBuf,
+        /// Where the copy is kept; defaults to the folder's `remote` in config.toml
+        url: Option<String>,
+    },
+    /// Check the terminal, config.toml, the keyring, write access to known folders, the random number generator and cipher speed
+    Doctor,
+    /// Print the completion script for a shell, e.g. `guardx completions bash > ~/.local/share/bash-completion/completions/guardx`
+    Completions {
+        shell: Shell,
+    },
+    /// Drive GuardX from another program with line-delimited JSON on stdin/stdout
+    Serve {
+        /// Instead, share this encrypted folder's files decrypted, read-only, over WebDAV on localhost until interrupted
+        #[arg(long, value_name = "FOLDER")]
+        webdav: Option<PathBuf>,
+        /// Port for --webdav; a free one is picked by default
+        #[arg(long, default_value_t = 0, requires = "webdav")]
+        port: u16,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditAction {
+    /// Check that no entry of the history log was changed, removed or cut off since it was written
+    Verify {
+        /// Head hash printed by an earlier verify and kept elsewhere; the chain must still contain it
+        #[arg(long, value_name = "HASH")]
+        head: Option<String>,
+    },
+}
+
+impl Command {
+    // Commands that read data from stdin and write it to stdout, so nothing else may go to either
+    fn pipes(&self) -> bool {
+        matches!(self, Command::Encrypt { stdin: true, .. } | Command::Decrypt { .. })
+    }
+
+    // Operations that change or check a vault go into the history log; listing and benchmarking do not
+    fn logged(&self) -> Option<(String, &Path)> {
+        match self {
+            Command::Import { path, .. } => Some(("Imported vault".to_string(), path)),
+            Command::Mirror { dest, .. } => Some(("Mirrored folder".to_string(), dest)),
+            Command::Verify { path, .. } => Some(("Verified".to_string(), path)),
+            Command::VerifyBackup { path, .. } => Some(("Verified backup".to_string(), path)),
+            Command::Encrypt { path: Some(path), dry_run: false, stdin: false, .. } => Some(("Encrypted to recipients".to_string(), path)),
+            Command::Sign { path, .. } => Some(("Signed".to_string(), path)),
+            Command::VerifySignature { path, .. } => Some(("Checked signatures".to_string(), path)),
+            Command::RedTeam { folder, .. } => Some(("Ran failure drills".to_string(), folder)),
+            Command::Push { path, .. } => Some(("Pushed to remote".to_string(), path)),
+            Command::Pull { path, .. } => Some(("Pulled from remote".to_string(), path)),
+            Command::Lifecycle { path, state } => Some((format!("Marked folder {}", state), path)),
+            Command::HardwareKey { bind: Some(path), .. } => Some(("Bound to YubiKey".to_string(), path)),
+            Command::HardwareKey { unbind: Some(path), .. } => Some(("Unbound from YubiKey".to_string(), path)),
+            _ => None,
+        }
+    }
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    if cli.json && cli.command.as_ref().is_some_and(Command::pipes) {
+        return Err(Unanswered("--json does not apply to --stdin, which writes the data itself to stdout".to_string()).into());
+    }
+    JSON.store(cli.json, Ordering::Relaxed);
+    let started = Instant::now();
+    let result = run_command(cli);
+    if json_output() {
+        let error = result.as_ref().err();
+        println!("{}", json!({
+            "type": "result",
+            "ok": error.is_none(),
+            "exit_code": error.map_or(EXIT_OK, exit_code),
+            "error": error.map(|e| format!("{:#}", e)),
+            "secs": started.elapsed().as_secs_f64(),
+        }));
+    }
+    result
+}
+
+fn run_command(cli: Cli) -> Result<()> {
+    let prompts = Prompts::new(&cli)?;
+    let Some(command) = cli.command else { return Ok(()) };
+    // Reports a malformed config.toml rather than stopping at it
+    if matches!(command, Command::Doctor) {
+        return doctor();
+    }
+    // Applies the key stretching settings before any file is opened
+    Config::load()?;
+    let logged = command.logged().map(|(operation, path)| (operation, path.to_path_buf()));
+    let note = cli.note.as_deref().and_then(clean_note);
+    if note.is_some() && logged.is_none() {
+        return Err(Unanswered("--note only applies to import, mirror, verify, verify-backup, encrypt, sign, verify-signature, red-team, push, pull, lifecycle and hardware-key --bind/--unbind".to_string()).into());
+    }
+    let result = match cli.timeout {
+        Some(_) if matches!(command, Command::Serve { .. }) => Err(anyhow::anyhow!("--timeout does not apply to serve")),
+        Some(limit) => run_with_timeout(prompts, command, limit),
+        None => dispatch(&prompts, command),
+    };
+    for warning in kdf::take_warnings() {
+        eprintln!("[!] {}", warning);
+    }
+    if let Some((operation, path)) = logged {
+        let entry = LogEntry::new(operation, Some(&path), result.is_ok(), note.as_deref());
+        if let Err(e) = oplog::append(&entry) {
+            eprintln!("[!] Could not write the history log: {:#}", e);
+        }
+    }
+    result
+}
+
+fn dispatch(prompts: &Prompts, command: Command) -> Result<()> {
+    match command {
+        Command::Import { path, name, force } => import(prompts, path, name, force),
+        Command::Mirror { source, dest } => mirror(prompts, source, dest),
+        Command::Verify { path, quick: true } => verify_quick(prompts, path),
+        Command::Verify { path, quick: false } => verify(prompts, path),
+        Command::VerifyBackup { path, backup, deep } => verify_backup(prompts, path, backup, deep),
+        Command::Keygen { name, signing: false } => keygen(name),
+        Command::Keygen { name, signing: true } => keygen_signing(name),
+        Command::Keys => keys(),
+        Command::Encrypt { path: Some(path), to, dry_run, stdin: false } => encrypt_to_recipients(path, to, dry_run),
+        Command::Encrypt { to, .. } => encrypt_stdin(prompts, to),
+        Command::Decrypt { .. } => decrypt_stdin(prompts),
+        Command::Sign { path, key } => sign(path, key),
+        Command::VerifySignature { path, signers } => verify_signature(path, signers),
+        Command::Bench { size_mb, files } => bench(size_mb, files),
+        Command::Lifecycle { path, state } => lifecycle(path, &state),
+        Command::Decoy { path, decoy, .. } => set_decoy(path, decoy),
+        Command::PurgeRetired => purge_retired(prompts),
+        Command::History { last } => history(last),
+        Command::ExportHistory { dest, since, until, vault, signing_key } => export_history(prompts, dest, since, until, vault, signing_key),
+        Command::VerifyExport { path, signers } => verify_export(prompts, path, signers),
+        Command::Audit { action: AuditAction::Verify { head } } => audit_verify(head),
+        Command::LockAll => lock_all(),
+        Command::HardwareKey { slot, bind, unbind, remove } => hardware_key(prompts, slot, bind, unbind, remove),
+        Command::Mount { folder, mountpoint } => mount(prompts, folder, mountpoint),
+        Command::Watch { dir } => watch(prompts, dir),
+        Command::RedTeam { folder, sample } => red_team(prompts, folder, sample),
+        Command::Daemon => daemon(prompts),
+        Command::Push { path, url } => push(path, url),
+        Command::Pull { path, url } => pull(path, url),
+        Command::Doctor => doctor(),
+        Command::Completions { shell } => completions(shell),
+        Command::Serve { webdav: Some(folder), port } => serve_webdav(prompts, folder, port),
+        Command::Serve { webdav: None, .. } => crate::protocol::serve(),
+    }
+}
+
+// Files a command still has to write, in order; the first one is the one in progress
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn set_pending(files: Vec<PathBuf>) {
+    *PENDING.lock().unwrap_or_else(|e| e.into_inner()) = files;
+}
+
+fn finish_pending(file: &Path) {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).retain(|f| f != file);
+}
+
+// Seconds a cancelled command gets to reach the next chunk boundary before it is abandoned
+const CANCEL_GRACE: Duration = Duration::from_secs(5);
+
+// The command runs on a worker so that a read stuck on a dead mount cannot keep the process past its
+// deadline. At the deadline the chunk loops are cancelled, which leaves each file either fully written
+// or as it was; a worker still blocked after CANCEL_GRACE is abandoned and its temporary file removed.
+fn run_with_timeout(prompts: Prompts, command: Command, limit: Duration) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(dispatch(&prompts, command));
+    });
+    match rx.recv_timeout(limit) {
+        Ok(result) => return result,
+        Err(RecvTimeoutError::Disconnected) => return Err(anyhow::anyhow!("The command stopped unexpectedly")),
+        Err(RecvTimeoutError::Timeout) => {}
+    }
+    crypto::cancel();
+    match rx.recv_timeout(CANCEL_GRACE) {
+        Ok(Ok(())) => return Ok(()),
+        Ok(Err(e)) if !Cancelled::of(&e) => return Err(e),
+        // The worker may have been waiting at a key prompt
+        _ => {
+            let _ = disable_raw_mode();
+        }
+    }
+    let pending = PENDING.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(current) = pending.first() {
+        let _ = std::fs::remove_file(temp_path(current));
+    }
+    for file in &pending {
+        emit(format!("[!] not finished: {}", file.display()), json!({ "type": "file", "file": file, "status": "unfinished" }));
+    }
+    Err(TimedOut { limit, incomplete: pending.len() }.into())
+}
+
+// Every question the CLI asks goes through here, so each one can be answered from an answers file,
+// --yes, or the terminal, in that order. Prompt names are part of the CLI contract; see the README.
+pub struct Prompts {
+    yes: bool,
+    fail_fast: bool,
+    // Whether yes/no questions can be read from stdin
+    interactive: bool,
+    // Whether keys can be asked for on the terminal, which works with stdin redirected as well
+    terminal: bool,
+    answers: toml::Table,
+    key_file: Option<PathBuf>,
+}
+
+// Environment variable scripts can pass the key in, unless `forbid_env_key` is set in config.toml
+const KEY_ENV: &str = "GUARDX_KEY";
+
+impl Prompts {
+    fn new(cli: &Cli) -> Result<Self> {
+        let answers = match &cli.answers {
+            Some(path) => {
+                let data = Zeroizing::new(std::fs::read_to_string(path).with_context(|| format!("Could not read answers file {:?}", path))?);
+                toml::from_str(&data).with_context(|| format!("Malformed answers file: {:?}", path))?
+            }
+            None => toml::Table::new(),
+        };
+        // Piped data takes up stdin, so nothing can be asked there then
+        let interactive = !cli.no_input && std::io::stdin().is_terminal() && !cli.command.as_ref().is_some_and(Command::pipes);
+        Ok(Prompts {
+            yes: cli.yes,
+            fail_fast: cli.fail_fast,
+            interactive,
+            terminal: !cli.no_input && has_terminal(),
+            answers,
+            key_file: cli.key_file.clone(),
+        })
+    }
+
+    fn unanswered(&self, name: &str) -> anyhow::Error {
+        let why = if self.interactive && self.terminal { "" } else { " and there is no terminal to ask (--no-input, or stdin is not a terminal)" };
+        let other = if name == "key" { format!(", pass --key-file or set {}", KEY_ENV) } else { String::new() };
+        Unanswered(format!("Prompt '{}' has no answer{}; add `{} = ...` to an --answers file{}", name, why, name, other)).into()
+    }
+
+    // From the answers file, then --key-file, then GUARDX_KEY, and only then the terminal
+    fn key(&self, name: &str, prompt: &str) -> Result<Zeroizing<String>> {
+        let key = match self.answers.get(name) {
+            Some(toml::Value::String(key)) => Zeroizing::new(key.clone()),
+            Some(_) => return Err(anyhow::anyhow!("Answer '{}' must be a string", name)),
+            None => match self.unattended_key(name)? {
+                Some(key) => key,
+                None if self.terminal => prompt_key(prompt)?,
+                None => return Err(self.unanswered(name)),
+            },
+        };
+        if key.is_empty() {
+            return Err(anyhow::anyhow!("Enter a key first"));
+        }
+        Ok(key)
+    }
+
+    // Only the vault key comes from --key-file or the environment; other passwords are asked for
+    fn unattended_key(&self, name: &str) -> Result<Option<Zeroizing<String>>> {
+        if name != "key" {
+            return Ok(None);
+        }
+        if let Some(path) = &self.key_file {
+            return read_key_file(path).map(Some);
+        }
+        let Some(value) = std::env::var_os(KEY_ENV) else { return Ok(None) };
+        if Config::load()?.forbid_env_key {
+            return Err(Unanswered(format!("{} is set, but config.toml forbids keys from the environment (forbid_env_key); use --key-file", KEY_ENV)).into());
+        }
+        let key = Zeroizing::new(value.into_string().map_err(|_| anyhow::anyhow!("{} is not valid UTF-8", KEY_ENV))?);
+        eprintln!("[!] Using the key from {}; other programs running as you may be able to read it, so prefer --key-file", KEY_ENV);
+        Ok(Some(key))
+    }
+
+    // The key files are encrypted with: bound to the YubiKey when one is enrolled
+    fn encryption_key(&self) -> Result<Zeroizing<String>> {
+        let key = self.key("key", "[Key] Enter encryption key: ")?;
+        if Token::load()?.is_none() {
+            return Ok(key);
+        }
+        eprintln!("[Key] Touch your YubiKey if it blinks...");
+        hardware::bind(&key)
+    }
+
+    fn confirm(&self, name: &str, question: &str) -> Result<bool> {
+        match self.answers.get(name) {
+            Some(toml::Value::Boolean(answer)) => return Ok(*answer),
+            Some(toml::Value::String(answer)) => return parse_yes_no(answer).with_context(|| format!("Answer '{}' must be yes or no", name)),
+            Some(_) => return Err(anyhow::anyhow!("Answer '{}' must be yes or no", name)),
+            None => {}
+        }
+        if self.yes {
+            return Ok(true);
+        }
+        if !self.interactive {
+            return Err(self.unanswered(name));
+        }
+        loop {
+            eprint!("{} [y/n] ", question);
+            std::io::stderr().flush()?;
+            let mut line = String::new();
+            if std::io::stdin().read_line(&mut line)? == 0 {
+                return Err(self.unanswered(name));
+            }
+            if let Some(answer) = parse_yes_no(line.trim()) {
+                return Ok(answer);
+            }
+        }
+    }
+}
+
+// The whole file but a trailing newline, which editors and `echo` add
+fn read_key_file(path: &Path) -> Result<Zeroizing<String>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path).with_context(|| format!("Could not open key file {:?}", path))?.permissions().mode();
+        if mode & 0o077 != 0 {
+            eprintln!("[!] Key file {:?} can be read by other users; restrict it with `chmod 600`", path);
+        }
+    }
+    let data = Zeroizing::new(std::fs::read_to_string(path).with_context(|| format!("Could not read key file {:?}", path))?);
+    let key = Zeroizing::new(data.trim_end_matches(['\n', '\r']).to_string());
+    if key.is_empty() {
+        return Err(anyhow::anyhow!("Key file {:?} is empty", path));
+    }
+    Ok(key)
+}
+
+// Keys are read from the controlling terminal, so a prompt works even with stdin redirected, as in
+// `tar c dir | guardx encrypt --stdin`; cron jobs and services have none
+fn has_terminal() -> bool {
+    #[cfg(unix)]
+    {
+        std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty").is_ok()
+    }
+    #[cfg(not(unix))]
+    {
+        std::io::stderr().is_terminal()
+    }
+}
+
+// Plain numbers are seconds
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len()));
+    let number: u64 = number.parse().map_err(|_| format!("'{}' is not a duration like 90, 30s, 5m or 2h", value))?;
+    let seconds = match unit {
+        "" | "s" => number,
+        "m" => number.saturating_mul(60),
+        "h" => number.saturating_mul(3600),
+        _ => return Err(format!("Unknown unit '{}'; use s, m or h", unit)),
+    };
+    if seconds == 0 {
+        return Err("The timeout must be at least one second".to_string());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+fn parse_yes_no(answer: &str) -> Option<bool> {
+    match answer.to_ascii_lowercase().as_str() {
+        "y" | "yes" | "true" => Some(true),
+        "n" | "no" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+// Reads a key from the terminal without echoing it
+fn prompt_key(prompt: &str) -> Result<Zeroizing<String>> {
+    eprint!("{}", prompt);
+    std::io::stderr().flush()?;
+    enable_raw_mode()?;
+    let mut key = Zeroizing::new(String::with_capacity(256));
+    let result = loop {
+        match event::read() {
+            Ok(Event::Key(k)) if k.kind == KeyEventKind::Press => match k.code {
+                KeyCode::Enter => break Ok(()),
+                KeyCode::Esc => break Err(anyhow::anyhow!("Cancelled")),
+                KeyCode::Char('c') if k.modifiers.contains(KeyModifiers::CONTROL) => break Err(anyhow::anyhow!("Cancelled")),
+                KeyCode::Char(c) => key.push(c),
+                KeyCode::Backspace => {
+                    key.pop();
+                }
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e.into()),
+        }
+    };
+    disable_raw_mode()?;
+    eprintln!();
+    result.map(|_| key)
+}
+
+fn mirror(prompts: &Prompts, source: PathBuf, dest: PathBuf) -> Result<()> {
+    if !source.is_dir() {
+        return Err(anyhow::anyhow!("{:?} is not a directory", source));
+    }
+    let config = Config::load()?;
+    config.check_writable(&dest)?;
+    let key = prompts.encryption_key()?;
+    std::fs::create_dir_all(&dest)?;
+    let cipher = config.cipher(&dest);
+
+    let files = files_in(&source)?;
+    let targets = files.iter().map(|path| Ok(dest.join(path.file_name().context("Invalid file name")?))).collect::<Result<Vec<_>>>()?;
+    set_pending(targets.clone());
+    let (mut attempted, mut first_error) = (0, None);
+    let (mut failed, mut cancelled) = (0, None);
+    for (path, target) in files.iter().zip(&targets) {
+        attempted += 1;
+        let label = target.file_name().unwrap_or_default().to_string_lossy();
+        let started = Instant::now();
+        let result = if target.is_file() {
+            reencrypt_delta(path, target, &key, cipher).map(|stats| format!("{} of {} chunks re-encrypted", stats.total - stats.reused, stats.total))
+        } else {
+            encrypt_to(path, target, &key, cipher).map(|()| "encrypted".to_string())
+        };
+        if let Err(e) = result.as_ref() {
+            if Cancelled::of(e) {
+                cancelled = result.err();
+                break;
+            }
+        }
+        finish_pending(target);
+        if let Ok(detail) = &result {
+            file_status(Status::Ok, &label, Some(detail), Some(started.elapsed()));
+        }
+        if let Err(e) = result {
+            file_status(Status::Failed, &label, Some(&format!("{:#}", e)), Some(started.elapsed()));
+            failed += 1;
+            let stop = prompts.fail_fast || !prompts.confirm("continue_on_error", "Continue with the remaining files?")?;
+            first_error.get_or_insert(e);
+            if stop {
+                break;
+            }
+        }
+    }
+    // Files that were not reached keep what they had, so the manifest still matches the folder
+    Manifest::build_hashed(&dest, &key)?.save(&dest)?;
+    if let Some(e) = cancelled {
+        return Err(e);
+    }
+    match first_error {
+        // Nothing went through, so the cause itself decides the exit code
+        Some(e) if failed == attempted => Err(e),
+        Some(_) => Err(Partial { failed, total: files.len() }.into()),
+        None => Ok(()),
+    }
+}
+
+fn verify(prompts: &Prompts, path: PathBuf) -> Result<()> {
+    let files = if path.is_dir() {
+        if !path.join(MANIFEST_NAME).is_file() {
+            return Err(anyhow::anyhow!("{:?} is not an encrypted folder", path));
+        }
+        files_in(&path)?
+    } else {
+        vec![path]
+    };
+    let key = prompts.encryption_key()?;
+
+    let (mut attempted, mut failed, mut first_error) = (0, 0, None);
+    for path in &files {
+        attempted += 1;
+        let label = original_name(path, &key).ok().flatten()
+            .unwrap_or_else(|| path.file_name().unwrap_or_default().to_string_lossy().to_string());
+        let started = Instant::now();
+        match verify_file(path, &key) {
+            Ok(()) => file_status(Status::Ok, &label, None, Some(started.elapsed())),
+            Err(e) if Cancelled::of(&e) => return Err(e),
+            Err(e) => {
+                file_status(Status::Failed, &label, Some(&format!("{:#}", e)), Some(started.elapsed()));
+                failed += 1;
+                first_error.get_or_insert(e);
+                if prompts.fail_fast {
+                    break;
+                }
+            }
+        }
+    }
+    say(format!("Checked {} of {} files, {} damaged", attempted, files.len(), failed));
+    match first_error {
+        Some(e) if failed == attempted => Err(e),
+        Some(_) => Err(Partial { failed, total: files.len() }.into()),
+        None => Ok(()),
+    }
+}
+
+// Hashes instead of decrypting; a clean SHA-256 manifest is upgraded to keyed BLAKE3 on the way
+fn verify_quick(prompts: &Prompts, path: PathBuf) -> Result<()> {
+    if !path.join(MANIFEST_NAME).is_file() {
+        return Err(anyhow::anyhow!("{:?} is not an encrypted folder", path));
+    }
+    let mut manifest = Manifest::load(&path)?;
+    let key = prompts.encryption_key()?;
+    let report = manifest.verify_hashes(&path, &key)?;
+    print_validation(&report, true);
+    let failed = report.missing.len() + report.damaged.len();
+    say(format!("Checked {} files, {} damaged or missing", manifest.files.len(), failed));
+    if failed > 0 {
+        return Err(Partial { failed, total: manifest.files.len() }.into());
+    }
+    if manifest.upgrade_hashes(&path, &key)? {
+        manifest.save(&path)?;
+        say("[OK] Manifest hashes upgraded from SHA-256 to keyed BLAKE3");
+    }
+    Ok(())
+}
+
+fn verify_backup(prompts: &Prompts, path: PathBuf, backup: Option<PathBuf>, deep: bool) -> Result<()> {
+    if !path.join(MANIFEST_NAME).is_file() {
+        return Err(anyhow::anyhow!("{:?} is not an encrypted folder", path));
+    }
+    let config = Config::load()?;
+    let backup = match backup {
+        Some(backup) => backup,
+        None => config.backup(&path).map(Path::to_path_buf)
+            .with_context(|| format!("No backup given and none set for {:?} in config.toml", path))?,
+    };
+    let key = if deep { Some(prompts.encryption_key()?) } else { None };
+    let report = backup::compare(&path, &backup, key.as_deref().map(|k| k.as_str()), backup::Policy::of(config.lifecycle(&path)))?;
+    for (name, drift) in &report.drift {
+        let status = if report.fails(drift) { Status::Failed } else { Status::Warning };
+        file_status(status, name, Some(&drift.describe()), None);
+    }
+    let failed = report.failed();
+    let total = report.matching.len() + failed;
+    say(format!("{} of {} files match the backup{}", report.matching.len(), total, if deep { " and decrypt" } else { "" }));
+    if failed > 0 {
+        return Err(Partial { failed, total }.into());
+    }
+    Ok(())
+}
+
+fn keygen(name: String) -> Result<()> {
+    let (path, identity) = keyring::generate(&name)?;
+    say(format!("[OK] Created identity '{}' in {:?}; keep it secret and back it up", name, path));
+    emit(format!("Public key: {}", identity.recipient()), json!({ "type": "identity", "name": name, "public_key": identity.recipient().to_string() }));
+    Ok(())
+}
+
+fn keygen_signing(name: String) -> Result<()> {
+    let (path, key) = signing::generate(&name)?;
+    say(format!("[OK] Created signing key '{}' in {:?}; keep it secret and back it up", name, path));
+    emit(format!("Public key: {}", key.public_key()), json!({ "type": "signing_key", "name": name, "public_key": key.public_key().to_string() }));
+    Ok(())
+}
+
+fn keys() -> Result<()> {
+    let identities = keyring::list()?;
+    if identities.is_empty() {
+        say(format!("No identities in {:?}; create one with `guardx keygen <name>`", keyring::dir()?));
+    }
+    for (name, identity) in identities {
+        emit(format!("{}  {}", name, identity.recipient()), json!({ "type": "identity", "name": name, "public_key": identity.recipient().to_string() }));
+    }
+    for (name, key) in signing::list()? {
+        emit(format!("{}  {} (signing)", name, key.public_key()), json!({ "type": "signing_key", "name": name, "public_key": key.public_key().to_string() }));
+    }
+    Ok(())
+}
+
+fn sign(path: PathBuf, key: Option<String>) -> Result<()> {
+    let key = signing::load(key.as_deref())?;
+    if !path.is_dir() {
+        signing::sign_file(&path, &key)?;
+        file_status(Status::Ok, &path.display().to_string(), Some("signed"), None);
+        return Ok(());
+    }
+    if !path.join(MANIFEST_NAME).is_file() {
+        return Err(anyhow::anyhow!("{:?} is not an encrypted folder; sign it after encrypting", path));
+    }
+    let files = files_in(&path)?;
+    signing::sign_folder(&path, &files, &key)?;
+    say(format!("[OK] Signed {} files with {}", files.len(), key.public_key()));
+    Ok(())
+}
+
+// Anything short of a valid signature from a trusted key counts as a failure
+fn verify_signature(path: PathBuf, signers: Vec<String>) -> Result<()> {
+    let mut trusted = signing::own_public_keys()?;
+    trusted.extend(signers);
+    let verdicts = if path.is_dir() {
+        if !path.join(MANIFEST_NAME).is_file() {
+            return Err(anyhow::anyhow!("{:?} is not an encrypted folder", path));
+        }
+        signing::verify_folder(&path, &files_in(&path)?, &trusted)?
+    } else {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        vec![(name, signing::verify_file(&path, &trusted)?)]
+    };
+    let mut failed = 0;
+    for (name, verdict) in &verdicts {
+        match verdict {
+            Verdict::Valid { signer } => file_status(Status::Ok, name, Some(&format!("signed by {}", signer)), None),
+            Verdict::UnknownSigner { signer } => file_status(Status::Failed, name, Some(&format!("signed by an untrusted key {}", signer)), None),
+            Verdict::Invalid => file_status(Status::Failed, name, Some("signature does not match; the file changed or was swapped"), None),
+            Verdict::Unsigned => file_status(Status::Failed, name, Some("not signed"), None),
+        }
+        if !matches!(verdict, Verdict::Valid { .. }) {
+            failed += 1;
+        }
+    }
+    say(format!("Checked {} signatures, {} failed", verdicts.len(), failed));
+    match failed {
+        0 => Ok(()),
+        // Nothing checked out, which is what a wholesale swap looks like
+        n if n == verdicts.len() => Err(anyhow::Error::new(GuardXError::Authentication).context("No file carries a valid signature from a trusted key")),
+        _ => Err(Partial { failed, total: verdicts.len() }.into()),
+    }
+}
+
+fn encrypt_to_recipients(path: PathBuf, to: Vec<String>, dry_run: bool) -> Result<()> {
+    let config = Config::load()?;
+    config.check_writable(&path)?;
+    let recipients = if to.is_empty() {
+        config.recipients(&path)?
+    } else {
+        to.iter().map(|r| keyring::resolve(r)).collect::<Result<Vec<_>>>()?
+    };
+    if recipients.is_empty() {
+        return Err(Unanswered(format!("No recipients for {:?}; pass --to or set `recipients` for the folder in config.toml", path)).into());
+    }
+    if !path.is_dir() && dry_run {
+        let size = std::fs::metadata(&path).with_context(|| format!("Could not open {:?}", path))?.len();
+        say(format!("Would encrypt {} ({}) to {} recipients", path.display(), human_size(size), recipients.len()));
+        say("Nothing was changed");
+        return Ok(());
+    }
+    if !path.is_dir() {
+        set_pending(vec![path.clone()]);
+        let started = Instant::now();
+        age::encrypt_file_to(&path, &recipients)?;
+        finish_pending(&path);
+        file_status(Status::Ok, &path.display().to_string(), Some(&format!("encrypted to {} recipients", recipients.len())), Some(started.elapsed()));
+        return Ok(());
+    }
+    if path.join(MANIFEST_NAME).is_file() {
+        return Err(anyhow::anyhow!("{:?} is already encrypted", path));
+    }
+    if dry_run {
+        // Files keep their names and get no checksums when encrypted to recipients
+        print_plan(&plan_encrypt(&path, false, false)?, &format!("to {} recipients", recipients.len()));
+        return Ok(());
+    }
+    let files = files_in(&path)?;
+    set_pending(files.clone());
+    // Each file is reported when the next one starts
+    let mut started = Instant::now();
+    encrypt_folder_to(&path, &recipients, &mut |i, _, _| {
+        if i > 0 {
+            finish_pending(&files[i - 1]);
+            file_status(Status::Ok, &files[i - 1].file_name().unwrap_or_default().to_string_lossy(), None, Some(started.elapsed()));
+            started = Instant::now();
+        }
+    })?;
+    if let Some(last) = files.last() {
+        finish_pending(last);
+        file_status(Status::Ok, &last.file_name().unwrap_or_default().to_string_lossy(), None, Some(started.elapsed()));
+    }
+    say(format!("Encrypted {} files to {} recipients", files.len(), recipients.len()));
+    Ok(())
+}
+
+// `tar c dir | guardx encrypt --stdin > dir.enc`: the data is encrypted as it streams through and never
+// touches the disk. To recipients it becomes an age file; otherwise a chunked GuardX file with the key.
+fn encrypt_stdin(prompts: &Prompts, to: Vec<String>) -> Result<()> {
+    if std::io::stdout().is_terminal() {
+        return Err(Unanswered("Not writing encrypted data to the terminal; redirect stdout to a file or another command".to_string()).into());
+    }
+    let mut output = BufWriter::new(std::io::stdout().lock());
+    if to.is_empty() {
+        let key = prompts.encryption_key()?;
+        crypto::encrypt_stream(&mut std::io::stdin(), &mut output, &key, Config::load()?.default_cipher())?;
+    } else {
+        let recipients = to.iter().map(|r| keyring::resolve(r)).collect::<Result<Vec<_>>>()?;
+        age::encrypt_stream_to(&mut std::io::stdin(), &mut output, &recipients)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+// Plaintext goes out chunk by chunk as each one authenticates, so on failure whatever came before is
+// already written; only the exit code says whether the whole stream was intact
+fn decrypt_stdin(prompts: &Prompts) -> Result<()> {
+    let mut input = std::io::stdin().lock();
+    // Enough of the header to tell GuardX from age, and age to recipients from age with a passphrase
+    let mut start = vec![0u8; age::MAGIC.len() + AGE_RECIPIENT.len()];
+    let len = format::read_full(&mut input, &mut start)?;
+    start.truncate(len);
+    let is_age = start.starts_with(age::MAGIC);
+    let key = if is_age && start[age::MAGIC.len()..].starts_with(AGE_RECIPIENT) {
+        keyring::all_as_key()?.context("The data is encrypted to public keys and there are no identities on this machine")?
+    } else {
+        prompts.encryption_key()?
+    };
+    let mut reader = std::io::Cursor::new(start).chain(input);
+    let mut output = BufWriter::new(std::io::stdout().lock());
+    if is_age {
+        age::decrypt_stream(&mut reader, &mut output, &key)?;
+    } else {
+        crypto::decrypt_stream(&mut reader, &mut output, &key)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+// How the first stanza of an age header to public keys starts
+const AGE_RECIPIENT: &[u8] = b"-> X25519 ";
+
+fn print_plan(plan: &Plan, how: &str) {
+    if json_output() {
+        let name = |path: &PathBuf| path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        println!("{}", json!({
+            "type": "plan",
+            "files": plan.process.iter().map(|(path, size)| json!({ "file": name(path), "size": size })).collect::<Vec<_>>(),
+            "total_size": plan.total_size(),
+            "estimated_secs": plan.estimate().as_secs_f64(),
+            "renames": plan.renames,
+            "checksums": plan.checksums,
+            "already_encrypted": plan.already_encrypted.iter().map(name).collect::<Vec<_>>(),
+            "skipped": plan.skipped.iter().map(|(path, reason)| json!({ "file": name(path), "reason": reason })).collect::<Vec<_>>(),
+        }));
+        return;
+    }
+    println!("Would encrypt {} files ({}) {}, in {}", plan.process.len(), human_size(plan.total_size()), how, rough_duration(plan.estimate()));
+    for (path, size) in &plan.process {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if plan.renames {
+            println!("  {} ({}) -> random name", name, human_size(*size));
+        } else {
+            println!("  {} ({})", name, human_size(*size));
+        }
+    }
+    for path in &plan.already_encrypted {
+        println!("[!] {} is already encrypted and would be encrypted again", path.file_name().unwrap_or_default().to_string_lossy());
+    }
+    if !plan.skipped.is_empty() {
+        println!("Would skip {}:", plan.skipped.len());
+        for (path, reason) in &plan.skipped {
+            println!("  {}: {}", path.file_name().unwrap_or_default().to_string_lossy(), reason);
+        }
+    }
+    println!("Nothing was changed");
+}
+
+fn lifecycle(path: PathBuf, state: &str) -> Result<()> {
+    let path = path.canonicalize().with_context(|| format!("Could not open {:?}", path))?;
+    let state = Lifecycle::from_name(state).with_context(|| format!("Unknown state '{}'", state))?;
+    let mut config = Config::load()?;
+    config.set_lifecycle(&path, state, entropy::timestamp());
+    config.save()?;
+    say(format!("[OK] {} is now {}", path.display(), state.name()));
+    Ok(())
+}
+
+fn lock_all() -> Result<()> {
+    lockall::request()?;
+    say("[OK] Every running GuardX will lock within a second");
+    Ok(())
+}
+
+fn hardware_key(prompts: &Prompts, slot: Option<u8>, bind: Option<PathBuf>, unbind: Option<PathBuf>, remove: bool) -> Result<()> {
+    if remove {
+        hardware::remove()?;
+        say("[OK] The YubiKey is no longer used; folders still bound to it open again once it is enrolled");
+        return Ok(());
+    }
+    if let Some(slot) = slot {
+        let token = Token::new(slot)?;
+        eprintln!("[Key] Touch your YubiKey if it blinks...");
+        token.respond(b"guardx enrollment check").context("The YubiKey could not be enrolled")?;
+        token.save()?;
+        say(format!("[OK] Keys are now bound to slot {} of your YubiKey", slot));
+        say("Folders encrypted before still need the key alone; move them over with --bind <folder>");
+    }
+    let Some(folder) = bind.as_ref().or(unbind.as_ref()) else {
+        if slot.is_none() {
+            match Token::load()? {
+                Some(token) => say(format!("Keys are bound to slot {} of a YubiKey", token.slot)),
+                None => say("No YubiKey enrolled; keys are used as typed"),
+            }
+            match hardware::detect() {
+                Ok(found) if found.is_empty() => say("No YubiKey connected"),
+                Ok(found) => found.iter().for_each(|device| emit(format!("Connected: {}", device), json!({ "type": "yubikey", "device": device.to_string() }))),
+                Err(e) => say(format!("[!] {:#}", e)),
+            }
+        }
+        return Ok(());
+    };
+    let token = Token::load()?.context("No YubiKey enrolled; enroll one with --slot")?;
+    let config = Config::load()?;
+    config.check_writable(folder)?;
+    let key = prompts.key("key", "[Key] Enter encryption key: ")?;
+    eprintln!("[Key] Touch your YubiKey if it blinks...");
+    let bound = token.bind(&key)?;
+    let (old, new) = if bind.is_some() { (&key, &bound) } else { (&bound, &key) };
+    let cipher = config.cipher(folder);
+    rekey_folder_with(folder, old, new, cipher, &mut |_, _, _| {})?;
+    match bind {
+        Some(_) => say(format!("[OK] {} now needs the YubiKey", folder.display())),
+        None => say(format!("[OK] {} opens with the key alone again", folder.display())),
+    }
+    Ok(())
+}
+
+// Runs until interrupted (or --timeout); each file goes into the history log as it is encrypted
+fn watch(prompts: &Prompts, dir: PathBuf) -> Result<()> {
+    let config = Config::load()?;
+    run_in_background(&config)?;
+    let key = if config.recipients(&dir)?.is_empty() { prompts.encryption_key()? } else { Zeroizing::new(String::new()) };
+    let mut guard = Guard::new(&dir, &key, &config)?;
+    say(format!("[OK] Watching {}; files dropped into it are encrypted once they settle (Ctrl+C stops)", dir.display()));
+    loop {
+        for activity in guard.poll(Duration::from_millis(500))? {
+            let name = activity.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let (entry, ok) = match &activity.result {
+                Ok(()) => {
+                    file_status(Status::Ok, &name, None, None);
+                    (format!("Watch encrypted {}", name), true)
+                }
+                Err(e) => {
+                    file_status(Status::Failed, &name, Some(&format!("{:#}", e)), None);
+                    (format!("Watch failed on {}: {:#}", name, e), false)
+                }
+            };
+            if let Err(e) = oplog::append(&LogEntry::new(entry, Some(&dir), ok, None)) {
+                eprintln!("[!] Could not write the history log: {:#}", e);
+            }
+        }
+    }
+}
+
+// The key is asked for once, and only when a scheduled folder has no recipients. Every run is printed and
+// goes into the history log, where the TUI's history shows it.
+fn daemon(prompts: &Prompts) -> Result<()> {
+    let config = Config::load()?;
+    run_in_background(&config)?;
+    let schedules = config.schedules()?;
+    if schedules.is_empty() {
+        return Err(anyhow::anyhow!("No folder has a schedule in config.toml"));
+    }
+    let mut needs_key = false;
+    for (dir, _) in &schedules {
+        needs_key |= config.recipients(dir)?.is_empty();
+    }
+    let key = if needs_key { prompts.encryption_key()? } else { Zeroizing::new(String::new()) };
+    let mut due: Vec<Option<i64>> = schedules.iter().map(|(_, schedule)| schedule.next_after(entropy::timestamp())).collect();
+    for ((dir, _), next) in schedules.iter().zip(&due) {
+        match next {
+            Some(time) => say(format!("[OK] {}: next run at {}", dir.display(), local_time(*time))),
+            None => say(format!("[!] {}: the schedule never comes due", dir.display())),
+        }
+    }
+    loop {
+        let now = entropy::timestamp();
+        for (i, (dir, schedule)) in schedules.iter().enumerate() {
+            if due[i].is_none_or(|time| time > now) {
+                continue;
+            }
+            let (entry, ok) = match encrypt_scheduled(dir, &key, &config) {
+                Ok(count) => (format!("Scheduled encryption of {} files", count), true),
+                Err(e) => (format!("Scheduled encryption failed: {:#}", e), false),
+            };
+            file_status(if ok { Status::Ok } else { Status::Failed }, &dir.display().to_string(), Some(&entry), None);
+            if let Err(e) = oplog::append(&LogEntry::new(entry, Some(dir), ok, None)) {
+                eprintln!("[!] Could not write the history log: {:#}", e);
+            }
+            due[i] = schedule.next_after(now);
+        }
+        // Wakes up often enough to notice a changed clock, such as after a suspend
+        let next = due.iter().flatten().min().map_or(60, |time| time - entropy::timestamp());
+        thread::sleep(Duration::from_secs(next.clamp(1, 60) as u64));
+    }
+}
+
+// `background_limit` and `background_nice`, said once so a slow run is not a mystery
+fn run_in_background(config: &Config) -> Result<()> {
+    config.apply_background()?;
+    let rate = config.background_rate()?;
+    if rate > 0 {
+        say(format!("[OK] Reading at most {}/s to encrypt (background_limit)", human_size(rate)));
+    }
+    if let Some(nice) = config.background_nice {
+        say(format!("[OK] Running at niceness {} (background_nice)", nice));
+    }
+    Ok(())
+}
+
+// The folder is only read; every drill runs on a copy in a sandbox under the temp directory
+fn red_team(prompts: &Prompts, folder: PathBuf, sample: usize) -> Result<()> {
+    let key = prompts.encryption_key()?;
+    let drills = redteam::run(&folder, &key, sample)?;
+    for drill in &drills {
+        emit(
+            format!("{} {} on {}: {}", if drill.passed { "[OK]" } else { "[X]" }, drill.scenario.label(), drill.file, drill.detail),
+            json!({ "type": "drill", "scenario": drill.scenario.label(), "file": drill.file, "passed": drill.passed, "detail": drill.detail }),
+        );
+    }
+    let failed = drills.iter().filter(|d| !d.passed).count();
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} of {} drills did not go as expected", failed, drills.len()));
+    }
+    say(format!("All {} drills went as expected", drills.len()));
+    Ok(())
+}
+
+fn remote_for(path: &Path, url: Option<String>) -> Result<Box<dyn remote::Backend>> {
+    let url = match url {
+        Some(url) => url,
+        None => Config::load()?.remote(path).map(str::to_string)
+            .with_context(|| format!("No URL given and no remote set for {:?} in config.toml", path))?,
+    };
+    remote::open(&url)
+}
+
+fn push(path: PathBuf, url: Option<String>) -> Result<()> {
+    let backend = remote_for(&path, url)?;
+    let transfer = remote::push(&path, backend.as_ref(), &mut |i, total, name| eprintln!("[{}/{}] {}", i + 1, total, name))?;
+    for name in &transfer.removed {
+        emit(format!("[OK] Removed {} from the remote copy", name), json!({ "type": "file", "file": name, "status": "removed" }));
+    }
+    for name in &transfer.sent {
+        if json_output() {
+            println!("{}", json!({ "type": "file", "file": name, "status": "sent" }));
+        }
+    }
+    say(format!("[OK] Sent {} files, {} unchanged", transfer.sent.len(), transfer.unchanged));
+    Ok(())
+}
+
+fn pull(path: PathBuf, url: Option<String>) -> Result<()> {
+    Config::load()?.check_writable(&path)?;
+    let backend = remote_for(&path, url)?;
+    let transfer = remote::pull(backend.as_ref(), &path, &mut |i, total, name| eprintln!("[{}/{}] {}", i + 1, total, name))?;
+    for name in &transfer.extraneous {
+        emit(format!("[!] {} is not in the remote copy; left in place", name), json!({ "type": "file", "file": name, "status": "extraneous" }));
+    }
+    for name in &transfer.sent {
+        if json_output() {
+            println!("{}", json!({ "type": "file", "file": name, "status": "fetched" }));
+        }
+    }
+    say(format!("[OK] Fetched {} files, {} unchanged", transfer.sent.len(), transfer.unchanged));
+    // Extraneous files were reported above
+    let report = Manifest::load(&path)?.validate(&path)?;
+    print_validation(&report, false);
+    if !report.missing.is_empty() || !report.damaged.is_empty() {
+        return Err(anyhow::anyhow!("The pulled folder does not match its manifest"));
+    }
+    Ok(())
+}
+
+// Nothing is decrypted to disk. With a terminal, Enter unmounts; otherwise it stays mounted until `umount`
+#[cfg(all(feature = "fuse", unix))]
+fn mount(prompts: &Prompts, folder: PathBuf, mountpoint: PathBuf) -> Result<()> {
+    if !folder.join(MANIFEST_NAME).is_file() {
+        return Err(anyhow::anyhow!("{:?} is not an encrypted folder", folder));
+    }
+    if !mountpoint.is_dir() {
+        return Err(anyhow::anyhow!("{:?} is not a folder to mount on", mountpoint));
+    }
+    let key = prompts.encryption_key()?;
+    let view = guardx::mount::Mount::open(&folder, &key)?;
+    for (path, why) in &view.skipped {
+        eprintln!("[!] Not shown: {}: {}", path.display(), why);
+    }
+    say(format!("[OK] {} files of {} at {}", view.file_count(), folder.display(), mountpoint.display()));
+    if !prompts.interactive {
+        say("Unmount with `fusermount -u` (Linux) or `umount` (macOS) to stop");
+        return view.serve(&mountpoint);
+    }
+    let session = view.spawn(&mountpoint)?;
+    eprint!("Press Enter to unmount... ");
+    std::io::stderr().flush()?;
+    std::io::stdin().read_line(&mut String::new())?;
+    // Dropping the session unmounts
+    drop(session);
+    say(format!("[OK] Unmounted {}", mountpoint.display()));
+    Ok(())
+}
+
+#[cfg(not(all(feature = "fuse", unix)))]
+fn mount(_prompts: &Prompts, _folder: PathBuf, _mountpoint: PathBuf) -> Result<()> {
+    Err(anyhow::anyhow!("Mounting needs FUSE, which this build does not have (Linux and macOS only)"))
+}
+
+// Like mount, but any platform's file manager can connect to it; runs until interrupted
+fn serve_webdav(prompts: &Prompts, folder: PathBuf, port: u16) -> Result<()> {
+    if !folder.join(MANIFEST_NAME).is_file() {
+        return Err(anyhow::anyhow!("{:?} is not an encrypted folder", folder));
+    }
+    let key = prompts.encryption_key()?;
+    let (share, skipped) = webdav::Share::open(&folder, &key, port)?;
+    for (path, why) in &skipped {
+        eprintln!("[!] Not shown: {}: {}", path.display(), why);
+    }
+    emit(
+        format!("[OK] {} files of {} at {}", share.file_count(), folder.display(), share.url()),
+        json!({ "type": "share", "files": share.file_count(), "folder": folder, "url": share.url() }),
+    );
+    say("Connect to that address from Finder (Go > Connect to Server) or map it as a network drive in Explorer; stop with Ctrl+C");
+    share.serve()
+}
+
+// Not written to the history log, which would tell anyone reading it that the folder's containers have a decoy
+fn set_decoy(path: PathBuf, decoy: Option<PathBuf>) -> Result<()> {
+    let path = path.canonicalize().with_context(|| format!("Could not open {:?}", path))?;
+    let decoy = decoy.map(|d| d.canonicalize().with_context(|| format!("Could not open {:?}", d))).transpose()?;
+    if decoy.as_ref().is_some_and(|d| d.starts_with(&path) || path.starts_with(d)) {
+        return Err(anyhow::anyhow!("The decoy folder and the folder must not contain each other"));
+    }
+    let mut config = Config::load()?;
+    config.vault_mut(&path).decoy = decoy.clone();
+    config.save()?;
+    match decoy {
+        Some(decoy) => say(format!("[OK] Containers of {} will open {} with the duress key", path.display(), decoy.display())),
+        None => say(format!("[OK] Containers of {} will have no decoy", path.display())),
+    }
+    Ok(())
+}
+
+// Each deletion is logged on its own, since one run can remove several folders
+fn purge_retired(prompts: &Prompts) -> Result<()> {
+    let mut config = Config::load()?;
+    let now = entropy::timestamp();
+    let due: Vec<PathBuf> = config.vaults.iter()
+        .filter(|v| v.lifecycle == Lifecycle::Retired && v.path.exists() && config.check_deletable(&v.path, now).is_ok())
+        .map(|v| v.path.clone())
+        .collect();
+    if due.is_empty() {
+        say("No retired folders are past their grace period");
+        return Ok(());
+    }
+    for path in &due {
+        emit(path.display(), json!({ "type": "folder", "path": path }));
+    }
+    if !prompts.confirm("purge_retired", &format!("Permanently delete these {} folders?", due.len()))? {
+        return Err(anyhow::anyhow!("Nothing was deleted"));
+    }
+    let mut registry = Registry::load()?;
+    let (mut failed, mut first_error) = (0, None);
+    for path in &due {
+        let result = remove_path(path, true);
+        if let Err(e) = oplog::append(&LogEntry::new("Purged retired folder", Some(path), result.is_ok(), None)) {
+            eprintln!("[!] Could not write the history log: {:#}", e);
+        }
+        match result {
+            Ok(_) => {
+                config.vaults.retain(|v| v.path != *path);
+                registry.vaults.retain(|v| v.path != *path);
+                emit(format!("[OK] deleted {}", path.display()), json!({ "type": "file", "file": path, "status": "deleted" }));
+            }
+            Err(e) => {
+                file_status(Status::Failed, &path.display().to_string(), Some(&format!("{:#}", e)), None);
+                failed += 1;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    config.save()?;
+    registry.save()?;
+    match first_error {
+        Some(e) if failed == due.len() => Err(e),
+        Some(_) => Err(Partial { failed, total: due.len() }.into()),
+        None => Ok(()),
+    }
+}
+
+fn history(last: Option<usize>) -> Result<()> {
+    let entries = oplog::load()?;
+    if entries.is_empty() {
+        say(format!("No operations recorded in {:?}", oplog::file()?));
+    }
+    let skip = last.map_or(0, |n| entries.len().saturating_sub(n));
+    for entry in &entries[skip..] {
+        print_entry(entry);
+    }
+    Ok(())
+}
+
+fn print_entry(entry: &LogEntry) {
+    if json_output() {
+        let mut value = serde_json::to_value(entry).unwrap_or_default();
+        value["type"] = "entry".into();
+        println!("{}", value);
+        return;
+    }
+    let mark = if entry.ok { "[OK]" } else { "[X]" };
+    let path = entry.path.as_ref().map(|p| format!(" {}", p.display())).unwrap_or_default();
+    println!("{} {} {}{}", local_time(entry.time), mark, entry.operation, path);
+    if let Some(note) = &entry.note {
+        println!("    \"{}\"", note);
+    }
+}
+
+fn local_time(time: i64) -> String {
+    chrono::DateTime::from_timestamp(time, 0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+// Start of the day in local time, or the last second of it with `end_of_day`
+fn parse_day(day: &str, end_of_day: bool) -> Result<i64> {
+    let date = chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d").with_context(|| format!("Expected a date like 2024-03-31, got '{}'", day))?;
+    let date = if end_of_day { date.succ_opt().context("Date out of range")? } else { date };
+    let start = date.and_hms_opt(0, 0, 0).and_then(|t| t.and_local_timezone(chrono::Local).earliest()).context("Date out of range")?;
+    Ok(start.timestamp() - end_of_day as i64)
+}
+
+fn export_history(prompts: &Prompts, dest: PathBuf, since: Option<String>, until: Option<String>, vault: Option<PathBuf>, signing_key: Option<String>) -> Result<()> {
+    let since = since.map(|d| parse_day(&d, false)).transpose()?;
+    let until = until.map(|d| parse_day(&d, true)).transpose()?;
+    let vault = vault.map(|v| v.canonicalize().with_context(|| format!("Could not open {:?}", v))).transpose()?;
+    let key = signing::load(signing_key.as_deref())?;
+    let export = Export::select(oplog::load()?, since, until, vault.as_deref());
+    let password = prompts.key("export_password", "[Key] Password for the export: ")?;
+    export.write(&dest, &password, &key)?;
+    say(format!("[OK] Exported {} entries to {}, signed by {}", export.entries.len(), dest.display(), key.public_key()));
+    say(format!("The recipient checks it with `guardx verify-export {} --signer {}` and the password", dest.display(), key.public_key()));
+    Ok(())
+}
+
+fn verify_export(prompts: &Prompts, path: PathBuf, signers: Vec<String>) -> Result<()> {
+    let mut trusted = signing::own_public_keys()?;
+    trusted.extend(signers);
+    let password = prompts.key("export_password", "[Key] Password of the export: ")?;
+    let (signer, export) = Export::open(&path, &password, &trusted)?;
+    say(format!("[OK] Signed by {}, exported {}", signer, local_time(export.exported)));
+    let since = export.since.map_or("the start".to_string(), local_time);
+    let until = export.until.map_or("the export".to_string(), local_time);
+    let vault = export.vault.as_ref().map_or("all folders".to_string(), |v| v.display().to_string());
+    say(format!("{} entries from {} to {}, {}", export.entries.len(), since, until, vault));
+    for entry in &export.entries {
+        print_entry(entry);
+    }
+    Ok(())
+}
+
+fn audit_verify(head: Option<String>) -> Result<()> {
+    let report = oplog::verify_audit()?;
+    if report.chained == 0 {
+        say(format!("[!] No entry of {:?} is chained; set `audit = true` in config.toml to start the chain", oplog::file()?));
+    }
+    for problem in &report.problems {
+        say(format!("[X] {}", problem));
+    }
+    if let Some(expected) = &head {
+        if !oplog::chain_contains(expected)? {
+            say(format!("[X] The head {} is no longer in the chain; the log was rewritten after it", expected));
+            return Err(GuardXError::Format("The history log does not contain the expected head".to_string()).into());
+        }
+    }
+    if !report.problems.is_empty() {
+        return Err(GuardXError::Format(format!("The history log was tampered with: {} problems", report.problems.len())).into());
+    }
+    if report.chained > 0 {
+        say(format!("[OK] {} chained entries intact{}", report.chained, if report.unchained > 0 { format!(", after {} from before audit mode", report.unchained) } else { String::new() }));
+    }
+    if let Some(hash) = &report.head {
+        emit(format!("Head: {}", hash), json!({ "type": "head", "hash": hash }));
+    }
+    Ok(())
+}
+
+fn import(prompts: &Prompts, path: PathBuf, name: Option<String>, force: bool) -> Result<()> {
+    let path = path.canonicalize().with_context(|| format!("Could not open {:?}", path))?;
+    if !path.is_dir() {
+        return Err(anyhow::anyhow!("{:?} is not a directory", path));
+    }
+    let manifest = Manifest::load(&path)?;
+    if !manifest.is_supported() {
+        return Err(GuardXError::Format(format!("Unsupported format version {} in {:?}", manifest.format_version, path)).into());
+    }
+
+    let report = manifest.validate(&path)?;
+    say(format!("Checked {} files listed in the manifest (format v{})", manifest.files.len(), manifest.format_version));
+    print_validation(&report, true);
+
+    if (!report.missing.is_empty() || !report.damaged.is_empty()) && !force {
+        let problem = format!("{} missing and {} damaged files; re-run with --force to register anyway", report.missing.len(), report.damaged.len());
+        if !prompts.confirm("register_damaged", "Register the vault anyway?").context(problem.clone())? {
+            return Err(anyhow::anyhow!(problem));
+        }
+    }
+
+    let name = name.unwrap_or_else(|| path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default());
+    let mut registry = Registry::load()?;
+    registry.register(VaultEntry {
+        name: name.clone(),
+        path: path.clone(),
+        format_version: manifest.format_version,
+        registered: entropy::timestamp(),
+    });
+    registry.save()?;
+    if report.is_clean() {
+        say(format!("[OK] Vault '{}' imported from {}", name, path.display()));
+    } else {
+        say(format!("[!] Vault '{}' imported from {} with problems", name, path.display()));
+    }
+    Ok(())
+}
+
+fn doctor() -> Result<()> {
+    let checks = crate::doctor::run();
+    for check in &checks {
+        let (mark, status) = match check.outcome {
+            Outcome::Ok => ("[OK]", "ok"),
+            Outcome::Warning => ("[!]", "warning"),
+            Outcome::Failed => ("[X]", "failed"),
+        };
+        emit(
+            format!("{} {}: {}", mark, check.area, check.detail),
+            json!({ "type": "check", "area": check.area, "status": status, "detail": check.detail }),
+        );
+    }
+    let failed = checks.iter().filter(|c| c.outcome == Outcome::Failed).count();
+    let warnings = checks.iter().filter(|c| c.outcome == Outcome::Warning).count();
+    say(format!("{} checks, {} failed, {} warnings", checks.len(), failed, warnings));
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} of {} checks failed", failed, checks.len()));
+    }
+    Ok(())
+}
+
+// Generated from the commands and flags above, so it never falls behind them
+fn completions(shell: Shell) -> Result<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+    Ok(())
+}
+
+fn bench(size_mb: usize, files: usize) -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("guardx-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let result = bench_ciphers()
+        .and_then(|()| bench_kdf())
+        .and_then(|()| bench_in(&dir, size_mb))
+        .and_then(|()| bench_folder(&dir.join("folder"), files))
+        .and_then(|()| bench_tiny_files(&dir.join("tiny"), files * 10));
+    std::fs::remove_dir_all(&dir)?;
+    result
+}
+
+// In memory, so this is the most any file operation can reach with each cipher
+fn bench_ciphers() -> Result<()> {
+    let preferred = preferred_cipher();
+    for cipher in Cipher::ALL {
+        let rate = crate::doctor::throughput(cipher)?;
+        let note = if cipher == preferred { ", the fastest here" } else { "" };
+        emit(
+            format!("{:<20} {:>8.1} MiB/s  (in memory{})", cipher.label(), rate, note),
+            json!({ "type": "bench", "mode": "cipher", "cipher": cipher.name(), "mib_per_sec": rate }),
+        );
+    }
+    if !aes_accelerated() {
+        say("No hardware AES here; ChaCha20-Poly1305 is the better choice for `cipher`");
+    }
+    Ok(())
+}
+
+// Once per key and folder; the stretched key is cached after that
+fn bench_kdf() -> Result<()> {
+    let kdf = kdf::current();
+    let start = Instant::now();
+    kdf.stretch("guardx-bench", &[0u8; 16])?;
+    let secs = start.elapsed().as_secs_f64();
+    emit(
+        format!("{:<20} {:>8.2}s per key  ({}; set `kdf` in config.toml to change it)", "Key stretching", secs, kdf),
+        json!({ "type": "bench", "mode": "kdf", "kdf": kdf.to_string(), "secs": secs }),
+    );
+    Ok(())
+}
+
+fn bench_in(dir: &std::path::Path, size_mb: usize) -> Result<()> {
+    let src = dir.join("input");
+    let dest = dir.join("output");
+    let block: Vec<u8> = (0..1024 * 1024).map(|i: u32| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+    let mut file = std::io::BufWriter::new(std::fs::File::create(&src)?);
+    for _ in 0..size_mb {
+        file.write_all(&block)?;
+    }
+    file.flush()?;
+    drop(file);
+
+    let cipher = preferred_cipher();
+    say(format!("Cipher: {}", cipher.label()));
+    let depth = pipeline_depth();
+    let mut timings = Vec::new();
+    for (label, chunks) in [("sequential", 0), ("pipelined", depth.max(2))] {
+        if dest.exists() {
+            std::fs::remove_file(&dest)?;
+        }
+        set_pipeline_depth(chunks);
+        let start = Instant::now();
+        let result = encrypt_to(&src, &dest, "guardx-bench", cipher);
+        let secs = start.elapsed().as_secs_f64();
+        set_pipeline_depth(depth);
+        result?;
+        emit(
+            format!("{:<12} {:>8.1} MiB/s  ({:.2}s, {} chunks in flight)", label, size_mb as f64 / secs, secs, chunks),
+            json!({ "type": "bench", "mode": label, "mib_per_sec": size_mb as f64 / secs, "secs": secs, "chunks_in_flight": chunks }),
+        );
+        timings.push(secs);
+    }
+    say(format!("Speedup: {:.2}x", timings[0] / timings[1]));
+    Ok(())
+}
+
+// A folder of files from 4 KiB to 1 MiB, encrypted and decrypted the way the TUI does it with the cipher
+// from config.toml, so key stretching, the manifest and every file's replace are included
+fn bench_folder(folder: &Path, count: usize) -> Result<()> {
+    let total = synthetic_folder(folder, count, &[4 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024])?;
+    let cipher = Config::load()?.default_cipher();
+    timed_folder("Folder encrypt", count, total, cipher, &|| encrypt_folder_with(folder, "guardx-bench", cipher, OutputFormat::GuardX, false, false, &mut |_, _, _| {}))?;
+    timed_folder("Folder decrypt", count, total, cipher, &|| decrypt_folder(folder, "guardx-bench"))?;
+    Ok(())
+}
+
+// Folders of tiny files spend their time in system calls, which is what the io_uring batches save
+fn bench_tiny_files(folder: &Path, count: usize) -> Result<()> {
+    if !io_uring_available() {
+        say(if cfg!(feature = "io-uring") { "io_uring: not allowed here, or the kernel is older than 5.11" } else { "io_uring: not in this build (the `io-uring` feature, Linux only)" });
+        return Ok(());
+    }
+    let total = synthetic_folder(folder, count, &[512, 1024, 2048, 4096])?;
+    let cipher = Config::load()?.default_cipher();
+    let mut secs = [0.0; 2];
+    for (i, on) in [false, true].into_iter().enumerate() {
+        set_io_uring(on);
+        let label = if on { "Tiny with io_uring" } else { "Tiny files" };
+        secs[i] = timed_folder(label, count, total, cipher, &|| encrypt_folder_with(folder, "guardx-bench", cipher, OutputFormat::GuardX, false, false, &mut |_, _, _| {}))?;
+        decrypt_folder(folder, "guardx-bench")?;
+    }
+    set_io_uring(true);
+    say(format!("io_uring speedup: {:.2}x", secs[0] / secs[1]));
+    Ok(())
+}
+
+// `count` files cycling through `sizes`; returns their total size
+fn synthetic_folder(folder: &Path, count: usize, sizes: &[usize]) -> Result<u64> {
+    std::fs::create_dir_all(folder)?;
+    let mut total = 0u64;
+    for i in 0..count {
+        let size = sizes[i % sizes.len()];
+        let data: Vec<u8> = (0..size as u32).map(|b| (b.wrapping_add(i as u32).wrapping_mul(2654435761) >> 24) as u8).collect();
+        std::fs::write(folder.join(format!("file-{:05}.bin", i)), &data)?;
+        total += size as u64;
+    }
+    Ok(total)
+}
+
+// Prints how fast `run` went through the folder; returns the seconds it took
+fn timed_folder(label: &str, count: usize, total: u64, cipher: Cipher, run: &dyn Fn() -> Result<()>) -> Result<f64> {
+    let start = Instant::now();
+    run()?;
+    let secs = start.elapsed().as_secs_f64();
+    let mib = total as f64 / (1024.0 * 1024.0);
+    emit(
+        format!("{:<20} {:>8.1} MiB/s  ({:.0} files/s, {} files, {}, {:.2}s)", label, mib / secs, count as f64 / secs, count, human_size(total), secs),
+        json!({ "type": "bench", "mode": label.to_lowercase().replace(' ', "_"), "cipher": cipher.name(), "files": count, "bytes": total, "mib_per_sec": mib / secs, "files_per_sec": count as f64 / secs, "secs": secs }),
+    );
+    Ok(secs)
+}
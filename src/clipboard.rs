@@ -0,0 +1,77 @@
+use anyhow::Result;
+use std::io::Write;
+
+// Copies to the system clipboard, or else with an OSC 52 escape sequence that the terminal hands to the
+// clipboard of the machine in front of you, which is what works over SSH. Over SSH it goes that way first,
+// since a system clipboard there belongs to the remote machine.
+pub struct Clipboard {
+    // Kept open: on X11 the copied text is only offered while this lives
+    system: Option<arboard::Clipboard>,
+    // What was copied last, hashed, so clearing leaves alone anything copied since
+    copied: Option<blake3::Hash>,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Via {
+    System,
+    Terminal,
+}
+
+impl Clipboard {
+    pub fn open() -> Clipboard {
+        let remote = std::env::var_os("SSH_CONNECTION").is_some() || std::env::var_os("SSH_TTY").is_some();
+        let system = if remote { None } else { arboard::Clipboard::new().ok() };
+        Clipboard { system, copied: None }
+    }
+
+    pub fn copy(&mut self, text: &str) -> Result<Via> {
+        self.copied = Some(blake3::hash(text.as_bytes()));
+        if let Some(system) = self.system.as_mut() {
+            if system.set_text(text).is_ok() {
+                return Ok(Via::System);
+            }
+        }
+        osc52(text)?;
+        Ok(Via::Terminal)
+    }
+
+    // Takes what is on the clipboard as the last copy, so that clear() empties it; for a key that was pasted
+    pub fn adopt(&mut self, text: &str) {
+        self.copied = Some(blake3::hash(text.as_bytes()));
+    }
+
+    // Empties the clipboard if it still holds the last copy. What went through the terminal cannot be read
+    // back, so that is overwritten regardless.
+    pub fn clear(&mut self) -> Result<()> {
+        let Some(copied) = self.copied.take() else { return Ok(()) };
+        match self.system.as_mut() {
+            Some(system) => {
+                if system.get_text().is_ok_and(|text| blake3::hash(text.as_bytes()) == copied) {
+                    system.clear()?;
+                }
+                Ok(())
+            }
+            None => osc52(""),
+        }
+    }
+}
+
+fn osc52(text: &str) -> Result<()> {
+    let mut out = std::io::stdout();
+    write!(out, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    out.flush()?;
+    Ok(())
+}
+
+// Standard base64 with padding, as OSC 52 takes it
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |acc, (i, b)| acc | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            out.push(if i <= group.len() { ALPHABET[((bits >> (18 - 6 * i)) & 63) as usize] as char } else { '=' });
+        }
+    }
+    out
+}
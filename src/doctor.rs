@@ -0,0 +1,243 @@
+use anyhow::Result;
+use crossterm::terminal;
+use guardx::biometric;
+use guardx::config::{Config, Lifecycle};
+use guardx::crypto::{aes_accelerated, preferred_cipher, Cipher};
+use guardx::entropy;
+use guardx::hardware::{self, Token};
+use guardx::keyring;
+use guardx::registry::Registry;
+use guardx::signing;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, CHACHA20_POLY1305};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// What `guardx doctor` looks at before anyone trusts a machine with a folder: whether the TUI can run,
+// whether config.toml and the keyring load, whether the folders GuardX knows can be written, and whether
+// the random number generator and the ciphers behave. Nothing here needs a key or changes a folder.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Ok,
+    Warning,
+    Failed,
+}
+
+pub struct Check {
+    pub area: &'static str,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(area: &'static str, outcome: Outcome, detail: impl Into<String>) -> Self {
+        Check { area, outcome, detail: detail.into() }
+    }
+}
+
+pub fn run() -> Vec<Check> {
+    let mut checks = Vec::new();
+    terminal_checks(&mut checks);
+    let config = config_check(&mut checks);
+    keyring_checks(&mut checks);
+    folder_checks(&mut checks, config.as_ref());
+    checks.push(rng_check());
+    checks.push(throughput_check());
+    checks
+}
+
+fn terminal_checks(checks: &mut Vec<Check>) {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        checks.push(Check::new("terminal", Outcome::Warning, "stdin or stdout is not a terminal; the TUI needs one, the CLI does not"));
+        return;
+    }
+    match terminal::size() {
+        Ok((width, height)) if width < 80 || height < 24 => {
+            checks.push(Check::new("terminal", Outcome::Warning, format!("{}x{}; the TUI's panels are cramped below 80x24", width, height)));
+        }
+        Ok((width, height)) => checks.push(Check::new("terminal", Outcome::Ok, format!("{}x{}", width, height))),
+        Err(e) => checks.push(Check::new("terminal", Outcome::Failed, format!("Could not read the size: {}", e))),
+    }
+    // The TUI needs raw mode for its key handling
+    match terminal::enable_raw_mode().and_then(|()| terminal::disable_raw_mode()) {
+        Ok(()) => checks.push(Check::new("terminal", Outcome::Ok, "raw mode works")),
+        Err(e) => checks.push(Check::new("terminal", Outcome::Failed, format!("raw mode does not work: {}", e))),
+    }
+    let term = std::env::var("TERM").unwrap_or_default();
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    let colours = if term == "dumb" {
+        checks.push(Check::new("terminal", Outcome::Warning, "TERM is dumb; the TUI draws nothing useful there"));
+        return;
+    } else if colorterm == "truecolor" || colorterm == "24bit" {
+        "24-bit colour"
+    } else if term.contains("256color") {
+        "256 colours; labels and the accent colour are approximated"
+    } else {
+        "basic colours; labels and the accent colour are approximated"
+    };
+    checks.push(Check::new("terminal", Outcome::Ok, colours));
+}
+
+// The config, or None when it does not load
+fn config_check(checks: &mut Vec<Check>) -> Option<Config> {
+    let path = match Config::file() {
+        Ok(path) => path,
+        Err(e) => {
+            checks.push(Check::new("config", Outcome::Failed, format!("{:#}", e)));
+            return None;
+        }
+    };
+    if !path.exists() {
+        checks.push(Check::new("config", Outcome::Ok, format!("{:?} does not exist yet; the defaults apply", path)));
+        return Some(Config::default());
+    }
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            checks.push(Check::new("config", Outcome::Failed, format!("{:#}", e)));
+            return None;
+        }
+    };
+    // Per-folder settings are only read when the folder is used, so a mistake in them would otherwise wait until then
+    let mut problems = Vec::new();
+    for vault in &config.vaults {
+        if let Some(name) = vault.cipher.as_deref().filter(|name| Cipher::from_name(name).is_none()) {
+            problems.push(format!("{}: unknown cipher {:?}", vault.path.display(), name));
+        }
+        if let Err(e) = config.recipients(&vault.path) {
+            problems.push(format!("{}: {:#}", vault.path.display(), e));
+        }
+    }
+    if let Err(e) = config.schedules() {
+        problems.push(format!("{:#}", e));
+    }
+    if problems.is_empty() {
+        checks.push(Check::new("config", Outcome::Ok, format!("{:?} is valid, {} folders configured", path, config.vaults.len())));
+    }
+    for problem in problems {
+        checks.push(Check::new("config", Outcome::Failed, problem));
+    }
+    Some(config)
+}
+
+fn keyring_checks(checks: &mut Vec<Check>) {
+    match (keyring::list(), signing::list()) {
+        (Ok(identities), Ok(keys)) => {
+            checks.push(Check::new("keyring", Outcome::Ok, format!("{} identities and {} signing keys", identities.len(), keys.len())));
+        }
+        (Err(e), _) | (_, Err(e)) => checks.push(Check::new("keyring", Outcome::Failed, format!("{:#}", e))),
+    }
+    match Token::load() {
+        Ok(Some(token)) => match hardware::detect() {
+            Ok(found) if found.is_empty() => {
+                checks.push(Check::new("keyring", Outcome::Warning, format!("Keys are bound to slot {} of a YubiKey, but none is connected", token.slot)));
+            }
+            Ok(_) => checks.push(Check::new("keyring", Outcome::Ok, format!("Keys are bound to slot {} of a connected YubiKey", token.slot))),
+            Err(e) => checks.push(Check::new("keyring", Outcome::Warning, format!("Keys are bound to slot {} of a YubiKey: {:#}", token.slot, e))),
+        },
+        Ok(None) => {}
+        Err(e) => checks.push(Check::new("keyring", Outcome::Failed, format!("{:#}", e))),
+    }
+    if biometric::is_stored() {
+        checks.push(Check::new("keyring", Outcome::Ok, format!("A key is stored for {}", biometric::name())));
+    }
+}
+
+// The data and config directories, then every configured or registered folder
+fn folder_checks(checks: &mut Vec<Check>, config: Option<&Config>) {
+    for (what, dir) in [("data", dirs::data_dir()), ("config", dirs::config_dir())] {
+        let Some(dir) = dir else {
+            checks.push(Check::new("folders", Outcome::Failed, format!("Could not find the {} directory", what)));
+            continue;
+        };
+        let dir = dir.join("guardx");
+        match std::fs::create_dir_all(&dir).map_err(anyhow::Error::from).and_then(|()| probe_write(&dir)) {
+            Ok(()) => checks.push(Check::new("folders", Outcome::Ok, format!("{} is writable", dir.display()))),
+            Err(e) => checks.push(Check::new("folders", Outcome::Failed, format!("{} is not writable: {:#}", dir.display(), e))),
+        }
+    }
+    let mut folders: Vec<PathBuf> = config.map(|c| c.vaults.iter().map(|v| v.path.clone()).collect()).unwrap_or_default();
+    if let Ok(registry) = Registry::load() {
+        folders.extend(registry.vaults.into_iter().map(|v| v.path));
+    }
+    folders.sort();
+    folders.dedup();
+    for folder in folders {
+        let check = if config.is_some_and(|c| c.lifecycle(&folder) == Lifecycle::Retired) {
+            Check::new("folders", Outcome::Ok, format!("{} is retired and read-only", folder.display()))
+        } else if !folder.is_dir() {
+            Check::new("folders", Outcome::Warning, format!("{} does not exist or is not mounted", folder.display()))
+        } else {
+            match probe_write(&folder) {
+                Ok(()) => Check::new("folders", Outcome::Ok, format!("{} is writable", folder.display())),
+                Err(e) => Check::new("folders", Outcome::Failed, format!("{} is not writable: {:#}", folder.display(), e)),
+            }
+        };
+        checks.push(check);
+    }
+}
+
+fn probe_write(dir: &Path) -> Result<()> {
+    let path = dir.join(format!(".guardx-doctor-{}", std::process::id()));
+    let written = std::fs::OpenOptions::new().write(true).create_new(true).open(&path).and_then(|mut file| file.write_all(b"guardx"));
+    let removed = std::fs::remove_file(&path);
+    written?;
+    removed?;
+    Ok(())
+}
+
+// Catches a generator that is stuck or badly broken, not a subtly weak one
+fn rng_check() -> Check {
+    const LEN: usize = 64 * 1024;
+    if entropy::is_seeded() {
+        return Check::new("random", Outcome::Failed, "Deterministic test mode: nonces and salts are predictable");
+    }
+    let (mut first, mut second) = (vec![0u8; LEN], vec![0u8; LEN]);
+    if let Err(e) = entropy::fill(&mut first).and_then(|()| entropy::fill(&mut second)) {
+        return Check::new("random", Outcome::Failed, format!("{:#}", e));
+    }
+    if first == second {
+        return Check::new("random", Outcome::Failed, "Two draws came out the same");
+    }
+    // Half the bits should be set; 1% off is hundreds of standard deviations for this many bits
+    let ones: u32 = first.iter().map(|b| b.count_ones()).sum();
+    let share = ones as f64 / (LEN * 8) as f64;
+    if !(0.49..=0.51).contains(&share) {
+        return Check::new("random", Outcome::Failed, format!("{:.1}% of the bits are set instead of about half", share * 100.0));
+    }
+    Check::new("random", Outcome::Ok, format!("{} KiB from the system generator look random", LEN / 1024))
+}
+
+fn throughput_check() -> Check {
+    let mut rates = Vec::new();
+    for cipher in Cipher::ALL {
+        match throughput(cipher) {
+            Ok(rate) => rates.push(format!("{} {:.0} MiB/s", cipher.label(), rate)),
+            Err(e) => return Check::new("ciphers", Outcome::Failed, format!("{}: {:#}", cipher.label(), e)),
+        }
+    }
+    let note = if aes_accelerated() { "" } else { ", no hardware AES" };
+    Check::new("ciphers", Outcome::Ok, format!("{}; new files use {}{}", rates.join(", "), preferred_cipher().label(), note))
+}
+
+// Sealing 1 MiB chunks in memory for half a second; key stretching and the disk are left out, so this is
+// the most any file operation can reach
+pub fn throughput(cipher: Cipher) -> Result<f64> {
+    let algorithm = match cipher {
+        Cipher::Aes256Gcm => &AES_256_GCM,
+        Cipher::ChaCha20Poly1305 => &CHACHA20_POLY1305,
+    };
+    let key = LessSafeKey::new(UnboundKey::new(algorithm, &[7u8; 32]).map_err(|_| anyhow::anyhow!("Could not set up the cipher"))?);
+    let mut chunk = vec![0u8; 1024 * 1024];
+    let started = Instant::now();
+    let mut sealed = 0u64;
+    while started.elapsed() < Duration::from_millis(500) {
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&sealed.to_be_bytes());
+        // The tag is what a file would store after the chunk; only the time spent matters here
+        let _tag = key.seal_in_place_separate_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut chunk)
+            .map_err(|_| anyhow::anyhow!("Sealing failed"))?;
+        sealed += 1;
+    }
+    Ok(sealed as f64 / started.elapsed().as_secs_f64())
+}
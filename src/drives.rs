@@ -0,0 +1,135 @@
+use crate::manifest::MANIFEST_NAME;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Removable drives and other mounted volumes, found by polling the folders the OS mounts them under
+// (and /proc/mounts on Linux), since there is no portable way to be told about mounts. A drive that
+// shows up is searched for encrypted folders up to SEARCH_DEPTH levels down; one that goes away is
+// reported so its folders can be dropped.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const SEARCH_DEPTH: usize = 2;
+
+pub enum Change {
+    // `vaults` is empty for a drive without encrypted folders
+    Attached { mount: PathBuf, vaults: Vec<PathBuf> },
+    Detached { mount: PathBuf },
+}
+
+// Every drive mounted where removable drives go
+pub fn mounts() -> Vec<PathBuf> {
+    let mut mounts = Vec::new();
+    for root in roots() {
+        let Ok(entries) = fs::read_dir(&root) else { continue };
+        // Symlinks are skipped: on macOS /Volumes links to the system disk
+        mounts.extend(entries.filter_map(|e| e.ok()).filter(|e| e.file_type().is_ok_and(|t| t.is_dir())).map(|e| e.path()));
+    }
+    // Drive letters are mounts themselves; A to C are left out
+    #[cfg(windows)]
+    mounts.extend((b'D'..=b'Z').map(|letter| PathBuf::from(format!("{}:\\", letter as char))).filter(|p| p.is_dir()));
+    #[cfg(target_os = "linux")]
+    mounts.extend(proc_mounts());
+    mounts.sort();
+    mounts.dedup();
+    // A drive mounted inside another one's folder belongs to that one's listing
+    let nested: Vec<PathBuf> = mounts.iter().filter(|m| mounts.iter().any(|outer| outer != *m && m.starts_with(outer))).cloned().collect();
+    mounts.retain(|m| !nested.contains(m));
+    mounts
+}
+
+// Volumes mounted deeper under the mount folders than the listing above looks, e.g. /media/usb/stick,
+// or for other users; pseudo filesystems have no device path and are left out
+#[cfg(target_os = "linux")]
+fn proc_mounts() -> Vec<PathBuf> {
+    let Ok(table) = fs::read_to_string("/proc/mounts") else { return Vec::new() };
+    table.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            // Spaces and tabs in mount points are written as octal escapes
+            let point = PathBuf::from(fields.next()?.replace("\\040", " ").replace("\\011", "\t"));
+            let under = ["/media", "/run/media", "/mnt"].iter().any(|root| point.starts_with(root) && point != Path::new(root));
+            (device.starts_with("/dev/") && under && point.is_dir()).then_some(point)
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn roots() -> Vec<PathBuf> {
+    vec![PathBuf::from("/Volumes")]
+}
+
+#[cfg(windows)]
+fn roots() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn roots() -> Vec<PathBuf> {
+    let mut roots = vec![PathBuf::from("/mnt")];
+    if let Ok(user) = std::env::var("USER") {
+        roots.push(Path::new("/media").join(&user));
+        roots.push(Path::new("/run/media").join(&user));
+    }
+    roots
+}
+
+// The drive itself if it is an encrypted folder, otherwise the encrypted folders on it
+pub fn find_vaults(mount: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    search(mount, SEARCH_DEPTH, &mut found);
+    found
+}
+
+fn search(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    if dir.join(MANIFEST_NAME).is_file() {
+        found.push(dir.to_path_buf());
+        return;
+    }
+    if depth == 0 {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut dirs: Vec<PathBuf> = entries.filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()) && !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .collect();
+    dirs.sort();
+    for dir in dirs {
+        search(&dir, depth - 1, found);
+    }
+}
+
+// Drives mounted when GuardX starts are reported as attached on the first poll, like newly plugged ones
+pub struct Watch {
+    known: BTreeSet<PathBuf>,
+    checked: Option<Instant>,
+}
+
+impl Watch {
+    pub fn new() -> Self {
+        Watch { known: BTreeSet::new(), checked: None }
+    }
+
+    // What changed since the last call; the mount points are read at most every POLL_INTERVAL
+    pub fn changes(&mut self) -> Vec<Change> {
+        if self.checked.is_some_and(|checked| checked.elapsed() < POLL_INTERVAL) {
+            return Vec::new();
+        }
+        self.checked = Some(Instant::now());
+        let current: BTreeSet<PathBuf> = mounts().into_iter().collect();
+        let mut changes: Vec<Change> = self.known.difference(&current).map(|mount| Change::Detached { mount: mount.clone() }).collect();
+        for mount in current.difference(&self.known) {
+            changes.push(Change::Attached { mount: mount.clone(), vaults: find_vaults(mount) });
+        }
+        self.known = current;
+        changes
+    }
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
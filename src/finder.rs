@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// What Ctrl+P in the TUI searches: every folder under the roots, and every file when asked, gathered on a
+// worker thread so nothing waits for the walk. Hidden folders are skipped, and the walk stops at MAX_ENTRIES
+// so a root like / cannot eat the memory.
+pub const MAX_ENTRIES: usize = 200_000;
+const MAX_DEPTH: usize = 16;
+// Entries are sent in batches, so the list fills in while the walk goes on
+const BATCH: usize = 2_000;
+
+pub struct Entry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+pub enum Progress {
+    Found(Vec<Entry>),
+    // `truncated` when the walk stopped at MAX_ENTRIES
+    Done { truncated: bool },
+}
+
+pub fn spawn(roots: Vec<PathBuf>, files: bool) -> Receiver<Progress> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut walk = Walk { tx, files, batch: Vec::new(), count: 0 };
+        let mut complete = true;
+        for root in roots {
+            if !walk.visit(&root, 0) {
+                complete = false;
+                break;
+            }
+        }
+        walk.flush();
+        let _ = walk.tx.send(Progress::Done { truncated: !complete });
+    });
+    rx
+}
+
+struct Walk {
+    tx: Sender<Progress>,
+    files: bool,
+    batch: Vec<Entry>,
+    count: usize,
+}
+
+impl Walk {
+    // False once the walk has to stop: too many entries, or nobody is listening any more
+    fn visit(&mut self, dir: &Path, depth: usize) -> bool {
+        let Ok(entries) = fs::read_dir(dir) else { return true };
+        let mut subdirs = Vec::new();
+        for entry in entries.filter_map(|e| e.ok()) {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            // Symlinked folders are not followed, so a loop cannot keep the walk going
+            let Ok(kind) = entry.file_type() else { continue };
+            if kind.is_dir() {
+                subdirs.push(entry.path());
+            } else if self.files && kind.is_file() && !self.add(Entry { path: entry.path(), is_dir: false }) {
+                return false;
+            }
+        }
+        subdirs.sort();
+        for sub in subdirs {
+            if !self.add(Entry { path: sub.clone(), is_dir: true }) {
+                return false;
+            }
+            if depth + 1 < MAX_DEPTH && !self.visit(&sub, depth + 1) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn add(&mut self, entry: Entry) -> bool {
+        if self.count >= MAX_ENTRIES {
+            return false;
+        }
+        self.count += 1;
+        self.batch.push(entry);
+        self.batch.len() < BATCH || self.flush()
+    }
+
+    fn flush(&mut self) -> bool {
+        self.batch.is_empty() || self.tx.send(Progress::Found(std::mem::take(&mut self.batch))).is_ok()
+    }
+}
+
+// How well `candidate` matches `query`, fzf style: the query's characters must appear in order, ignoring case.
+// Runs of consecutive characters and characters at the start of a path component or word score higher, and
+// shorter candidates win ties. None when it does not match.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0i64;
+    let mut from = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().flat_map(char::to_lowercase) {
+        let at = from + candidate[from..].iter().position(|&c| c == wanted)?;
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == at) {
+            score += 5;
+        }
+        if at == 0 || matches!(candidate[at - 1], '/' | '\\' | '_' | '-' | ' ' | '.') {
+            score += 8;
+        }
+        previous = Some(at);
+        from = at + 1;
+    }
+    Some(score * 100 - candidate.len() as i64)
+}
+
+// Indices of the best `limit` entries for `query`, best first
+pub fn search(entries: &[Entry], query: &str, limit: usize) -> Vec<usize> {
+    let mut scored: Vec<(i64, usize)> = entries.iter().enumerate()
+        .filter_map(|(i, entry)| score(query, &entry.path.to_string_lossy()).map(|s| (s, i)))
+        .collect();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    scored.into_iter().take(limit).map(|(_, i)| i).collect()
+}
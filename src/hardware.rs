@@ -0,0 +1,122 @@
+use crate::manifest::{from_hex, to_hex};
+use anyhow::{Context, Result};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+// Binds the key to a YubiKey. With one enrolled, files are encrypted with a key derived from the typed key
+// and the YubiKey's HMAC-SHA1 challenge-response, so they cannot be opened without the YubiKey. The
+// challenge is derived from the typed key too, so nothing secret is stored: the enrollment file only names
+// the slot. The YubiKey is reached through `ykman` or, failing that, `ykchalresp`, so one of them has to
+// be installed. FIDO2 hmac-secret is not supported.
+const CHALLENGE_CONTEXT: &str = "guardx hardware challenge v1";
+const KEY_CONTEXT: &str = "guardx hardware key v1";
+// HMAC-SHA1
+const RESPONSE_LEN: usize = 20;
+
+pub fn file() -> Result<PathBuf> {
+    Ok(dirs::config_dir().context("Could not find config directory")?.join("guardx").join("hardware-key"))
+}
+
+#[derive(Clone, Copy)]
+pub struct Token {
+    pub slot: u8,
+}
+
+impl Token {
+    pub fn new(slot: u8) -> Result<Self> {
+        if !matches!(slot, 1 | 2) {
+            return Err(anyhow::anyhow!("A YubiKey has challenge-response slots 1 and 2, not {}", slot));
+        }
+        Ok(Token { slot })
+    }
+
+    // The enrolled YubiKey slot, if there is one
+    pub fn load() -> Result<Option<Self>> {
+        let path = file()?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let slot = contents.trim().parse().ok().with_context(|| format!("Malformed hardware key enrollment in {:?}", path))?;
+        Token::new(slot).map(Some)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = file()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, format!("{}\n", self.slot))?;
+        Ok(())
+    }
+
+    // Blocks until the YubiKey answers, which takes a touch when the slot is set up to require one
+    pub fn respond(&self, challenge: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let (slot, challenge) = (self.slot.to_string(), to_hex(challenge));
+        let ykchalresp_slot = format!("-{}", slot);
+        let tools: [(&str, [&str; 4]); 2] = [
+            ("ykman", ["otp", "calculate", &slot, &challenge]),
+            ("ykchalresp", [&ykchalresp_slot, "-H", "-x", &challenge]),
+        ];
+        for (program, args) in tools {
+            let output = match Command::new(program).args(args).stdin(Stdio::null()).output() {
+                Ok(output) => output,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("Could not run {}", program)),
+            };
+            let answer = Zeroizing::new(output.stdout);
+            if !output.status.success() {
+                let why = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                return Err(anyhow::anyhow!("The YubiKey did not answer on slot {} ({}): {}", self.slot, program, why));
+            }
+            return std::str::from_utf8(&answer).ok()
+                .and_then(|answer| from_hex(answer.trim()))
+                .filter(|response| response.len() == RESPONSE_LEN)
+                .map(Zeroizing::new)
+                .with_context(|| format!("Unexpected answer from {}", program));
+        }
+        Err(anyhow::anyhow!("Neither ykman nor ykchalresp is installed; one of them is needed to reach the YubiKey"))
+    }
+
+    // The key files are actually encrypted with: 64 hex digits, whatever the typed key was
+    pub fn bind(&self, key: &str) -> Result<Zeroizing<String>> {
+        let challenge = blake3::derive_key(CHALLENGE_CONTEXT, key.as_bytes());
+        let response = self.respond(&challenge)?;
+        let mut hasher = blake3::Hasher::new_derive_key(KEY_CONTEXT);
+        hasher.update(&response);
+        hasher.update(key.as_bytes());
+        Ok(Zeroizing::new(to_hex(hasher.finalize().as_bytes())))
+    }
+}
+
+// The key bound to the enrolled YubiKey, or the key itself when none is enrolled
+pub fn bind(key: &str) -> Result<Zeroizing<String>> {
+    match Token::load()? {
+        Some(token) => token.bind(key),
+        None => Ok(Zeroizing::new(key.to_string())),
+    }
+}
+
+pub fn remove() -> Result<()> {
+    match fs::remove_file(file()?) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// Connected YubiKeys as `ykman list` describes them
+pub fn detect() -> Result<Vec<String>> {
+    let output = match Command::new("ykman").arg("list").stdin(Stdio::null()).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(anyhow::anyhow!("ykman is not installed, so YubiKeys cannot be listed")),
+        Err(e) => return Err(e).context("Could not run ykman"),
+    };
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("ykman list failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect())
+}
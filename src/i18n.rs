@@ -0,0 +1,135 @@
+use ratatui::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+use unicode_bidi::{BidiInfo, Level};
+
+// The TUI's messages come from the catalogs in assets/locales, one TOML file per language with a table per
+// part of the screen. Anything a catalog leaves out is shown in English, and anything not in a catalog yet
+// (most error messages, the CLI) stays English too.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Language {
+    English,
+    Persian,
+}
+
+const CATALOGS: [(Language, &str); 2] = [
+    (Language::English, include_str!("../assets/locales/en.toml")),
+    (Language::Persian, include_str!("../assets/locales/fa.toml")),
+];
+
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+impl Language {
+    // One of config::LANGUAGES, or a locale such as "fa_IR.UTF-8"
+    pub fn from_code(code: &str) -> Option<Language> {
+        let code = code.to_ascii_lowercase();
+        if code.starts_with("fa") || code.starts_with("per") {
+            Some(Language::Persian)
+        } else if code.starts_with("en") {
+            Some(Language::English)
+        } else {
+            None
+        }
+    }
+
+    // In its own language, for Settings
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Persian => "فارسی",
+        }
+    }
+
+    pub fn is_rtl(self) -> bool {
+        self == Language::Persian
+    }
+
+    pub fn next(self) -> Language {
+        match self {
+            Language::English => Language::Persian,
+            Language::Persian => Language::English,
+        }
+    }
+}
+
+// The configured language, or else the locale's; English when neither is one with a catalog
+pub fn detect(configured: Option<&str>) -> Language {
+    configured.and_then(Language::from_code)
+        .or_else(|| {
+            ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+                .filter_map(|var| std::env::var(var).ok())
+                .find(|value| !value.is_empty())
+                .and_then(|value| Language::from_code(&value))
+        })
+        .unwrap_or(Language::English)
+}
+
+pub fn set(language: Language) {
+    CURRENT.store(language as u8, Ordering::Relaxed);
+}
+
+pub fn current() -> Language {
+    match CURRENT.load(Ordering::Relaxed) {
+        1 => Language::Persian,
+        _ => Language::English,
+    }
+}
+
+// "section.key" to message, per language
+fn catalog(language: Language) -> &'static HashMap<String, String> {
+    static LOADED: OnceLock<Vec<(Language, HashMap<String, String>)>> = OnceLock::new();
+    let loaded = LOADED.get_or_init(|| {
+        CATALOGS.iter().map(|(language, text)| {
+            let table: toml::Table = toml::from_str(text).expect("message catalogs are valid TOML");
+            let mut messages = HashMap::new();
+            for (section, entries) in &table {
+                for (key, message) in entries.as_table().into_iter().flatten() {
+                    if let Some(message) = message.as_str() {
+                        messages.insert(format!("{}.{}", section, key), message.to_string());
+                    }
+                }
+            }
+            (*language, messages)
+        }).collect()
+    });
+    &loaded.iter().find(|(l, _)| *l == language).expect("every language has a catalog").1
+}
+
+// The message for `key` in the current language, e.g. tr("help.quit")
+pub fn tr(key: &str) -> String {
+    catalog(current()).get(key)
+        .or_else(|| catalog(Language::English).get(key))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+// tr with its `{name}` placeholders filled in
+pub fn trf(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    args.iter().fold(tr(key), |message, (name, value)| message.replace(&format!("{{{}}}", name), &value.to_string()))
+}
+
+// Terminals draw cells left to right, so right-to-left text is put in display order here: Persian words read
+// correctly, with any English words and numbers inside them kept left to right
+pub fn visual(text: &str) -> String {
+    if !current().is_rtl() || text.is_ascii() {
+        return text.to_string();
+    }
+    let bidi = BidiInfo::new(text, Some(Level::rtl()));
+    bidi.paragraphs.iter().map(|para| bidi.reorder_line(para, para.range.clone())).collect()
+}
+
+// A line of spans in reading order: in a right-to-left language the spans are laid out from the right
+pub fn line(spans: Vec<Span<'static>>) -> Line<'static> {
+    if !current().is_rtl() {
+        return Line::from(spans);
+    }
+    let spans: Vec<Span> = spans.into_iter().rev().map(|span| Span::styled(visual(&span.content), span.style)).collect();
+    Line::from(spans).alignment(Alignment::Right)
+}
+
+// How a paragraph of messages lines up in the current language
+pub fn alignment() -> Alignment {
+    if current().is_rtl() { Alignment::Right } else { Alignment::Left }
+}
@@ -0,0 +1,48 @@
+//! GuardX's vaults as a library. The `GuardX` binary is a terminal UI and CLI on top of this crate.
+//!
+//! The format, key stretching and folder encryption live in the `guardx-core` crate, which has no terminal
+//! in it; they are re-exported here under the same paths, so `guardx::crypto` is `guardx_core::crypto`.
+//! This crate adds what works with vaults around them: backups, containers, mounts, remotes and the rest.
+
+pub use guardx_core::{age, crypto, entropy, error, filter, format, kdf, manifest, throttle};
+#[cfg(feature = "native")]
+pub use guardx_core::{config, filesystem, journal, keyring, oplog, registry, schedule, signing, storage};
+
+#[cfg(feature = "native")]
+pub mod backup;
+#[cfg(feature = "platform-unlock")]
+pub mod biometric;
+#[cfg(feature = "native")]
+pub mod drives;
+#[cfg(feature = "native")]
+pub mod finder;
+#[cfg(feature = "native")]
+pub mod hardware;
+#[cfg(feature = "native")]
+pub mod lockall;
+#[cfg(feature = "native")]
+pub mod logging;
+#[cfg(all(feature = "fuse", unix))]
+pub mod mount;
+#[cfg(feature = "native")]
+pub mod redteam;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "native")]
+pub mod report;
+#[cfg(feature = "remote")]
+pub mod s3;
+#[cfg(feature = "native")]
+pub mod session;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+#[cfg(feature = "native")]
+pub mod totp;
+#[cfg(feature = "native")]
+pub mod vault;
+#[cfg(feature = "native")]
+pub mod view;
+#[cfg(feature = "watch")]
+pub mod watch;
+#[cfg(feature = "webdav")]
+pub mod webdav;
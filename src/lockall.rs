@@ -0,0 +1,61 @@
+use crate::entropy;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// `guardx lock-all` reaches every running GuardX through a file in the data directory: each request
+// writes a fresh random token, and a running app locks when the token differs from the one it last saw.
+// A request made before an app started does not lock it.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn file() -> Result<PathBuf> {
+    let dir = dirs::data_dir().context("Could not find data directory")?.join("guardx");
+    Ok(dir.join("lock-all"))
+}
+
+pub fn request() -> Result<()> {
+    let path = file()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut token = [0u8; 16];
+    entropy::fill(&mut token)?;
+    fs::write(&path, token.iter().map(|b| format!("{:02x}", b)).collect::<String>())?;
+    Ok(())
+}
+
+fn token() -> Option<String> {
+    fs::read_to_string(file().ok()?).ok()
+}
+
+pub struct Watch {
+    seen: Option<String>,
+    checked: Instant,
+}
+
+impl Watch {
+    pub fn new() -> Self {
+        Watch { seen: token(), checked: Instant::now() }
+    }
+
+    // True once for every request made since the last call; the file is read at most every POLL_INTERVAL
+    pub fn requested(&mut self) -> bool {
+        if self.checked.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.checked = Instant::now();
+        let current = token();
+        if current.is_some() && current != self.seen {
+            self.seen = current;
+            return true;
+        }
+        false
+    }
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
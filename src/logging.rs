@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+
+// Diagnostics for after the fact: what GuardX did and what went wrong, one line per event in a file per
+// day under `logs` in the data directory, so an error the status bar has since overwritten can still be
+// read. Keys are never logged; paths are, so the logs say which folders were worked on.
+pub const DEFAULT_LEVEL: &str = "info";
+
+// Days of logs kept; older files are removed as new ones are started
+const KEPT_FILES: usize = 7;
+
+pub fn dir() -> Result<PathBuf> {
+    Ok(dirs::data_dir().context("Could not find data directory")?.join("guardx").join("logs"))
+}
+
+// `level` is "off", "error", "warn", "info", "debug" or "trace", as `log_level` in config.toml. Events
+// before this are dropped, and so is everything when it fails.
+pub fn init(level: &str) -> Result<()> {
+    let filter = LevelFilter::from_str(level).map_err(|_| anyhow::anyhow!("Unknown log_level {:?}; use off, error, warn, info, debug or trace", level))?;
+    let dir = dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("Could not create {:?}", dir))?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("guardx")
+        .filename_suffix("log")
+        .max_log_files(KEPT_FILES)
+        .build(&dir)
+        .with_context(|| format!("Could not open the log in {:?}", dir))?;
+    tracing_subscriber::fmt()
+        .with_writer(appender)
+        .with_ansi(false)
+        .with_max_level(filter)
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("Could not start logging: {}", e))
+}
+
+// The last `count` lines of the newest log, oldest first; empty when nothing has been logged yet
+pub fn tail(count: usize) -> Result<Vec<String>> {
+    let dir = dir()?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    // Dated names, so the newest sorts last
+    let newest = fs::read_dir(&dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("guardx.")) && path.extension().is_some_and(|ext| ext == "log"))
+        .max();
+    let Some(path) = newest else { return Ok(Vec::new()) };
+    let data = fs::read(&path).with_context(|| format!("Could not read {:?}", path))?;
+    let text = String::from_utf8_lossy(&data);
+    let lines: Vec<&str> = text.lines().collect();
+    Ok(lines[lines.len().saturating_sub(count)..].iter().map(|line| line.to_string()).collect())
+}
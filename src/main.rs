@@ -1,23 +1,57 @@
 use anyhow::Result;
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::prelude::*;
-use crossterm::terminal::{SetTitle};
+use clap::Parser;
 use std::io;
 
 mod ui;
-mod filesystem;
-mod crypto;
+mod cli;
+mod protocol;
+mod doctor;
+mod theme;
+mod i18n;
+mod clipboard;
 
+use guardx::config::{self, Config};
+use guardx::entropy;
+use guardx::logging;
 use ui::{App, run_app};
 
 fn main() -> Result<()> {
+    // clap exits with 2 on bad arguments, which the exit-code contract reserves for a wrong key
+    let args = cli::Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { cli::EXIT_USAGE } else { cli::EXIT_OK })
+    });
+    config::select_profile(args.profile.clone());
+    if entropy::is_seeded() {
+        eprintln!("[!] Deterministic test mode: nonces and salts are predictable, do not use for real data");
+    }
+    // A malformed config.toml is reported by whatever loads it next
+    let level = Config::load().ok().and_then(|c| c.log_level).unwrap_or_else(|| logging::DEFAULT_LEVEL.to_string());
+    if let Err(e) = logging::init(&level) {
+        eprintln!("[!] Not logging: {:#}", e);
+    }
+    if args.command.is_some() {
+        if let Err(e) = cli::run(args) {
+            tracing::error!("{:#}", e);
+            eprintln!("Error: {:?}", e);
+            std::process::exit(cli::exit_code(&e));
+        }
+        return Ok(());
+    }
+
+    if !args.roots.is_empty() {
+        cli::remember_roots(&args.roots)?;
+    }
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    // With bracketed paste a pasted key arrives as one event rather than as a burst of keystrokes
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
@@ -28,7 +62,8 @@ fn main() -> Result<()> {
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
 
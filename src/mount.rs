@@ -0,0 +1,173 @@
+use crate::crypto::DecryptingReader;
+use crate::view::{Entry, View};
+use anyhow::{Context, Result};
+use fuser::{BackgroundSession, FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, Request};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use zeroize::Zeroizing;
+
+// Shows a View of an encrypted folder through FUSE, read-only; reads decrypt just the chunks they touch
+// into memory, so nothing decrypted is ever written to disk
+const ROOT: u64 = 1;
+// The folder is not expected to change under the mount
+const TTL: Duration = Duration::from_secs(60);
+
+pub struct Mount {
+    key: Zeroizing<String>,
+    // Inode n + 2 is entries[n]
+    entries: Vec<Entry>,
+    // Files that cannot be shown, with why
+    pub skipped: Vec<(PathBuf, String)>,
+    uid: u32,
+    gid: u32,
+    mtime: SystemTime,
+    readers: HashMap<u64, DecryptingReader>,
+    next_handle: u64,
+}
+
+impl Mount {
+    // Fails with GuardXError::Authentication when the key does not open the folder
+    pub fn open(dir: &Path, key: &str) -> Result<Self> {
+        let view = View::open(dir, key)?;
+        let meta = std::fs::metadata(dir).with_context(|| format!("Could not open {:?}", dir))?;
+        Ok(Mount {
+            key: Zeroizing::new(key.to_string()),
+            entries: view.entries,
+            skipped: view.skipped,
+            uid: meta.uid(),
+            gid: meta.gid(),
+            mtime: meta.modified()?,
+            readers: HashMap::new(),
+            next_handle: 1,
+        })
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Blocks until the mountpoint is unmounted with `umount` or `fusermount -u`
+    pub fn serve(self, mountpoint: &Path) -> Result<()> {
+        fuser::mount2(self, mountpoint, &options()).with_context(|| format!("Could not mount on {:?}", mountpoint))
+    }
+
+    // Mounted until the session is dropped
+    pub fn spawn(self, mountpoint: &Path) -> Result<BackgroundSession> {
+        fuser::spawn_mount2(self, mountpoint, &options()).with_context(|| format!("Could not mount on {:?}", mountpoint))
+    }
+
+    fn entry(&self, ino: u64) -> Option<&Entry> {
+        ino.checked_sub(2).and_then(|n| self.entries.get(n as usize))
+    }
+
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let (kind, perm, size, mtime) = match ino {
+            ROOT => (FileType::Directory, 0o555, 0, self.mtime),
+            _ => {
+                let entry = self.entry(ino)?;
+                (FileType::RegularFile, 0o444, entry.size, entry.mtime)
+            }
+        };
+        Some(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind,
+            perm,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+}
+
+fn options() -> Vec<MountOption> {
+    vec![MountOption::RO, MountOption::NoExec, MountOption::FSName("guardx".to_string()), MountOption::Subtype("guardx".to_string())]
+}
+
+impl Filesystem for Mount {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let found = match parent {
+            ROOT => self.entries.iter().position(|e| e.name.as_os_str() == name).and_then(|n| self.attr(n as u64 + 2)),
+            _ => None,
+        };
+        match found {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        if ino != ROOT {
+            return reply.error(libc::ENOTDIR);
+        }
+        let dots = [(ROOT, FileType::Directory, OsStr::new(".")), (ROOT, FileType::Directory, OsStr::new(".."))];
+        let files = self.entries.iter().enumerate().map(|(n, e)| (n as u64 + 2, FileType::RegularFile, e.name.as_os_str()));
+        // Each entry's offset is the one to continue from after it
+        for (i, (ino, kind, name)) in dots.into_iter().chain(files).enumerate().skip(offset as usize) {
+            if reply.add(ino, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            return reply.error(libc::EROFS);
+        }
+        let Some(entry) = self.entry(ino) else { return reply.error(libc::ENOENT) };
+        match DecryptingReader::open(&entry.path, &self.key) {
+            Ok(reader) => {
+                let handle = self.next_handle;
+                self.next_handle += 1;
+                self.readers.insert(handle, reader);
+                reply.opened(handle, 0);
+            }
+            Err(_) => reply.error(libc::EIO),
+        }
+    }
+
+    // A damaged chunk fails the read with EIO rather than handing out unauthenticated bytes
+    fn read(&mut self, _req: &Request, _ino: u64, fh: u64, offset: i64, size: u32, _flags: i32, _lock: Option<u64>, reply: ReplyData) {
+        let Some(reader) = self.readers.get_mut(&fh) else { return reply.error(libc::EBADF) };
+        let mut buf = Zeroizing::new(vec![0u8; size as usize]);
+        let result = reader.seek(SeekFrom::Start(offset as u64)).and_then(|_| {
+            let mut filled = 0;
+            while filled < buf.len() {
+                match reader.read(&mut buf[filled..])? {
+                    0 => break,
+                    n => filled += n,
+                }
+            }
+            Ok(filled)
+        });
+        match result {
+            Ok(filled) => reply.data(&buf[..filled]),
+            Err(_) => reply.error(libc::EIO),
+        }
+    }
+
+    fn release(&mut self, _req: &Request, _ino: u64, fh: u64, _flags: i32, _lock: Option<u64>, _flush: bool, reply: ReplyEmpty) {
+        self.readers.remove(&fh);
+        reply.ok();
+    }
+}
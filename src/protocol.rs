@@ -0,0 +1,264 @@
+use guardx::backup;
+use guardx::config::Config;
+use guardx::crypto::{Cipher, OutputFormat};
+use guardx::entropy;
+use guardx::filesystem::{decrypt_folder_with, encrypt_folder_with};
+use guardx::hardware::Token;
+use guardx::lockall;
+use guardx::manifest::Manifest;
+use guardx::registry::Registry;
+use guardx::totp::Totp;
+use guardx::vault;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+// `guardx serve`: frontends drive GuardX as a subprocess, one JSON request per line on stdin and one
+// JSON event per line on stdout. Every event carries "protocol"; bump PROTOCOL_VERSION on any
+// incompatible change to the shapes below and only ever add optional fields otherwise.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    Encrypt { path: PathBuf, key: Option<Zeroizing<String>>, cipher: Option<String>, format: Option<String>, encrypt_names: Option<bool>, checksums: Option<bool> },
+    // `totp` is a code from the enrolled authenticator, asked for when left out and a second factor is enrolled
+    Decrypt { path: PathBuf, key: Option<Zeroizing<String>>, totp: Option<String> },
+    Verify { path: PathBuf },
+    // `backup` defaults to the folder's backup in config.toml; `key` is only asked for with `deep`
+    VerifyBackup { path: PathBuf, backup: Option<PathBuf>, #[serde(default)] deep: bool, key: Option<Zeroizing<String>> },
+    // `decoy` defaults to the folder's decoy in config.toml; with one, the duress key is asked for as well
+    Archive { path: PathBuf, dest: Option<PathBuf>, key: Option<Zeroizing<String>>, decoy: Option<PathBuf>, duress_key: Option<Zeroizing<String>> },
+    Extract { path: PathBuf, dest: Option<PathBuf>, key: Option<Zeroizing<String>>, totp: Option<String> },
+    ListVaults,
+    LockAll,
+    Shutdown,
+}
+
+#[derive(Deserialize)]
+struct Incoming {
+    id: Option<u64>,
+    #[serde(flatten)]
+    request: Request,
+}
+
+// Reply to a prompt event; `id` must match the request that asked
+#[derive(Deserialize)]
+struct Answer {
+    id: Option<u64>,
+    answer: Zeroizing<String>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    Ready { version: &'a str },
+    State { state: &'a str },
+    Progress { done: usize, total: usize, path: &'a Path },
+    Prompt { name: &'a str, secret: bool },
+    Result {
+        ok: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<Value>,
+    },
+}
+
+#[derive(Serialize)]
+struct Outgoing<'a> {
+    protocol: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+struct Server<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+}
+
+pub fn serve() -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut server = Server { input: stdin.lock(), output: std::io::stdout().lock() };
+    server.run()
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    fn emit(&mut self, id: Option<u64>, event: Event) -> Result<()> {
+        serde_json::to_writer(&mut self.output, &Outgoing { protocol: PROTOCOL_VERSION, id, event })?;
+        self.output.write_all(b"\n")?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    // Lines may carry keys, so the buffer is wiped once parsed; None means stdin closed
+    fn read_line(&mut self) -> Result<Option<Zeroizing<String>>> {
+        let mut line = Zeroizing::new(String::new());
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line))
+    }
+
+    fn run(&mut self) -> Result<()> {
+        self.emit(None, Event::Ready { version: env!("CARGO_PKG_VERSION") })?;
+        while let Some(line) = self.read_line()? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Incoming { id, request } = match serde_json::from_str(&line) {
+                Ok(incoming) => incoming,
+                Err(e) => {
+                    self.emit(None, Event::Result { ok: false, error: Some(format!("Invalid request: {}", e)), data: None })?;
+                    continue;
+                }
+            };
+            if let Request::Shutdown = request {
+                self.emit(id, Event::Result { ok: true, error: None, data: None })?;
+                break;
+            }
+            self.emit(id, Event::State { state: "busy" })?;
+            let result = match self.handle(id, request) {
+                Ok(data) => Event::Result { ok: true, error: None, data },
+                Err(e) => Event::Result { ok: false, error: Some(format!("{:#}", e)), data: None },
+            };
+            self.emit(id, result)?;
+            self.emit(id, Event::State { state: "idle" })?;
+        }
+        Ok(())
+    }
+
+    fn key(&mut self, id: Option<u64>, given: Option<Zeroizing<String>>) -> Result<Zeroizing<String>> {
+        let key = self.ask(id, "key", true, given)?;
+        self.bind(id, key)
+    }
+
+    // With a YubiKey enrolled, keys are bound to it; the "touch" state tells the frontend to ask for a touch
+    fn bind(&mut self, id: Option<u64>, key: Zeroizing<String>) -> Result<Zeroizing<String>> {
+        let Some(token) = Token::load()? else { return Ok(key) };
+        self.emit(id, Event::State { state: "touch" })?;
+        token.bind(&key)
+    }
+
+    // With a second factor enrolled, decrypting needs a current code as well as the key
+    fn second_factor(&mut self, id: Option<u64>, given: Option<String>) -> Result<()> {
+        let Some(totp) = Totp::load()? else { return Ok(()) };
+        let code = self.ask(id, "totp", false, given.map(Zeroizing::new))?;
+        if !totp.check(&code, entropy::timestamp()) {
+            return Err(anyhow::anyhow!("Wrong second factor code"));
+        }
+        Ok(())
+    }
+
+    fn ask(&mut self, id: Option<u64>, name: &str, secret: bool, given: Option<Zeroizing<String>>) -> Result<Zeroizing<String>> {
+        if let Some(key) = given.filter(|k| !k.is_empty()) {
+            return Ok(key);
+        }
+        self.emit(id, Event::Prompt { name, secret })?;
+        let line = self.read_line()?.with_context(|| format!("Input closed while waiting for the {}", name))?;
+        let answer: Answer = serde_json::from_str(&line).with_context(|| format!("Expected an answer to the {} prompt", name))?;
+        if answer.id != id {
+            return Err(anyhow::anyhow!("Expected the answer for request {:?}, got {:?}", id, answer.id));
+        }
+        if answer.answer.is_empty() {
+            return Err(anyhow::anyhow!("No {} given", name.replace('_', " ")));
+        }
+        Ok(answer.answer)
+    }
+
+    fn handle(&mut self, id: Option<u64>, request: Request) -> Result<Option<Value>> {
+        match request {
+            Request::Encrypt { path, key, cipher, format, encrypt_names, checksums } => {
+                let config = Config::load()?;
+                config.check_writable(&path)?;
+                let cipher = match cipher {
+                    Some(name) => Cipher::from_name(&name).with_context(|| format!("Unknown cipher '{}'", name))?,
+                    None => config.cipher(&path),
+                };
+                let format = match format {
+                    Some(name) => OutputFormat::from_name(&name).with_context(|| format!("Unknown format '{}'", name))?,
+                    None => config.output_format(&path),
+                };
+                let hide_names = encrypt_names.unwrap_or_else(|| config.encrypt_names(&path));
+                let checksums = checksums.unwrap_or_else(|| config.checksums(&path));
+                let key = self.key(id, key)?;
+                encrypt_folder_with(&path, &key, cipher, format, hide_names, checksums, &mut |done, total, file| {
+                    let _ = self.emit(id, Event::Progress { done, total, path: file });
+                })?;
+                Ok(None)
+            }
+            Request::Decrypt { path, key, totp } => {
+                Config::load()?.check_writable(&path)?;
+                let key = self.key(id, key)?;
+                self.second_factor(id, totp)?;
+                decrypt_folder_with(&path, &key, &mut |done, total, file| {
+                    let _ = self.emit(id, Event::Progress { done, total, path: file });
+                })?;
+                Ok(None)
+            }
+            Request::Verify { path } => {
+                let manifest = Manifest::load(&path)?;
+                let report = manifest.validate(&path)?;
+                Ok(Some(json!({
+                    "clean": report.is_clean(),
+                    "missing": report.missing,
+                    "damaged": report.damaged,
+                    "extraneous": report.extraneous,
+                })))
+            }
+            Request::VerifyBackup { path, backup, deep, key } => {
+                let config = Config::load()?;
+                let backup = match backup {
+                    Some(backup) => backup,
+                    None => config.backup(&path).map(Path::to_path_buf).context("No backup given and none set in config.toml")?,
+                };
+                let key = if deep { Some(self.key(id, key)?) } else { None };
+                let report = backup::compare(&path, &backup, key.as_deref().map(|k| k.as_str()), backup::Policy::of(config.lifecycle(&path)))?;
+                let drift: Vec<_> = report.drift.iter().map(|(name, drift)| json!({ "path": name, "drift": drift.describe() })).collect();
+                Ok(Some(json!({
+                    "clean": report.is_clean(),
+                    "matching": report.matching,
+                    "drift": drift,
+                })))
+            }
+            Request::Archive { path, dest, key, decoy, duress_key } => {
+                let dest = dest.unwrap_or_else(|| vault::container_path(&path));
+                if dest.exists() {
+                    return Err(anyhow::anyhow!("{:?} already exists", dest));
+                }
+                let config = Config::load()?;
+                let cipher = config.cipher(&path);
+                let decoy = decoy.or_else(|| config.decoy(&path).map(Path::to_path_buf));
+                let key = self.key(id, key)?;
+                match decoy {
+                    Some(decoy) => {
+                        let duress_key = self.ask(id, "duress_key", true, duress_key)?;
+                        let duress_key = self.bind(id, duress_key)?;
+                        vault::create_with_decoy(&path, &decoy, &dest, &key, &duress_key, cipher)?;
+                    }
+                    None => vault::create(&path, &dest, &key, cipher)?,
+                }
+                Ok(Some(json!({ "dest": dest })))
+            }
+            Request::Extract { path, dest, key, totp } => {
+                let dest = dest.unwrap_or_else(|| vault::extract_path(&path));
+                let key = self.key(id, key)?;
+                self.second_factor(id, totp)?;
+                vault::extract(&path, &dest, &key)?;
+                Ok(Some(json!({ "dest": dest })))
+            }
+            Request::ListVaults => Ok(Some(serde_json::to_value(Registry::load()?.vaults)?)),
+            // This process keeps no keys between requests; the running apps do
+            Request::LockAll => {
+                lockall::request()?;
+                Ok(None)
+            }
+            Request::Shutdown => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::crypto::{decrypt_file, decrypt_stream, is_chunked, replace_with, verify_file};
+use crate::entropy;
+use crate::error::GuardXError;
+use crate::filesystem::files_in;
+use crate::format::{Header, MAGIC, RECORD_OVERHEAD};
+use crate::manifest::Manifest;
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Rehearses what goes wrong in practice on copies of a few files from an encrypted folder: a wrong key, a
+// truncated file, a flipped bit and a run cut off halfway. Each drill passes when GuardX refuses cleanly,
+// leaves the file exactly as it was with no partial output behind, and, after an interruption, still opens
+// the file in full. The copies live in a temporary sandbox that is removed afterwards; the folder itself is
+// only read. Nothing decrypted is kept, but the interrupted run writes part of a file to the sandbox before
+// it is cut off, as a real one would.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scenario {
+    WrongKey,
+    Truncated,
+    FlippedBit,
+    Interrupted,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 4] = [Scenario::WrongKey, Scenario::Truncated, Scenario::FlippedBit, Scenario::Interrupted];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Scenario::WrongKey => "wrong key",
+            Scenario::Truncated => "truncated file",
+            Scenario::FlippedBit => "flipped bit",
+            Scenario::Interrupted => "interrupted run",
+        }
+    }
+}
+
+pub struct Drill {
+    pub scenario: Scenario,
+    // The copy's name in the folder
+    pub file: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+// Removed on drop, so a failing drill cannot leave copies behind
+struct Sandbox(PathBuf);
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Drills every scenario on up to `sample` chunked files picked at random. Fails with
+// GuardXError::Authentication when the key does not open the folder, since every drill needs the right key.
+pub fn run(dir: &Path, key: &str, sample: usize) -> Result<Vec<Drill>> {
+    Manifest::load(dir).with_context(|| format!("{:?} is not an encrypted folder", dir))?.check_key(key)?;
+    let mut files = Vec::new();
+    for path in files_in(dir)? {
+        if is_chunked(&path)? {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow::anyhow!("{:?} has no GuardX-format files to drill on", dir));
+    }
+    // Partial Fisher-Yates: the first `sample` files end up a random pick
+    let sample = sample.clamp(1, files.len());
+    for i in 0..sample {
+        let mut random = [0u8; 8];
+        entropy::fill(&mut random)?;
+        let j = i + (u64::from_le_bytes(random) % (files.len() - i) as u64) as usize;
+        files.swap(i, j);
+    }
+    files.truncate(sample);
+
+    let mut id = [0u8; 8];
+    entropy::fill(&mut id)?;
+    let sandbox = Sandbox(std::env::temp_dir().join(format!("guardx-red-team-{}", hex(&id))));
+    fs::create_dir_all(&sandbox.0).with_context(|| format!("Could not create the sandbox {:?}", sandbox.0))?;
+
+    let mut drills = Vec::new();
+    for (n, source) in files.iter().enumerate() {
+        let file = source.file_name().unwrap_or_default().to_string_lossy().to_string();
+        // The untouched copy must open, or the drills below would prove nothing
+        verify_file(source, key).with_context(|| format!("{} does not decrypt as it is, so it cannot be drilled on", file))?;
+        for (i, scenario) in Scenario::ALL.into_iter().enumerate() {
+            let copy = sandbox.0.join(format!("{}-{}", n, i));
+            fs::copy(source, &copy).with_context(|| format!("Could not copy {} into the sandbox", file))?;
+            let (passed, detail) = drill(scenario, &copy, key).unwrap_or_else(|e| (false, format!("the drill itself failed: {:#}", e)));
+            drills.push(Drill { scenario, file: file.clone(), passed, detail });
+        }
+    }
+    Ok(drills)
+}
+
+fn drill(scenario: Scenario, copy: &Path, key: &str) -> Result<(bool, String)> {
+    let len = fs::metadata(copy)?.len();
+    match scenario {
+        Scenario::WrongKey => {
+            let mut wrong = [0u8; 16];
+            entropy::fill(&mut wrong)?;
+            let before = fs::read(copy)?;
+            judge(copy, &before, decrypt_file(copy, &hex(&wrong)), |f| matches!(f, GuardXError::Authentication))
+        }
+        Scenario::Truncated => {
+            OpenOptions::new().write(true).open(copy)?.set_len(len / 2)?;
+            let before = fs::read(copy)?;
+            judge(copy, &before, decrypt_file(copy, key), |_| true)
+        }
+        Scenario::FlippedBit => {
+            let at = first_ciphertext_byte(copy)?;
+            let mut file = OpenOptions::new().read(true).write(true).open(copy)?;
+            let mut byte = [0u8; 1];
+            file.seek(SeekFrom::Start(at))?;
+            file.read_exact(&mut byte)?;
+            file.seek(SeekFrom::Start(at))?;
+            file.write_all(&[byte[0] ^ 0x01])?;
+            drop(file);
+            let before = fs::read(copy)?;
+            judge(copy, &before, decrypt_file(copy, key), |f| matches!(f, GuardXError::Authentication))
+        }
+        // Reading stops halfway through, as when a drive is pulled or the process is killed
+        Scenario::Interrupted => {
+            let before = fs::read(copy)?;
+            let mut cut = BufReader::new(File::open(copy)?.take(len / 2));
+            match replace_with(copy, |w| decrypt_stream(&mut cut, w, key)) {
+                Ok(()) => return Ok((false, "finished although its input was cut off".to_string())),
+                Err(e) if GuardXError::of(&e).is_none() => return Ok((false, format!("failed the wrong way: {:#}", e))),
+                Err(_) => {}
+            }
+            if let Err(e) = untouched(copy, &before) {
+                return Ok((false, format!("cut off halfway, but {:#}", e)));
+            }
+            Ok(match verify_file(copy, key) {
+                Ok(()) => (true, "cut off halfway, left the file as it was, and it still opens in full".to_string()),
+                Err(e) => (false, format!("no longer opens after the interruption: {:#}", e)),
+            })
+        }
+    }
+}
+
+// Passes when `result` failed with the expected kind of failure and the file was not touched
+fn judge(copy: &Path, before: &[u8], result: Result<()>, expected: impl Fn(&GuardXError) -> bool) -> Result<(bool, String)> {
+    let refusal = match result {
+        Ok(()) => return Ok((false, "opened anyway".to_string())),
+        Err(e) => match GuardXError::of(&e) {
+            Some(f) if expected(&f) => f.to_string(),
+            _ => return Ok((false, format!("failed the wrong way: {:#}", e))),
+        },
+    };
+    Ok(match untouched(copy, before) {
+        Ok(()) => (true, format!("refused ({}) and left the file as it was", refusal)),
+        Err(e) => (false, format!("refused ({}), but {:#}", refusal, e)),
+    })
+}
+
+// Decrypting a file with a hidden name writes under its real name, so the whole sandbox is searched for leftovers
+fn untouched(copy: &Path, before: &[u8]) -> Result<()> {
+    let sandbox = copy.parent().context("The copy is not in the sandbox")?;
+    for entry in fs::read_dir(sandbox)? {
+        if entry?.path().extension().is_some_and(|ext| ext == "gxtmp") {
+            return Err(anyhow::anyhow!("left partial output behind"));
+        }
+    }
+    if !copy.is_file() || fs::read(copy)? != before {
+        return Err(anyhow::anyhow!("changed the file"));
+    }
+    Ok(())
+}
+
+// Inside the first chunk's ciphertext, past the header and the record's type, length and nonce
+fn first_ciphertext_byte(path: &Path) -> Result<u64> {
+    let mut file = BufReader::new(File::open(path)?);
+    file.seek(SeekFrom::Start(MAGIC.len() as u64))?;
+    let header_len = Header::read_after_magic(&mut file)?.to_bytes().len() as u64;
+    let mut head = [0u8; 5];
+    file.read_exact(&mut head)?;
+    let len = u32::from_le_bytes([head[1], head[2], head[3], head[4]]) as u64;
+    Ok(header_len + RECORD_OVERHEAD as u64 + len / 2)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
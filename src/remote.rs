@@ -0,0 +1,178 @@
+use crate::age;
+use crate::crypto::{is_chunked, replace_with};
+use crate::filesystem::files_in;
+use crate::manifest::{Manifest, CHECKSUMS_NAME, MANIFEST_NAME, SIGNATURES_NAME};
+use crate::s3::S3;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+// Somewhere off the machine to keep a copy of an encrypted folder, for `guardx push` and `guardx pull`.
+// Only what is already encrypted is ever sent: the folder's encrypted files, its manifest, and its
+// encrypted checksums and signatures. A push refuses a folder that still holds plaintext.
+pub trait Backend {
+    // The files directly under the remote location
+    fn list(&self) -> Result<Vec<RemoteFile>>;
+    fn upload(&self, name: &str, data: &mut dyn Read, len: u64) -> Result<()>;
+    fn download(&self, name: &str, to: &mut dyn Write) -> Result<()>;
+    fn remove(&self, name: &str) -> Result<()>;
+}
+
+pub struct RemoteFile {
+    pub name: String,
+    pub size: u64,
+}
+
+// Kept next to the files on the remote: the BLAKE3 hash of each file as stored, so both directions only
+// transfer what changed. Hashes of ciphertext reveal nothing about the plaintext.
+pub const INDEX_NAME: &str = ".guardx-remote.json";
+
+#[derive(Serialize, Deserialize, Default)]
+struct Index {
+    files: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+pub struct Transfer {
+    pub sent: Vec<String>,
+    pub unchanged: usize,
+    // Deleted from the remote because they are gone from the folder
+    pub removed: Vec<String>,
+    // In the folder but not in the remote copy; a pull leaves them alone
+    pub extraneous: Vec<String>,
+}
+
+// `s3://bucket/prefix` is the only kind so far
+pub fn open(url: &str) -> Result<Box<dyn Backend>> {
+    match url.split_once("://") {
+        Some(("s3", rest)) => {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            Ok(Box::new(S3::new(bucket, prefix)?))
+        }
+        _ => Err(anyhow::anyhow!("Unsupported remote {:?}; use s3://bucket/prefix", url)),
+    }
+}
+
+// Sends the files that differ from the remote copy, the manifest last so the copy never lists files it
+// does not have yet, then removes what the folder no longer has. `progress` gets (done, total, name).
+pub fn push(dir: &Path, backend: &dyn Backend, progress: &mut dyn FnMut(usize, usize, &str)) -> Result<Transfer> {
+    let manifest = Manifest::load(dir).with_context(|| format!("{:?} is not an encrypted folder", dir))?;
+    let mut names = Vec::new();
+    for path in files_in(dir)? {
+        // v1 vaults hold legacy files, which have no header to recognise them by
+        if manifest.format_version >= 2 && !is_chunked(&path)? && !age::is_age(&path)? {
+            return Err(anyhow::anyhow!("{:?} is not encrypted; encrypt the folder before pushing it", path));
+        }
+        names.push(file_name(&path)?);
+    }
+    for reserved in [CHECKSUMS_NAME, SIGNATURES_NAME, MANIFEST_NAME] {
+        if dir.join(reserved).is_file() {
+            names.push(reserved.to_string());
+        }
+    }
+
+    let remote = backend.list()?;
+    let old = load_index(backend, &remote)?.unwrap_or_default();
+    let mut index = Index::default();
+    let mut transfer = Transfer::default();
+    for (i, name) in names.iter().enumerate() {
+        progress(i, names.len(), name);
+        let path = dir.join(name);
+        let hash = hash_file(&path)?;
+        let stored = remote.iter().any(|f| f.name == *name) && old.files.get(name) == Some(&hash);
+        if stored {
+            transfer.unchanged += 1;
+        } else {
+            let len = fs::metadata(&path)?.len();
+            backend.upload(name, &mut File::open(&path)?, len).with_context(|| format!("Could not send {}", name))?;
+            transfer.sent.push(name.clone());
+        }
+        index.files.insert(name.clone(), hash);
+    }
+    let data = serde_json::to_vec_pretty(&index)?;
+    backend.upload(INDEX_NAME, &mut &data[..], data.len() as u64).context("Could not send the index")?;
+    for file in remote.iter().filter(|f| f.name != INDEX_NAME && !index.files.contains_key(&f.name)) {
+        backend.remove(&file.name).with_context(|| format!("Could not remove {} from the remote copy", file.name))?;
+        transfer.removed.push(file.name.clone());
+    }
+    Ok(transfer)
+}
+
+// Fetches the files whose hash differs from the local ones into `dir`, creating it if needed. Each file is
+// checked against the index before it replaces anything, so a damaged download never lands.
+pub fn pull(backend: &dyn Backend, dir: &Path, progress: &mut dyn FnMut(usize, usize, &str)) -> Result<Transfer> {
+    let remote = backend.list()?;
+    let index = load_index(backend, &remote)?.context("There is no GuardX copy there to pull")?;
+    fs::create_dir_all(dir)?;
+    let mut names: Vec<&String> = index.files.keys().collect();
+    names.sort_by_key(|name| *name == MANIFEST_NAME);
+    let mut transfer = Transfer::default();
+    for (i, name) in names.iter().enumerate() {
+        progress(i, names.len(), name);
+        // Names come from the remote, so they must stay inside the folder
+        if name.is_empty() || name.contains(['/', '\\']) || *name == "." || *name == ".." {
+            return Err(anyhow::anyhow!("The remote copy lists an invalid file name: {:?}", name));
+        }
+        let path = dir.join(name);
+        let expected = &index.files[*name];
+        if path.is_file() && hash_file(&path)? == *expected {
+            transfer.unchanged += 1;
+            continue;
+        }
+        replace_with(&path, |w| {
+            let mut hashing = Hashing { inner: w, hasher: blake3::Hasher::new() };
+            backend.download(name, &mut hashing)?;
+            if hashing.hasher.finalize().to_hex().as_str() != expected {
+                return Err(anyhow::anyhow!("{} does not match the remote index; it changed during the pull or was damaged", name));
+            }
+            Ok(())
+        })?;
+        transfer.sent.push(name.to_string());
+    }
+    for path in files_in(dir)? {
+        let name = file_name(&path)?;
+        if !index.files.contains_key(&name) {
+            transfer.extraneous.push(name);
+        }
+    }
+    Ok(transfer)
+}
+
+fn load_index(backend: &dyn Backend, remote: &[RemoteFile]) -> Result<Option<Index>> {
+    if !remote.iter().any(|f| f.name == INDEX_NAME) {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    backend.download(INDEX_NAME, &mut data).context("Could not fetch the index")?;
+    serde_json::from_slice(&data).map(Some).context("Malformed remote index")
+}
+
+fn file_name(path: &Path) -> Result<String> {
+    path.file_name().and_then(|n| n.to_str()).map(str::to_string).with_context(|| format!("File name is not valid UTF-8: {:?}", path))
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+struct Hashing<'a, W: Write> {
+    inner: &'a mut W,
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> Write for Hashing<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
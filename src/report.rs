@@ -0,0 +1,66 @@
+use crate::journal::Operation;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+// What a folder's encryption or decryption did, shown when it finishes and exportable as text or JSON.
+// Reports name the files, so an exported one tells whoever reads it what the folder holds.
+#[derive(Serialize)]
+pub struct Report {
+    pub operation: Operation,
+    pub folder: PathBuf,
+    pub finished: i64,
+    pub elapsed_secs: f64,
+    // Size of the processed files as they were before the operation
+    pub bytes: u64,
+    pub processed: Vec<String>,
+    pub skipped: Vec<Item>,
+    pub failed: Vec<Item>,
+}
+
+#[derive(Serialize)]
+pub struct Item {
+    pub file: String,
+    pub reason: String,
+}
+
+impl Report {
+    pub fn text(&self) -> String {
+        let mut text = format!("GuardX {} report\n", self.operation.name());
+        text.push_str(&format!("Folder: {}\n", self.folder.display()));
+        text.push_str(&format!("Finished: {}\n", local_time(self.finished)));
+        text.push_str(&format!("Elapsed: {:.1} s\n", self.elapsed_secs));
+        text.push_str(&format!("Processed: {} files, {} bytes\n", self.processed.len(), self.bytes));
+        for (heading, items) in [("Skipped", &self.skipped), ("Failed", &self.failed)] {
+            text.push_str(&format!("{}: {}\n", heading, items.len()));
+            for item in items {
+                text.push_str(&format!("  {}: {}\n", item.file, item.reason));
+            }
+        }
+        if !self.processed.is_empty() {
+            text.push_str("Processed files:\n");
+            for file in &self.processed {
+                text.push_str(&format!("  {}\n", file));
+            }
+        }
+        text
+    }
+
+    // Into `reports` in the data directory, named after the folder and the time; returns where it went
+    pub fn export(&self, json: bool) -> Result<PathBuf> {
+        let dir = dirs::data_dir().context("Could not find data directory")?.join("guardx").join("reports");
+        fs::create_dir_all(&dir)?;
+        let folder = self.folder.file_name().unwrap_or_default().to_string_lossy();
+        let stamp = DateTime::from_timestamp(self.finished, 0).unwrap_or_default().with_timezone(&Local).format("%Y%m%d-%H%M%S");
+        let path = dir.join(format!("{}-{}-{}.{}", folder, self.operation.name(), stamp, if json { "json" } else { "txt" }));
+        let data = if json { serde_json::to_string_pretty(self)? } else { self.text() };
+        fs::write(&path, data).with_context(|| format!("Could not write {:?}", path))?;
+        Ok(path)
+    }
+}
+
+fn local_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default().with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string()
+}
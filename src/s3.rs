@@ -0,0 +1,204 @@
+use crate::remote::{Backend, RemoteFile};
+use anyhow::{Context, Result};
+use ring::{digest, hmac};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+// An S3 bucket, or another service with the same API (MinIO, Cloudflare R2, Backblaze B2, Wasabi), as
+// `s3://bucket/prefix`. Credentials come from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, plus
+// AWS_SESSION_TOKEN for temporary ones; the region from AWS_REGION or AWS_DEFAULT_REGION, us-east-1 when
+// neither is set. AWS_ENDPOINT_URL points at another service, whose buckets are then addressed by path.
+// Every request is signed with Signature Version 4.
+pub struct S3 {
+    agent: ureq::Agent,
+    // Scheme and host the requests go to
+    endpoint: String,
+    host: String,
+    // "/bucket" when the bucket is addressed by path, empty when it is part of the host
+    bucket_path: String,
+    // Ends with '/' unless empty
+    prefix: String,
+    region: String,
+    access_key: String,
+    secret_key: Zeroizing<String>,
+    session_token: Option<String>,
+}
+
+// SHA-256 of an empty body
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+// Uploads stream the file rather than hashing it first; TLS protects it in transit
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+impl S3 {
+    pub fn new(bucket: &str, prefix: &str) -> Result<Self> {
+        if bucket.is_empty() {
+            return Err(anyhow::anyhow!("The S3 URL needs a bucket: s3://bucket/prefix"));
+        }
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let access_key = var("AWS_ACCESS_KEY_ID").context("Set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY to use S3")?;
+        let secret_key = Zeroizing::new(var("AWS_SECRET_ACCESS_KEY").context("Set AWS_SECRET_ACCESS_KEY to use S3")?);
+        let region = var("AWS_REGION").or_else(|| var("AWS_DEFAULT_REGION")).unwrap_or_else(|| "us-east-1".to_string());
+        let (endpoint, bucket_path) = match var("AWS_ENDPOINT_URL") {
+            Some(url) => (url.trim_end_matches('/').to_string(), format!("/{}", uri_encode(bucket, true))),
+            None => (format!("https://{}.s3.{}.amazonaws.com", bucket, region), String::new()),
+        };
+        let host = endpoint.split_once("://").map_or(endpoint.as_str(), |(_, rest)| rest).to_string();
+        if host.is_empty() || host.contains('/') {
+            return Err(anyhow::anyhow!("AWS_ENDPOINT_URL must be a scheme and host, like https://minio.local:9000"));
+        }
+        let prefix = prefix.trim_matches('/');
+        Ok(S3 {
+            agent: ureq::AgentBuilder::new().timeout_connect(Duration::from_secs(30)).timeout_read(Duration::from_secs(120)).build(),
+            endpoint,
+            host,
+            bucket_path,
+            prefix: if prefix.is_empty() { String::new() } else { format!("{}/", prefix) },
+            region,
+            access_key,
+            secret_key,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    // A request for `key` (empty for the bucket itself) carrying a Signature V4 Authorization header
+    fn signed(&self, method: &str, key: &str, query: &[(&str, &str)], payload_hash: &str) -> ureq::Request {
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+        let path = format!("{}/{}", self.bucket_path, uri_encode(key, false));
+        let mut params: Vec<String> = query.iter().map(|(k, v)| format!("{}={}", uri_encode(k, true), uri_encode(v, true))).collect();
+        params.sort();
+        let query_string = params.join("&");
+
+        let mut headers = vec![("host", self.host.clone()), ("x-amz-content-sha256", payload_hash.to_string()), ("x-amz-date", amz_date.clone())];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query_string, canonical_headers, signed_headers, payload_hash);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref()));
+        let mut signing_key = Zeroizing::new(hmac_sha256(format!("AWS4{}", &*self.secret_key).as_bytes(), date.as_bytes()));
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = Zeroizing::new(hmac_sha256(&signing_key[..], part.as_bytes()));
+        }
+        let signature = hex(&hmac_sha256(&signing_key[..], string_to_sign.as_bytes()));
+
+        let url = match query_string.as_str() {
+            "" => format!("{}{}", self.endpoint, path),
+            query => format!("{}{}?{}", self.endpoint, path, query),
+        };
+        let mut request = self.agent.request(method, &url).set(
+            "Authorization",
+            &format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.access_key, scope, signed_headers, signature),
+        );
+        // ureq sets Host from the URL
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.set(name, value);
+        }
+        request
+    }
+
+    fn object(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+impl Backend for S3 {
+    fn list(&self) -> Result<Vec<RemoteFile>> {
+        let mut files = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.prefix.as_str()), ("delimiter", "/")];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let body = checked(self.signed("GET", "", &query, EMPTY_SHA256).call(), "Listing the bucket")?.into_string()?;
+            for contents in elements(&body, "Contents") {
+                let key = element(contents, "Key").context("Malformed S3 listing")?;
+                let size = element(contents, "Size").and_then(|s| s.parse().ok()).context("Malformed S3 listing")?;
+                if let Some(name) = key.strip_prefix(&self.prefix).filter(|n| !n.is_empty()) {
+                    files.push(RemoteFile { name: name.to_string(), size });
+                }
+            }
+            match element(&body, "NextContinuationToken") {
+                Some(next) if element(&body, "IsTruncated").as_deref() == Some("true") => token = Some(next),
+                _ => return Ok(files),
+            }
+        }
+    }
+
+    fn upload(&self, name: &str, data: &mut dyn Read, len: u64) -> Result<()> {
+        // With a length ureq sends the body as is; S3 does not take chunked uploads
+        let request = self.signed("PUT", &self.object(name), &[], UNSIGNED_PAYLOAD).set("Content-Length", &len.to_string());
+        checked(request.send(data), &format!("Uploading {}", name)).map(drop)
+    }
+
+    fn download(&self, name: &str, to: &mut dyn Write) -> Result<()> {
+        let response = checked(self.signed("GET", &self.object(name), &[], EMPTY_SHA256).call(), &format!("Downloading {}", name))?;
+        io::copy(&mut response.into_reader(), to)?;
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        checked(self.signed("DELETE", &self.object(name), &[], EMPTY_SHA256).call(), &format!("Removing {}", name)).map(drop)
+    }
+}
+
+// S3 explains a refusal in an XML body with a Code and a Message
+fn checked(result: Result<ureq::Response, ureq::Error>, what: &str) -> Result<ureq::Response> {
+    match result {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            let code = element(&body, "Code").unwrap_or_default();
+            let message = element(&body, "Message").unwrap_or_default();
+            Err(anyhow::anyhow!("{} failed with HTTP {}: {} {}", what, status, code, message))
+        }
+        Err(e) => Err(anyhow::Error::new(e).context(format!("{} failed", what))),
+    }
+}
+
+// Every `<tag>...</tag>` in `xml`; S3's listings are flat enough not to need a real parser
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let inner = &rest[start + open.len()..];
+        let Some(end) = inner.find(&close) else { break };
+        found.push(&inner[..end]);
+        rest = &inner[end + close.len()..];
+    }
+    found
+}
+
+fn element(xml: &str, tag: &str) -> Option<String> {
+    elements(xml, tag).first().map(|text| unescape(text))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&#13;", "\r").replace("&amp;", "&")
+}
+
+// Percent-encodes everything but unreserved characters; '/' too unless it separates path segments
+fn uri_encode(text: &str, encode_slash: bool) -> String {
+    let mut out = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(byte as char),
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
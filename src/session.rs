@@ -0,0 +1,172 @@
+use crate::age::{self, Recipient};
+use crate::crypto::{self, reencrypt_delta, replace_with, Cipher};
+use crate::entropy;
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// A working session: encrypted files checked out as plaintext copies in a private folder so other programs
+// can open them. Each copy remembers the hash of what was checked out, so closing the session re-encrypts
+// exactly the copies that changed and wipes the rest. A copy is only removed once its file is sealed again.
+pub struct Session {
+    dir: PathBuf,
+    copies: Vec<WorkingCopy>,
+    checked_out: usize,
+}
+
+pub struct WorkingCopy {
+    pub source: PathBuf,
+    pub copy: PathBuf,
+    checked_out: blake3::Hash,
+    seal: Seal,
+}
+
+// How a changed copy goes back into its file
+enum Seal {
+    GuardX(Cipher),
+    AgePassphrase,
+    AgeRecipients(Vec<Recipient>),
+}
+
+pub enum Outcome {
+    Unchanged,
+    Reencrypted,
+    // The copy is left where it is so the changes are not lost
+    Failed(String),
+}
+
+impl Session {
+    pub fn new() -> Result<Self> {
+        let mut id = [0u8; 8];
+        entropy::fill(&mut id)?;
+        let name = format!("guardx-session-{}", id.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        let dir = std::env::temp_dir().join(name);
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir).with_context(|| format!("Could not create the session folder {:?}", dir))?;
+        Ok(Session { dir, copies: Vec::new(), checked_out: 0 })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn copies(&self) -> &[WorkingCopy] {
+        &self.copies
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.copies.is_empty()
+    }
+
+    // Decrypts `source` into the session under its real name and returns the copy's path. A file that is
+    // already checked out returns the existing copy, changes and all. `recipients` are the folder's public
+    // keys, which age files encrypted to them go back to.
+    pub fn check_out(&mut self, source: &Path, key: &str, cipher: Cipher, recipients: Vec<Recipient>) -> Result<PathBuf> {
+        if let Some(existing) = self.copies.iter().find(|c| c.source == source) {
+            return Ok(existing.copy.clone());
+        }
+        let seal = if age::is_age(source)? {
+            if recipients.is_empty() { Seal::AgePassphrase } else { Seal::AgeRecipients(recipients) }
+        } else {
+            Seal::GuardX(cipher)
+        };
+        let name = crypto::original_name(source, key).ok().flatten()
+            .or_else(|| source.file_name().map(|n| n.to_string_lossy().to_string()))
+            .context("Invalid file name")?;
+        // One folder per copy, so two files with the same name never collide
+        let folder = self.dir.join(self.checked_out.to_string());
+        fs::create_dir(&folder)?;
+        self.checked_out += 1;
+        let copy = folder.join(name);
+        crypto::decrypt_to(source, &copy, key)?;
+        let checked_out = hash(&copy)?;
+        self.copies.push(WorkingCopy { source: source.to_path_buf(), copy: copy.clone(), checked_out, seal });
+        Ok(copy)
+    }
+
+    // Copies whose contents differ from what was checked out
+    pub fn touched(&self) -> Vec<&WorkingCopy> {
+        self.copies.iter().filter(|c| c.is_touched()).collect()
+    }
+
+    // Re-encrypts the changed copies into their files (or, with `keep_changes` off, throws the changes away)
+    // and wipes every copy that is no longer needed. Copies that failed stay in the session.
+    pub fn close(&mut self, key: &str, keep_changes: bool) -> Vec<(PathBuf, Outcome)> {
+        let mut outcomes = Vec::new();
+        let mut kept = Vec::new();
+        for copy in self.copies.drain(..) {
+            let outcome = if keep_changes && copy.is_touched() {
+                match copy.reseal(key) {
+                    Ok(()) => Outcome::Reencrypted,
+                    Err(e) => Outcome::Failed(format!("{:#}", e)),
+                }
+            } else {
+                Outcome::Unchanged
+            };
+            if let Outcome::Failed(_) = outcome {
+                outcomes.push((copy.source.clone(), outcome));
+                kept.push(copy);
+                continue;
+            }
+            if let Err(e) = wipe(&copy.copy) {
+                outcomes.push((copy.source.clone(), Outcome::Failed(format!("Could not wipe the working copy: {}", e))));
+                kept.push(copy);
+                continue;
+            }
+            outcomes.push((copy.source, outcome));
+        }
+        self.copies = kept;
+        if self.copies.is_empty() {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+        outcomes
+    }
+}
+
+impl WorkingCopy {
+    // A copy that cannot be read any more counts as touched, so closing reports it instead of wiping it
+    pub fn is_touched(&self) -> bool {
+        hash(&self.copy).map_or(true, |h| h != self.checked_out)
+    }
+
+    fn reseal(&self, key: &str) -> Result<()> {
+        match &self.seal {
+            // Unchanged chunks keep their ciphertext, and a sealed name stays in the header
+            Seal::GuardX(cipher) => reencrypt_delta(&self.copy, &self.source, key, *cipher).map(drop),
+            Seal::AgePassphrase => replace_with(&self.source, |w| age::encrypt_stream(&mut BufReader::new(File::open(&self.copy)?), w, key)),
+            Seal::AgeRecipients(recipients) => {
+                replace_with(&self.source, |w| age::encrypt_stream_to(&mut BufReader::new(File::open(&self.copy)?), w, recipients))
+            }
+        }
+    }
+}
+
+fn hash(path: &Path) -> Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize())
+}
+
+// Overwrites the plaintext before removing it; best effort, since SSDs and copy-on-write filesystems may
+// keep the old blocks around
+pub fn wipe(path: &Path) -> Result<()> {
+    let len = fs::metadata(path)?.len();
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    let zeros = [0u8; 64 * 1024];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        left -= n as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+    fs::remove_file(path)?;
+    if let Some(folder) = path.parent() {
+        let _ = fs::remove_dir(folder);
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use crate::crypto::{decrypt_stream, encrypt_stream, original_name_in, temp_path, Cipher};
+use crate::format::{self, MAGIC};
+use anyhow::{Context, Result};
+use ssh2::{CheckResult, KnownHostFileKind, OpenFlags, OpenType, RenameFlags, Session};
+use std::io::{BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// A folder on an SFTP server, as `sftp://[user@]host[:port][/path]`, browsed from the folder list. Its
+// files are encrypted and decrypted by streaming them through this machine: the plaintext only ever
+// exists on the server and in memory here, never on the local disk. The server must already be in
+// ~/.ssh/known_hosts; logging in goes through the SSH agent, or an unencrypted key in ~/.ssh.
+pub struct Root {
+    pub url: String,
+    // The folder the URL points at, as the server resolves it
+    pub path: PathBuf,
+    host: String,
+    // Kept for as long as `sftp` is in use
+    _session: Session,
+    sftp: ssh2::Sftp,
+}
+
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    // Seconds since the epoch
+    pub modified: Option<u64>,
+}
+
+// Keys tried in order when the agent has none the server accepts
+const KEY_FILES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+impl Root {
+    pub fn connect(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix("sftp://").with_context(|| format!("Not an SFTP URL: {:?}; use sftp://user@host/path", url))?;
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host)) => (user.to_string(), host),
+            None => (std::env::var("USER").or_else(|_| std::env::var("USERNAME")).context("No user in the SFTP URL and none logged in")?, authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().with_context(|| format!("Invalid port in {:?}", url))?),
+            None => (host_port, 22),
+        };
+        if host.is_empty() {
+            return Err(anyhow::anyhow!("The SFTP URL needs a host: sftp://user@host/path"));
+        }
+
+        let tcp = TcpStream::connect((host, port)).with_context(|| format!("Could not reach {}:{}", host, port))?;
+        tcp.set_read_timeout(Some(Duration::from_secs(60)))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake().with_context(|| format!("SSH handshake with {} failed", host))?;
+        check_host_key(&session, host, port)?;
+        authenticate(&session, &user)?;
+
+        let sftp = session.sftp().context("The server does not offer SFTP")?;
+        // Without a path the login folder
+        let wanted = if path.is_empty() { PathBuf::from(".") } else { PathBuf::from(format!("/{}", path)) };
+        let path = sftp.realpath(&wanted).with_context(|| format!("{} is not on the server", wanted.display()))?;
+        Ok(Root { url: url.to_string(), path, host: host.to_string(), _session: session, sftp })
+    }
+
+    // Folders first, then files, each by name; GuardX's temporary files are left out
+    pub fn list(&self, dir: &Path) -> Result<Vec<Entry>> {
+        let mut entries: Vec<Entry> = self.sftp.readdir(dir).with_context(|| format!("Could not list {}", dir.display()))?
+            .into_iter()
+            .filter_map(|(path, stat)| {
+                let name = path.file_name()?.to_string_lossy().to_string();
+                (!name.ends_with(".gxtmp")).then(|| Entry { name, is_dir: stat.is_dir(), size: stat.size.unwrap_or(0), modified: stat.mtime })
+            })
+            .collect();
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+        Ok(entries)
+    }
+
+    // How the file is shown in the history, e.g. sftp://nas/home/me/notes.txt
+    pub fn describe(&self, path: &Path) -> String {
+        format!("sftp://{}{}", self.host, path.display())
+    }
+
+    pub fn is_chunked(&self, path: &Path) -> Result<bool> {
+        let mut magic = [0u8; 6];
+        let n = format::read_full(&mut self.sftp.open(path)?, &mut magic)?;
+        Ok(n == magic.len() && &magic == MAGIC)
+    }
+
+    // In GuardX's format under the same name; hidden names and age files are not offered on SFTP
+    pub fn encrypt(&self, path: &Path, key: &str, cipher: Cipher) -> Result<()> {
+        if self.is_chunked(path)? {
+            return Err(anyhow::anyhow!("{} is already encrypted", self.describe(path)));
+        }
+        self.replace_with(path, |w| encrypt_stream(&mut BufReader::new(self.sftp.open(path)?), w, key, cipher))
+    }
+
+    // A file with a sealed name comes back under that name, like `decrypt_file`; returns where the plaintext went
+    pub fn decrypt(&self, path: &Path, key: &str) -> Result<PathBuf> {
+        if !self.is_chunked(path)? {
+            return Err(anyhow::anyhow!("{} is not a GuardX-format file", self.describe(path)));
+        }
+        let dest = match original_name_in(&mut BufReader::new(self.sftp.open(path)?), key)? {
+            Some(name) => path.with_file_name(name),
+            None => path.to_path_buf(),
+        };
+        if dest != path && self.sftp.stat(&dest).is_ok() {
+            return Err(anyhow::anyhow!("{} already exists", self.describe(&dest)));
+        }
+        self.replace_with(&dest, |w| decrypt_stream(&mut BufReader::new(self.sftp.open(path)?), w, key))?;
+        if dest != path {
+            self.sftp.unlink(path)?;
+        }
+        Ok(dest)
+    }
+
+    // As `crypto::replace_with`, on the server: the file is only replaced once `write` succeeded
+    fn replace_with<F>(&self, dest: &Path, write: F) -> Result<()>
+    where
+        F: FnOnce(&mut BufWriter<ssh2::File>) -> Result<()>,
+    {
+        let tmp = temp_path(dest);
+        let result: Result<()> = (|| {
+            let file = self.sftp.open_mode(&tmp, OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE, 0o600, OpenType::File)?;
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            let _ = self.sftp.unlink(&tmp);
+            return Err(e);
+        }
+        // Servers speaking SFTP version 3 (OpenSSH among them) refuse to rename over an existing file, so
+        // the old one is removed first there; the finished file is already in place under its temporary name
+        if self.sftp.rename(&tmp, dest, Some(RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE)).is_err() {
+            if self.sftp.stat(dest).is_ok() {
+                self.sftp.unlink(dest).with_context(|| format!("Could not replace {}", self.describe(dest)))?;
+            }
+            self.sftp.rename(&tmp, dest, None).with_context(|| format!("Could not move {} into place", self.describe(dest)))?;
+        }
+        Ok(())
+    }
+}
+
+// Only servers already in known_hosts are trusted; anything else could be someone in between
+fn check_host_key(session: &Session, host: &str, port: u16) -> Result<()> {
+    let mut known = session.known_hosts()?;
+    let file = dirs::home_dir().context("Could not find home directory")?.join(".ssh").join("known_hosts");
+    if file.is_file() {
+        known.read_file(&file, KnownHostFileKind::OpenSSH).with_context(|| format!("Could not read {:?}", file))?;
+    }
+    let (key, _) = session.host_key().context("The server sent no host key")?;
+    match known.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => Err(anyhow::anyhow!("{} is not in ~/.ssh/known_hosts; connect once with ssh to check and add its key", host)),
+        CheckResult::Mismatch => Err(anyhow::anyhow!("The host key of {} does not match ~/.ssh/known_hosts; refusing to connect", host)),
+        CheckResult::Failure => Err(anyhow::anyhow!("Could not check the host key of {}", host)),
+    }
+}
+
+fn authenticate(session: &Session, user: &str) -> Result<()> {
+    if session.userauth_agent(user).is_ok() && session.authenticated() {
+        return Ok(());
+    }
+    let ssh = dirs::home_dir().context("Could not find home directory")?.join(".ssh");
+    for name in KEY_FILES {
+        let key = ssh.join(name);
+        if key.is_file() && session.userauth_pubkey_file(user, None, &key, None).is_ok() && session.authenticated() {
+            return Ok(());
+        }
+    }
+    Err(anyhow::anyhow!("The server did not accept {}'s keys; add one to the SSH agent", user))
+}
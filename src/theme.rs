@@ -0,0 +1,216 @@
+use anyhow::{Context, Result};
+use guardx::config::{Config, Profile, THEME_PRESETS};
+use ratatui::style::Color;
+use std::str::FromStr;
+
+// Every colour the TUI draws with. The presets are built in; more themes are TOML files in `themes` in the
+// config directory, named after the file, that start from a preset (`base`, dark by default) and set any
+// of the colours below by name ("light-cyan"), hex ("#282a36") or 256-colour index ("236").
+#[derive(Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    pub text: Color,
+    pub accent: Color,
+    pub border: Color,
+    // The selected row in lists and tables
+    pub selection: Color,
+    pub selection_text: Color,
+    // Numbers, headers, hints and anything archived
+    pub muted: Color,
+    pub encrypted: Color,
+    pub plain: Color,
+    pub success: Color,
+    pub warning: Color,
+    pub error: Color,
+    // Running operations and watched folders
+    pub busy: Color,
+    pub remote: Color,
+    // Behind confirmations and prompts, and behind the preview's border
+    pub popup: Color,
+    pub preview: Color,
+}
+
+impl Theme {
+    // One of THEME_PRESETS
+    pub fn preset(name: &str) -> Option<Theme> {
+        let rgb = |hex: u32| Color::Rgb((hex >> 16) as u8, (hex >> 8) as u8, hex as u8);
+        let dark = Theme {
+            name: "dark".to_string(),
+            background: Color::Rgb(20, 20, 30),
+            text: Color::White,
+            accent: Color::Cyan,
+            border: Color::Gray,
+            selection: Color::Rgb(50, 50, 70),
+            selection_text: Color::White,
+            muted: Color::DarkGray,
+            encrypted: Color::LightCyan,
+            plain: Color::LightGreen,
+            success: Color::Green,
+            warning: Color::Yellow,
+            error: Color::Red,
+            busy: Color::Magenta,
+            remote: Color::LightBlue,
+            popup: Color::Rgb(20, 20, 20),
+            preview: Color::Rgb(30, 30, 40),
+        };
+        let theme = match name {
+            "dark" => dark,
+            "light" => Theme { name: name.to_string(), background: Color::Gray, text: Color::Black, accent: Color::Blue, border: Color::DarkGray, ..dark },
+            "solarized" => Theme {
+                name: name.to_string(),
+                background: rgb(0x002b36),
+                text: rgb(0x93a1a1),
+                accent: rgb(0x268bd2),
+                border: rgb(0x586e75),
+                selection: rgb(0x073642),
+                selection_text: rgb(0xfdf6e3),
+                muted: rgb(0x586e75),
+                encrypted: rgb(0x2aa198),
+                plain: rgb(0x859900),
+                success: rgb(0x859900),
+                warning: rgb(0xb58900),
+                error: rgb(0xdc322f),
+                busy: rgb(0xd33682),
+                remote: rgb(0x6c71c4),
+                popup: rgb(0x073642),
+                preview: rgb(0x073642),
+            },
+            "dracula" => Theme {
+                name: name.to_string(),
+                background: rgb(0x282a36),
+                text: rgb(0xf8f8f2),
+                accent: rgb(0xbd93f9),
+                border: rgb(0x6272a4),
+                selection: rgb(0x44475a),
+                selection_text: rgb(0xf8f8f2),
+                muted: rgb(0x6272a4),
+                encrypted: rgb(0x8be9fd),
+                plain: rgb(0x50fa7b),
+                success: rgb(0x50fa7b),
+                warning: rgb(0xf1fa8c),
+                error: rgb(0xff5555),
+                busy: rgb(0xff79c6),
+                remote: rgb(0xffb86c),
+                popup: rgb(0x21222c),
+                preview: rgb(0x21222c),
+            },
+            "gruvbox" => Theme {
+                name: name.to_string(),
+                background: rgb(0x282828),
+                text: rgb(0xebdbb2),
+                accent: rgb(0xfabd2f),
+                border: rgb(0x665c54),
+                selection: rgb(0x504945),
+                selection_text: rgb(0xfbf1c7),
+                muted: rgb(0x928374),
+                encrypted: rgb(0x83a598),
+                plain: rgb(0xb8bb26),
+                success: rgb(0xb8bb26),
+                warning: rgb(0xfe8019),
+                error: rgb(0xfb4934),
+                busy: rgb(0xd3869b),
+                remote: rgb(0x8ec07c),
+                popup: rgb(0x1d2021),
+                preview: rgb(0x1d2021),
+            },
+            // Black and white with the Okabe-Ito colours, which stay apart with any kind of colour blindness;
+            // nothing is drawn in dark grey
+            "high-contrast" => Theme {
+                name: name.to_string(),
+                background: Color::Black,
+                text: Color::White,
+                accent: rgb(0xf0e442),
+                border: Color::White,
+                selection: Color::White,
+                selection_text: Color::Black,
+                muted: Color::Gray,
+                encrypted: rgb(0x56b4e9),
+                plain: rgb(0xf0e442),
+                success: rgb(0x009e73),
+                warning: rgb(0xe69f00),
+                error: rgb(0xd55e00),
+                busy: rgb(0xcc79a7),
+                remote: Color::White,
+                popup: Color::Black,
+                preview: Color::Black,
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    // A preset, or else the theme file of that name
+    pub fn named(name: &str) -> Result<Theme> {
+        match Self::preset(name) {
+            Some(theme) => Ok(theme),
+            None => Self::load(name),
+        }
+    }
+
+    // The profile's theme; dark without one, or when its file does not load
+    pub fn of(profile: Option<&Profile>) -> Theme {
+        let dark = || Self::preset("dark").expect("dark is a preset");
+        profile.and_then(|p| p.theme.as_deref()).map_or_else(dark, |name| Self::named(name).unwrap_or_else(|_| dark()))
+    }
+
+    fn load(name: &str) -> Result<Theme> {
+        let path = Config::themes_dir()?.join(format!("{}.toml", name));
+        let text = std::fs::read_to_string(&path).with_context(|| format!("Could not read {:?}", path))?;
+        let table: toml::Table = toml::from_str(&text).with_context(|| format!("Malformed theme: {:?}", path))?;
+        let base = match table.get("base") {
+            Some(base) => base.as_str().context("base is not a string")?,
+            None => "dark",
+        };
+        let mut theme = Self::preset(base).with_context(|| format!("Unknown base {:?} in {:?}; use {}", base, path, THEME_PRESETS.join(", ")))?;
+        theme.name = name.to_string();
+        for (key, value) in table.iter().filter(|(key, _)| *key != "base") {
+            let value = value.as_str().with_context(|| format!("{} in {:?} is not a string", key, path))?;
+            let color = Color::from_str(value).map_err(|_| anyhow::anyhow!("{} in {:?}: {:?} is not a colour", key, path, value))?;
+            let slot = theme.slot(key).with_context(|| format!("Unknown colour {:?} in {:?}", key, path))?;
+            *slot = color;
+        }
+        Ok(theme)
+    }
+
+    fn slot(&mut self, key: &str) -> Option<&mut Color> {
+        Some(match key {
+            "background" => &mut self.background,
+            "text" => &mut self.text,
+            "accent" => &mut self.accent,
+            "border" => &mut self.border,
+            "selection" => &mut self.selection,
+            "selection_text" => &mut self.selection_text,
+            "muted" => &mut self.muted,
+            "encrypted" => &mut self.encrypted,
+            "plain" => &mut self.plain,
+            "success" => &mut self.success,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            "busy" => &mut self.busy,
+            "remote" => &mut self.remote,
+            "popup" => &mut self.popup,
+            "preview" => &mut self.preview,
+            _ => return None,
+        })
+    }
+}
+
+// The presets, then the theme files in name order
+pub fn available() -> Vec<String> {
+    let mut names: Vec<String> = THEME_PRESETS.iter().map(|name| name.to_string()).collect();
+    let mut files: Vec<String> = Config::themes_dir().ok()
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let stem = path.file_stem()?.to_string_lossy().to_string();
+            path.extension().is_some_and(|ext| ext == "toml").then_some(stem)
+        })
+        .filter(|name| !names.contains(name))
+        .collect();
+    files.sort();
+    names.extend(files);
+    names
+}
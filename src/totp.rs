@@ -0,0 +1,136 @@
+use crate::entropy;
+use crate::manifest::{from_hex, to_hex};
+use anyhow::{Context, Result};
+use ring::hmac;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+// Time-based one-time codes (RFC 6238: HMAC-SHA1, 30-second steps, 6 digits), the kind every
+// authenticator app generates. With a secret enrolled, GuardX asks for a code before it decrypts. This
+// is a gate in GuardX itself: the files are still encrypted with the key alone, and the secret is only
+// protected by the permissions of its file.
+pub const DIGITS: usize = 6;
+const STEP: i64 = 30;
+// Codes from one step before or after are accepted too, for clocks that are a little off
+const SKEW: i64 = 1;
+const SECRET_LEN: usize = 20;
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn file() -> Result<PathBuf> {
+    Ok(dirs::config_dir().context("Could not find config directory")?.join("guardx").join("totp"))
+}
+
+pub struct Totp {
+    secret: Zeroizing<Vec<u8>>,
+}
+
+impl Totp {
+    pub fn generate() -> Result<Self> {
+        let mut secret = Zeroizing::new(vec![0u8; SECRET_LEN]);
+        entropy::fill(&mut secret[..])?;
+        Ok(Totp { secret })
+    }
+
+    // The enrolled secret, if there is one
+    pub fn load() -> Result<Option<Self>> {
+        let path = file()?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => Zeroizing::new(contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let secret = from_hex(contents.trim()).with_context(|| format!("Malformed second factor secret in {:?}", path))?;
+        Ok(Some(Totp { secret: Zeroizing::new(secret) }))
+    }
+
+    // Replaces any earlier enrollment; the file is only readable by its owner
+    pub fn save(&self) -> Result<()> {
+        let path = file()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        remove()?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut out = options.open(&path)?;
+        out.write_all(Zeroizing::new(to_hex(&self.secret)).as_bytes())?;
+        out.sync_all()?;
+        Ok(())
+    }
+
+    // What authenticator apps ask for when the secret is typed in instead of scanned
+    pub fn secret_base32(&self) -> String {
+        let mut out = String::new();
+        for group in self.secret.chunks(5) {
+            let mut bits = [0u8; 5];
+            bits[..group.len()].copy_from_slice(group);
+            let value = bits.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+            for i in 0..(group.len() * 8).div_ceil(5) {
+                out.push(BASE32[(value >> (35 - i * 5) & 31) as usize] as char);
+            }
+        }
+        out
+    }
+
+    // The otpauth:// link the QR code carries
+    pub fn uri(&self, account: &str) -> String {
+        let account: String = account.chars().filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')).collect();
+        format!("otpauth://totp/GuardX:{}?secret={}&issuer=GuardX&digits={}&period={}", account, self.secret_base32(), DIGITS, STEP)
+    }
+
+    pub fn code_at(&self, time: i64) -> String {
+        let counter = time.div_euclid(STEP) as u64;
+        let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &self.secret), &counter.to_be_bytes());
+        let tag = tag.as_ref();
+        let offset = (tag[tag.len() - 1] & 0x0f) as usize;
+        let value = u32::from_be_bytes([tag[offset], tag[offset + 1], tag[offset + 2], tag[offset + 3]]) & 0x7fff_ffff;
+        format!("{:0width$}", value % 10u32.pow(DIGITS as u32), width = DIGITS)
+    }
+
+    pub fn check(&self, code: &str, now: i64) -> bool {
+        let code = code.trim();
+        code.len() == DIGITS && (-SKEW..=SKEW).any(|step| self.code_at(now + step * STEP) == code)
+    }
+}
+
+pub fn remove() -> Result<()> {
+    match fs::remove_file(file()?) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SHA-1 secret of RFC 6238's test vectors
+    fn rfc() -> Totp {
+        Totp { secret: Zeroizing::new(b"12345678901234567890".to_vec()) }
+    }
+
+    #[test]
+    fn codes_match_the_rfc_6238_vectors() {
+        // Appendix B lists 8 digits; these are their last 6
+        let vectors = [(59, "287082"), (1111111109, "081804"), (1111111111, "050471"), (1234567890, "005924"), (2000000000, "279037"), (20000000000, "353130")];
+        for (time, code) in vectors {
+            assert_eq!(rfc().code_at(time), code, "at {}", time);
+        }
+        assert_eq!(rfc().secret_base32(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    }
+
+    #[test]
+    fn a_code_is_accepted_one_step_either_side() {
+        let totp = rfc();
+        assert!(totp.check(" 287082 ", 59));
+        assert!(totp.check("287082", 59 + STEP));
+        assert!(totp.check("287082", 59 - STEP));
+        assert!(!totp.check("287082", 59 + 2 * STEP));
+        assert!(!totp.check("28708", 59));
+        assert!(!totp.check("94287082", 59));
+    }
+}
@@ -1,37 +1,230 @@
-use crate::filesystem::FileSystem;
-use crate::crypto::{encrypt_file, decrypt_file};
-use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEventKind, MouseEventKind};
+use guardx::backup;
+use guardx::biometric;
+use guardx::config::{self, Config, Label, Lifecycle};
+use guardx::drives::{self, Change};
+use guardx::entropy;
+use guardx::error::GuardXError;
+use guardx::filesystem::{files_in, verify_folder, FileSystem, Job, Plan, Undo};
+use guardx::storage::Kind;
+use guardx::filter;
+use guardx::finder::{self, Progress};
+use guardx::hardware::{self, Token};
+use guardx::journal::{self, Interrupted, Operation};
+use guardx::kdf;
+use guardx::keyring;
+use guardx::lockall;
+use guardx::logging;
+use guardx::oplog::{self, clean_note, LogEntry, MAX_NOTE_LEN};
+use guardx::age;
+use guardx::crypto::{aes_accelerated, header_info, is_chunked, original_name, set_pipeline_depth, Cipher, KeyVerifier, OutputFormat};
+use guardx::manifest::{sha256_file, Manifest, MANIFEST_NAME};
+use guardx::report::{Item, Report};
+use guardx::session::{Outcome, Session};
+use guardx::sftp;
+use guardx::signing::{self, Verdict};
+use guardx::totp::{self, Totp};
+use guardx::vault::{self, Container};
+use guardx::watch::{Changes, Guard};
+use crate::clipboard::{Clipboard, Via};
+use crate::i18n::{self, tr, trf};
+use crate::theme::{self, Theme};
+use anyhow::{Context, Result};
+use crossterm::event::{self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, List, ListItem, Paragraph, ListState, BorderType, Table, Row, Cell, Clear, Gauge},
+    widgets::{Block, Borders, List, ListItem, Paragraph, ListState, BorderType, Table, Row, Cell, Clear, Gauge, Wrap},
     style::{Style, Color, Modifier},
 };
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
 use std::time::{Duration, Instant};
 use std::fs;
 use std::fs::Metadata;
 use std::time::SystemTime;
 use chrono::DateTime as ChronoDateTime;
 use chrono::Utc;
+use zeroize::{Zeroize, Zeroizing};
+
+// Previewing a container entry decrypts at most this much of it
+const PREVIEW_LIMIT: u64 = 1024 * 1024;
+// Files whose hash the quick check on opening a vault compares
+const QUICK_CHECK_SAMPLE: usize = 8;
+// Wrong second factor codes in a row before GuardX locks itself
+const MAX_TOTP_FAILURES: u32 = 5;
 
 pub struct App {
     fs: FileSystem,
     selected_dir: ListState,
     selected_file: ListState,
-    current_files: Vec<(String, Metadata, bool)>,
-    key_input: String,
+    // Metadata is read when a file first scrolls into view, or for all of them when sorting needs it
+    current_files: Vec<(String, Option<Metadata>, bool)>,
+    // The selected folder could not be read when it was last listed
+    files_denied: bool,
+    // First file shown in the files table, and how many rows it had room for
+    file_offset: usize,
+    file_rows: usize,
+    // Folders listed before, so moving the cursor back to one does not read it again; see `listing`
+    listings: HashMap<PathBuf, Listing>,
+    key_input: Zeroizing<String>,
     mode: Mode,
     status: String,
     should_quit: bool,
-    last_processed: Instant,
+    // When the last destructive key was taken, for the repeat guard
+    last_destructive: Option<Instant>,
     success_timer: Option<Instant>,
-    progress: f64,
-    in_progress: bool,
     preview_content: Option<String>,
-    history: Vec<(String, Instant, bool)>,
+    // The preview shows a decrypted container entry, so copying it (Y) starts the clipboard's clear timer
+    preview_decrypted: bool,
+    clipboard: Clipboard,
+    // When the clipboard is emptied of decrypted text; `clipboard_clear_for` after it was copied
+    clipboard_clear_at: Option<Instant>,
+    clipboard_clear_for: Duration,
+    // Oldest first, from the persistent log: earlier sessions, `guardx` commands and the daemon included
+    history: Vec<LogEntry>,
+    // Entries added to the history since perform last appended them to the persistent log
+    unlogged: Vec<LogEntry>,
+    // Full-screen history (I): the selected entry among those shown, and whether only failures are
+    history_view: ListState,
+    history_failures_only: bool,
+    history_from_files: bool,
+    // Outcomes ([OK], [!] and [X] in the status bar) newest last, shown as toasts for `toast_for` and kept
+    // for the notification center (!) until NOTICES_KEPT push them out
+    notices: VecDeque<Notice>,
+    toast_for: Duration,
+    notice_view: ListState,
+    notices_from_files: bool,
+    // The details view (F3) of the selected file; its SHA-256 comes from a worker while `details_hash` is open
+    details: Vec<(&'static str, String)>,
+    details_hash: Option<mpsc::Receiver<Result<String>>>,
+    // Bookmark popup ('): the selected bookmark
+    bookmark_view: ListState,
+    // Which bookmarks point at no folder, looked up when the bookmarks open
+    bookmark_missing: Vec<bool>,
+    // Two-pane layout (C): the other pane, which copies, moves and encryptions from the selected folder go to
+    other_pane: Option<Pane>,
     settings: Settings,
     animation_step: usize,
     info_mode: bool,
+    // Files in all the listed folders, for the dashboard; counted every frame while it is shown
+    file_count: usize,
+    last_activity: Instant,
+    lock_watch: lockall::Watch,
+    // Removable drives with encrypted folders on them, offered one at a time while browsing
+    drive_watch: drives::Watch,
+    drive_offers: Vec<(PathBuf, Vec<PathBuf>)>,
+    unlock_input: Zeroizing<String>,
+    lock_verifier: Option<KeyVerifier>,
+    // Enrolled second factor; once a code is accepted, decrypting needs none until the key changes or GuardX locks
+    totp: Option<Totp>,
+    totp_passed: bool,
+    totp_input: String,
+    totp_failures: u32,
+    // Where an accepted (or cancelled) code returns to
+    totp_return: Option<Mode>,
+    // Secret being enrolled in the security screen, with its QR code, until a code from it is typed in
+    totp_pending: Option<(Totp, String)>,
+    // Enrolled YubiKey every key is bound to, and what waits for it to be touched
+    hardware: Option<Token>,
+    touch_pending: Option<Touch>,
+    // Touch ID or Windows Hello, asked once its prompt is on screen, and whether a key is stored for it
+    platform_pending: Option<Confirm>,
+    biometric_stored: bool,
+    delete_permanently: bool,
+    undo_stack: Vec<(usize, Undo)>,
+    // Stored (random) name -> real name for files whose name is sealed in the header
+    revealed_names: HashMap<String, String>,
+    rename_input: String,
+    // Typed after n (a folder, or a nested path of them) or f (a file in the selected folder)
+    create_name: String,
+    create_from_files: bool,
+    sort_key: SortKey,
+    sort_reversed: bool,
+    container: Option<(std::path::PathBuf, Container)>,
+    selected_entry: ListState,
+    // SFTP root being browsed (the `sftp` list in config.toml, shown after the folders), the folder open
+    // in it and that folder's entries
+    sftp: Option<sftp::Root>,
+    sftp_dir: PathBuf,
+    sftp_entries: Vec<sftp::Entry>,
+    selected_sftp: ListState,
+    // Current key, new key and its confirmation while re-keying a folder
+    rekey_old: Zeroizing<String>,
+    rekey_new: Zeroizing<String>,
+    rekey_confirm: Zeroizing<String>,
+    // Opens the decoy slot of a container being archived from a folder that has a decoy
+    duress_key: Zeroizing<String>,
+    // Type-ahead (/) types into `jump_input`; `jump_files` says which list it searches, and it ends on its own
+    // TYPE_AHEAD_PAUSE after the last letter
+    jump_input: String,
+    jump_files: bool,
+    typed_at: Option<Instant>,
+    // The fuzzy finder's (Ctrl+P) index of everything under `finder_roots`, filled in by a worker while
+    // `finder_rx` is open, with the query being typed and the indices of its best matches
+    finder_entries: Vec<finder::Entry>,
+    finder_rx: Option<mpsc::Receiver<Progress>>,
+    finder_roots: Vec<PathBuf>,
+    finder_truncated: bool,
+    finder_query: String,
+    finder_matches: Vec<usize>,
+    finder_view: ListState,
+    finder_from_files: bool,
+    // The command palette (:): what is typed, the indices into COMMANDS of its best matches, and the list it
+    // was opened from
+    palette_query: String,
+    palette_matches: Vec<usize>,
+    palette_view: ListState,
+    palette_from_files: bool,
+    // The key of the command picked in the palette, handled next as if it had been pressed
+    palette_pick: Option<(KeyCode, KeyModifiers)>,
+    // Where the folder list and the files table were last drawn, inside their borders, so clicks and the
+    // wheel land on the row under the pointer; empty while the files table is not shown
+    folders_area: Rect,
+    files_area: Rect,
+    // Time and row of the last click, for telling a double click
+    last_click: Option<(Instant, u16)>,
+    preview_scroll: u16,
+    // Outcome of the quick check run when a vault is opened: true when nothing looked wrong
+    vault_checks: HashMap<PathBuf, bool>,
+    vault_check_tx: mpsc::Sender<(PathBuf, bool)>,
+    vault_check_rx: mpsc::Receiver<(PathBuf, bool)>,
+    // Encryptions and decryptions running on workers, by folder; only those folders are off limits meanwhile
+    jobs: HashMap<PathBuf, Running>,
+    job_tx: mpsc::Sender<JobEvent>,
+    job_rx: mpsc::Receiver<JobEvent>,
+    // Where each file of a folder's last operation got to, by file name; seeded from the journal at start
+    file_statuses: HashMap<PathBuf, HashMap<String, FileStatus>>,
+    // Folders whose dropped-in files are encrypted as they arrive (w)
+    guards: Vec<Guard>,
+    // Changes other programs make to the selected folder, so its files table stays current
+    dir_changes: Option<Changes>,
+    // File name and, for damaged files, why it failed to authenticate
+    verify_report: Vec<(String, Option<String>)>,
+    verify_note: Option<String>,
+    // Encryption waiting for its summary to be confirmed (e), with what it would do
+    preflight: Option<(Job, Plan)>,
+    // Folder whose decryption was refused for the wrong key; it starts again once a key is entered
+    retry_decrypt: Option<PathBuf>,
+    // Summaries of finished operations, oldest first, waiting to be shown or dismissed
+    reports: Vec<Report>,
+    report_from_files: bool,
+    // The end of the log file while the log viewer (O) is open, how many lines it is scrolled up from the
+    // newest, and whether it only shows warnings and errors
+    log_lines: Vec<String>,
+    log_scroll: usize,
+    log_problems_only: bool,
+    log_from_files: bool,
+    // Note typed with N; attached to the next significant operation in the history and the persistent log
+    pending_note: Option<String>,
+    note_input: String,
+    // Files opened with o as plaintext working copies; closing re-encrypts the ones that changed
+    session: Option<Session>,
+    quit_after_session: bool,
 }
 
 #[derive(PartialEq)]
@@ -40,21 +233,551 @@ pub enum Mode {
     NavigateFiles,
     EnterKey,
     CreateFolder,
+    CreateFile,
     Preview,
     Settings,
     ConfirmDeleteFolder,
     ConfirmDeleteFile,
+    RenameFile,
+    BrowseContainer,
+    BrowseSftp,
+    VerifyReport,
+    RekeyOld,
+    RekeyNew,
+    RekeyConfirm,
+    EnterDuressKey,
+    EnterTotp,
+    Security,
+    SecurityEnroll,
+    SecurityRemove,
+    TypeAhead,
+    EnterNote,
+    ConfirmSessionEnd,
+    ConfirmDrive,
+    ConfirmEncrypt,
+    OperationReport,
+    LogViewer,
+    HistoryBrowser,
+    Bookmarks,
+    Finder,
+    Palette,
+    Notifications,
+    Details,
+    Locked,
+}
+
+// What App::update is given: input from the terminal, Tick once a frame, or what an Effect came to
+pub enum Action {
+    Tick,
+    Key(KeyEvent),
+    // A command picked in the palette, handled as its key is
+    Picked(KeyCode, KeyModifiers),
+    Paste(String),
+    Mouse(MouseEvent),
+    Resize,
+    // The saved key, or None when there is none
+    KeyLoaded(Option<Zeroizing<String>>),
+    KeySaved(Result<()>),
+    // The first file of a folder as text; None when the folder has no files
+    FolderPreviewed(Result<Option<String>>),
+    FolderDeleted { index: usize, dir: Option<PathBuf>, permanent: bool, result: Result<Option<Undo>> },
+    FileDeleted { path: PathBuf, permanent: bool, result: Result<Option<Undo>> },
+    // Where the frame just drawn put things
+    Drawn(Drawn),
+}
+
+impl Action {
+    pub fn of(event: Event) -> Option<Action> {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => Some(Action::Key(key)),
+            Event::Paste(text) => Some(Action::Paste(text)),
+            Event::Mouse(mouse) => Some(Action::Mouse(mouse)),
+            Event::Resize(..) => Some(Action::Resize),
+            _ => None,
+        }
+    }
+}
+
+// What App::update leaves to App::perform: everything that reads or writes files, asks another program, a
+// server or a device, or needs the terminal. Effects whose outcome is plain data hand it back to update as
+// an Action; the rest say how they went in the status bar and the history, as update would.
+#[derive(PartialEq, Debug)]
+pub enum Effect {
+    OpenInSession,
+    EditConfig,
+    Relayout,
+    PreviewFirstFile(usize),
+    LoadSavedKey,
+    SaveKey,
+    DeleteFolder { index: usize, permanent: bool },
+    DeleteFile { dir: usize, name: String, permanent: bool },
+    // The selected folder's files; `fresh` reads them even when the cached listing looks current
+    ListFiles { fresh: bool },
+    // F5, which also says how many files there are
+    RefreshListing,
+    // After other programs changed the selected folder; keeps the selected file
+    RefreshFiles,
+    // Metadata of the files table's rows that came into view without it
+    StatFiles(Range<usize>),
+    SortFiles,
+    // Once a frame: finished jobs, the guards, removable drives and `guardx lock-all`
+    Poll,
+    CountFiles,
+    ClearClipboard,
+    // History entries not yet in the persistent log
+    WriteHistory,
+    Lock,
+    LockAll(String),
+    // Their prompts are on screen, so these may block until the YubiKey is touched or the system confirms
+    Touch(Touch),
+    Platform(Confirm),
+    ReleaseKey,
+    ForgetStoredKey,
+    DetectHardware,
+    SaveTotp,
+    RemoveTotp,
+    SetKey,
+    RevealNames,
+    Unlock,
+    Encrypt(usize),
+    Decrypt(usize),
+    StartEncrypt,
+    Undo,
+    Archive,
+    FinishArchive,
+    Verify,
+    VerifyBackup,
+    Sign,
+    CheckSignatures,
+    Rekey,
+    Arrange(Arrangement),
+    Expand,
+    ToggleShowArchived,
+    CycleLifecycle,
+    ToggleWatch,
+    SaveConfig,
+    OpenBookmarks,
+    GoToBookmark(usize),
+    GoToFound,
+    TogglePanes,
+    SwapPanes,
+    Transfer(Transfer),
+    OpenLog,
+    EndSession { quit: bool },
+    CloseSession { keep_changes: bool },
+    AnswerDrive(bool),
+    CycleProfile,
+    CycleTheme { forward: bool },
+    ExportReport { json: bool },
+    YankPath,
+    YankContents,
+    PreviewFile,
+    OpenDetails,
+    OpenContainer,
+    PreviewEntry,
+    ExtractEntry,
+    ExtractContainer,
+    OpenSftp,
+    EnterSftpDir,
+    LeaveSftpDir,
+    Sftp(Operation),
+    Create,
+    Rename,
+}
+
+// P, [, ] and L reorder the folder list and save the config
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Arrangement {
+    TogglePin,
+    MoveUp,
+    MoveDown,
+    CycleLabel,
+}
+
+// What ui() worked out while drawing, for update to keep: ratatui's scroll offsets, where the folder list
+// and the files table landed, for the mouse (empty when not shown), and the files table's rows
+pub struct Drawn {
+    folders_area: Rect,
+    files_area: Rect,
+    file_offset: usize,
+    file_rows: usize,
+    folder_offset: usize,
+    history_offset: usize,
+    bookmark_offset: usize,
+    finder_offset: usize,
+    palette_offset: usize,
+    notice_offset: usize,
+}
+
+impl Drawn {
+    // What a frame that shows none of it keeps
+    fn of(app: &App) -> Drawn {
+        Drawn {
+            folders_area: Rect::default(),
+            files_area: Rect::default(),
+            file_offset: app.file_offset,
+            file_rows: 0,
+            folder_offset: app.selected_dir.offset(),
+            history_offset: app.history_view.offset(),
+            bookmark_offset: app.bookmark_view.offset(),
+            finder_offset: app.finder_view.offset(),
+            palette_offset: app.palette_view.offset(),
+            notice_offset: app.notice_view.offset(),
+        }
+    }
+}
+
+// Keys waiting for the YubiKey; it blocks until touched, so the prompt is drawn before it is asked
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Touch {
+    Key,
+    Unlock,
+    Rekey,
+    Duress,
+}
+
+// A folder's files as last read, and the folder's modification time then
+pub struct Listing {
+    files: Vec<(String, Option<Metadata>, bool)>,
+    modified: Option<SystemTime>,
+    read: Instant,
+}
+
+const TYPE_AHEAD_PAUSE: Duration = Duration::from_millis(1500);
+
+// Matches the fuzzy finder lists at most
+const FINDER_MATCHES: usize = 200;
+
+// Where a palette command's key is handled: in the folder list, the files list or Settings
+#[derive(PartialEq, Clone, Copy)]
+enum Scope {
+    Folders,
+    Files,
+    Settings,
+}
+
+struct PaletteCommand {
+    name: &'static str,
+    scope: Scope,
+    key: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+const fn command(name: &'static str, scope: Scope, key: KeyCode) -> PaletteCommand {
+    PaletteCommand { name, scope, key, modifiers: KeyModifiers::NONE }
+}
+
+const fn ctrl(name: &'static str, c: char) -> PaletteCommand {
+    PaletteCommand { name, scope: Scope::Folders, key: KeyCode::Char(c), modifiers: KeyModifiers::CONTROL }
+}
+
+// Everything the palette (:) offers, in the order it lists them with nothing typed. Each runs as its key
+// would, so a new key only needs a line here to be found by name; the files list's commands are offered when
+// the palette was opened from it.
+const COMMANDS: &[PaletteCommand] = &[
+    command("Encrypt folder", Scope::Folders, KeyCode::Char('e')),
+    command("Decrypt folder", Scope::Folders, KeyCode::Char('d')),
+    command("Enter key", Scope::Folders, KeyCode::Char('k')),
+    command("Load saved key", Scope::Folders, KeyCode::Char('l')),
+    command("Save key", Scope::Folders, KeyCode::Char('v')),
+    command("Set key with Touch ID or Windows Hello", Scope::Folders, KeyCode::Char('h')),
+    command("Re-key folder (change its key)", Scope::Folders, KeyCode::Char('K')),
+    command("Verify folder integrity", Scope::Folders, KeyCode::Char('V')),
+    command("Verify folder against its backup", Scope::Folders, KeyCode::Char('B')),
+    command("Sign folder", Scope::Folders, KeyCode::Char('G')),
+    command("Check folder signatures", Scope::Folders, KeyCode::Char('g')),
+    command("Archive folder into a container", Scope::Folders, KeyCode::Char('a')),
+    command("Watch folder (encrypt dropped-in files)", Scope::Folders, KeyCode::Char('w')),
+    command("New folder (or nested folders)", Scope::Folders, KeyCode::Char('n')),
+    command("New empty file", Scope::Folders, KeyCode::Char('f')),
+    command("Preview first file", Scope::Folders, KeyCode::Char('p')),
+    command("Copy folder path", Scope::Folders, KeyCode::Char('y')),
+    command("Move folder to trash", Scope::Folders, KeyCode::Char('r')),
+    command("Delete folder permanently", Scope::Folders, KeyCode::Char('R')),
+    command("Undo last operation", Scope::Folders, KeyCode::Char('u')),
+    command("Attach a note to the next operation", Scope::Folders, KeyCode::Char('N')),
+    command("Pin or unpin folder", Scope::Folders, KeyCode::Char('P')),
+    command("Move pinned folder up", Scope::Folders, KeyCode::Char('[')),
+    command("Move pinned folder down", Scope::Folders, KeyCode::Char(']')),
+    command("Cycle folder colour label", Scope::Folders, KeyCode::Char('L')),
+    command("Cycle folder lifecycle (active, archived, retired)", Scope::Folders, KeyCode::Char('Z')),
+    command("Show or hide archived folders", Scope::Folders, KeyCode::Char('H')),
+    command("Bookmark folder", Scope::Folders, KeyCode::Char('b')),
+    command("Open bookmarks", Scope::Folders, KeyCode::Char('\'')),
+    ctrl("Find anywhere under the roots", 'p'),
+    command("Find in list by name", Scope::Folders, KeyCode::Char('/')),
+    command("Switch flat or tree folder list", Scope::Folders, KeyCode::Char('T')),
+    command("Show or hide two panes", Scope::Folders, KeyCode::Char('C')),
+    command("Swap panes", Scope::Folders, KeyCode::Tab),
+    command("Refresh listing", Scope::Folders, KeyCode::F(5)),
+    command("Show or hide info and history", Scope::Folders, KeyCode::Char('i')),
+    command("Open history", Scope::Folders, KeyCode::Char('I')),
+    command("Open log", Scope::Folders, KeyCode::Char('O')),
+    command("Notification center", Scope::Folders, KeyCode::Char('!')),
+    command("Dismiss notifications", Scope::Folders, KeyCode::Esc),
+    command("End session (re-encrypt opened files)", Scope::Folders, KeyCode::Char('W')),
+    ctrl("Lock", 'l'),
+    command("Quit", Scope::Folders, KeyCode::Char('q')),
+    command("Open file in editor or default app", Scope::Files, KeyCode::Char('o')),
+    command("Preview file", Scope::Files, KeyCode::Char('p')),
+    command("Browse container", Scope::Files, KeyCode::Enter),
+    command("File details (permissions, hashes, header)", Scope::Files, KeyCode::F(3)),
+    command("Copy file path", Scope::Files, KeyCode::Char('y')),
+    command("Copy file contents", Scope::Files, KeyCode::Char('Y')),
+    command("Rename file", Scope::Files, KeyCode::Char('m')),
+    command("Move file to trash", Scope::Files, KeyCode::Char('r')),
+    command("Delete file permanently", Scope::Files, KeyCode::Char('R')),
+    command("Copy file to other pane", Scope::Files, KeyCode::Char('c')),
+    command("Move file to other pane", Scope::Files, KeyCode::Char('M')),
+    command("Encrypt file into other pane", Scope::Files, KeyCode::Char('e')),
+    command("Sort files by next column", Scope::Files, KeyCode::Char('s')),
+    command("Reverse file order", Scope::Files, KeyCode::Char('S')),
+    command("Settings", Scope::Folders, KeyCode::Char('t')),
+    command("Next theme", Scope::Settings, KeyCode::Char('1')),
+    command("Previous theme", Scope::Settings, KeyCode::Char('2')),
+    command("Key length 16", Scope::Settings, KeyCode::Char('3')),
+    command("Key length 32", Scope::Settings, KeyCode::Char('4')),
+    command("Cycle auto-lock", Scope::Settings, KeyCode::Char('5')),
+    command("Cycle memory in flight", Scope::Settings, KeyCode::Char('6')),
+    command("Cycle folder cipher", Scope::Settings, KeyCode::Char('7')),
+    command("Switch trash or permanent delete", Scope::Settings, KeyCode::Char('8')),
+    command("Switch relative or absolute dates", Scope::Settings, KeyCode::Char('9')),
+    command("Encrypt file names in folder", Scope::Settings, KeyCode::Char('0')),
+    command("Checksums for folder", Scope::Settings, KeyCode::Char('c')),
+    command("Switch folder file format (GuardX or age)", Scope::Settings, KeyCode::Char('f')),
+    command("Re-encrypt changed session files without asking", Scope::Settings, KeyCode::Char('w')),
+    command("Switch ASCII or emoji symbols", Scope::Settings, KeyCode::Char('a')),
+    command("Show or hide colour labels", Scope::Settings, KeyCode::Char('l')),
+    command("Switch letters in lists between commands and type-ahead", Scope::Settings, KeyCode::Char('j')),
+    command("Switch language", Scope::Settings, KeyCode::Char('g')),
+    command("Switch profile", Scope::Settings, KeyCode::Char('p')),
+    command("Edit config.toml", Scope::Settings, KeyCode::Char('e')),
+    command("Security (second factor)", Scope::Settings, KeyCode::Char('s')),
+];
+
+// Outcomes the notification center keeps, and how many toasts are stacked at once
+const NOTICES_KEPT: usize = 100;
+const TOASTS_SHOWN: usize = 3;
+const DEFAULT_TOAST_SECONDS: u64 = 5;
+
+// Y copies at most this much of a file
+const MAX_YANK: u64 = 1024 * 1024;
+const DEFAULT_CLIPBOARD_CLEAR_SECONDS: u64 = 30;
+
+// How soon after the previous destructive key another is taken for a key held down or a slip, and ignored
+const REPEAT_GUARD: Duration = Duration::from_millis(300);
+
+#[derive(PartialEq, Clone, Copy)]
+enum Severity {
+    Success,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    // From the mark the status starts with; plain progress and prompts are not notices
+    fn of(status: &str) -> Option<Severity> {
+        if status.starts_with("[OK]") {
+            Some(Severity::Success)
+        } else if status.starts_with("[!]") {
+            Some(Severity::Warning)
+        } else if status.starts_with("[X]") {
+            Some(Severity::Error)
+        } else {
+            None
+        }
+    }
+
+    fn color(self, theme: &Theme) -> Color {
+        match self {
+            Severity::Success => theme.success,
+            Severity::Warning => theme.warning,
+            Severity::Error => theme.error,
+        }
+    }
+}
+
+struct Notice {
+    text: String,
+    severity: Severity,
+    shown: Instant,
+    time: i64,
+    // Esc while browsing takes the toasts down early
+    dismissed: bool,
+}
+
+// A second click on the same row within this is a double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+// Lines of the preview one notch of the wheel scrolls
+const WHEEL_LINES: u16 = 3;
+
+// Below this the TUI only says the terminal is too small
+const MIN_WIDTH: u16 = 40;
+const MIN_HEIGHT: u16 = 12;
+
+// Narrower than this, the folder list and the right-hand panel take turns filling the width
+const NARROW_WIDTH: u16 = 70;
+
+// Shorter than this, the controls bar is left out so the lists keep their rows
+const SHORT_HEIGHT: u16 = 22;
+
+// Each of the two panes needs this much, or only the selected folder's files are drawn
+const MIN_PANE_WIDTH: u16 = 36;
+
+// The history panel (i) sits over the files table, so narrower terminals leave it out; I shows the history
+const HISTORY_WIDTH: u16 = 35;
+const HISTORY_MIN_WIDTH: u16 = 100;
+
+// Listings kept at most, the least recently read going first
+const CACHED_LISTINGS: usize = 64;
+
+// A listing this old gets its age in the files table's title, since changes to files that do not touch the
+// folder itself (a file growing, say) are only picked up by F5
+const LISTING_AGE_SHOWN: Duration = Duration::from_secs(120);
+
+// The folder shown beside the selected one in the two-pane layout, with its files as last read
+pub struct Pane {
+    dir: PathBuf,
+    files: Vec<String>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Transfer {
+    Copy,
+    Move,
+    Encrypt,
+}
+
+impl Transfer {
+    fn verb(self) -> &'static str {
+        match self {
+            Transfer::Copy => "copy",
+            Transfer::Move => "move",
+            Transfer::Encrypt => "encrypt",
+        }
+    }
+
+    fn done(self) -> &'static str {
+        match self {
+            Transfer::Copy => "Copied",
+            Transfer::Move => "Moved",
+            Transfer::Encrypt => "Encrypted",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Confirm {
+    Release,
+    Store,
+}
+
+pub struct Running {
+    operation: Operation,
+    done: usize,
+    total: usize,
+    // The pending note when it started, for its history entry
+    note: Option<String>,
+    started: Instant,
+    // The folder's files when it started, with their sizes, and what the pre-flight summary said it would skip
+    files: Vec<(String, u64)>,
+    skipped: Vec<(String, String)>,
+}
+
+#[derive(PartialEq, Clone)]
+pub enum FileStatus {
+    Pending,
+    Running,
+    Done,
+    Failed(String),
+    Skipped,
+}
+
+impl FileStatus {
+    fn label(&self) -> &'static str {
+        match self {
+            FileStatus::Pending => "pending",
+            FileStatus::Running => "running",
+            FileStatus::Done => "done",
+            FileStatus::Failed(_) => "failed",
+            FileStatus::Skipped => "skipped",
+        }
+    }
+
+    fn color(&self, theme: &Theme) -> Color {
+        match self {
+            FileStatus::Pending => theme.muted,
+            FileStatus::Running => theme.busy,
+            FileStatus::Done => theme.success,
+            FileStatus::Failed(_) => theme.error,
+            FileStatus::Skipped => theme.warning,
+        }
+    }
+}
+
+// Files an interrupted operation has yet to reach are pending until it is resumed
+fn journaled_statuses() -> HashMap<PathBuf, HashMap<String, FileStatus>> {
+    journal::load().unwrap_or_default().into_iter()
+        .map(|entry| {
+            let names = entry.remaining.iter().map(|f| (f.file_name().unwrap_or_default().to_string_lossy().to_string(), FileStatus::Pending));
+            (entry.dir, names.collect())
+        })
+        .collect()
+}
+
+pub enum JobEvent {
+    // `file` is the one about to be processed
+    Progress { dir: PathBuf, done: usize, total: usize, file: PathBuf },
+    Finished { dir: PathBuf, operation: Operation, result: Result<()> },
 }
 
 pub struct Settings {
     theme: Theme,
     key_length: usize,
+    lock_timeout: Option<Duration>,
+    in_flight_chunks: usize,
+    permanent_delete: bool,
+    relative_dates: bool,
+    // Re-encrypt changed working copies when a session ends without asking first
+    auto_reencrypt: bool,
+    // Folders expand into their subfolders with → and collapse with ←
+    tree_view: bool,
+    // ASCII stand-ins for emoji, arrows and other symbols, for console fonts that lack them
+    ascii: bool,
+    // Text next to everything colour alone tells apart: label names, outcomes, the selected row and the
+    // active list, so nothing depends on telling colours apart
+    labels: bool,
+    // Letters in the folder and file lists jump to a name rather than run commands
+    type_ahead: bool,
 }
 
-#[derive(PartialEq)]
-pub enum Theme {
-    Dark,
-    Light,
+#[derive(PartialEq, Clone, Copy)]
+pub enum SortKey {
+    Name,
+    Size,
+    Created,
+    Status,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Size,
+            SortKey::Size => SortKey::Created,
+            SortKey::Created => SortKey::Status,
+            SortKey::Status => SortKey::Name,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Name => "Name",
+            SortKey::Size => "Size",
+            SortKey::Created => "Created",
+            SortKey::Status => "Status",
+        }
+    }
 }
 
 impl App {
@@ -65,395 +788,4002 @@ impl App {
         let mut selected_file = ListState::default();
         selected_file.select(None);
         let current_files = if !fs.dirs.is_empty() { Self::load_files(&fs, 0).unwrap_or_default() } else { vec![] };
-        Ok(App {
+        let (vault_check_tx, vault_check_rx) = mpsc::channel();
+        let (job_tx, job_rx) = mpsc::channel();
+        i18n::set(i18n::detect(fs.config.language.as_deref()));
+        let theme = Theme::of(fs.config.profile());
+        let clipboard_clear_for = Duration::from_secs(fs.config.clipboard_clear_seconds.unwrap_or(DEFAULT_CLIPBOARD_CLEAR_SECONDS));
+        let toast_for = Duration::from_secs(fs.config.toast_seconds.unwrap_or(DEFAULT_TOAST_SECONDS));
+        let type_ahead = fs.config.type_ahead;
+        let mut app = App {
             fs,
             selected_dir,
             selected_file,
             current_files,
-            key_input: String::new(),
+            files_denied: false,
+            file_offset: 0,
+            file_rows: 0,
+            listings: HashMap::new(),
+            key_input: Self::secret_buffer(),
             mode: Mode::NavigateFolders,
-            status: "Welcome to GuardX!".to_string(),
+            status: tr("status.welcome"),
             should_quit: false,
-            last_processed: Instant::now(),
+            last_destructive: None,
             success_timer: None,
-            progress: 0.0,
-            in_progress: false,
             preview_content: None,
-            history: Vec::new(),
-            settings: Settings { theme: Theme::Dark, key_length: 32 },
+            preview_decrypted: false,
+            clipboard: Clipboard::open(),
+            clipboard_clear_at: None,
+            clipboard_clear_for,
+            history: oplog::load().unwrap_or_default(),
+            unlogged: Vec::new(),
+            history_view: ListState::default(),
+            history_failures_only: false,
+            history_from_files: false,
+            details: Vec::new(),
+            details_hash: None,
+            notices: VecDeque::new(),
+            toast_for,
+            notice_view: ListState::default(),
+            notices_from_files: false,
+            bookmark_view: ListState::default(),
+            bookmark_missing: Vec::new(),
+            other_pane: None,
+            settings: Settings { theme, key_length: 32, lock_timeout: Some(Duration::from_secs(5 * 60)), in_flight_chunks: 8, permanent_delete: false, relative_dates: true, auto_reencrypt: false, tree_view: false, ascii: !unicode_likely(), labels: std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()), type_ahead },
             animation_step: 0,
             info_mode: false,
-        })
+            file_count: 0,
+            last_activity: Instant::now(),
+            lock_watch: lockall::Watch::new(),
+            drive_watch: drives::Watch::new(),
+            drive_offers: Vec::new(),
+            unlock_input: Self::secret_buffer(),
+            lock_verifier: None,
+            totp: Totp::load().context("Could not load the second factor")?,
+            totp_passed: false,
+            totp_input: String::new(),
+            totp_failures: 0,
+            totp_return: None,
+            totp_pending: None,
+            hardware: Token::load().context("Could not load the hardware key enrollment")?,
+            touch_pending: None,
+            platform_pending: None,
+            biometric_stored: biometric::is_stored(),
+            delete_permanently: false,
+            undo_stack: Vec::new(),
+            revealed_names: HashMap::new(),
+            rename_input: String::new(),
+            create_name: String::new(),
+            create_from_files: false,
+            sort_key: SortKey::Name,
+            sort_reversed: false,
+            container: None,
+            selected_entry: ListState::default(),
+            sftp: None,
+            sftp_dir: PathBuf::new(),
+            sftp_entries: Vec::new(),
+            selected_sftp: ListState::default(),
+            rekey_old: Self::secret_buffer(),
+            rekey_new: Self::secret_buffer(),
+            rekey_confirm: Self::secret_buffer(),
+            duress_key: Self::secret_buffer(),
+            jump_input: String::new(),
+            jump_files: false,
+            typed_at: None,
+            finder_entries: Vec::new(),
+            finder_rx: None,
+            finder_roots: Vec::new(),
+            finder_truncated: false,
+            finder_query: String::new(),
+            finder_matches: Vec::new(),
+            finder_view: ListState::default(),
+            finder_from_files: false,
+            palette_query: String::new(),
+            palette_matches: Vec::new(),
+            palette_view: ListState::default(),
+            palette_from_files: false,
+            palette_pick: None,
+            folders_area: Rect::default(),
+            files_area: Rect::default(),
+            last_click: None,
+            preview_scroll: 0,
+            vault_checks: HashMap::new(),
+            vault_check_tx,
+            vault_check_rx,
+            jobs: HashMap::new(),
+            job_tx,
+            job_rx,
+            guards: Vec::new(),
+            dir_changes: None,
+            file_statuses: journaled_statuses(),
+            verify_report: Vec::new(),
+            verify_note: None,
+            preflight: None,
+            retry_decrypt: None,
+            reports: Vec::new(),
+            report_from_files: false,
+            log_lines: Vec::new(),
+            log_scroll: 0,
+            log_problems_only: false,
+            log_from_files: false,
+            pending_note: None,
+            note_input: String::new(),
+            session: None,
+            quit_after_session: false,
+        };
+        app.check_selected_vault();
+        app.watch_selected_dir();
+        app.index_roots();
+        Ok(app)
     }
 
-    fn get_theme_styles(&self) -> (Color, Color, Color, Color) {
-        match self.settings.theme {
-            Theme::Dark => (Color::Rgb(20, 20, 30), Color::White, Color::Cyan, Color::Gray),
-            Theme::Light => (Color::Gray, Color::Black, Color::Blue, Color::DarkGray),
+    // Reserve up front so typing never reallocates and leaves stale copies of the key behind
+    fn secret_buffer() -> Zeroizing<String> {
+        Zeroizing::new(String::with_capacity(256))
+    }
+
+    // Drops everything secret from memory; only a salted verifier of the key survives
+    fn lock(&mut self) {
+        self.lock_verifier = if self.key_input.is_empty() { None } else { KeyVerifier::new(&self.key_input).ok() };
+        self.forget_secrets();
+        self.status = format!("[!] {}", tr("status.locked"));
+        self.push_history("Auto-locked", true, None);
+    }
+
+    // Ctrl+L here, or `guardx lock-all` from anywhere. Not even a verifier is kept, so after unlocking the
+    // key has to be entered again with k.
+    fn lock_all(&mut self, reason: &str) {
+        self.lock_verifier = None;
+        self.forget_secrets();
+        self.status = format!("[!] {}; enter the key again (k) after unlocking", reason);
+        self.push_history(reason.to_string(), true, None);
+    }
+
+    fn forget_secrets(&mut self) {
+        // The key is about to go, so changed working copies are sealed now rather than asked about
+        self.close_session(true);
+        // Guards keep a copy of the key, so they go with it
+        if !self.guards.is_empty() {
+            self.push_history(format!("Stopped watching {} folders", self.guards.len()), true, None);
+            self.guards.clear();
+        }
+        kdf::forget_derived_keys();
+        self.key_input.zeroize();
+        self.unlock_input.zeroize();
+        if let Some(content) = self.preview_content.as_mut() {
+            content.zeroize();
         }
+        self.preview_content = None;
+        self.container = None;
+        self.sftp = None;
+        self.sftp_entries.clear();
+        self.clear_rekey();
+        self.duress_key.zeroize();
+        self.totp_passed = false;
+        self.totp_input.clear();
+        self.totp_return = None;
+        self.totp_pending = None;
+        self.touch_pending = None;
+        self.platform_pending = None;
+        self.verify_report.clear();
+        self.preflight = None;
+        self.retry_decrypt = None;
+        self.reports.clear();
+        self.forget_names();
+        self.selected_file.select(None);
+        self.mode = Mode::Locked;
     }
 
-    fn load_files(fs: &FileSystem, dir_idx: usize) -> Result<Vec<(String, Metadata, bool)>> {
-        if dir_idx >= fs.dirs.len() { return Ok(vec![]); }
-        let dir = &fs.dirs[dir_idx];
-        let mut files = Vec::new();
-        match fs::read_dir(dir) {
-            Ok(entries) => {
-                for entry in entries {
-                    match entry {
-                        Ok(entry) => {
-                            let path = entry.path();
-                            match entry.metadata() {
-                                Ok(metadata) => {
-                                    if path.is_file() {
-                                        let encrypted = path.extension().map_or(false, |ext| ext == "enc");
-                                        files.push((entry.file_name().to_string_lossy().to_string(), metadata, encrypted));
-                                    }
-                                }
-                                Err(_) => {}
-                            }
-                        }
-                        Err(_) => {}
-                    }
+    fn try_unlock(&mut self) {
+        let unlocked = match &self.lock_verifier {
+            Some(verifier) => verifier.matches(&self.unlock_input),
+            None => true,
+        };
+        if unlocked {
+            if self.lock_verifier.is_some() {
+                self.key_input.clear();
+                self.key_input.push_str(&self.unlock_input);
+            }
+            self.lock_verifier = None;
+            self.mode = Mode::NavigateFolders;
+            self.reveal_names();
+            self.status = "[OK] Unlocked".to_string();
+            self.success_timer = Some(Instant::now());
+        } else {
+            self.status = "[X] Wrong key, still locked".to_string();
+            self.push_history("Unlock failed", false, None);
+        }
+        self.unlock_input.zeroize();
+    }
+
+    fn start_touch(&mut self, touch: Touch) {
+        let Some(token) = self.hardware else { return };
+        self.status = format!("[Key] Touch your YubiKey (slot {}) if it blinks...", token.slot);
+        self.touch_pending = Some(touch);
+    }
+
+    // Replaces the typed keys with the ones bound to the YubiKey, then carries on with what asked for them
+    fn finish_touch(&mut self, touch: Touch) {
+        let Some(token) = self.hardware else { return };
+        let bind = |buffer: &mut Zeroizing<String>| -> anyhow::Result<()> {
+            let bound = token.bind(buffer)?;
+            buffer.clear();
+            buffer.push_str(&bound);
+            Ok(())
+        };
+        let result = match touch {
+            Touch::Key => bind(&mut self.key_input),
+            Touch::Unlock => bind(&mut self.unlock_input),
+            Touch::Rekey => bind(&mut self.rekey_old).and_then(|()| bind(&mut self.rekey_new)),
+            Touch::Duress => bind(&mut self.duress_key),
+        };
+        let failed = result.is_err();
+        match (touch, result) {
+            (Touch::Key, Ok(())) => self.key_set(),
+            (Touch::Unlock, Ok(())) => self.try_unlock(),
+            (Touch::Rekey, Ok(())) => self.apply_rekey(),
+            (Touch::Duress, Ok(())) => {
+                if let Some(selected) = self.selected_dir.selected() {
+                    self.finish_archive(selected);
                 }
-                Ok(files)
             }
-            Err(_) => Ok(vec![])
+            (_, Err(e)) => {
+                self.status = format!("[X] {:#}", e);
+                self.push_history("YubiKey did not answer", false, None);
+            }
+        }
+        // Typed keys are only any use bound, so none of them outlive a failed touch
+        match touch {
+            Touch::Key if failed => self.key_input.zeroize(),
+            Touch::Key => {}
+            Touch::Unlock => self.unlock_input.zeroize(),
+            Touch::Rekey => self.clear_rekey(),
+            Touch::Duress => self.duress_key.zeroize(),
         }
     }
 
-    fn update_current_files(&mut self) {
-        if let Some(selected) = self.selected_dir.selected() {
-            match Self::load_files(&self.fs, selected) {
-                Ok(files) => {
-                    self.current_files = files;
-                    self.selected_file.select(if self.current_files.is_empty() { None } else { Some(0) });
-                    if self.current_files.is_empty() && self.fs.get_files(selected).is_err() {
-                        self.status = "[!] Access Denied to this folder".to_string();
+    fn start_release(&mut self) {
+        self.biometric_stored = biometric::is_stored();
+        if !self.biometric_stored {
+            self.status = format!("[!] No key stored for {}; store one on the security screen (t, then s)", biometric::name());
+            return;
+        }
+        self.status = format!("[Key] Confirm with {}...", biometric::name());
+        self.platform_pending = Some(Confirm::Release);
+    }
+
+    fn start_store(&mut self) {
+        if self.hardware.is_some() {
+            // The key in memory is the one bound to the YubiKey; stored, it would open the files without it
+            self.status = "[!] The key is bound to your YubiKey and cannot be stored".to_string();
+        } else if self.key_input.is_empty() {
+            self.status = "[!] Enter a key first (k)".to_string();
+        } else {
+            self.status = format!("[Key] Storing the key for {}...", biometric::name());
+            self.platform_pending = Some(Confirm::Store);
+        }
+    }
+
+    fn finish_platform(&mut self, confirm: Confirm) {
+        let name = biometric::name();
+        match confirm {
+            Confirm::Store => match biometric::store(&self.key_input) {
+                Ok(()) => {
+                    self.biometric_stored = true;
+                    self.status = format!("[OK] h now sets the key after {} confirms", name);
+                    self.push_history(format!("Stored key for {}", name), true, None);
+                }
+                Err(e) => self.status = format!("[X] {:#}", e),
+            },
+            Confirm::Release => match biometric::release() {
+                Ok(key) if self.mode == Mode::Locked && self.lock_verifier.is_some() => {
+                    self.unlock_input.clear();
+                    self.unlock_input.push_str(&key);
+                    if self.hardware.is_some() {
+                        self.start_touch(Touch::Unlock);
+                    } else {
+                        self.try_unlock();
+                    }
+                }
+                Ok(key) => {
+                    if self.mode == Mode::Locked {
+                        self.try_unlock();
+                    }
+                    self.key_input.clear();
+                    self.key_input.push_str(&key);
+                    self.totp_passed = false;
+                    if self.hardware.is_some() {
+                        self.start_touch(Touch::Key);
+                    } else {
+                        self.key_set();
                     }
                 }
                 Err(e) => {
-                    self.current_files.clear();
-                    self.selected_file.select(None);
-                    self.status = format!("[!] Access Denied: {}", e);
+                    self.status = format!("[X] {:#}", e);
+                    self.push_history(format!("{} unlock failed", name), false, None);
                 }
+            },
+        }
+    }
+
+    fn key_set(&mut self) {
+        self.reveal_names();
+        self.status = format!("[OK] Key ({} chars) set!", self.key_input.chars().count());
+        self.success_timer = Some(Instant::now());
+        self.push_history("Set key", true, None);
+        self.resume_interrupted();
+        // A decryption refused for the wrong key tries again with this one
+        if let Some(index) = self.retry_decrypt.take().and_then(|dir| self.fs.dirs.iter().position(|d| *d == dir)) {
+            self.start_decrypt(index);
+        }
+    }
+
+    fn cycle_lock_timeout(&mut self) {
+        self.settings.lock_timeout = match self.settings.lock_timeout.map(|d| d.as_secs() / 60) {
+            None => Some(Duration::from_secs(60)),
+            Some(1) => Some(Duration::from_secs(5 * 60)),
+            Some(5) => Some(Duration::from_secs(15 * 60)),
+            _ => None,
+        };
+    }
+
+    // Each chunk is 1 MiB, so this bounds the memory a large file encryption keeps in flight
+    fn cycle_in_flight(&mut self) {
+        self.settings.in_flight_chunks = match self.settings.in_flight_chunks {
+            0 => 2,
+            2 => 4,
+            4 => 8,
+            8 => 16,
+            _ => 0,
+        };
+        set_pipeline_depth(self.settings.in_flight_chunks);
+    }
+
+    // p in Settings: the next profile in config.toml, then none. The folder list, filters, default cipher
+    // and theme follow it. The key is forgotten, since work and personal folders rarely share one.
+    fn cycle_profile(&mut self) {
+        if !self.jobs.is_empty() || self.session.is_some() {
+            self.status = "[!] Wait for running operations to finish and close open files before switching profiles".to_string();
+            return;
+        }
+        if self.fs.config.profiles.is_empty() {
+            self.status = "[!] No profiles yet; add [[profile]] tables to config.toml".to_string();
+            return;
+        }
+        let current = self.fs.config.profile().map(|p| p.name.clone());
+        let mut names: Vec<Option<String>> = vec![None];
+        names.extend(self.fs.config.profiles.iter().map(|p| Some(p.name.clone())));
+        let next = names.iter().position(|name| *name == current).map_or(0, |i| (i + 1) % names.len());
+        config::select_profile(names[next].clone());
+        let fs = match FileSystem::new() {
+            Ok(fs) => fs,
+            Err(e) => {
+                config::select_profile(current);
+                self.status = format!("[X] Could not switch profiles: {:#}", e);
+                return;
             }
-        } else {
-            self.current_files.clear();
-            self.selected_file.select(None);
+        };
+        self.fs = fs;
+        // Mounted drives are reported again, so they are listed alongside the new roots
+        self.drive_watch = drives::Watch::new();
+        self.drive_offers.clear();
+        if !self.guards.is_empty() {
+            self.push_history(format!("Stopped watching {} folders", self.guards.len()), true, None);
+            self.guards.clear();
         }
+        kdf::forget_derived_keys();
+        self.key_input.zeroize();
+        self.forget_names();
+        self.totp_passed = false;
+        self.retry_decrypt = None;
+        // Undo entries point into the old folder list
+        self.undo_stack.clear();
+        self.other_pane = None;
+        self.index_roots();
+        self.settings.theme = Theme::of(self.fs.config.profile());
+        self.selected_dir.select(if self.fs.dirs.is_empty() { None } else { Some(0) });
+        self.selected_file.select(None);
+        self.update_current_files();
+        let name = names[next].as_deref().unwrap_or("none");
+        self.status = format!("[OK] Profile: {}; enter the key again (k)", name);
+        self.push_history(format!("Switched to profile {}", name), true, None);
     }
-}
 
-pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<()> {
-    const DEBOUNCE_DURATION: Duration = Duration::from_millis(150);
+    // Auto -> AES-256-GCM -> ChaCha20-Poly1305 -> Auto for the selected folder, saved to the config
+    fn cycle_vault_cipher(&mut self) -> Option<Effect> {
+        let dir = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned()?;
+        let next = match self.fs.config.cipher_for(&dir) {
+            None => Some(Cipher::Aes256Gcm),
+            Some(Cipher::Aes256Gcm) => Some(Cipher::ChaCha20Poly1305),
+            Some(Cipher::ChaCha20Poly1305) => None,
+        };
+        self.fs.config.vault_mut(&dir).cipher = next.map(|c| c.name().to_string());
+        Some(Effect::SaveConfig)
+    }
 
-    loop {
-        if let Err(e) = terminal.draw(|f| ui(f, &mut app)) {
-            eprintln!("Draw error: {}", e);
-            return Err(anyhow::Error::from(e));
+    fn toggle_vault_names(&mut self) -> Option<Effect> {
+        let dir = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned()?;
+        let vault = self.fs.config.vault_mut(&dir);
+        vault.encrypt_names = !vault.encrypt_names;
+        Some(Effect::SaveConfig)
+    }
+
+    fn toggle_vault_format(&mut self) -> Option<Effect> {
+        let dir = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned()?;
+        let next = match self.fs.config.output_format(&dir) {
+            OutputFormat::GuardX => Some(OutputFormat::Age.name().to_string()),
+            OutputFormat::Age => None,
+        };
+        self.fs.config.vault_mut(&dir).format = next;
+        Some(Effect::SaveConfig)
+    }
+
+    fn toggle_vault_checksums(&mut self) -> Option<Effect> {
+        let dir = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned()?;
+        let vault = self.fs.config.vault_mut(&dir);
+        vault.checksums = !vault.checksums;
+        Some(Effect::SaveConfig)
+    }
+
+    // The entered key, or else this machine's identities, which open folders encrypted to their public keys
+    fn unlock_key(&self) -> Option<Zeroizing<String>> {
+        if !self.key_input.is_empty() {
+            return Some(self.key_input.clone());
         }
+        keyring::all_as_key().ok().flatten()
+    }
 
-        if let Some(start) = app.success_timer {
-            if start.elapsed() > Duration::from_secs(2) {
-                app.success_timer = None;
-                app.status = "Ready".to_string();
-            } else {
-                app.animation_step = (start.elapsed().as_millis() / 150 % 4) as usize;
-            }
-        }
-
-        if app.in_progress {
-            app.progress += 0.05;
-            if app.progress >= 1.0 {
-                app.progress = 0.0;
-                app.in_progress = false;
-            }
-        }
-
-        if event::poll(Duration::from_millis(50))? {
-            match event::read()? {
-                Event::Key(key) if key.kind == KeyEventKind::Press => {
-                    let now = Instant::now();
-                    if now.duration_since(app.last_processed) >= DEBOUNCE_DURATION {
-                        app.last_processed = now;
-                        match app.mode {
-                            Mode::NavigateFolders => match key.code {
-                                KeyCode::Char('q') => app.should_quit = true,
-                                KeyCode::Up => {
-                                    if let Some(selected) = app.selected_dir.selected() {
-                                        app.selected_dir.select(Some(selected.saturating_sub(1)));
-                                        app.update_current_files();
-                                    }
-                                }
-                                KeyCode::Down => {
-                                    let len = app.fs.dirs.len();
-                                    if len > 0 {
-                                        app.selected_dir.select(Some((app.selected_dir.selected().unwrap_or(0) + 1).min(len - 1)));
-                                        app.update_current_files();
-                                    }
-                                }
-                                KeyCode::Right => {
-                                    if !app.current_files.is_empty() {
-                                        app.mode = Mode::NavigateFiles;
-                                        app.status = "Navigating files (← to return)".to_string();
-                                    }
-                                }
-                                KeyCode::Char('e') => {
-                                    if app.key_input.is_empty() {
-                                        app.status = "[!] Enter a key first (k)".to_string();
-                                    } else if let Some(selected) = app.selected_dir.selected() {
-                                        app.in_progress = true;
-                                        app.progress = 0.0;
-                                        if let Err(e) = app.fs.encrypt_dir(selected, &app.key_input) {
-                                            app.status = format!("[X] Encryption failed: {}", e);
-                                            app.history.push((format!("Encrypt failed: {}", e), Instant::now(), false));
-                                            app.in_progress = false;
-                                        } else {
-                                            app.status = "[OK] Folder encrypted!".to_string();
-                                            app.history.push(("Encrypted folder".to_string(), Instant::now(), true));
-                                            app.success_timer = Some(Instant::now());
-                                            app.in_progress = false;
-                                            app.fs.mark_encrypted(selected, true);
-                                            app.update_current_files();
-                                        }
-                                    }
-                                }
-                                KeyCode::Char('d') => {
-                                    if app.key_input.is_empty() {
-                                        app.status = "[!] Enter a key first (k)".to_string();
-                                    } else if let Some(selected) = app.selected_dir.selected() {
-                                        app.in_progress = true;
-                                        app.progress = 0.0;
-                                        if let Err(e) = app.fs.decrypt_dir(selected, &app.key_input) {
-                                            app.status = format!("[X] Decryption failed: {}", e);
-                                            app.history.push((format!("Decrypt failed: {}", e), Instant::now(), false));
-                                            app.in_progress = false;
-                                        } else {
-                                            app.status = "[OK] Folder decrypted!".to_string();
-                                            app.history.push(("Decrypted folder".to_string(), Instant::now(), true));
-                                            app.success_timer = Some(Instant::now());
-                                            app.in_progress = false;
-                                            app.fs.mark_encrypted(selected, false);
-                                            app.update_current_files();
-                                        }
-                                    }
-                                }
-                                KeyCode::Char('k') => {
-                                    app.mode = Mode::EnterKey;
-                                    app.key_input.clear();
-                                    app.status = "[Key] Enter encryption key: ".to_string();
-                                }
-                                KeyCode::Char('n') => {
-                                    app.mode = Mode::CreateFolder;
-                                    app.key_input.clear();
-                                    app.status = "[Folder] Enter new folder name: ".to_string();
-                                }
-                                KeyCode::Char('p') => {
-                                    if let Some(selected) = app.selected_dir.selected() {
-                                        match app.fs.get_files(selected) {
-                                            Ok(files) => {
-                                                if let Some(first_file) = files.first() {
-                                                    let path = app.fs.dirs[selected].join(first_file);
-                                                    app.preview_content = fs::read_to_string(&path).ok().or(Some("Unable to read file".to_string()));
-                                                    app.mode = Mode::Preview;
-                                                } else {
-                                                    app.status = "[!] No files to preview".to_string();
-                                                }
-                                            }
-                                            Err(_) => {
-                                                app.status = "[!] Access Denied to this folder".to_string();
-                                            }
-                                        }
-                                    }
-                                }
-                                KeyCode::Char('t') => app.mode = Mode::Settings,
-                                KeyCode::Char('r') => app.mode = Mode::ConfirmDeleteFolder,
-                                KeyCode::Char('i') => app.info_mode = !app.info_mode,
-                                KeyCode::Char('l') => {
-                                    if let Ok(key) = fs::read_to_string("saved_key.enc") {
-                                        app.key_input = key.trim().to_string();
-                                        app.status = "[OK] Key loaded!".to_string();
-                                        app.success_timer = Some(Instant::now());
-                                        app.history.push(("Loaded key".to_string(), Instant::now(), true));
-                                    } else {
-                                        app.status = "[X] No saved key found".to_string();
-                                    }
-                                }
-                                KeyCode::Char('v') => {
-                                    if !app.key_input.is_empty() {
-                                        fs::write("saved_key.enc", &app.key_input)?;
-                                        app.status = "[OK] Key saved!".to_string();
-                                        app.success_timer = Some(Instant::now());
-                                        app.history.push(("Saved key".to_string(), Instant::now(), true));
-                                    } else {
-                                        app.status = "[!] No key to save".to_string();
-                                    }
-                                }
-                                _ => {}
-                            },
-                            Mode::NavigateFiles => match key.code {
-                                KeyCode::Up => {
-                                    if let Some(selected) = app.selected_file.selected() {
-                                        app.selected_file.select(Some(selected.saturating_sub(1)));
-                                    }
-                                }
-                                KeyCode::Down => {
-                                    let len = app.current_files.len();
-                                    if len > 0 {
-                                        app.selected_file.select(Some((app.selected_file.selected().unwrap_or(0) + 1).min(len - 1)));
-                                    }
-                                }
-                                KeyCode::Left => {
-                                    app.mode = Mode::NavigateFolders;
-                                    app.status = "Back to folders".to_string();
-                                    app.selected_file.select(None);
-                                }
-                                KeyCode::Char('q') => app.should_quit = true,
-                                KeyCode::Char('p') => {
-                                    if let Some(dir_idx) = app.selected_dir.selected() {
-                                        if let Some(file_idx) = app.selected_file.selected() {
-                                            let path = app.fs.dirs[dir_idx].join(&app.current_files[file_idx].0);
-                                            app.preview_content = fs::read_to_string(&path).ok().or(Some("Unable to read file".to_string()));
-                                            app.mode = Mode::Preview;
-                                        }
-                                    }
-                                }
-                                KeyCode::Char('r') => app.mode = Mode::ConfirmDeleteFile,
-                                _ => {}
-                            },
-                            Mode::EnterKey => match key.code {
-                                KeyCode::Enter => {
-                                    app.mode = Mode::NavigateFolders;
-                                    app.status = format!("[OK] Key '{}' set!", app.key_input);
-                                    app.success_timer = Some(Instant::now());
-                                    app.history.push(("Set key".to_string(), Instant::now(), true));
-                                }
-                                KeyCode::Char(c) => {
-                                    app.key_input.push(c);
-                                    app.status = format!("[Key] Enter encryption key: {}", app.key_input);
-                                }
-                                KeyCode::Backspace => {
-                                    app.key_input.pop();
-                                    app.status = format!("[Key] Enter encryption key: {}", app.key_input);
-                                }
-                                KeyCode::Esc => app.mode = Mode::NavigateFolders,
-                                KeyCode::Char('q') => app.should_quit = true,
-                                _ => {}
-                            },
-                            Mode::CreateFolder => match key.code {
-                                KeyCode::Enter => {
-                                    if let Err(e) = app.fs.create_folder(&app.key_input) {
-                                        app.status = format!("[X] Folder creation failed: {}", e);
-                                        app.history.push((format!("Create folder failed: {}", e), Instant::now(), false));
-                                    } else {
-                                        app.status = format!("[OK] Folder '{}' created!", app.key_input);
-                                        app.history.push(("Created folder".to_string(), Instant::now(), true));
-                                        app.success_timer = Some(Instant::now());
-                                        app.update_current_files();
-                                    }
-                                    app.key_input.clear();
-                                    app.mode = Mode::NavigateFolders;
-                                }
-                                KeyCode::Char(c) => {
-                                    app.key_input.push(c);
-                                    app.status = format!("[Folder] Enter new folder name: {}", app.key_input);
-                                }
-                                KeyCode::Backspace => {
-                                    app.key_input.pop();
-                                    app.status = format!("[Folder] Enter new folder name: {}", app.key_input);
-                                }
-                                KeyCode::Esc => app.mode = Mode::NavigateFolders,
-                                KeyCode::Char('q') => app.should_quit = true,
-                                _ => {}
-                            },
-                            Mode::Preview => match key.code {
-                                KeyCode::Esc | KeyCode::Char('q') => {
-                                    app.mode = if app.selected_file.selected().is_some() { Mode::NavigateFiles } else { Mode::NavigateFolders };
-                                    app.preview_content = None;
-                                    app.status = "Back to navigation".to_string();
-                                }
-                                _ => {}
-                            },
-                            Mode::Settings => match key.code {
-                                KeyCode::Char('1') => app.settings.theme = Theme::Dark,
-                                KeyCode::Char('2') => app.settings.theme = Theme::Light,
-                                KeyCode::Char('3') => app.settings.key_length = 16,
-                                KeyCode::Char('4') => app.settings.key_length = 32,
-                                KeyCode::Esc => app.mode = Mode::NavigateFolders,
-                                KeyCode::Char('q') => app.should_quit = true,
-                                _ => {}
-                            },
-                            Mode::ConfirmDeleteFolder => match key.code {
-                                KeyCode::Char('y') => {
-                                    if let Some(selected) = app.selected_dir.selected() {
-                                        let path = app.fs.dirs[selected].clone();
-                                        if let Err(e) = fs::remove_dir_all(&path) {
-                                            app.status = format!("[X] Delete failed: {}", e);
-                                            app.history.push((format!("Delete failed: {}", e), Instant::now(), false));
-                                        } else {
-                                            app.fs.dirs.remove(selected);
-                                            app.status = "[OK] Folder deleted!".to_string();
-                                            app.history.push(("Deleted folder".to_string(), Instant::now(), true));
-                                            app.success_timer = Some(Instant::now());
-                                            if app.fs.dirs.is_empty() {
-                                                app.selected_dir.select(None);
-                                            } else {
-                                                app.selected_dir.select(Some(selected.min(app.fs.dirs.len() - 1)));
-                                            }
-                                            app.update_current_files();
-                                        }
-                                    }
-                                    app.mode = Mode::NavigateFolders;
-                                }
-                                KeyCode::Char('n') | KeyCode::Esc => app.mode = Mode::NavigateFolders,
-                                _ => {}
-                            },
-                            Mode::ConfirmDeleteFile => match key.code {
-                                KeyCode::Char('y') => {
-                                    if let Some(dir_idx) = app.selected_dir.selected() {
-                                        if let Some(file_idx) = app.selected_file.selected() {
-                                            let path = app.fs.dirs[dir_idx].join(&app.current_files[file_idx].0);
-                                            if let Err(e) = fs::remove_file(&path) {
-                                                app.status = format!("[X] File delete failed: {}", e);
-                                                app.history.push((format!("File delete failed: {}", e), Instant::now(), false));
-                                            } else {
-                                                app.status = "[OK] File deleted!".to_string();
-                                                app.history.push(("Deleted file".to_string(), Instant::now(), true));
-                                                app.success_timer = Some(Instant::now());
-                                                app.update_current_files();
-                                            }
-                                        }
-                                    }
-                                    app.mode = Mode::NavigateFiles;
-                                }
-                                KeyCode::Char('n') | KeyCode::Esc => app.mode = Mode::NavigateFiles,
-                                _ => {}
-                            },
-                        }
-                    }
+    // Only headers are read, so this stays cheap enough to run on every folder change
+    fn reveal_names(&mut self) {
+        self.forget_names();
+        let Some(dir) = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)) else { return };
+        if self.key_input.is_empty() {
+            return;
+        }
+        // Hidden names are 32 hex digits, so other files are not opened; that matters in huge folders
+        for (name, _, _) in self.current_files.iter().filter(|(name, _, _)| name.len() == 32 && name.bytes().all(|b| b.is_ascii_hexdigit())) {
+            if let Ok(Some(real)) = original_name(&dir.join(name), &self.key_input) {
+                self.revealed_names.insert(name.clone(), real);
+            }
+        }
+    }
+
+    fn forget_names(&mut self) {
+        for name in self.revealed_names.values_mut() {
+            name.zeroize();
+        }
+        self.revealed_names.clear();
+    }
+
+    // History entry for a significant operation, also appended to the persistent log; the pending note, if
+    // any, goes with it
+    fn record(&mut self, entry: String, ok: bool, path: Option<PathBuf>) {
+        let note = self.pending_note.take();
+        self.keep_history(LogEntry::new(entry, path.as_deref(), ok, note.as_deref()));
+    }
+
+    // Everything in the history is also appended to the persistent log, so it is still there next time
+    fn push_history(&mut self, entry: impl Into<String>, ok: bool, path: Option<PathBuf>) {
+        self.keep_history(LogEntry::new(entry, path.as_deref(), ok, None));
+    }
+
+    // Written by perform, after each effect and for update with Effect::WriteHistory
+    fn keep_history(&mut self, entry: LogEntry) {
+        self.unlogged.push(entry.clone());
+        self.history.push(entry);
+    }
+
+    fn write_history(&mut self) {
+        for entry in std::mem::take(&mut self.unlogged) {
+            if let Err(e) = oplog::append(&entry) {
+                self.status = format!("{} [!] Could not write the history log: {}", self.status, e);
+            }
+        }
+    }
+
+    fn start_note(&mut self) {
+        self.note_input = self.pending_note.clone().unwrap_or_default();
+        self.mode = Mode::EnterNote;
+        self.status = format!("[Note] Note for the next operation: {}", self.note_input);
+    }
+
+    fn note_input(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Enter => {
+                self.pending_note = clean_note(&self.note_input);
+                self.note_input.clear();
+                self.mode = Mode::NavigateFolders;
+                self.status = match &self.pending_note {
+                    Some(note) => format!("[OK] Note \"{}\" goes with the next operation", note),
+                    None => "Note cleared".to_string(),
+                };
+            }
+            KeyCode::Char(c) if self.note_input.chars().count() < MAX_NOTE_LEN => {
+                self.note_input.push(c);
+                self.status = format!("[Note] Note for the next operation: {}", self.note_input);
+            }
+            KeyCode::Backspace => {
+                self.note_input.pop();
+                self.status = format!("[Note] Note for the next operation: {}", self.note_input);
+            }
+            KeyCode::Esc => {
+                self.note_input.clear();
+                self.mode = Mode::NavigateFolders;
+                self.status = tr("status.back_to_navigation");
+            }
+            _ => {}
+        }
+    }
+
+    // Checks the selected file out into the working session and opens the plaintext copy
+    fn open_in_session<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        let (Some(dir_idx), Some(path)) = (self.selected_dir.selected(), self.selected_file_path()) else { return Ok(()) };
+        let dir = self.fs.dirs[dir_idx].clone();
+        // Plaintext needs no working copy; the file itself is opened
+        if !self.fs.is_encrypted(dir_idx) {
+            self.status = match open_external(terminal, &path) {
+                Ok(true) => "[OK] Editor closed".to_string(),
+                Ok(false) => format!("[OK] Opened {} in the default app", path.display()),
+                Err(e) => format!("[X] Could not open {}: {:#}", path.display(), e),
+            };
+            self.refresh_files();
+            return Ok(());
+        }
+        if let Err(e) = self.fs.config.check_writable(&dir) {
+            self.status = format!("[!] {}", e);
+            return Ok(());
+        }
+        let Some(key) = self.unlock_key() else {
+            self.status = "[!] Enter a key first (k)".to_string();
+            return Ok(());
+        };
+        if !self.second_factor() {
+            return Ok(());
+        }
+        let recipients = match self.fs.config.recipients(&dir) {
+            Ok(recipients) => recipients,
+            Err(e) => {
+                self.status = format!("[X] {:#}", e);
+                return Ok(());
+            }
+        };
+        let session = match self.session.take() {
+            Some(session) => session,
+            None => match Session::new() {
+                Ok(session) => session,
+                Err(e) => {
+                    self.status = format!("[X] Could not start a session: {:#}", e);
+                    return Ok(());
                 }
-                Event::Mouse(mouse) => {
-                    if let MouseEventKind::Down(_) = mouse.kind {
-                        let y = mouse.row;
-                        if y >= 4 && y < main_area_height(&app) + 4 {
-                            if app.mode == Mode::NavigateFolders {
-                                let new_idx = (y - 4) as usize;
-                                if new_idx < app.fs.dirs.len() {
-                                    app.selected_dir.select(Some(new_idx));
-                                    app.update_current_files();
-                                }
-                            } else if app.mode == Mode::NavigateFiles {
-                                let new_idx = (y - 4) as usize;
-                                if new_idx < app.current_files.len() {
-                                    app.selected_file.select(Some(new_idx));
-                                }
-                            }
-                        }
+            },
+        };
+        let session = self.session.insert(session);
+        let copy = match session.check_out(&path, &key, self.fs.cipher_for(dir_idx), recipients) {
+            Ok(copy) => copy,
+            Err(e) => {
+                self.status = format!("[X] Could not open the file: {:#}", e);
+                return Ok(());
+            }
+        };
+        self.status = match open_external(terminal, &copy) {
+            Ok(true) => "[OK] Editor closed; W ends the session and re-encrypts what changed".to_string(),
+            Ok(false) => "[OK] Opened in the default app; press W when done to re-encrypt what changed".to_string(),
+            Err(e) => format!("[X] Could not open the working copy {:?}: {:#}", copy, e),
+        };
+        Ok(())
+    }
+
+    // Asks before re-encrypting changed copies unless that is automatic; `quit` quits once the session is closed
+    fn end_session(&mut self, quit: bool) {
+        // Quitting would cut the workers off between files, where the journal cannot pick them up
+        if quit && !self.jobs.is_empty() {
+            self.status = format!("[!] Wait for {} running operations to finish before quitting", self.jobs.len());
+            return;
+        }
+        // A second q after copies could not be closed quits anyway and leaves them in the session folder
+        if quit && self.quit_after_session {
+            self.should_quit = true;
+            return;
+        }
+        let touched = self.session.as_ref().map_or(0, |s| s.touched().len());
+        if touched > 0 && !self.settings.auto_reencrypt {
+            self.quit_after_session = quit;
+            self.mode = Mode::ConfirmSessionEnd;
+            self.status = format!("[!] {} files changed in the session. Re-encrypt them? [y/n, Esc keeps working]", touched);
+            return;
+        }
+        self.close_session(true);
+        if quit {
+            self.should_quit = self.session.is_none();
+            self.quit_after_session = self.session.is_some();
+        }
+    }
+
+    // Copies that could not be sealed or wiped stay in the session, so nothing is lost
+    fn close_session(&mut self, keep_changes: bool) {
+        let Some(mut session) = self.session.take() else { return };
+        let key = self.unlock_key().unwrap_or_else(Self::secret_buffer);
+        let outcomes = session.close(&key, keep_changes);
+        let mut failed = 0;
+        for (source, outcome) in outcomes {
+            let name = source.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            match outcome {
+                Outcome::Unchanged => {}
+                Outcome::Reencrypted => self.record(format!("Re-encrypted {} after session", name), true, Some(source)),
+                Outcome::Failed(e) => {
+                    failed += 1;
+                    self.record(format!("Session close failed for {}: {}", name, e), false, Some(source));
+                }
+            }
+        }
+        if failed > 0 {
+            self.status = format!("[X] {} working copies could not be closed and are still in {:?}", failed, session.dir());
+            self.session = Some(session);
+        } else {
+            self.status = "[OK] Session closed, no plaintext copies left".to_string();
+            self.success_timer = Some(Instant::now());
+        }
+        self.update_current_files();
+    }
+
+    // With a second factor enrolled, the first decryption after setting the key asks for a code. Returns
+    // false when it had to ask; the action is repeated once the code is accepted.
+    fn second_factor(&mut self) -> bool {
+        if self.totp.is_none() || self.totp_passed {
+            return true;
+        }
+        self.totp_input.clear();
+        self.totp_return = Some(std::mem::replace(&mut self.mode, Mode::EnterTotp));
+        self.status = "[2FA] Code from your authenticator: ".to_string();
+        false
+    }
+
+    // Digits only; shared by the decryption gate and the security screen
+    fn totp_typing(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char(c) if c.is_ascii_digit() && self.totp_input.len() < totp::DIGITS => self.totp_input.push(c),
+            KeyCode::Backspace => {
+                self.totp_input.pop();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Whether the typed code is valid for `totp`; the input is cleared either way
+    fn take_code(&mut self, totp: Option<&Totp>) -> bool {
+        let valid = totp.is_some_and(|t| t.check(&self.totp_input, entropy::timestamp()));
+        self.totp_input.clear();
+        valid
+    }
+
+    fn totp_gate_input(&mut self, code: KeyCode) -> Option<Effect> {
+        if self.totp_typing(code) {
+            self.status = format!("[2FA] Code from your authenticator: {}", self.totp_input);
+            return None;
+        }
+        match code {
+            KeyCode::Esc => {
+                self.totp_input.clear();
+                self.mode = self.totp_return.take().unwrap_or(Mode::NavigateFolders);
+                self.status = "Cancelled; nothing was decrypted".to_string();
+            }
+            KeyCode::Enter if self.totp_input.len() == totp::DIGITS => {
+                let totp = self.totp.take();
+                let valid = self.take_code(totp.as_ref());
+                self.totp = totp;
+                if valid {
+                    self.totp_passed = true;
+                    self.totp_failures = 0;
+                    self.mode = self.totp_return.take().unwrap_or(Mode::NavigateFolders);
+                    self.status = "[OK] Code accepted; repeat the action to decrypt".to_string();
+                    self.push_history("Second factor accepted", true, None);
+                    self.success_timer = Some(Instant::now());
+                } else {
+                    self.totp_failures += 1;
+                    self.push_history("Wrong second factor code", false, None);
+                    if self.totp_failures >= MAX_TOTP_FAILURES {
+                        self.totp_failures = 0;
+                        return Some(Effect::LockAll(format!("Locked after {} wrong codes", MAX_TOTP_FAILURES)));
                     }
+                    self.status = "[X] Wrong code, try again: ".to_string();
                 }
-                _ => {}
             }
+            _ => {}
+        }
+        None
+    }
+
+    // Enrolling replaces nothing: an enrolled factor has to be removed first, which takes a current code
+    fn detect_hardware(&mut self) {
+        self.status = match hardware::detect() {
+            Ok(found) if found.is_empty() => "[!] No YubiKey connected".to_string(),
+            Ok(found) => format!("[OK] Connected: {}", found.join("; ")),
+            Err(e) => format!("[X] {:#}", e),
+        };
+    }
+
+    fn start_totp_enroll(&mut self) {
+        if self.totp.is_some() {
+            self.status = "[!] A second factor is already enrolled; remove it first (r)".to_string();
+            return;
+        }
+        let account = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| "vault".to_string());
+        let enrolled = Totp::generate().and_then(|totp| {
+            let qr = qrcode::QrCode::new(totp.uri(&account))?
+                .render::<qrcode::render::unicode::Dense1x2>()
+                .dark_color(qrcode::render::unicode::Dense1x2::Light)
+                .light_color(qrcode::render::unicode::Dense1x2::Dark)
+                .build();
+            Ok((totp, qr))
+        });
+        match enrolled {
+            Ok(pending) => {
+                self.totp_pending = Some(pending);
+                self.totp_input.clear();
+                self.mode = Mode::SecurityEnroll;
+                self.status = "[2FA] Scan the code, then type the code the app shows: ".to_string();
+            }
+            Err(e) => self.status = format!("[X] Could not start enrollment: {:#}", e),
+        }
+    }
+
+    fn security_input(&mut self, code: KeyCode) -> Option<Effect> {
+        let label = if self.mode == Mode::SecurityEnroll { "type the code the app shows" } else { "current code to remove the second factor" };
+        if self.totp_typing(code) {
+            self.status = format!("[2FA] {}: {}", label, self.totp_input);
+            return None;
+        }
+        match code {
+            KeyCode::Esc => {
+                self.totp_input.clear();
+                self.totp_pending = None;
+                self.mode = Mode::Security;
+                self.status = "Cancelled".to_string();
+            }
+            KeyCode::Enter if self.totp_input.len() == totp::DIGITS && self.mode == Mode::SecurityEnroll => {
+                let (pending, qr) = self.totp_pending.take()?;
+                let valid = self.take_code(Some(&pending));
+                self.totp_pending = Some((pending, qr));
+                if !valid {
+                    self.status = "[X] That code does not match; check the app's clock and try again: ".to_string();
+                    return None;
+                }
+                return Some(Effect::SaveTotp);
+            }
+            KeyCode::Enter if self.totp_input.len() == totp::DIGITS => {
+                let totp = self.totp.take();
+                let valid = self.take_code(totp.as_ref());
+                self.totp = totp;
+                if !valid {
+                    self.status = "[X] Wrong code, the second factor stays on: ".to_string();
+                    self.push_history("Wrong second factor code", false, None);
+                    return None;
+                }
+                return Some(Effect::RemoveTotp);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // The enrolment code checked out; the new factor is kept once it is saved
+    fn save_totp(&mut self) {
+        let Some((pending, _)) = self.totp_pending.take() else { return };
+        match pending.save() {
+            Ok(()) => {
+                self.totp = Some(pending);
+                self.totp_passed = true;
+                self.mode = Mode::Security;
+                self.status = "[OK] Second factor enrolled; decrypting now also asks for a code".to_string();
+                self.record("Enrolled second factor".to_string(), true, None);
+                self.success_timer = Some(Instant::now());
+            }
+            Err(e) => {
+                self.mode = Mode::Security;
+                self.status = format!("[X] Could not save the second factor: {:#}", e);
+            }
+        }
+    }
+
+    fn remove_totp(&mut self) {
+        match totp::remove() {
+            Ok(()) => {
+                self.totp = None;
+                self.mode = Mode::Security;
+                self.status = "[OK] Second factor removed".to_string();
+                self.record("Removed second factor".to_string(), true, None);
+            }
+            Err(e) => self.status = format!("[X] Could not remove the second factor: {:#}", e),
+        }
+    }
+
+    // Ties an undoable operation to the history entry just pushed for it
+    fn record_undo(&mut self, undo: Option<Undo>) {
+        if let Some(undo) = undo {
+            self.undo_stack.push((self.history.len() - 1, undo));
+        }
+    }
+
+    fn undo_last(&mut self) {
+        let Some((_, op)) = self.undo_stack.pop() else {
+            self.status = "[!] Nothing to undo".to_string();
+            return;
+        };
+        match self.fs.undo(&op) {
+            Ok(()) => {
+                self.status = format!("[OK] Undo: {}", op.describe());
+                self.push_history(format!("Undo: {}", op.describe()), true, None);
+                self.success_timer = Some(Instant::now());
+                if self.selected_dir.selected().is_none() && !self.fs.dirs.is_empty() {
+                    self.selected_dir.select(Some(0));
+                }
+                self.update_current_files();
+            }
+            Err(e) => {
+                self.status = format!("[X] Undo failed: {}", e);
+                self.push_history(format!("Undo failed: {}", e), false, None);
+            }
+        }
+    }
+
+    // Packs the whole folder into `<folder>.gxvault` next to it; the folder itself is left alone
+    fn archive_selected_dir(&mut self) {
+        if self.key_input.is_empty() {
+            self.status = "[!] Enter a key first (k)".to_string();
+            return;
+        }
+        let Some(selected) = self.selected_dir.selected() else { return };
+        let dir = self.fs.dirs[selected].clone();
+        let dest = vault::container_path(&dir);
+        if dest.exists() {
+            self.status = format!("[X] {:?} already exists", dest);
+            return;
+        }
+        if self.fs.config.decoy(&dir).is_some() {
+            self.duress_key.zeroize();
+            self.mode = Mode::EnterDuressKey;
+            self.status = "[Archive] Duress key: ".to_string();
+            return;
+        }
+        self.finish_archive(selected);
+    }
+
+    // The duress key for a folder with a decoy; it must differ from the key
+    fn duress_input(&mut self, code: KeyCode) -> Option<Effect> {
+        match code {
+            KeyCode::Char(c) => self.duress_key.push(c),
+            KeyCode::Backspace => {
+                self.duress_key.pop();
+            }
+            KeyCode::Esc => {
+                self.duress_key.zeroize();
+                self.mode = Mode::NavigateFolders;
+                self.status = "Archive cancelled".to_string();
+                return None;
+            }
+            KeyCode::Enter if !self.duress_key.is_empty() => {
+                self.mode = Mode::NavigateFolders;
+                if self.hardware.is_some() {
+                    self.start_touch(Touch::Duress);
+                    return None;
+                }
+                return Some(Effect::FinishArchive);
+            }
+            _ => {}
+        }
+        self.status = format!("[Archive] Duress key: {}", "*".repeat(self.duress_key.chars().count()));
+        None
+    }
+
+    fn finish_archive(&mut self, selected: usize) {
+        let dir = self.fs.dirs[selected].clone();
+        let dest = vault::container_path(&dir);
+        let cipher = self.fs.cipher_for(selected);
+        let result = match self.fs.config.decoy(&dir) {
+            Some(decoy) => vault::create_with_decoy(&dir, decoy, &dest, &self.key_input, &self.duress_key, cipher),
+            None => vault::create(&dir, &dest, &self.key_input, cipher),
+        };
+        match result {
+            Ok(()) => {
+                self.status = format!("[OK] Archived to {:?}", dest);
+                self.record("Archived folder".to_string(), true, Some(dir));
+                self.success_timer = Some(Instant::now());
+                self.update_current_files();
+            }
+            Err(e) => {
+                self.status = format!("[X] Archive failed: {:#}", e);
+                self.record(format!("Archive failed: {}", e), false, Some(dir));
+            }
+        }
+    }
+
+    // Re-reads every file of the selected vault with the current key; nothing is written
+    fn verify_selected_dir(&mut self) {
+        let Some(key) = self.unlock_key() else {
+            self.status = "[!] Enter a key first (k)".to_string();
+            return;
+        };
+        let Some(selected) = self.selected_dir.selected() else { return };
+        let dir = self.fs.dirs[selected].clone();
+        self.verify_note = self.pending_note.clone();
+        match verify_folder(&dir, &key) {
+            Ok(results) => {
+                let mut report: Vec<_> = results.into_iter().map(|(path, result)| {
+                    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    (self.revealed_names.get(&name).cloned().unwrap_or(name), result.err().map(|e| format!("{:#}", e)))
+                }).collect();
+                report.sort_by(|a, b| b.1.is_some().cmp(&a.1.is_some()).then_with(|| a.0.cmp(&b.0)));
+                let damaged = report.iter().filter(|(_, error)| error.is_some()).count();
+                if damaged == 0 {
+                    self.status = format!("[OK] All {} files verified", report.len());
+                    self.record("Verified folder".to_string(), true, Some(dir));
+                    self.success_timer = Some(Instant::now());
+                } else {
+                    self.status = format!("[X] {} of {} files are damaged", damaged, report.len());
+                    self.record(format!("Verify found {} damaged files", damaged), false, Some(dir));
+                }
+                self.verify_report = report;
+                self.mode = Mode::VerifyReport;
+            }
+            Err(e) => {
+                self.status = format!("[X] Verify failed: {:#}", e);
+                self.record(format!("Verify failed: {}", e), false, Some(dir));
+            }
+        }
+    }
+
+    // Compares the selected folder with its backup (`backup` in config.toml) by chunk hashes; needs no key
+    fn verify_selected_backup(&mut self) {
+        let Some(selected) = self.selected_dir.selected() else { return };
+        let dir = self.fs.dirs[selected].clone();
+        let Some(backup) = self.fs.config.backup(&dir).map(Path::to_path_buf) else {
+            self.status = "[!] No backup set for this folder; add `backup = \"...\"` to it in config.toml".to_string();
+            return;
+        };
+        self.verify_note = self.pending_note.clone();
+        match backup::compare(&dir, &backup, None, backup::Policy::of(self.fs.config.lifecycle(&dir))) {
+            Ok(report) => {
+                let drifted = report.failed();
+                let total = report.matching.len() + drifted;
+                if drifted == 0 {
+                    self.status = format!("[OK] All {} files match the backup", total);
+                    self.record("Verified backup".to_string(), true, Some(dir));
+                    self.success_timer = Some(Instant::now());
+                } else {
+                    self.status = format!("[X] {} of {} files differ from the backup", drifted, total);
+                    self.record(format!("Backup differs in {} files", drifted), false, Some(dir));
+                }
+                let mut lines: Vec<_> = report.drift.iter().map(|(name, drift)| (name.clone(), Some(drift.describe()))).collect();
+                lines.extend(report.matching.into_iter().map(|name| (name, None)));
+                self.verify_report = lines;
+                self.mode = Mode::VerifyReport;
+            }
+            Err(e) => {
+                self.status = format!("[X] Backup check failed: {:#}", e);
+                self.record(format!("Backup check failed: {}", e), false, Some(dir));
+            }
+        }
+    }
+
+    // Signs every file of the selected encrypted folder with this machine's signing key
+    fn sign_selected_dir(&mut self) {
+        let Some(selected) = self.selected_dir.selected().filter(|&i| self.fs.is_encrypted(i)) else {
+            self.status = "[!] Only encrypted folders can be signed".to_string();
+            return;
+        };
+        let dir = self.fs.dirs[selected].clone();
+        let result = signing::load(None).and_then(|key| {
+            let files = files_in(&dir)?;
+            signing::sign_folder(&dir, &files, &key)?;
+            Ok(files.len())
+        });
+        match result {
+            Ok(count) => {
+                self.status = format!("[OK] Signed {} files", count);
+                self.record("Signed folder".to_string(), true, Some(dir));
+                self.success_timer = Some(Instant::now());
+            }
+            Err(e) => {
+                self.status = format!("[X] Signing failed: {:#}", e);
+                self.record(format!("Signing failed: {}", e), false, Some(dir));
+            }
+        }
+    }
+
+    // Checks the selected folder's signatures against this machine's signing keys; no key is needed
+    fn check_selected_signatures(&mut self) {
+        let Some(selected) = self.selected_dir.selected().filter(|&i| self.fs.is_encrypted(i)) else {
+            self.status = "[!] Only encrypted folders carry signatures".to_string();
+            return;
+        };
+        let dir = self.fs.dirs[selected].clone();
+        self.verify_note = self.pending_note.clone();
+        let result = signing::own_public_keys().and_then(|trusted| signing::verify_folder(&dir, &files_in(&dir)?, &trusted));
+        match result {
+            Ok(verdicts) => {
+                let mut report: Vec<_> = verdicts.into_iter().map(|(name, verdict)| {
+                    let error = match verdict {
+                        Verdict::Valid { .. } => None,
+                        Verdict::UnknownSigner { signer } => Some(format!("signed by an untrusted key {}", signer)),
+                        Verdict::Invalid => Some("signature does not match; the file changed or was swapped".to_string()),
+                        Verdict::Unsigned => Some("not signed".to_string()),
+                    };
+                    (self.revealed_names.get(&name).cloned().unwrap_or(name), error)
+                }).collect();
+                report.sort_by(|a, b| b.1.is_some().cmp(&a.1.is_some()).then_with(|| a.0.cmp(&b.0)));
+                let failed = report.iter().filter(|(_, error)| error.is_some()).count();
+                if failed == 0 {
+                    self.status = format!("[OK] All {} signatures are valid", report.len());
+                    self.record("Checked signatures".to_string(), true, Some(dir));
+                    self.success_timer = Some(Instant::now());
+                } else {
+                    self.status = format!("[X] {} of {} files have no valid signature", failed, report.len());
+                    self.record(format!("Signature check found {} bad files", failed), false, Some(dir));
+                }
+                self.verify_report = report;
+                self.mode = Mode::VerifyReport;
+            }
+            Err(e) => {
+                self.status = format!("[X] Signature check failed: {:#}", e);
+                self.record(format!("Signature check failed: {}", e), false, Some(dir));
+            }
+        }
+    }
+
+    // `number` is what the lists show, so it counts from 1
+    fn jump_to(&mut self, number: usize, files: bool) -> Option<Effect> {
+        let len = if files { self.current_files.len() } else { self.fs.dirs.len() };
+        if number == 0 || number > len {
+            self.status = format!("[!] No {} {}", if files { "file" } else { "folder" }, number);
+            return None;
+        }
+        if files {
+            self.selected_file.select(Some(number - 1));
+            return None;
+        }
+        self.selected_dir.select(Some(number - 1));
+        Some(Effect::ListFiles { fresh: false })
+    }
+
+    fn toggle_tree_view(&mut self) -> Option<Effect> {
+        self.settings.tree_view = !self.settings.tree_view;
+        if !self.settings.tree_view {
+            let dir = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned();
+            self.fs.collapse_all();
+            // A subfolder that was selected hands the selection to its folder
+            let index = dir.and_then(|dir| self.fs.dirs.iter().position(|d| dir.starts_with(d)));
+            self.selected_dir.select(index.or(if self.fs.dirs.is_empty() { None } else { Some(0) }));
+        }
+        self.status = if self.settings.tree_view {
+            format!("Tree view: {} expands a folder, {} collapses it", glyph(self.settings.ascii, "→", "Right"), glyph(self.settings.ascii, "←", "Left"))
+        } else {
+            "Flat folder list".to_string()
+        };
+        (!self.settings.tree_view).then_some(Effect::ListFiles { fresh: true })
+    }
+
+    // Whether → in the tree view has a folder to expand: not when it is expanded already or has no subfolders
+    fn expandable(&self) -> bool {
+        let Some(index) = self.selected_dir.selected().filter(|&i| i < self.fs.dirs.len() && !self.fs.is_expanded(i)) else { return false };
+        self.fs.has_subfolders(index) != Some(false)
+    }
+
+    // → in the tree view; false when the folder turned out to have no subfolders, so → goes to its files
+    fn expand_selected(&mut self) -> bool {
+        if !self.expandable() {
+            return false;
+        }
+        self.arrange_dirs(|fs, i| fs.expand(i).map(|_| ()));
+        self.selected_dir.selected().is_some_and(|i| self.fs.has_subfolders(i) == Some(true)) || self.status.starts_with("[!]")
+    }
+
+    // ← in the tree view collapses the selected folder, or else selects the folder it is under
+    fn collapse_selected(&mut self) -> Option<Effect> {
+        let index = self.selected_dir.selected()?;
+        if self.fs.is_expanded(index) {
+            self.fs.collapse(index);
+            return None;
+        }
+        let parent = self.fs.parent_index(index)?;
+        self.selected_dir.select(Some(parent));
+        Some(Effect::ListFiles { fresh: false })
+    }
+
+    fn start_type_ahead(&mut self, files: bool) {
+        self.jump_input.clear();
+        self.jump_files = files;
+        self.typed_at = Some(Instant::now());
+        self.mode = Mode::TypeAhead;
+        self.status = "/".to_string();
+    }
+
+    // Selects the first folder or file whose name starts with what was typed, or else contains it, ignoring case
+    fn type_ahead(&mut self, code: KeyCode) -> Option<Effect> {
+        match code {
+            KeyCode::Char(c) => self.jump_input.push(c),
+            KeyCode::Backspace => {
+                self.jump_input.pop();
+            }
+            _ => return None,
+        }
+        self.typed_at = Some(Instant::now());
+        let typed = self.jump_input.to_lowercase();
+        let names: Vec<String> = if self.jump_files {
+            self.current_files.iter().map(|(name, _, _)| self.revealed_names.get(name).unwrap_or(name).to_lowercase()).collect()
+        } else {
+            self.fs.dirs.iter().map(|d| d.file_name().unwrap_or(d.as_os_str()).to_string_lossy().to_lowercase()).collect()
+        };
+        let found = names.iter().position(|name| name.starts_with(&typed)).or_else(|| names.iter().position(|name| name.contains(&typed)));
+        match found {
+            Some(index) if !typed.is_empty() => {
+                self.status = format!("/{}", self.jump_input);
+                if self.jump_files {
+                    self.selected_file.select(Some(index));
+                } else if self.selected_dir.selected() != Some(index) {
+                    self.selected_dir.select(Some(index));
+                    return Some(Effect::ListFiles { fresh: false });
+                }
+            }
+            _ if typed.is_empty() => self.status = "/".to_string(),
+            _ => self.status = format!("/{} (no match)", self.jump_input),
+        }
+        None
+    }
+
+    fn end_type_ahead(&mut self) {
+        self.typed_at = None;
+        self.mode = if self.jump_files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+        self.status = tr("status.ready");
+    }
+
+    // Pinning, moving and labelling reorder the list, so the selection follows the folder by path
+    fn arrange_dirs(&mut self, change: fn(&mut FileSystem, usize) -> Result<()>) {
+        let Some(selected) = self.selected_dir.selected() else { return };
+        let dir = self.fs.dirs.get(selected).cloned();
+        if let Err(e) = change(&mut self.fs, selected) {
+            self.status = format!("[!] {}", e);
+            return;
+        }
+        let index = dir.and_then(|dir| self.fs.dirs.iter().position(|d| *d == dir));
+        self.selected_dir.select(index.or(Some(selected)));
+    }
+
+    // Lifecycle changes go into the history log like any other significant operation
+    fn cycle_lifecycle(&mut self) {
+        let Some(selected) = self.selected_dir.selected() else { return };
+        let Some(dir) = self.fs.dirs.get(selected).cloned() else { return };
+        match self.fs.cycle_lifecycle(selected) {
+            Ok(state) => {
+                if !state.is_active() {
+                    self.guards.retain(|g| g.dir() != dir);
+                }
+                self.status = match state {
+                    Lifecycle::Archived if !self.fs.show_archived => "[OK] Folder archived and hidden (H shows archived folders)".to_string(),
+                    Lifecycle::Retired => "[OK] Folder retired: read-only, deletable after the grace period".to_string(),
+                    state => format!("[OK] Folder is now {}", state.name()),
+                };
+                self.record(format!("Marked folder {}", state.name()), true, Some(dir.clone()));
+                self.success_timer = Some(Instant::now());
+                let index = self.fs.dirs.iter().position(|d| *d == dir).or(Some(selected.min(self.fs.dirs.len().saturating_sub(1))));
+                self.selected_dir.select(index.filter(|_| !self.fs.dirs.is_empty()));
+                self.update_current_files();
+            }
+            Err(e) => self.status = format!("[X] Could not change the folder's state: {}", e),
+        }
+    }
+
+    fn drive_changed(&mut self, change: Change) {
+        match change {
+            Change::Attached { mount, vaults } => {
+                let back = self.fs.come_online(&mount);
+                if !back.is_empty() {
+                    self.status = format!("[OK] {} is back; {} folders are online again", mount.display(), back.len());
+                    self.push_history(format!("Back online: {}", mount.display()), true, None);
+                    self.resume_interrupted();
+                    self.update_current_files();
+                }
+                if !self.fs.config.ignore_drives {
+                    let selected = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned();
+                    let added = self.fs.add_drive(&mount, &vaults);
+                    let index = selected.and_then(|dir| self.fs.dirs.iter().position(|d| *d == dir));
+                    self.selected_dir.select(index.or(if self.fs.dirs.is_empty() { None } else { Some(0) }));
+                    if added > 0 {
+                        self.status = format!("[Drive] Listed {} folders from {}", added, mount.display());
+                        self.push_history(format!("Drive: {}", mount.display()), true, None);
+                    }
+                    self.update_current_files();
+                    return;
+                }
+                let vaults: Vec<PathBuf> = vaults.into_iter().filter(|v| !self.fs.dirs.contains(v)).collect();
+                if !vaults.is_empty() {
+                    self.drive_offers.push((mount, vaults));
+                }
+            }
+            Change::Detached { mount } => {
+                self.drive_offers.retain(|(m, _)| *m != mount);
+                if self.mode == Mode::ConfirmDrive && self.drive_offers.is_empty() {
+                    self.mode = Mode::NavigateFolders;
+                }
+                if self.container.as_ref().is_some_and(|(path, _)| path.starts_with(&mount)) {
+                    self.container = None;
+                    if self.mode == Mode::BrowseContainer {
+                        self.mode = Mode::NavigateFolders;
+                    }
+                }
+                let gone = self.fs.go_offline(&mount);
+                if gone.is_empty() {
+                    return;
+                }
+                // Whatever was under way on a folder that went offline cannot go on
+                let selected = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned();
+                if selected.as_ref().is_some_and(|dir| gone.contains(dir)) && self.mode != Mode::Locked {
+                    self.clear_rekey();
+                    self.mode = Mode::NavigateFolders;
+                }
+                self.fs.remove_drive(&mount);
+                let index = selected.and_then(|dir| self.fs.dirs.iter().position(|d| *d == dir));
+                self.selected_dir.select(index.or(if self.fs.dirs.is_empty() { None } else { Some(0) }));
+                self.update_current_files();
+                let offline = gone.iter().filter(|dir| self.fs.dirs.contains(dir)).count();
+                self.status = if offline == 0 {
+                    format!("[!] {} was removed", mount.display())
+                } else {
+                    format!("[!] {} was removed; {} folders are offline until it is back", mount.display(), offline)
+                };
+                self.push_history(format!("Offline: {}", mount.display()), true, None);
+            }
+        }
+    }
+
+    // Finishes encryptions and decryptions that a removed drive or a full disk cut short, for the folders
+    // that are back
+    fn resume_interrupted(&mut self) {
+        let entries = match journal::load() {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.status = format!("[X] {:#}", e);
+                return;
+            }
+        };
+        let resumable: Vec<_> = entries.into_iter().filter(|e| e.dir.is_dir() && !self.jobs.contains_key(&e.dir)).collect();
+        for entry in resumable {
+            self.resume_entry(entry);
+        }
+        self.update_current_files();
+    }
+
+    // e and d on a folder whose last operation was cut short finish that operation instead of starting
+    // over, which would encrypt its encrypted files a second time; true when there was one
+    fn resume_journaled(&mut self, index: usize) -> bool {
+        let Some(entry) = self.fs.dirs.get(index).and_then(|dir| journal::find(dir).ok().flatten()) else { return false };
+        self.resume_entry(entry);
+        self.update_current_files();
+        true
+    }
+
+    fn resume_entry(&mut self, entry: Interrupted) {
+        let name = entry.dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if self.key_input.is_empty() {
+            self.status = format!("[!] {} finishes its {} once the key is entered (k)", name, entry.operation.name());
+            return;
+        }
+        let result = self.fs.resume(&entry, &self.key_input);
+        self.settle_statuses(&entry.dir, result.as_ref().err());
+        match result {
+            Ok(()) => {
+                if let Some(i) = self.fs.dirs.iter().position(|d| *d == entry.dir) {
+                    self.fs.mark_encrypted(i, entry.operation == Operation::Encrypt);
+                }
+                self.status = format!("[OK] Finished the {} of {}", entry.operation.name(), name);
+                self.record(format!("Resumed {}", entry.operation.name()), true, Some(entry.dir.clone()));
+            }
+            Err(e) => {
+                self.status = format!("[X] Could not finish the {} of {}: {}", entry.operation.name(), name, failure_message(&e));
+                self.record(format!("Resume failed: {}", e), false, Some(entry.dir.clone()));
+            }
+        }
+    }
+
+    fn start_decrypt(&mut self, index: usize) {
+        if self.resume_journaled(index) {
+            return;
+        }
+        let key = self.unlock_key();
+        if key.is_none() {
+            self.status = "[!] Enter a key first (k)".to_string();
+        } else if let (Some(key), true) = (key, self.second_factor()) {
+            let job = self.fs.decrypt_job(index, &key);
+            self.start_job(index, Operation::Decrypt, job);
+        }
+    }
+
+    // Runs on a worker; the folder is busy until its JobEvent::Finished comes back
+    // Nothing is encrypted until the summary of what would happen is confirmed
+    fn preflight(&mut self, index: usize, job: Result<Job>) {
+        match job.and_then(|job| job.plan().map(|plan| (job, plan))) {
+            Ok((job, plan)) => {
+                self.status = format!("[?] Encrypt {} files ({}) in {}? [y/n]", plan.process.len(), human_size(plan.total_size()), rough_duration(plan.estimate()));
+                self.preflight = Some((job, plan));
+                self.mode = Mode::ConfirmEncrypt;
+            }
+            Err(e) => self.job_failed(Operation::Encrypt, self.fs.dirs.get(index).cloned(), e),
+        }
+    }
+
+    fn confirm_encrypt(&mut self, confirmed: bool) {
+        self.mode = Mode::NavigateFolders;
+        let Some((job, plan)) = self.preflight.take() else { return };
+        if !confirmed {
+            self.status = "Encryption cancelled; nothing was changed".to_string();
+            return;
+        }
+        // The folder list may have changed while the summary was up
+        let dir = job.dir().to_path_buf();
+        let Some(index) = self.fs.dirs.iter().position(|d| *d == dir) else {
+            self.status = "[!] The folder is no longer listed".to_string();
+            return;
+        };
+        self.start_job(index, Operation::Encrypt, Ok(job));
+        if let Some(running) = self.jobs.get_mut(&dir) {
+            running.skipped = plan.skipped.iter().map(|(path, reason)| (path.file_name().unwrap_or_default().to_string_lossy().to_string(), reason.clone())).collect();
+        }
+    }
+
+    fn start_job(&mut self, index: usize, operation: Operation, job: Result<Job>) {
+        let job = match job {
+            Ok(job) => job,
+            Err(e) => return self.job_failed(operation, self.fs.dirs.get(index).cloned(), e),
+        };
+        let dir = job.dir().to_path_buf();
+        let files = files_in(&dir).unwrap_or_default().iter()
+            .map(|f| (f.file_name().unwrap_or_default().to_string_lossy().to_string(), f.metadata().map_or(0, |m| m.len())))
+            .collect::<Vec<_>>();
+        self.file_statuses.insert(dir.clone(), files.iter().map(|(name, _)| (name.clone(), FileStatus::Pending)).collect());
+        let running = Running { operation, done: 0, total: 0, note: self.pending_note.take(), started: Instant::now(), files, skipped: Vec::new() };
+        self.jobs.insert(dir.clone(), running);
+        let verb = if operation == Operation::Encrypt { "Encrypting" } else { "Decrypting" };
+        self.status = format!("[..] {} {} in the background; other folders can be used meanwhile", verb, dir.display());
+        let tx = self.job_tx.clone();
+        std::thread::spawn(move || {
+            let result = job.run(&mut |done, total, file| {
+                let _ = tx.send(JobEvent::Progress { dir: dir.clone(), done, total, file: file.to_path_buf() });
+            });
+            let _ = tx.send(JobEvent::Finished { dir, operation, result });
+        });
+    }
+
+    fn job_event(&mut self, event: JobEvent) {
+        match event {
+            JobEvent::Progress { dir, done, total, file } => {
+                if let Some(running) = self.jobs.get_mut(&dir) {
+                    (running.done, running.total) = (done, total);
+                }
+                let statuses = self.file_statuses.entry(dir.clone()).or_default();
+                for status in statuses.values_mut().filter(|s| **s == FileStatus::Running) {
+                    *status = FileStatus::Done;
+                }
+                let name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+                // A hidden name comes back as the real one once decrypted
+                if let Some(real) = self.revealed_names.get(&name) {
+                    statuses.insert(real.clone(), FileStatus::Running);
+                }
+                statuses.insert(name, FileStatus::Running);
+                if self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)) == Some(&dir) {
+                    self.refresh_files();
+                }
+            }
+            JobEvent::Finished { dir, operation, result } => {
+                // The job's history entry gets the note pending when it started; one typed since waits
+                let running = self.jobs.remove(&dir);
+                let note = running.as_ref().and_then(|running| running.note.clone());
+                let pending = std::mem::replace(&mut self.pending_note, note);
+                self.settle_statuses(&dir, result.as_ref().err());
+                if let Some(running) = &running {
+                    self.reports.push(self.job_report(&dir, running, result.as_ref().err()));
+                }
+                match result {
+                    Ok(()) => {
+                        if let Some(i) = self.fs.dirs.iter().position(|d| *d == dir) {
+                            self.fs.mark_encrypted(i, operation == Operation::Encrypt);
+                        }
+                        // A decrypted folder is meant to stay plaintext
+                        if operation == Operation::Decrypt && self.guards.iter().any(|g| g.dir() == dir) {
+                            self.guards.retain(|g| g.dir() != dir);
+                            self.push_history(format!("Stopped watching {}", dir.display()), true, None);
+                        }
+                        let (done, entry) = match operation {
+                            Operation::Encrypt => ("encrypted", "Encrypted folder"),
+                            Operation::Decrypt => ("decrypted", "Decrypted folder"),
+                        };
+                        self.status = format!("[OK] {} {}!", dir.display(), done);
+                        self.record(entry.to_string(), true, Some(dir.clone()));
+                        self.success_timer = Some(Instant::now());
+                    }
+                    Err(e) => self.job_failed(operation, Some(dir.clone()), e),
+                }
+                self.pending_note = pending;
+                if self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)) == Some(&dir) {
+                    self.update_current_files();
+                }
+            }
+        }
+    }
+
+    // A finished operation needs no statuses. Otherwise the file in progress failed with the error and the
+    // rest were skipped, unless the journal holds them for when the drive is back.
+    fn settle_statuses(&mut self, dir: &Path, error: Option<&anyhow::Error>) {
+        let Some(error) = error else {
+            self.file_statuses.remove(dir);
+            return;
+        };
+        let journaled = journal::find(dir).ok().flatten().map(|entry| entry.remaining).unwrap_or_default();
+        let Some(statuses) = self.file_statuses.get_mut(dir) else { return };
+        let is_journaled = |name: &str| journaled.iter().any(|f| f.file_name().is_some_and(|n| n.to_string_lossy() == name));
+        for (name, status) in statuses.iter_mut() {
+            *status = match &*status {
+                FileStatus::Running | FileStatus::Pending if is_journaled(name) => FileStatus::Pending,
+                FileStatus::Running => FileStatus::Failed(format!("{:#}", error)),
+                FileStatus::Pending => FileStatus::Skipped,
+                status => status.clone(),
+            };
+        }
+    }
+
+    // Called once the statuses are settled; after a success every file was processed
+    fn job_report(&self, dir: &Path, running: &Running, error: Option<&anyhow::Error>) -> Report {
+        let item = |file: &str, reason: &str| Item { file: file.to_string(), reason: reason.to_string() };
+        let mut report = Report {
+            operation: running.operation,
+            folder: dir.to_path_buf(),
+            finished: entropy::timestamp(),
+            elapsed_secs: running.started.elapsed().as_secs_f64(),
+            bytes: 0,
+            processed: Vec::new(),
+            skipped: running.skipped.iter().map(|(file, reason)| item(file, reason)).collect(),
+            failed: Vec::new(),
+        };
+        let statuses = self.file_statuses.get(dir);
+        for (name, size) in &running.files {
+            match statuses.and_then(|s| s.get(name)) {
+                Some(FileStatus::Failed(reason)) if error.is_some() => report.failed.push(item(name, reason)),
+                Some(FileStatus::Pending) if error.is_some() => report.skipped.push(item(name, "its drive went away; finished when it is back")),
+                Some(FileStatus::Skipped) if error.is_some() => report.skipped.push(item(name, "not reached after the failure")),
+                _ => {
+                    report.processed.push(name.clone());
+                    report.bytes += size;
+                }
+            }
+        }
+        // Failing after the last file, say while writing the manifest, leaves no file to blame
+        if let (Some(e), true) = (error, report.failed.is_empty()) {
+            report.failed.push(item(&dir.display().to_string(), &format!("{:#}", e)));
+        }
+        report
+    }
+
+    // The oldest report not yet dismissed opens once the folder or file list is back
+    fn show_report(&mut self) {
+        if !self.reports.is_empty() && matches!(self.mode, Mode::NavigateFolders | Mode::NavigateFiles) {
+            self.report_from_files = self.mode == Mode::NavigateFiles;
+            self.mode = Mode::OperationReport;
+        }
+    }
+
+    fn report_input(&mut self, code: KeyCode) -> Option<Effect> {
+        match code {
+            KeyCode::Char(c @ ('t' | 'j')) if !self.reports.is_empty() => return Some(Effect::ExportReport { json: c == 'j' }),
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => {
+                if !self.reports.is_empty() {
+                    self.reports.remove(0);
+                }
+                if self.reports.is_empty() {
+                    self.mode = if self.report_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn open_history(&mut self) {
+        if self.history.is_empty() {
+            self.status = "[!] Nothing in the history yet".to_string();
+            return;
+        }
+        self.history_from_files = self.mode == Mode::NavigateFiles;
+        self.mode = Mode::HistoryBrowser;
+        self.history_view.select(self.shown_history().len().checked_sub(1));
+    }
+
+    // An outcome the next status would otherwise overwrite
+    fn notify(&mut self, status: String) {
+        let Some(severity) = Severity::of(&status) else { return };
+        if self.notices.len() == NOTICES_KEPT {
+            self.notices.pop_front();
+        }
+        self.notices.push_back(Notice { text: status, severity, shown: Instant::now(), time: entropy::timestamp(), dismissed: false });
+    }
+
+    // Newest last, and no longer than `toast_for` on screen
+    fn toasts(&self) -> Vec<&Notice> {
+        let mut toasts: Vec<&Notice> = self.notices.iter().rev().take(TOASTS_SHOWN).filter(|n| !n.dismissed && n.shown.elapsed() < self.toast_for).collect();
+        toasts.reverse();
+        toasts
+    }
+
+    // Hides the toasts still up; the center keeps them
+    fn dismiss_toasts(&mut self) {
+        for notice in &mut self.notices {
+            notice.dismissed = true;
+        }
+    }
+
+    fn open_notifications(&mut self) {
+        if self.notices.is_empty() {
+            self.status = "Nothing to show in the notification center yet".to_string();
+            return;
+        }
+        self.notices_from_files = self.mode == Mode::NavigateFiles;
+        self.mode = Mode::Notifications;
+        self.notice_view.select(Some(self.notices.len() - 1));
+    }
+
+    // Newest at the bottom, like the history
+    fn notice_input(&mut self, code: KeyCode) {
+        let last = self.notices.len().saturating_sub(1);
+        let selected = self.notice_view.selected().unwrap_or(last);
+        match code {
+            KeyCode::Up => self.notice_view.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => self.notice_view.select(Some((selected + 1).min(last))),
+            KeyCode::Home => self.notice_view.select(Some(0)),
+            KeyCode::End => self.notice_view.select(Some(last)),
+            KeyCode::Char('c') => {
+                self.notices.clear();
+                self.mode = if self.notices_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+            }
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('!') => {
+                self.mode = if self.notices_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+            }
+            _ => {}
+        }
+    }
+
+    fn shown_history(&self) -> Vec<&LogEntry> {
+        self.history.iter().filter(|entry| !self.history_failures_only || !entry.ok).collect()
+    }
+
+    // Newest at the bottom, where it opens
+    fn history_input(&mut self, code: KeyCode) {
+        let last = self.shown_history().len().saturating_sub(1);
+        let selected = self.history_view.selected().unwrap_or(last);
+        match code {
+            KeyCode::Up => self.history_view.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => self.history_view.select(Some((selected + 1).min(last))),
+            KeyCode::PageUp => self.history_view.select(Some(selected.saturating_sub(20))),
+            KeyCode::PageDown => self.history_view.select(Some((selected + 20).min(last))),
+            KeyCode::Home => self.history_view.select(Some(0)),
+            KeyCode::End => self.history_view.select(Some(last)),
+            KeyCode::Char('f') => {
+                self.history_failures_only = !self.history_failures_only;
+                self.history_view.select(self.shown_history().len().checked_sub(1));
+            }
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('I') => {
+                self.mode = if self.history_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+            }
+            _ => {}
+        }
+    }
+
+    fn toggle_bookmark(&mut self) -> Option<Effect> {
+        let dir = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned()?;
+        let bookmarked = self.fs.config.toggle_bookmark(&dir);
+        let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        self.status = if bookmarked { format!("[OK] Bookmarked {} (' to jump)", name) } else { format!("Removed the bookmark on {}", name) };
+        Some(Effect::SaveConfig)
+    }
+
+    fn open_bookmarks(&mut self) {
+        if self.fs.config.bookmarks.is_empty() {
+            self.status = "[!] No bookmarks yet; bookmark a folder with b".to_string();
+            return;
+        }
+        self.mode = Mode::Bookmarks;
+        self.bookmark_missing = self.fs.config.bookmarks.iter().map(|dir| !dir.is_dir()).collect();
+        self.bookmark_view.select(Some(0));
+    }
+
+    fn bookmark_input(&mut self, code: KeyCode) -> Option<Effect> {
+        let last = self.fs.config.bookmarks.len().saturating_sub(1);
+        let selected = self.bookmark_view.selected().unwrap_or(0);
+        match code {
+            KeyCode::Up => self.bookmark_view.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => self.bookmark_view.select(Some((selected + 1).min(last))),
+            KeyCode::Enter | KeyCode::Right => return Some(Effect::GoToBookmark(selected)),
+            KeyCode::Char(c @ '1'..='9') => return Some(Effect::GoToBookmark(c as usize - '1' as usize)),
+            KeyCode::Char('d') | KeyCode::Delete => {
+                let removed = (selected < self.fs.config.bookmarks.len()).then(|| self.fs.config.bookmarks.remove(selected));
+                if let Some(removed) = &removed {
+                    self.bookmark_missing.remove(selected);
+                    self.status = format!("Removed the bookmark on {}", removed.display());
+                }
+                if self.fs.config.bookmarks.is_empty() {
+                    self.mode = Mode::NavigateFolders;
+                } else {
+                    self.bookmark_view.select(Some(selected.min(self.fs.config.bookmarks.len() - 1)));
+                }
+                return removed.map(|_| Effect::SaveConfig);
+            }
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('\'') => self.mode = Mode::NavigateFolders,
+            _ => {}
+        }
+        None
+    }
+
+    fn go_to_bookmark(&mut self, index: usize) {
+        let Some(dir) = self.fs.config.bookmarks.get(index).cloned() else { return };
+        self.mode = Mode::NavigateFolders;
+        self.select_folder(&dir);
+    }
+
+    // Selects the folder, listing it until GuardX quits when it is outside the listed ones; false when it
+    // cannot be selected
+    fn select_folder(&mut self, dir: &Path) -> bool {
+        if !dir.is_dir() {
+            self.status = format!("[X] {} does not exist or is not mounted", dir.display());
+            return false;
+        }
+        if !self.fs.dirs.iter().any(|d| d == dir) {
+            self.fs.attach(&[dir.to_path_buf()]);
+        }
+        match self.fs.dirs.iter().position(|d| d == dir) {
+            Some(i) => {
+                self.selected_dir.select(Some(i));
+                self.show_current_files();
+                true
+            }
+            // Archived folders are only listed while H shows them
+            None => {
+                self.status = format!("[!] {} is archived; show archived folders with H", dir.display());
+                false
+            }
+        }
+    }
+
+    // Walks the roots again on a worker; drives and profiles change the roots, and F5 in the finder asks for it
+    fn index_roots(&mut self) {
+        self.finder_roots = self.fs.roots.clone();
+        self.finder_entries.clear();
+        self.finder_matches.clear();
+        self.finder_truncated = false;
+        self.finder_rx = Some(finder::spawn(self.finder_roots.clone(), self.fs.config.finder_files));
+    }
+
+    fn poll_finder(&mut self) {
+        let Some(rx) = self.finder_rx.as_ref() else { return };
+        let (mut found, mut finished) = (false, false);
+        loop {
+            match rx.try_recv() {
+                Ok(Progress::Found(entries)) => {
+                    self.finder_entries.extend(entries);
+                    found = true;
+                }
+                Ok(Progress::Done { truncated }) => {
+                    self.finder_truncated = truncated;
+                    finished = true;
+                    break;
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+        if finished {
+            self.finder_rx = None;
+        }
+        if found && self.mode == Mode::Finder {
+            self.search_finder();
+        }
+    }
+
+    // Ctrl+P; opens at once, and matches show up as the index fills in when it is still being built
+    fn open_finder(&mut self, files: bool) {
+        if self.finder_roots != self.fs.roots {
+            self.index_roots();
+        }
+        self.finder_from_files = files;
+        self.finder_query.clear();
+        self.search_finder();
+        self.mode = Mode::Finder;
+    }
+
+    // With nothing typed the folders are listed in the order they were found
+    fn search_finder(&mut self) {
+        self.finder_matches = if self.finder_query.is_empty() {
+            (0..self.finder_entries.len().min(FINDER_MATCHES)).collect()
+        } else {
+            finder::search(&self.finder_entries, &self.finder_query, FINDER_MATCHES)
+        };
+        self.finder_view.select(if self.finder_matches.is_empty() { None } else { Some(0) });
+    }
+
+    fn open_palette(&mut self, files: bool) {
+        self.palette_from_files = files;
+        self.palette_query.clear();
+        self.search_palette();
+        self.mode = Mode::Palette;
+    }
+
+    // A number typed into the palette jumps to that folder or file, as its first entry
+    fn palette_number(&self) -> Option<usize> {
+        self.palette_query.trim().parse().ok().filter(|&n| n > 0)
+    }
+
+    fn search_palette(&mut self) {
+        let offered = COMMANDS.iter().enumerate().filter(|(_, c)| c.scope != Scope::Files || self.palette_from_files);
+        self.palette_matches = if self.palette_query.is_empty() {
+            offered.map(|(i, _)| i).collect()
+        } else {
+            let mut scored: Vec<(i64, usize)> = offered.filter_map(|(i, c)| finder::score(&self.palette_query, c.name).map(|s| (s, i))).collect();
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+            scored.into_iter().map(|(_, i)| i).collect()
+        };
+        let rows = self.palette_matches.len() + usize::from(self.palette_number().is_some());
+        self.palette_view.select(if rows == 0 { None } else { Some(0) });
+    }
+
+    fn palette_input(&mut self, code: KeyCode) -> Option<Effect> {
+        let selected = self.palette_view.selected().unwrap_or(0);
+        let rows = self.palette_matches.len() + usize::from(self.palette_number().is_some());
+        match code {
+            KeyCode::Up => self.palette_view.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => self.palette_view.select(Some((selected + 1).min(rows.saturating_sub(1)))),
+            KeyCode::Enter => return self.run_palette(),
+            KeyCode::Esc => self.mode = if self.palette_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders },
+            KeyCode::Backspace => {
+                self.palette_query.pop();
+                self.search_palette();
+            }
+            KeyCode::Char(c) => {
+                self.palette_query.push(c);
+                self.search_palette();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // The command's key is handled on the next turn of the event loop, in the list or screen it belongs to
+    fn run_palette(&mut self) -> Option<Effect> {
+        let files = self.palette_from_files;
+        self.mode = if files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+        let mut row = self.palette_view.selected()?;
+        if let Some(number) = self.palette_number() {
+            if row == 0 {
+                return self.jump_to(number, files);
+            }
+            row -= 1;
+        }
+        let command = self.palette_matches.get(row).map(|&i| &COMMANDS[i])?;
+        match command.scope {
+            Scope::Folders if files => {
+                self.mode = Mode::NavigateFolders;
+                self.selected_file.select(None);
+            }
+            Scope::Settings => self.mode = Mode::Settings,
+            _ => {}
+        }
+        self.palette_pick = Some((command.key, command.modifiers));
+        None
+    }
+
+    // In $VISUAL or $EDITOR, or the system's app for it; most of it is read when GuardX starts
+    fn edit_config<B: Backend>(&mut self, terminal: &mut Terminal<B>) {
+        let path = match Config::file() {
+            Ok(path) => path,
+            Err(e) => {
+                self.status = format!("[X] {:#}", e);
+                return;
+            }
+        };
+        self.status = match open_external(terminal, &path) {
+            Ok(true) => match Config::load() {
+                Ok(_) => "[OK] config.toml saved; restart GuardX to apply it".to_string(),
+                Err(e) => format!("[!] {:#}", e),
+            },
+            Ok(false) => format!("Opened {}; restart GuardX once it is saved", path.display()),
+            Err(e) => format!("[X] {:#}", e),
+        };
+    }
+
+    fn finder_input(&mut self, code: KeyCode) -> Option<Effect> {
+        let selected = self.finder_view.selected().unwrap_or(0);
+        match code {
+            KeyCode::Up => self.finder_view.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => self.finder_view.select(Some((selected + 1).min(self.finder_matches.len().saturating_sub(1)))),
+            KeyCode::Enter => return Some(Effect::GoToFound),
+            KeyCode::F(5) => {
+                self.index_roots();
+                self.search_finder();
+            }
+            KeyCode::Esc => self.mode = if self.finder_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders },
+            KeyCode::Backspace => {
+                self.finder_query.pop();
+                self.search_finder();
+            }
+            KeyCode::Char(c) => {
+                self.finder_query.push(c);
+                self.search_finder();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // A folder is selected like a bookmark; a file selects its folder, then the file
+    fn go_to_found(&mut self) {
+        let Some(entry) = self.finder_view.selected().and_then(|i| self.finder_matches.get(i)).and_then(|&i| self.finder_entries.get(i)) else { return };
+        let (path, is_dir) = (entry.path.clone(), entry.is_dir);
+        self.mode = Mode::NavigateFolders;
+        let dir = if is_dir { path.as_path() } else { path.parent().unwrap_or(Path::new("/")) };
+        if !self.select_folder(dir) || is_dir {
+            return;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match self.current_files.iter().position(|(file, _, _)| *file == name) {
+            Some(index) => {
+                self.selected_file.select(Some(index));
+                self.mode = Mode::NavigateFiles;
+            }
+            None => self.status = format!("[!] {} is no longer there", path.display()),
+        }
+    }
+
+    // C: the other pane starts on the selected folder; pick another one for it with Tab and the folder list
+    fn toggle_panes(&mut self) {
+        if self.other_pane.take().is_some() {
+            self.status = "Single pane".to_string();
+            return;
+        }
+        let Some(dir) = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned() else { return };
+        self.other_pane = Some(Pane { dir, files: Vec::new() });
+        self.refresh_other_pane();
+        self.status = "Two panes: Tab switches, c copies, M moves and e encrypts the selected file into the other pane".to_string();
+    }
+
+    // Tab: the other pane's folder becomes the selected one, and the selected one goes to the other pane
+    fn swap_panes(&mut self) {
+        let Some(current) = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned() else { return };
+        let Some(pane) = self.other_pane.as_mut() else { return };
+        let Some(index) = self.fs.dirs.iter().position(|d| *d == pane.dir) else {
+            self.status = format!("[!] {} is no longer listed", pane.dir.display());
+            pane.dir = current;
+            self.refresh_other_pane();
+            return;
+        };
+        pane.dir = current;
+        self.selected_dir.select(Some(index));
+        self.show_current_files();
+        self.refresh_other_pane();
+        if self.current_files.is_empty() {
+            self.mode = Mode::NavigateFolders;
+        }
+    }
+
+    fn refresh_other_pane(&mut self) {
+        let Some(pane) = self.other_pane.as_mut() else { return };
+        let files = self.fs.dirs.iter().position(|d| *d == pane.dir).and_then(|i| self.fs.get_files(i).ok());
+        pane.files = files.unwrap_or_default();
+        pane.files.sort_by_key(|name| name.to_lowercase());
+    }
+
+    // c, M and e on a file with two panes open: copy, move or encrypt it into the other pane's folder
+    fn transfer_to_other_pane(&mut self, action: Transfer) {
+        let Some(pane) = self.other_pane.as_ref() else { return };
+        let (Some(index), Some(file)) = (self.selected_dir.selected(), self.selected_file.selected().and_then(|i| self.current_files.get(i))) else { return };
+        let name = file.0.clone();
+        let Some(target) = self.fs.dirs.iter().position(|d| *d == pane.dir) else {
+            self.status = format!("[!] {} is no longer listed", pane.dir.display());
+            return;
+        };
+        if let Some(running) = self.jobs.get(&pane.dir) {
+            self.status = format!("[!] {} is busy with its {}", pane.dir.display(), running.operation.name());
+            return;
+        }
+        let shown = self.revealed_names.get(&name).unwrap_or(&name).clone();
+        let target_dir = pane.dir.clone();
+        let result = match action {
+            Transfer::Copy => self.fs.transfer_file(index, &name, target, true),
+            Transfer::Move => self.fs.transfer_file(index, &name, target, false),
+            Transfer::Encrypt => self.fs.encrypt_copy(index, &name, target, &self.key_input).map(|_| None),
+        };
+        match result {
+            Ok(undo) => {
+                self.status = format!("[OK] {} {} to {}", action.done(), shown, target_dir.display());
+                self.success_timer = Some(Instant::now());
+                self.record(format!("{} {}", action.done(), shown), true, Some(target_dir));
+                self.record_undo(undo);
+                self.refresh_files();
+                self.refresh_other_pane();
+            }
+            Err(e) => {
+                self.status = format!("[X] Could not {} {}: {}", action.verb(), shown, failure_message(&e));
+                self.record(format!("{} failed: {}", action.done(), e), false, Some(target_dir));
+            }
+        }
+    }
+
+    fn open_log(&mut self) {
+        match logging::tail(LOG_VIEWER_LINES) {
+            Ok(lines) => {
+                self.log_lines = lines;
+                self.log_scroll = 0;
+                self.log_from_files = self.mode == Mode::NavigateFiles;
+                self.mode = Mode::LogViewer;
+            }
+            Err(e) => self.status = format!("[X] Could not read the log: {:#}", e),
+        }
+    }
+
+    fn shown_log_lines(&self) -> Vec<&String> {
+        self.log_lines.iter().filter(|line| !self.log_problems_only || line.contains(" ERROR ") || line.contains(" WARN ")).collect()
+    }
+
+    fn log_input(&mut self, code: KeyCode) -> Option<Effect> {
+        let last = self.shown_log_lines().len().saturating_sub(1);
+        match code {
+            KeyCode::Up => self.log_scroll = (self.log_scroll + 1).min(last),
+            KeyCode::Down => self.log_scroll = self.log_scroll.saturating_sub(1),
+            KeyCode::PageUp => self.log_scroll = (self.log_scroll + 20).min(last),
+            KeyCode::PageDown => self.log_scroll = self.log_scroll.saturating_sub(20),
+            KeyCode::Home => self.log_scroll = last,
+            KeyCode::End => self.log_scroll = 0,
+            KeyCode::Char('f') => {
+                self.log_problems_only = !self.log_problems_only;
+                self.log_scroll = 0;
+            }
+            KeyCode::Char('r') => return Some(Effect::OpenLog),
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('O') => {
+                self.log_lines.clear();
+                self.mode = if self.log_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn job_failed(&mut self, operation: Operation, dir: Option<PathBuf>, e: anyhow::Error) {
+        let (what, entry, again) = match operation {
+            Operation::Encrypt => ("Encryption", "Encrypt", 'e'),
+            Operation::Decrypt => ("Decryption", "Decrypt", 'd'),
+        };
+        let browsing = matches!(self.mode, Mode::NavigateFolders | Mode::NavigateFiles);
+        self.status = match GuardXError::of(&e) {
+            // The key prompt opens straight away, and the decryption starts again once a key is entered
+            Some(GuardXError::Authentication) if operation == Operation::Decrypt && browsing => {
+                self.retry_decrypt = dir.clone();
+                self.mode = Mode::EnterKey;
+                self.key_input.clear();
+                "[Key] That key does not open the folder; enter the right one to try again: ".to_string()
+            }
+            Some(GuardXError::DiskFull(_)) => format!("[X] {} stopped: the disk is full. Free some space, then press {} to finish it", what, again),
+            Some(GuardXError::NotFound(_)) => {
+                self.update_current_files();
+                format!("[X] {} failed: {}", what, failure_message(&e))
+            }
+            _ => format!("[X] {} failed: {}", what, failure_message(&e)),
+        };
+        self.record(format!("{} failed: {}", entry, e), false, dir);
+    }
+
+    // Keys that read or change the selected folder are refused while a job runs on it
+    fn busy_refusal(&self, code: KeyCode) -> Option<String> {
+        let touches = match self.mode {
+            Mode::NavigateFolders => matches!(code, KeyCode::Right | KeyCode::Char('e' | 'd' | 'p' | 'r' | 'R' | 'a' | 'V' | 'K' | 'G' | 'B' | 'g' | 'Z' | 'w' | 'f')),
+            Mode::NavigateFiles => matches!(code, KeyCode::Enter | KeyCode::F(2) | KeyCode::Char('o' | 'p' | 'r' | 'R' | 'm' | 'c' | 'M' | 'e' | 'f')),
+            _ => false,
+        };
+        let dir = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).filter(|_| touches)?;
+        let running = self.jobs.get(dir)?;
+        Some(format!("[!] {} is busy with its {}; other folders can be used meanwhile", dir.display(), running.operation.name()))
+    }
+
+    // Keys that delete, overwrite or re-encrypt something, or say yes to that
+    fn destructive(&self, code: KeyCode) -> bool {
+        match self.mode {
+            Mode::NavigateFolders => matches!(code, KeyCode::Char('e' | 'd' | 'r' | 'R' | 'K' | 'u' | 'W')),
+            Mode::NavigateFiles => matches!(code, KeyCode::Char('r' | 'R' | 'M' | 'e')),
+            Mode::ConfirmDeleteFolder | Mode::ConfirmDeleteFile | Mode::ConfirmEncrypt | Mode::ConfirmDrive | Mode::ConfirmSessionEnd => {
+                matches!(code, KeyCode::Char('y') | KeyCode::Enter)
+            }
+            _ => false,
+        }
+    }
+
+    // w: start or stop encrypting the files dropped into the selected encrypted folder
+    fn toggle_watch(&mut self) {
+        let Some(dir) = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned() else { return };
+        if self.guards.iter().any(|g| g.dir() == dir) {
+            self.guards.retain(|g| g.dir() != dir);
+            self.status = format!("[OK] Stopped watching {}", dir.display());
+            self.push_history(format!("Stopped watching {}", dir.display()), true, None);
+            return;
+        }
+        if self.selected_dir.selected().is_some_and(|i| self.fs.is_offline(i)) {
+            self.status = format!("[!] {} is offline; plug its drive back in", dir.display());
+            return;
+        }
+        match Guard::new(&dir, &self.key_input, &self.fs.config) {
+            Ok(guard) => {
+                self.guards.push(guard);
+                self.status = format!("[OK] Watching {}: files dropped into it are encrypted within seconds (w stops)", dir.display());
+                self.push_history(format!("Watching {}", dir.display()), true, None);
+            }
+            Err(e) => self.status = format!("[X] Could not watch {}: {:#}", dir.display(), e),
+        }
+    }
+
+    // Weak key stretching in files that were opened, kept in the history so a status update cannot hide it
+    fn show_kdf_warnings(&mut self) {
+        for warning in kdf::take_warnings() {
+            self.status = format!("{} [!] {}", self.status, warning);
+            self.push_history(warning, false, None);
+        }
+    }
+
+    // Guards of folders with a job running wait for it; a guard that fails (its drive went away) is stopped
+    fn poll_guards(&mut self) {
+        let mut stopped = Vec::new();
+        let mut activity = Vec::new();
+        for (i, guard) in self.guards.iter_mut().enumerate() {
+            if self.jobs.contains_key(guard.dir()) {
+                continue;
+            }
+            match guard.poll(Duration::ZERO) {
+                Ok(done) => activity.extend(done.into_iter().map(|a| (guard.dir().to_path_buf(), a))),
+                Err(e) => stopped.push((i, e)),
+            }
+        }
+        for (i, e) in stopped.into_iter().rev() {
+            let guard = self.guards.remove(i);
+            self.status = format!("[X] Stopped watching {}: {:#}", guard.dir().display(), e);
+            self.push_history(format!("Stopped watching {}", guard.dir().display()), false, None);
+        }
+        let mut refresh = false;
+        for (dir, activity) in activity {
+            let name = activity.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let (entry, ok) = match activity.result {
+                Ok(()) => (format!("Watch encrypted {}", name), true),
+                Err(e) => (format!("Watch failed on {}: {:#}", name, e), false),
+            };
+            self.status = format!("{} {}", if ok { "[OK]" } else { "[X]" }, entry);
+            // Not through record, which would hand these the pending note
+            self.push_history(entry, ok, Some(dir.clone()));
+            refresh |= self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)) == Some(&dir);
+        }
+        if refresh {
+            self.update_current_files();
+        }
+    }
+
+    fn offer_drive(&mut self) {
+        let Some((mount, vaults)) = self.drive_offers.first() else { return };
+        self.mode = Mode::ConfirmDrive;
+        self.status = format!("[Drive] {} has {} encrypted folders. List them until it is removed? [y/n]", mount.display(), vaults.len());
+    }
+
+    fn answer_drive(&mut self, attach: bool) {
+        self.mode = Mode::NavigateFolders;
+        if self.drive_offers.is_empty() {
+            return;
+        }
+        let (mount, vaults) = self.drive_offers.remove(0);
+        if !attach {
+            self.status = format!("{} not listed", mount.display());
+            return;
+        }
+        let selected = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned();
+        let added = self.fs.attach(&vaults);
+        let index = selected.and_then(|dir| self.fs.dirs.iter().position(|d| *d == dir));
+        self.selected_dir.select(index.or(if self.fs.dirs.is_empty() { None } else { Some(0) }));
+        self.update_current_files();
+        self.status = format!("[OK] Listed {} folders from {}", added, mount.display());
+        self.success_timer = Some(Instant::now());
+        self.push_history(format!("Attached {}", mount.display()), true, None);
+    }
+
+    // With several roots, the first unpinned folder of each root's group carries its name on a line above
+    fn folder_header(&self, index: usize) -> Option<usize> {
+        let dir = self.fs.dirs.get(index)?;
+        if self.fs.roots.len() < 2 || self.fs.config.pin(dir).is_some() {
+            return None;
+        }
+        let group = self.fs.group(dir);
+        let starts = match index.checked_sub(1).and_then(|i| self.fs.dirs.get(i)) {
+            Some(above) => self.fs.config.pin(above).is_some() || self.fs.group(above) != group,
+            None => true,
+        };
+        starts.then_some(group)
+    }
+
+    // Folder under the pointer, counting rows from where the list was scrolled to; headers belong to the
+    // folder below them, and SFTP roots follow the folders
+    fn folder_at(&self, column: u16, row: u16) -> Option<usize> {
+        if !contains(self.folders_area, column, row) {
+            return None;
+        }
+        let mut top = self.folders_area.y;
+        for index in self.selected_dir.offset()..self.fs.dirs.len() + self.fs.config.sftp_roots.len() {
+            top += if index < self.fs.dirs.len() && self.folder_header(index).is_some() { 2 } else { 1 };
+            if row < top {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    // File under the pointer; the table's header and the blank line under it come first
+    fn file_at(&self, column: u16, row: u16) -> Option<usize> {
+        let first = self.files_area.y + 2;
+        if !contains(self.files_area, column, row) || row < first {
+            return None;
+        }
+        Some((row - first) as usize + self.file_offset).filter(|&index| index < self.current_files.len())
+    }
+
+    fn mouse(&mut self, mouse: MouseEvent) -> Option<Effect> {
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => self.click(mouse.column, mouse.row),
+            MouseEventKind::ScrollUp => self.wheel(KeyCode::Up, mouse.column, mouse.row),
+            MouseEventKind::ScrollDown => self.wheel(KeyCode::Down, mouse.column, mouse.row),
+            _ => None,
+        }
+    }
+
+    // A click selects the folder or file under the pointer and moves to its list; a double click enters the
+    // folder or previews the file, like → and p
+    fn click(&mut self, column: u16, row: u16) -> Option<Effect> {
+        if !matches!(self.mode, Mode::NavigateFolders | Mode::NavigateFiles) {
+            return None;
+        }
+        let double = self.last_click.is_some_and(|(at, last)| last == row && at.elapsed() < DOUBLE_CLICK);
+        // A third click starts over instead of making another double click
+        self.last_click = if double { None } else { Some((Instant::now(), row)) };
+        if let Some(index) = self.folder_at(column, row) {
+            if self.mode == Mode::NavigateFiles {
+                self.mode = Mode::NavigateFolders;
+                self.selected_file.select(None);
+            }
+            // The first click of a double click selected the folder already
+            if self.selected_dir.selected() != Some(index) {
+                self.selected_dir.select(Some(index));
+                return Some(Effect::ListFiles { fresh: false });
+            }
+            if double {
+                return self.enter_folder();
+            }
+        } else if let Some(index) = self.file_at(column, row) {
+            self.mode = Mode::NavigateFiles;
+            self.selected_file.select(Some(index));
+            if double {
+                match self.busy_refusal(KeyCode::Char('p')) {
+                    Some(refusal) => self.status = refusal,
+                    None => return Some(Effect::PreviewFile),
+                }
+            }
+        }
+        None
+    }
+
+    // Popups scroll wherever the pointer is; otherwise the list under the pointer does, and becomes the active one
+    fn wheel(&mut self, code: KeyCode, column: u16, row: u16) -> Option<Effect> {
+        match self.mode {
+            Mode::Preview => self.scroll_preview(code, WHEEL_LINES),
+            Mode::HistoryBrowser => self.history_input(code),
+            Mode::LogViewer => return self.log_input(code),
+            Mode::Bookmarks => return self.bookmark_input(code),
+            Mode::Finder => return self.finder_input(code),
+            Mode::Palette => return self.palette_input(code),
+            Mode::Notifications => self.notice_input(code),
+            Mode::NavigateFolders | Mode::NavigateFiles if contains(self.folders_area, column, row) => {
+                if self.mode == Mode::NavigateFiles {
+                    self.mode = Mode::NavigateFolders;
+                    self.selected_file.select(None);
+                }
+                return self.step_folder(code == KeyCode::Down);
+            }
+            Mode::NavigateFolders | Mode::NavigateFiles if contains(self.files_area, column, row) && !self.current_files.is_empty() => {
+                self.mode = Mode::NavigateFiles;
+                self.step_file(code == KeyCode::Down);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn step_folder(&mut self, down: bool) -> Option<Effect> {
+        let len = self.fs.dirs.len() + self.fs.config.sftp_roots.len();
+        let selected = self.selected_dir.selected().filter(|_| len > 0)?;
+        self.selected_dir.select(Some(if down { (selected + 1).min(len - 1) } else { selected.saturating_sub(1) }));
+        Some(Effect::ListFiles { fresh: false })
+    }
+
+    fn step_file(&mut self, down: bool) {
+        let len = self.current_files.len();
+        if len == 0 {
+            return;
+        }
+        let selected = self.selected_file.selected();
+        self.selected_file.select(Some(match selected {
+            Some(selected) if down => (selected + 1).min(len - 1),
+            Some(selected) => selected.saturating_sub(1),
+            None => 0,
+        }));
+    }
+
+    // Double click on a folder: an SFTP root is connected to, any other folder's files are entered
+    fn enter_folder(&mut self) -> Option<Effect> {
+        if let Some(refusal) = self.busy_refusal(KeyCode::Right) {
+            self.status = refusal;
+        } else if self.selected_sftp_root().is_some() {
+            return Some(Effect::OpenSftp);
+        } else if !self.current_files.is_empty() {
+            self.enter_files();
+        }
+        None
+    }
+
+    fn enter_files(&mut self) {
+        self.mode = Mode::NavigateFiles;
+        self.status = trf("status.navigating_files", &[("key", &glyph(self.settings.ascii, "←", "Left"))]);
+    }
+
+    // The prompt keeps to its own buffer, so the key stays loaded
+    fn start_create(&mut self, file: bool) {
+        if file && self.selected_dir.selected().is_none() {
+            return;
+        }
+        self.create_name.clear();
+        self.create_from_files = self.mode == Mode::NavigateFiles;
+        self.mode = if file { Mode::CreateFile } else { Mode::CreateFolder };
+        self.create_prompt();
+    }
+
+    fn create_prompt(&mut self) {
+        self.status = if self.mode == Mode::CreateFile {
+            format!("[File] New file in the selected folder (a/b.txt makes a too): {}", self.create_name)
+        } else {
+            format!("[Folder] New folder (a/b/c makes all three): {}", self.create_name)
+        };
+    }
+
+    fn create_input(&mut self, code: KeyCode) -> Option<Effect> {
+        match code {
+            KeyCode::Enter => return Some(Effect::Create),
+            KeyCode::Esc => {
+                self.mode = if self.create_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+                self.status = tr("status.ready");
+            }
+            KeyCode::Backspace => {
+                self.create_name.pop();
+                self.create_prompt();
+            }
+            KeyCode::Char(c) => {
+                self.create_name.push(c);
+                self.create_prompt();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // Enter on the prompt
+    fn create(&mut self) {
+        let file = self.mode == Mode::CreateFile;
+        self.mode = if self.create_from_files { Mode::NavigateFiles } else { Mode::NavigateFolders };
+        if file {
+            let Some(index) = self.selected_dir.selected() else { return };
+            match self.fs.create_file(index, &self.create_name) {
+                Ok(path) => {
+                    self.status = format!("[OK] Created {}", path.display());
+                    self.push_history("Created file", true, Some(path.clone()));
+                    self.success_timer = Some(Instant::now());
+                    self.refresh_files();
+                    let name = Path::new(self.create_name.trim()).iter().next().unwrap_or_default().to_string_lossy().to_string();
+                    if let Some(at) = self.current_files.iter().position(|(file, _, _)| *file == name) {
+                        self.selected_file.select(Some(at));
+                        self.mode = Mode::NavigateFiles;
+                    }
+                }
+                Err(e) => {
+                    self.status = format!("[X] File creation failed: {:#}", e);
+                    self.push_history(format!("Create file failed: {:#}", e), false, None);
+                }
+            }
+            return;
+        }
+        match self.fs.create_folder(&self.create_name) {
+            Ok(path) => {
+                self.status = format!("[OK] Created {}", path.display());
+                self.push_history("Created folder", true, Some(path.clone()));
+                self.success_timer = Some(Instant::now());
+                self.update_current_files();
+            }
+            Err(e) => {
+                self.status = format!("[X] Folder creation failed: {:#}", e);
+                self.push_history(format!("Create folder failed: {:#}", e), false, None);
+            }
+        }
+    }
+
+    fn preview_selected_file(&mut self) {
+        let Some(dir) = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)) else { return };
+        let Some((name, _, _)) = self.selected_file.selected().and_then(|i| self.current_files.get(i)) else { return };
+        let path = dir.join(name);
+        self.preview_content = self.fs.storage.read_to_string(&path).ok().or(Some("Unable to read file".to_string()));
+        self.preview_decrypted = false;
+        self.mode = Mode::Preview;
+    }
+
+    // y in the lists and the preview; the selected file's path, or the folder's in the folder list
+    fn yank_path(&mut self) {
+        let path = match self.mode {
+            Mode::NavigateFolders => self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned(),
+            _ => self.selected_file_path(),
+        };
+        let Some(path) = path else { return };
+        let text = path.display().to_string();
+        self.status = match self.clipboard.copy(&text) {
+            Ok(via) => format!("[OK] Copied {}{}", text, via_note(via)),
+            Err(e) => format!("[X] Could not copy: {:#}", e),
+        };
+    }
+
+    // Y: the preview's text, or a plaintext file's; the clipboard is emptied again after a while when the
+    // text was decrypted
+    fn yank_contents(&mut self) {
+        let text = if self.mode == Mode::Preview {
+            match self.preview_content.clone() {
+                Some(text) => Zeroizing::new(text),
+                None => return,
+            }
+        } else {
+            let Some(path) = self.selected_file_path() else { return };
+            if is_chunked(&path).unwrap_or(false) || age::is_age(&path).unwrap_or(false) || path.extension().is_some_and(|ext| ext == "enc") {
+                self.status = "[!] The file is encrypted; decrypt it first, or copy a container entry from its preview".to_string();
+                return;
+            }
+            match fs::metadata(&path) {
+                Ok(meta) if meta.len() > MAX_YANK => {
+                    self.status = format!("[!] {} is over {}; too much to copy", path.display(), human_size(MAX_YANK));
+                    return;
+                }
+                Err(e) => {
+                    self.status = format!("[X] {}: {}", path.display(), e);
+                    return;
+                }
+                Ok(_) => {}
+            }
+            match self.fs.storage.read_to_string(&path) {
+                Ok(text) => Zeroizing::new(text),
+                Err(_) => {
+                    self.status = format!("[!] {} is not text", path.display());
+                    return;
+                }
+            }
+        };
+        let decrypted = self.mode == Mode::Preview && self.preview_decrypted;
+        self.status = match self.clipboard.copy(&text) {
+            Ok(via) if decrypted && !self.clipboard_clear_for.is_zero() => {
+                self.clipboard_clear_at = Some(Instant::now() + self.clipboard_clear_for);
+                format!("[OK] Copied {} characters{}; the clipboard is cleared in {} s", text.chars().count(), via_note(via), self.clipboard_clear_for.as_secs())
+            }
+            Ok(via) => format!("[OK] Copied {} characters{}", text.chars().count(), via_note(via)),
+            Err(e) => format!("[X] Could not copy: {:#}", e),
+        };
+    }
+
+    // A paste comes in one piece; only the key prompt takes one, and the pasted key does not stay on the clipboard
+    fn paste(&mut self, text: String) {
+        let text = Zeroizing::new(text);
+        if self.mode != Mode::EnterKey {
+            return;
+        }
+        self.key_input.push_str(text.trim_end_matches(['\r', '\n']));
+        self.status = format!("[Key] Enter encryption key: {}", "*".repeat(self.key_input.chars().count()));
+        if !self.clipboard_clear_for.is_zero() {
+            self.clipboard.adopt(&text);
+            self.clipboard_clear_at = Some(Instant::now() + self.clipboard_clear_for);
+        }
+    }
+
+    // Everything the file system and, for encrypted files, the header say about the selected file; none of it
+    // needs the key except a sealed name
+    fn open_details(&mut self) {
+        let Some(path) = self.selected_file_path() else { return };
+        let meta = match fs::symlink_metadata(&path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                self.status = format!("[X] {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let mut rows = vec![
+            ("Path", path.display().to_string()),
+            ("Size", format!("{} ({} bytes)", human_size(meta.len()), meta.len())),
+            ("Permissions", permissions(&meta)),
+        ];
+        if let Some(owner) = owner(&meta) {
+            rows.push(("Owner", owner));
+        }
+        for (label, time) in [("Modified", meta.modified()), ("Accessed", meta.accessed()), ("Created", meta.created())] {
+            rows.push((label, time.map_or_else(|_| "not recorded".to_string(), |t| chrono::DateTime::<chrono::Local>::from(t).format("%Y-%m-%d %H:%M:%S %:z").to_string())));
+        }
+        match header_info(&path) {
+            Ok(Some(info)) => {
+                rows.push(("Format", format!("GuardX v{}, chunked", info.version)));
+                rows.push(("Cipher", info.cipher.label().to_string()));
+                rows.push(("Key stretching", info.kdf.map_or_else(|| "none (written before key stretching)".to_string(), |kdf| format!("{} ({})", kdf.family().label(), kdf))));
+                rows.push(("Chunks", format!("{} of {}{}", info.chunks, human_size(info.chunk_size as u64), if info.chunk_map { ", with chunk map" } else { "" })));
+                rows.push(("Revision", info.revision.map_or_else(|| "not recorded (map not sealed)".to_string(), |revision| revision.to_string())));
+                rows.push(("File id", info.file_id.iter().map(|b| format!("{:02x}", b)).collect()));
+                let name = if !info.name_sealed {
+                    "not sealed; the file keeps its own name".to_string()
+                } else if self.key_input.is_empty() {
+                    "sealed; enter the key (k) to show it".to_string()
+                } else {
+                    match original_name(&path, &self.key_input) {
+                        Ok(Some(name)) => name,
+                        Ok(None) => "not sealed; the file keeps its own name".to_string(),
+                        Err(e) => format!("sealed, and the key does not open it ({})", failure_message(&e)),
+                    }
+                };
+                rows.push(("Original name", name));
+            }
+            Ok(None) if age::is_age(&path).unwrap_or(false) => rows.push(("Format", "age v1".to_string())),
+            Ok(None) if path.extension().is_some_and(|ext| ext == "enc") && !is_chunked(&path).unwrap_or(true) => {
+                rows.push(("Format", "legacy GuardX (AES-256-GCM, one block, no header)".to_string()));
+            }
+            Ok(None) => {}
+            Err(e) => rows.push(("Header", format!("unreadable: {}", failure_message(&e)))),
+        }
+        if meta.is_file() {
+            let (tx, rx) = mpsc::channel();
+            std::thread::spawn(move || {
+                let _ = tx.send(sha256_file(&path));
+            });
+            self.details_hash = Some(rx);
+            rows.push(("SHA-256", "computing...".to_string()));
+        }
+        self.details = rows;
+        self.mode = Mode::Details;
+    }
+
+    fn poll_details(&mut self) {
+        let Some(rx) = self.details_hash.as_ref() else { return };
+        let hash = match rx.try_recv() {
+            Ok(Ok(hash)) => hash,
+            Ok(Err(e)) => format!("unreadable: {}", failure_message(&e)),
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => "unreadable".to_string(),
+        };
+        if let Some(row) = self.details.iter_mut().find(|(label, _)| *label == "SHA-256") {
+            row.1 = hash;
+        }
+        self.details_hash = None;
+    }
+
+    fn scroll_preview(&mut self, code: KeyCode, lines: u16) {
+        let last = self.preview_content.as_ref().map_or(0, |content| content.lines().count().saturating_sub(1)).min(u16::MAX as usize) as u16;
+        self.preview_scroll = match code {
+            KeyCode::Up => self.preview_scroll.saturating_sub(lines),
+            KeyCode::Down => (self.preview_scroll + lines).min(last),
+            KeyCode::PageUp => self.preview_scroll.saturating_sub(20),
+            KeyCode::PageDown => (self.preview_scroll + 20).min(last),
+            KeyCode::Home => 0,
+            KeyCode::End => last,
+            _ => self.preview_scroll,
+        };
+    }
+
+    fn toggle_show_archived(&mut self) {
+        let dir = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned();
+        self.fs.toggle_show_archived();
+        let index = dir.and_then(|dir| self.fs.dirs.iter().position(|d| *d == dir));
+        self.selected_dir.select(index.or(if self.fs.dirs.is_empty() { None } else { Some(0) }));
+        self.status = if self.fs.show_archived { "Showing archived folders" } else { "Archived folders hidden" }.to_string();
+        self.update_current_files();
+    }
+
+    fn start_rekey(&mut self) {
+        let Some(selected) = self.selected_dir.selected() else { return };
+        if !self.fs.is_encrypted(selected) {
+            self.status = "[!] Only encrypted folders can be re-keyed".to_string();
+            return;
+        }
+        self.clear_rekey();
+        self.mode = Mode::RekeyOld;
+        self.rekey_prompt();
+    }
+
+    fn clear_rekey(&mut self) {
+        self.rekey_old.zeroize();
+        self.rekey_new.zeroize();
+        self.rekey_confirm.zeroize();
+    }
+
+    fn rekey_buffer(&mut self) -> &mut Zeroizing<String> {
+        match self.mode {
+            Mode::RekeyOld => &mut self.rekey_old,
+            Mode::RekeyNew => &mut self.rekey_new,
+            _ => &mut self.rekey_confirm,
+        }
+    }
+
+    fn rekey_prompt(&mut self) {
+        let label = match self.mode {
+            Mode::RekeyOld => "Current key",
+            Mode::RekeyNew => "New key",
+            _ => "Repeat the new key",
+        };
+        let typed = self.rekey_buffer().chars().count();
+        self.status = format!("[Rekey] {}: {}", label, "*".repeat(typed));
+    }
+
+    // Current key, then the new key twice; the folder is only touched once all three are in
+    fn rekey_input(&mut self, code: KeyCode) -> Option<Effect> {
+        match code {
+            KeyCode::Char(c) => self.rekey_buffer().push(c),
+            KeyCode::Backspace => {
+                self.rekey_buffer().pop();
+            }
+            KeyCode::Esc => {
+                self.clear_rekey();
+                self.mode = Mode::NavigateFolders;
+                self.status = "Rekey cancelled".to_string();
+                return None;
+            }
+            KeyCode::Enter if !self.rekey_buffer().is_empty() => match self.mode {
+                Mode::RekeyOld => self.mode = Mode::RekeyNew,
+                Mode::RekeyNew => self.mode = Mode::RekeyConfirm,
+                _ => return self.finish_rekey(),
+            },
+            _ => {}
+        }
+        self.rekey_prompt();
+        None
+    }
+
+    fn finish_rekey(&mut self) -> Option<Effect> {
+        if self.rekey_new.as_str() != self.rekey_confirm.as_str() {
+            self.rekey_new.zeroize();
+            self.rekey_confirm.zeroize();
+            self.mode = Mode::RekeyNew;
+            self.rekey_prompt();
+            self.status = format!("[X] The new keys did not match. {}", self.status);
+            return None;
+        }
+        if self.rekey_new.as_str() == self.rekey_old.as_str() {
+            self.clear_rekey();
+            self.mode = Mode::NavigateFolders;
+            self.status = "[!] The new key is the same as the current one".to_string();
+            return None;
+        }
+        self.mode = Mode::NavigateFolders;
+        if self.hardware.is_some() {
+            self.start_touch(Touch::Rekey);
+            return None;
+        }
+        Some(Effect::Rekey)
+    }
+
+    fn apply_rekey(&mut self) {
+        let Some(selected) = self.selected_dir.selected() else { return };
+        match self.fs.rekey_dir(selected, &self.rekey_old, &self.rekey_new) {
+            Ok(()) => {
+                self.key_input.clear();
+                self.key_input.push_str(&self.rekey_new);
+                self.status = "[OK] Folder re-encrypted under the new key".to_string();
+                self.record("Re-keyed folder".to_string(), true, self.fs.dirs.get(selected).cloned());
+                self.success_timer = Some(Instant::now());
+            }
+            Err(e) => {
+                self.status = format!("[X] Rekey failed: {:#}", e);
+                self.record(format!("Rekey failed: {}", e), false, self.fs.dirs.get(selected).cloned());
+            }
+        }
+        self.update_current_files();
+    }
+
+    fn selected_file_path(&self) -> Option<std::path::PathBuf> {
+        let dir = self.fs.dirs.get(self.selected_dir.selected()?)?;
+        Some(dir.join(&self.current_files.get(self.selected_file.selected()?)?.0))
+    }
+
+    fn open_selected_container(&mut self) {
+        let Some(path) = self.selected_file_path().filter(|p| vault::is_container(p)) else { return };
+        if self.key_input.is_empty() {
+            self.status = "[!] Enter a key first (k)".to_string();
+            return;
+        }
+        if !self.second_factor() {
+            return;
+        }
+        match Container::open(&path, &self.key_input) {
+            Ok(container) => {
+                let (files, size) = vault::summary(container.entries());
+                self.status = format!("Container holds {} files, {} unpacked (p preview, e extract entry, x extract all)", files, human_size(size));
+                self.selected_entry.select(if container.entries().is_empty() { None } else { Some(0) });
+                self.container = Some((path, container));
+                self.mode = Mode::BrowseContainer;
+            }
+            Err(e) => {
+                self.status = format!("[X] Could not open container: {:#}", e);
+                self.push_history(format!("Open container failed: {}", e), false, None);
+            }
+        }
+    }
+
+    fn selected_entry_path(&self) -> Option<String> {
+        let (_, container) = self.container.as_ref()?;
+        Some(container.entries().get(self.selected_entry.selected()?)?.path.clone())
+    }
+
+    // Only the selected entry's frames are decrypted; the rest of the container stays sealed
+    fn preview_container_entry(&mut self) {
+        let Some(entry) = self.selected_entry_path() else { return };
+        let Some((_, container)) = &mut self.container else { return };
+        match container.read_entry(&entry, PREVIEW_LIMIT) {
+            Ok(data) => {
+                self.preview_content = Some(String::from_utf8(data.to_vec()).unwrap_or_else(|_| "Binary entry, extract it to view (e)".to_string()));
+                self.preview_decrypted = true;
+                self.mode = Mode::Preview;
+            }
+            Err(e) => self.status = format!("[X] Could not preview {}: {:#}", entry, e),
+        }
+    }
+
+    // Writes the selected file (or folder) next to the container
+    fn extract_container_entry(&mut self) {
+        let Some(entry) = self.selected_entry_path() else { return };
+        let Some((path, container)) = &mut self.container else { return };
+        let name = entry.rsplit('/').next().unwrap_or(&entry);
+        let dest = path.with_file_name(name);
+        let source = path.clone();
+        match container.extract_entry(&entry, &dest) {
+            Ok(()) => {
+                self.status = format!("[OK] Extracted {} to {:?}", entry, dest);
+                self.record(format!("Extracted {} from container", entry), true, Some(source));
+                self.success_timer = Some(Instant::now());
+                self.update_current_files();
+            }
+            Err(e) => {
+                self.status = format!("[X] Extract failed: {:#}", e);
+                self.record(format!("Extract failed: {}", e), false, Some(source));
+            }
+        }
+    }
+
+    fn extract_container(&mut self) {
+        let Some((path, container)) = &mut self.container else { return };
+        let dest = vault::extract_path(path);
+        let source = path.clone();
+        match container.extract(&dest) {
+            Ok(()) => {
+                self.status = format!("[OK] Extracted to {:?}", dest);
+                self.record("Extracted container".to_string(), true, Some(source));
+                self.success_timer = Some(Instant::now());
+            }
+            Err(e) => {
+                self.status = format!("[X] Extract failed: {:#}", e);
+                self.record(format!("Extract failed: {}", e), false, Some(source));
+            }
+        }
+    }
+
+    // SFTP roots come after the local folders in the folder list
+    fn selected_sftp_root(&self) -> Option<&str> {
+        let index = self.selected_dir.selected()?.checked_sub(self.fs.dirs.len())?;
+        self.fs.config.sftp_roots.get(index).map(String::as_str)
+    }
+
+    // Keys that act on a local folder do nothing useful on an SFTP root
+    fn sftp_refusal(&self, code: KeyCode) -> Option<String> {
+        let touches = self.mode == Mode::NavigateFolders
+            && matches!(code, KeyCode::Char('e' | 'd' | 'p' | 'r' | 'R' | 'a' | 'V' | 'K' | 'G' | 'B' | 'g' | 'Z' | 'w' | 'P' | '[' | ']' | 'L'));
+        let url = self.selected_sftp_root().filter(|_| touches)?;
+        Some(format!("[!] {} is on an SFTP server; open it with Enter to work on its files", url))
+    }
+
+    // Connecting blocks until the server answers or gives up
+    fn open_sftp(&mut self) {
+        let Some(url) = self.selected_sftp_root().map(str::to_string) else { return };
+        match sftp::Root::connect(&url) {
+            Ok(root) => {
+                self.sftp_dir = root.path.clone();
+                self.sftp = Some(root);
+                self.mode = Mode::BrowseSftp;
+                self.load_sftp_dir(None);
+            }
+            Err(e) => {
+                self.status = format!("[X] Could not open {}: {:#}", url, e);
+                self.push_history(format!("SFTP connection failed: {}", e), false, None);
+            }
+        }
+    }
+
+    // Lists the open folder again, selecting `select` if it is there
+    fn load_sftp_dir(&mut self, select: Option<&str>) {
+        let Some(root) = &self.sftp else { return };
+        match root.list(&self.sftp_dir) {
+            Ok(entries) => {
+                self.status = format!("{}: {} entries", root.describe(&self.sftp_dir), entries.len());
+                let found = select.and_then(|name| entries.iter().position(|e| e.name == name));
+                self.selected_sftp.select(found.or(if entries.is_empty() { None } else { Some(0) }));
+                self.sftp_entries = entries;
+            }
+            Err(e) => self.status = format!("[X] {:#}", e),
+        }
+    }
+
+    fn enter_sftp_dir(&mut self) {
+        let Some(entry) = self.selected_sftp.selected().and_then(|i| self.sftp_entries.get(i)).filter(|e| e.is_dir) else { return };
+        self.sftp_dir.push(&entry.name);
+        self.load_sftp_dir(None);
+    }
+
+    // Not above the root from config.toml; from there it closes the connection
+    fn leave_sftp_dir(&mut self) {
+        if self.sftp.as_ref().is_none_or(|root| self.sftp_dir == root.path) {
+            self.close_sftp();
+            return;
+        }
+        let left = self.sftp_dir.file_name().map(|n| n.to_string_lossy().to_string());
+        self.sftp_dir.pop();
+        self.load_sftp_dir(left.as_deref());
+    }
+
+    fn close_sftp(&mut self) {
+        self.sftp = None;
+        self.sftp_entries.clear();
+        self.mode = Mode::NavigateFolders;
+        self.status = tr("status.back_to_folders");
+    }
+
+    // The file goes from the server through this machine's memory and back; nothing touches the local disk
+    fn sftp_operation(&mut self, operation: Operation) {
+        let Some(name) = self.selected_sftp.selected().and_then(|i| self.sftp_entries.get(i)).filter(|e| !e.is_dir).map(|e| e.name.clone()) else { return };
+        let key = match operation {
+            Operation::Encrypt => Some(self.key_input.clone()).filter(|k| !k.is_empty()),
+            Operation::Decrypt => self.unlock_key(),
+        };
+        let Some(key) = key else {
+            self.status = "[!] Enter a key first (k)".to_string();
+            return;
+        };
+        if operation == Operation::Decrypt && !self.second_factor() {
+            return;
+        }
+        let Some(root) = &self.sftp else { return };
+        let path = self.sftp_dir.join(&name);
+        let result = match operation {
+            Operation::Encrypt => root.encrypt(&path, &key, self.fs.config.default_cipher()).map(|()| path.clone()),
+            Operation::Decrypt => root.decrypt(&path, &key),
+        };
+        let shown = PathBuf::from(root.describe(&path));
+        let verb = if operation == Operation::Encrypt { "Encrypted" } else { "Decrypted" };
+        match result {
+            Ok(dest) => {
+                let dest = dest.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or(name);
+                self.load_sftp_dir(Some(&dest));
+                self.status = format!("[OK] {} {}", verb, dest);
+                self.record(format!("{} on SFTP", verb), true, Some(shown));
+                self.success_timer = Some(Instant::now());
+            }
+            Err(e) => {
+                self.status = format!("[X] {} failed: {:#}", operation.name(), e);
+                self.record(format!("SFTP {} failed: {}", operation.name(), e), false, Some(shown));
+            }
+        }
+    }
+
+    // On a collision the popup stays open so the name can be corrected
+    fn rename_selected_file(&mut self) {
+        let (Some(dir_idx), Some(file_idx)) = (self.selected_dir.selected(), self.selected_file.selected()) else {
+            self.mode = Mode::NavigateFiles;
+            return;
+        };
+        let old_name = self.current_files[file_idx].0.clone();
+        if self.rename_input.trim() == old_name {
+            self.mode = Mode::NavigateFiles;
+            return;
+        }
+        match self.fs.rename_file(dir_idx, &old_name, &self.rename_input) {
+            Ok(undo) => {
+                let new_name = self.rename_input.trim().to_string();
+                self.status = format!("[OK] Renamed '{}' to '{}'", old_name, new_name);
+                self.push_history("Renamed file", true, None);
+                self.record_undo(Some(undo));
+                self.success_timer = Some(Instant::now());
+                self.update_current_files();
+                if let Some(idx) = self.current_files.iter().position(|(name, _, _)| *name == new_name) {
+                    self.selected_file.select(Some(idx));
+                }
+                self.mode = Mode::NavigateFiles;
+            }
+            Err(e) => {
+                self.status = format!("[X] Rename failed: {}", e);
+                self.push_history(format!("Rename failed: {}", e), false, None);
+            }
+        }
+    }
+
+    fn get_theme_styles(&self) -> (Color, Color, Color, Color) {
+        let theme = &self.settings.theme;
+        (theme.background, theme.text, theme.accent, theme.border)
+    }
+
+    // 1 and 2 in Settings: the next or previous theme, theme files included. A file that does not load is
+    // skipped, and why shows in the status bar.
+    fn cycle_theme(&mut self, forward: bool) {
+        let names = theme::available();
+        let mut index = names.iter().position(|name| *name == self.settings.theme.name).unwrap_or(0);
+        let mut problem = None;
+        for _ in 1..names.len() {
+            index = if forward { (index + 1) % names.len() } else { (index + names.len() - 1) % names.len() };
+            match Theme::named(&names[index]) {
+                Ok(theme) => {
+                    self.settings.theme = theme;
+                    break;
+                }
+                Err(e) => problem = Some(format!("[!] Skipped the theme {}: {:#}", names[index], e)),
+            }
+        }
+        self.status = problem.unwrap_or_else(|| format!("Theme: {}", self.settings.theme.name));
+    }
+
+    // For this session; `language` in config.toml keeps one
+    fn cycle_language(&mut self) {
+        let language = i18n::current().next();
+        i18n::set(language);
+        self.status = trf("status.language", &[("name", &language.name())]);
+    }
+
+    // Without metadata, which is read for the rows on screen only
+    fn load_files(fs: &FileSystem, dir_idx: usize) -> Result<Vec<(String, Option<Metadata>, bool)>> {
+        if dir_idx >= fs.dirs.len() { return Ok(vec![]); }
+        let dir = &fs.dirs[dir_idx];
+        let filter = filter::for_dir(dir);
+        let mut files = Vec::new();
+        match fs.storage.list(dir) {
+            Ok(entries) => {
+                for entry in entries {
+                    let name = entry.name();
+                    if entry.kind == Kind::File && name != MANIFEST_NAME && !filter.excludes_file(dir, &entry.path) {
+                        let encrypted = entry.path.extension().is_some_and(|ext| ext == "enc") || vault::is_container(&entry.path);
+                        files.push((name, None, encrypted));
+                    }
+                }
+                Ok(files)
+            }
+            Err(_) => Ok(vec![])
+        }
+    }
+
+    // Reads the metadata of the files in `range` that have none yet
+    fn stat_files(&mut self, range: Range<usize>) {
+        let Some(dir) = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)) else { return };
+        let end = range.end.min(self.current_files.len());
+        for (name, meta, _) in self.current_files[range.start.min(end)..end].iter_mut().filter(|(_, meta, _)| meta.is_none()) {
+            *meta = fs::metadata(dir.join(name.as_str())).ok();
+        }
+    }
+
+    // The first file to show so the selected one is within the `height` rows the files table has
+    fn file_scroll(&self, height: usize) -> usize {
+        let selected = self.selected_file.selected().unwrap_or(0);
+        let mut offset = self.file_offset;
+        if selected < offset {
+            offset = selected;
+        } else if selected >= offset + height {
+            offset = selected + 1 - height;
+        }
+        offset.min(self.current_files.len().saturating_sub(height))
+    }
+
+    // Ties fall back to the name so the order never depends on directory order; the selected file stays selected
+    fn sort_files(&mut self) {
+        if matches!(self.sort_key, SortKey::Size | SortKey::Created) {
+            self.stat_files(0..self.current_files.len());
+        }
+        let selected = self.selected_file.selected().and_then(|i| self.current_files.get(i)).map(|(name, _, _)| name.clone());
+        let (key, reversed) = (self.sort_key, self.sort_reversed);
+        let names = &self.revealed_names;
+        self.current_files.sort_by(|(a_name, a_meta, a_enc), (b_name, b_meta, b_enc)| {
+            let (a_name, b_name) = (names.get(a_name).unwrap_or(a_name), names.get(b_name).unwrap_or(b_name));
+            let order = match key {
+                SortKey::Name => std::cmp::Ordering::Equal,
+                SortKey::Size => a_meta.as_ref().map(|m| m.len()).cmp(&b_meta.as_ref().map(|m| m.len())),
+                SortKey::Created => a_meta.as_ref().and_then(|m| m.created().ok()).cmp(&b_meta.as_ref().and_then(|m| m.created().ok())),
+                SortKey::Status => a_enc.cmp(b_enc),
+            }.then_with(|| a_name.to_lowercase().cmp(&b_name.to_lowercase()));
+            if reversed { order.reverse() } else { order }
+        });
+        if let Some(name) = selected {
+            self.selected_file.select(self.current_files.iter().position(|(n, _, _)| *n == name));
+        }
+    }
+
+    // Runs Manifest::quick_check on a worker so opening a big vault stays instant; the result becomes a
+    // badge in the folder list
+    fn check_selected_vault(&mut self) {
+        let Some(selected) = self.selected_dir.selected().filter(|&i| self.fs.is_encrypted(i)) else { return };
+        let dir = self.fs.dirs[selected].clone();
+        if self.fs.config.lifecycle(&dir) == Lifecycle::Archived {
+            return;
+        }
+        let key = (!self.key_input.is_empty()).then(|| Zeroizing::new(self.key_input.to_string()));
+        let tx = self.vault_check_tx.clone();
+        std::thread::spawn(move || {
+            let healthy = Manifest::load(&dir)
+                .and_then(|manifest| manifest.quick_check(&dir, key.as_ref().map(|k| k.as_str()), QUICK_CHECK_SAMPLE))
+                .is_ok_and(|report| report.is_clean());
+            let _ = tx.send((dir, healthy));
+        });
+    }
+
+    // Folders that cannot be watched (too many watches, some network mounts) are only refreshed on selection
+    fn watch_selected_dir(&mut self) {
+        let dir = self.selected_dir.selected().filter(|&i| !self.fs.is_offline(i)).and_then(|i| self.fs.dirs.get(i));
+        if self.dir_changes.as_ref().map(|c| c.dir()) == dir.map(|d| d.as_path()) {
+            return;
+        }
+        self.dir_changes = dir.and_then(|d| Changes::new(d).ok());
+    }
+
+    // Reloads the selected folder's files after they changed under it, keeping the selection and without
+    // another quick check
+    fn refresh_files(&mut self) {
+        let Some(selected) = self.selected_dir.selected() else { return };
+        let Ok(files) = self.listing(selected, true) else { return };
+        let name = self.selected_file.selected().and_then(|i| self.current_files.get(i)).map(|(name, _, _)| name.clone());
+        let index = self.selected_file.selected();
+        self.current_files = files;
+        self.reveal_names();
+        self.selected_file.select(None);
+        self.sort_files();
+        let found = name.and_then(|name| self.current_files.iter().position(|(n, _, _)| *n == name));
+        // A selected file that went away leaves the selection where it was
+        let len = self.current_files.len();
+        self.selected_file.select(found.or(index.filter(|_| len > 0).map(|i| i.min(len - 1))));
+        self.stat_files(self.file_offset..self.file_offset + self.file_rows);
+        if len == 0 && self.mode == Mode::NavigateFiles {
+            self.mode = Mode::NavigateFolders;
+        }
+        self.refresh_other_pane();
+    }
+
+    // Reads the selected folder's files again, after they were changed or with F5
+    fn update_current_files(&mut self) {
+        self.list_selected(true);
+    }
+
+    // For cursor moves, which show a folder listed before from the cache
+    fn show_current_files(&mut self) {
+        self.list_selected(false);
+    }
+
+    fn refresh_listing(&mut self) {
+        let Some(dir) = self.selected_dir.selected().and_then(|i| self.fs.dirs.get(i)).cloned() else { return };
+        self.update_current_files();
+        self.status = format!("[OK] Read {} again: {} files", dir.display(), self.current_files.len());
+    }
+
+    // A folder's files, from the cache when `fresh` is not asked for and the folder's modification time is the
+    // one it had when it was read; that costs one stat instead of a listing, which adds up over NFS
+    fn listing(&mut self, index: usize, fresh: bool) -> Result<Vec<(String, Option<Metadata>, bool)>> {
+        let Some(dir) = self.fs.dirs.get(index).cloned() else { return Ok(vec![]) };
+        let modified = fs::metadata(&dir).and_then(|m| m.modified()).ok();
+        if let Some(listing) = self.listings.get(&dir).filter(|l| !fresh && l.modified.is_some() && l.modified == modified) {
+            return Ok(listing.files.clone());
+        }
+        let files = Self::load_files(&self.fs, index)?;
+        if self.listings.len() >= CACHED_LISTINGS && !self.listings.contains_key(&dir) {
+            if let Some(oldest) = self.listings.iter().min_by_key(|(_, l)| l.read).map(|(d, _)| d.clone()) {
+                self.listings.remove(&oldest);
+            }
+        }
+        self.listings.insert(dir, Listing { files: files.clone(), modified, read: Instant::now() });
+        Ok(files)
+    }
+
+    fn list_selected(&mut self, fresh: bool) {
+        self.watch_selected_dir();
+        if let Some(selected) = self.selected_dir.selected() {
+            match self.listing(selected, fresh) {
+                Ok(files) => {
+                    self.current_files = files;
+                    self.file_offset = 0;
+                    self.reveal_names();
+                    self.sort_files();
+                    self.selected_file.select(if self.current_files.is_empty() { None } else { Some(0) });
+                    // The rows the table will show, so a new listing is drawn with its sizes and dates
+                    self.stat_files(0..self.file_rows);
+                    self.files_denied = self.current_files.is_empty() && self.fs.get_files(selected).is_err();
+                    if self.files_denied {
+                        self.status = "[!] Access Denied to this folder".to_string();
+                    }
+                    self.check_selected_vault();
+                    self.refresh_other_pane();
+                }
+                Err(e) => {
+                    self.current_files.clear();
+                    self.selected_file.select(None);
+                    self.files_denied = true;
+                    self.status = format!("[!] Access Denied: {}", e);
+                }
+            }
+        } else {
+            self.current_files.clear();
+            self.selected_file.select(None);
+            self.files_denied = false;
+        }
+    }
+
+    // Every change to the App goes through here; what needs the terminal or the file system comes back as
+    // an Effect for perform
+    pub fn update(&mut self, action: Action) -> Result<Vec<Effect>> {
+        let mut effects = Vec::new();
+        match action {
+            Action::KeyLoaded(key) => effects.extend(self.key_loaded(key)),
+            Action::KeySaved(result) => match result {
+                Ok(()) => {
+                    self.status = "[OK] Key saved!".to_string();
+                    self.success_timer = Some(Instant::now());
+                    self.push_history("Saved key", true, None);
+                }
+                Err(e) => self.status = format!("[X] Could not save the key: {:#}", e),
+            },
+            Action::FolderPreviewed(result) => match result {
+                Ok(Some(content)) => {
+                    self.preview_content = Some(content);
+                    self.preview_decrypted = false;
+                    self.mode = Mode::Preview;
+                }
+                Ok(None) => self.status = "[!] No files to preview".to_string(),
+                Err(_) => self.status = "[!] Access Denied to this folder".to_string(),
+            },
+            Action::FolderDeleted { index, dir, permanent, result } => effects.extend(self.folder_deleted(index, dir, permanent, result)),
+            Action::FileDeleted { path, permanent, result } => effects.extend(self.file_deleted(path, permanent, result)),
+            Action::Tick => effects = self.tick(),
+            Action::Drawn(drawn) => effects.extend(self.drawn(drawn)),
+            Action::Key(key) => effects.extend(self.press(key, false)?),
+            Action::Picked(code, modifiers) => effects.extend(self.press(KeyEvent::new(code, modifiers), true)?),
+            Action::Paste(text) => {
+                self.last_activity = Instant::now();
+                self.paste(text);
+            }
+            Action::Mouse(mouse) => {
+                self.last_activity = Instant::now();
+                effects.extend(self.mouse(mouse));
+            }
+            Action::Resize => {
+                self.last_click = None;
+                effects.push(Effect::Relayout);
+            }
+        }
+        // What went into the history meanwhile goes into the log file too
+        if !self.unlogged.is_empty() {
+            effects.push(Effect::WriteHistory);
+        }
+        Ok(effects)
+    }
+
+    // Updates with `action`, then performs what it asks for, updating with each outcome in turn
+    pub fn dispatch<B: Backend>(&mut self, terminal: &mut Terminal<B>, action: Action) -> Result<()> {
+        let mut effects = VecDeque::from(self.update(action)?);
+        while let Some(effect) = effects.pop_front() {
+            if let Some(outcome) = self.perform(terminal, effect)? {
+                effects.extend(self.update(outcome)?);
+            }
+        }
+        Ok(())
+    }
+
+    // Does what update asked for, and says how it went as the Action to update with next
+    pub fn perform<B: Backend>(&mut self, terminal: &mut Terminal<B>, effect: Effect) -> Result<Option<Action>> {
+        let outcome = match effect {
+            Effect::OpenInSession => {
+                self.open_in_session(terminal)?;
+                None
+            }
+            Effect::EditConfig => {
+                self.edit_config(terminal);
+                None
+            }
+            // The next frame is laid out for the new size; clearing drops what the old layout left behind
+            Effect::Relayout => {
+                terminal.autoresize()?;
+                terminal.clear()?;
+                None
+            }
+            Effect::PreviewFirstFile(index) => {
+                let first = self.fs.get_files(index).map(|files| files.into_iter().next());
+                Some(Action::FolderPreviewed(first.map(|first| {
+                    first.map(|name| self.fs.storage.read_to_string(&self.fs.dirs[index].join(name)).unwrap_or_else(|_| "Unable to read file".to_string()))
+                })))
+            }
+            Effect::LoadSavedKey => Some(Action::KeyLoaded(fs::read_to_string("saved_key.enc").ok().map(Zeroizing::new))),
+            Effect::SaveKey => Some(Action::KeySaved(fs::write("saved_key.enc", self.key_input.as_bytes()).map_err(Into::into))),
+            Effect::DeleteFolder { index, permanent } => {
+                let dir = self.fs.dirs.get(index).cloned();
+                Some(Action::FolderDeleted { index, dir, permanent, result: self.fs.remove_dir(index, permanent) })
+            }
+            Effect::DeleteFile { dir, name, permanent } => {
+                let path = self.fs.dirs[dir].join(&name);
+                Some(Action::FileDeleted { path, permanent, result: self.fs.remove_file(dir, &name, permanent) })
+            }
+            Effect::ListFiles { fresh } => {
+                self.list_selected(fresh);
+                None
+            }
+            Effect::RefreshListing => {
+                self.refresh_listing();
+                None
+            }
+            Effect::RefreshFiles => {
+                self.refresh_files();
+                None
+            }
+            Effect::StatFiles(range) => {
+                self.stat_files(range);
+                None
+            }
+            Effect::SortFiles => {
+                self.sort_files();
+                None
+            }
+            Effect::Poll => {
+                self.poll();
+                None
+            }
+            Effect::CountFiles => {
+                self.file_count = self.fs.dirs.iter().map(|d| fs::read_dir(d).map(|dir| dir.count()).unwrap_or(0)).sum();
+                None
+            }
+            Effect::ClearClipboard => {
+                if let Err(e) = self.clipboard.clear() {
+                    self.status = format!("[X] Could not clear the clipboard: {:#}", e);
+                }
+                None
+            }
+            // Written below, after every effect
+            Effect::WriteHistory => None,
+            Effect::Lock => {
+                self.lock();
+                None
+            }
+            Effect::LockAll(reason) => {
+                self.lock_all(&reason);
+                None
+            }
+            Effect::Touch(touch) => {
+                self.finish_touch(touch);
+                None
+            }
+            Effect::Platform(confirm) => {
+                self.finish_platform(confirm);
+                None
+            }
+            Effect::ReleaseKey => {
+                self.start_release();
+                None
+            }
+            Effect::ForgetStoredKey => {
+                self.status = match biometric::forget() {
+                    Ok(()) => format!("[OK] Key removed from {}", biometric::name()),
+                    Err(e) => format!("[X] {:#}", e),
+                };
+                self.biometric_stored = biometric::is_stored();
+                None
+            }
+            Effect::DetectHardware => {
+                self.detect_hardware();
+                None
+            }
+            Effect::SaveTotp => {
+                self.save_totp();
+                None
+            }
+            Effect::RemoveTotp => {
+                self.remove_totp();
+                None
+            }
+            Effect::SetKey => {
+                self.key_set();
+                None
+            }
+            Effect::RevealNames => {
+                self.reveal_names();
+                None
+            }
+            Effect::Unlock => {
+                self.try_unlock();
+                None
+            }
+            Effect::Encrypt(index) => {
+                if !self.resume_journaled(index) {
+                    let job = self.fs.encrypt_job(index, &self.key_input);
+                    self.preflight(index, job);
+                }
+                None
+            }
+            Effect::Decrypt(index) => {
+                self.start_decrypt(index);
+                None
+            }
+            Effect::StartEncrypt => {
+                self.confirm_encrypt(true);
+                None
+            }
+            Effect::Undo => {
+                self.undo_last();
+                None
+            }
+            Effect::Archive => {
+                self.archive_selected_dir();
+                None
+            }
+            Effect::FinishArchive => {
+                if let Some(selected) = self.selected_dir.selected() {
+                    self.finish_archive(selected);
+                }
+                self.duress_key.zeroize();
+                None
+            }
+            Effect::Verify => {
+                self.verify_selected_dir();
+                None
+            }
+            Effect::VerifyBackup => {
+                self.verify_selected_backup();
+                None
+            }
+            Effect::Sign => {
+                self.sign_selected_dir();
+                None
+            }
+            Effect::CheckSignatures => {
+                self.check_selected_signatures();
+                None
+            }
+            Effect::Rekey => {
+                self.apply_rekey();
+                self.clear_rekey();
+                None
+            }
+            Effect::Arrange(arrangement) => {
+                let change: fn(&mut FileSystem, usize) -> Result<()> = match arrangement {
+                    Arrangement::TogglePin => FileSystem::toggle_pin,
+                    Arrangement::MoveUp => |fs, i| fs.move_pinned(i, true),
+                    Arrangement::MoveDown => |fs, i| fs.move_pinned(i, false),
+                    Arrangement::CycleLabel => FileSystem::cycle_label,
+                };
+                self.arrange_dirs(change);
+                None
+            }
+            // → on a folder that turns out to have no subfolders goes to its files instead
+            Effect::Expand => {
+                if !self.expand_selected() && !self.current_files.is_empty() {
+                    self.enter_files();
+                }
+                None
+            }
+            Effect::ToggleShowArchived => {
+                self.toggle_show_archived();
+                None
+            }
+            Effect::CycleLifecycle => {
+                self.cycle_lifecycle();
+                None
+            }
+            Effect::ToggleWatch => {
+                self.toggle_watch();
+                None
+            }
+            Effect::SaveConfig => {
+                if let Err(e) = self.fs.config.save() {
+                    self.status = format!("[X] Could not save config: {:#}", e);
+                }
+                None
+            }
+            Effect::OpenBookmarks => {
+                self.open_bookmarks();
+                None
+            }
+            Effect::GoToBookmark(index) => {
+                self.go_to_bookmark(index);
+                None
+            }
+            Effect::GoToFound => {
+                self.go_to_found();
+                None
+            }
+            Effect::TogglePanes => {
+                self.toggle_panes();
+                None
+            }
+            Effect::SwapPanes => {
+                self.swap_panes();
+                None
+            }
+            Effect::Transfer(transfer) => {
+                self.transfer_to_other_pane(transfer);
+                None
+            }
+            Effect::OpenLog => {
+                self.open_log();
+                None
+            }
+            Effect::EndSession { quit } => {
+                self.end_session(quit);
+                None
+            }
+            Effect::CloseSession { keep_changes } => {
+                self.close_session(keep_changes);
+                self.should_quit = self.quit_after_session && self.session.is_none();
+                None
+            }
+            Effect::AnswerDrive(attach) => {
+                self.answer_drive(attach);
+                None
+            }
+            Effect::CycleProfile => {
+                self.cycle_profile();
+                None
+            }
+            Effect::CycleTheme { forward } => {
+                self.cycle_theme(forward);
+                None
+            }
+            Effect::ExportReport { json } => {
+                if let Some(report) = self.reports.first() {
+                    self.status = match report.export(json) {
+                        Ok(path) => format!("[OK] Report saved to {}", path.display()),
+                        Err(e) => format!("[X] Could not save the report: {:#}", e),
+                    };
+                }
+                None
+            }
+            Effect::YankPath => {
+                self.yank_path();
+                None
+            }
+            Effect::YankContents => {
+                self.yank_contents();
+                None
+            }
+            Effect::PreviewFile => {
+                self.preview_selected_file();
+                None
+            }
+            Effect::OpenDetails => {
+                self.open_details();
+                None
+            }
+            Effect::OpenContainer => {
+                self.open_selected_container();
+                None
+            }
+            Effect::PreviewEntry => {
+                self.preview_container_entry();
+                None
+            }
+            Effect::ExtractEntry => {
+                self.extract_container_entry();
+                None
+            }
+            Effect::ExtractContainer => {
+                self.extract_container();
+                None
+            }
+            Effect::OpenSftp => {
+                self.open_sftp();
+                None
+            }
+            Effect::EnterSftpDir => {
+                self.enter_sftp_dir();
+                None
+            }
+            Effect::LeaveSftpDir => {
+                self.leave_sftp_dir();
+                None
+            }
+            Effect::Sftp(operation) => {
+                self.sftp_operation(operation);
+                None
+            }
+            Effect::Create => {
+                self.create();
+                None
+            }
+            Effect::Rename => {
+                self.rename_selected_file();
+                None
+            }
+        };
+        self.write_history();
+        Ok(outcome)
+    }
+
+    // Finished jobs, what the watchers encrypted, and drives and lock-all requests from outside
+    fn poll(&mut self) {
+        while let Ok(event) = self.job_rx.try_recv() {
+            self.job_event(event);
+        }
+        self.poll_guards();
+        for change in self.drive_watch.changes() {
+            self.drive_changed(change);
+        }
+        if self.lock_watch.requested() && self.mode != Mode::Locked {
+            self.lock_all("Locked by guardx lock-all");
+        }
+    }
+
+    fn key_loaded(&mut self, key: Option<Zeroizing<String>>) -> Option<Effect> {
+        let Some(key) = key else {
+            self.status = "[X] No saved key found".to_string();
+            return None;
+        };
+        self.key_input.clear();
+        self.key_input.push_str(key.trim());
+        if self.hardware.is_some() {
+            self.start_touch(Touch::Key);
+            return None;
+        }
+        self.status = "[OK] Key loaded!".to_string();
+        self.success_timer = Some(Instant::now());
+        self.push_history("Loaded key", true, None);
+        Some(Effect::RevealNames)
+    }
+
+    fn folder_deleted(&mut self, index: usize, dir: Option<PathBuf>, permanent: bool, result: Result<Option<Undo>>) -> Option<Effect> {
+        match result {
+            Err(e) => {
+                self.status = format!("[X] Delete failed: {}", e);
+                self.record(format!("Delete failed: {}", e), false, dir);
+                None
+            }
+            Ok(undo) => {
+                let (status, entry) = if permanent { ("[OK] Folder deleted!", "Deleted folder") } else { ("[OK] Folder moved to trash!", "Trashed folder") };
+                self.status = status.to_string();
+                self.record(entry.to_string(), true, dir);
+                self.record_undo(undo);
+                self.success_timer = Some(Instant::now());
+                if self.fs.dirs.is_empty() {
+                    self.selected_dir.select(None);
+                } else {
+                    self.selected_dir.select(Some(index.min(self.fs.dirs.len() - 1)));
+                }
+                Some(Effect::ListFiles { fresh: true })
+            }
+        }
+    }
+
+    fn file_deleted(&mut self, path: PathBuf, permanent: bool, result: Result<Option<Undo>>) -> Option<Effect> {
+        match result {
+            Err(e) => {
+                self.status = format!("[X] File delete failed: {}", e);
+                self.record(format!("File delete failed: {}", e), false, Some(path));
+                None
+            }
+            Ok(undo) => {
+                let (status, entry) = if permanent { ("[OK] File deleted!", "Deleted file") } else { ("[OK] File moved to trash!", "Trashed file") };
+                self.status = status.to_string();
+                self.record(entry.to_string(), true, Some(path));
+                self.record_undo(undo);
+                self.success_timer = Some(Instant::now());
+                Some(Effect::ListFiles { fresh: true })
+            }
+        }
+    }
+
+    // Keeps where the frame put things. A prompt that waited to be on screen is answered now, and rows that
+    // came into view without their sizes and dates are read.
+    fn drawn(&mut self, drawn: Drawn) -> Option<Effect> {
+        self.folders_area = drawn.folders_area;
+        self.files_area = drawn.files_area;
+        self.file_offset = drawn.file_offset;
+        self.file_rows = drawn.file_rows;
+        *self.selected_dir.offset_mut() = drawn.folder_offset;
+        *self.history_view.offset_mut() = drawn.history_offset;
+        *self.bookmark_view.offset_mut() = drawn.bookmark_offset;
+        *self.finder_view.offset_mut() = drawn.finder_offset;
+        *self.palette_view.offset_mut() = drawn.palette_offset;
+        *self.notice_view.offset_mut() = drawn.notice_offset;
+        if let Some(touch) = self.touch_pending.take() {
+            return Some(Effect::Touch(touch));
+        }
+        if let Some(confirm) = self.platform_pending.take() {
+            return Some(Effect::Platform(confirm));
+        }
+        let end = (drawn.file_offset + drawn.file_rows).min(self.current_files.len());
+        let shown = drawn.file_offset.min(end)..end;
+        self.current_files[shown.clone()].iter().any(|(_, meta, _)| meta.is_none()).then_some(Effect::StatFiles(shown))
+    }
+
+    // Timers and what other threads finished since the last frame; polling the rest is left to perform
+    fn tick(&mut self) -> Vec<Effect> {
+        let mut effects = vec![Effect::Poll];
+        if let Some(start) = self.success_timer {
+            if start.elapsed() > Duration::from_secs(2) {
+                self.success_timer = None;
+                self.status = tr("status.ready");
+            } else {
+                self.animation_step = (start.elapsed().as_millis() / 150 % 4) as usize;
+            }
+        }
+
+        while let Ok((dir, healthy)) = self.vault_check_rx.try_recv() {
+            self.vault_checks.insert(dir, healthy);
+        }
+        self.show_report();
+        self.show_kdf_warnings();
+        self.poll_finder();
+        self.poll_details();
+        if self.clipboard_clear_at.is_some_and(|at| Instant::now() >= at) {
+            self.clipboard_clear_at = None;
+            effects.push(Effect::ClearClipboard);
+        }
+        if self.mode == Mode::TypeAhead && self.typed_at.is_some_and(|at| at.elapsed() >= TYPE_AHEAD_PAUSE) {
+            self.end_type_ahead();
+        }
+        // Typed input and confirmations refer to a file by its place in the list, so it only moves while browsing
+        if matches!(self.mode, Mode::NavigateFolders | Mode::NavigateFiles) && self.dir_changes.as_mut().is_some_and(|c| c.changed()) {
+            effects.push(Effect::RefreshFiles);
+        }
+        if self.info_mode {
+            effects.push(Effect::CountFiles);
+        }
+
+        if let Some(timeout) = self.settings.lock_timeout {
+            if self.mode != Mode::Locked && self.last_activity.elapsed() >= timeout {
+                effects.push(Effect::Lock);
+            }
+        }
+
+        if !self.drive_offers.is_empty() && matches!(self.mode, Mode::NavigateFolders | Mode::NavigateFiles) {
+            self.offer_drive();
+        }
+        effects
+    }
+
+    // A key press, or with `picked` a command picked in the palette, which skips the repeat guard
+    fn press(&mut self, key: KeyEvent, picked: bool) -> Result<Option<Effect>> {
+        let now = Instant::now();
+        self.last_activity = now;
+        // Works from every screen, including the ones that take typed input
+        if key.code == KeyCode::Char('l') && key.modifiers.contains(KeyModifiers::CONTROL) && self.mode != Mode::Locked {
+            return Ok(Some(Effect::LockAll("Locked (Ctrl+L)".to_string())));
+        }
+        if key.code == KeyCode::Char('p') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            match self.mode {
+                Mode::NavigateFolders | Mode::NavigateFiles => self.open_finder(self.mode == Mode::NavigateFiles),
+                Mode::Finder => return Ok(self.finder_input(KeyCode::Esc)),
+                _ => {}
+            }
+            return Ok(None);
+        }
+        // With type-ahead on, a letter in the lists starts jumping to a name instead of running its command
+        if !picked && self.settings.type_ahead && matches!(self.mode, Mode::NavigateFolders | Mode::NavigateFiles) {
+            if let KeyCode::Char(c) = key.code {
+                if c.is_alphabetic() && !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+                    self.start_type_ahead(self.mode == Mode::NavigateFiles);
+                    return Ok(self.type_ahead(key.code));
+                }
+            }
+        }
+        // Typing and held arrows always keep up; only a destructive key right on the heels of another, as
+        // when one is held down, is dropped, and the status says so
+        if !picked && self.destructive(key.code) {
+            let repeated = self.last_destructive.is_some_and(|at| now.duration_since(at) < REPEAT_GUARD);
+            self.last_destructive = Some(now);
+            if repeated {
+                self.status = "[!] Ignored a key pressed right after another; press it again to go ahead".to_string();
+                return Ok(None);
+            }
+        }
+        if let Some(refusal) = self.busy_refusal(key.code).or_else(|| self.sftp_refusal(key.code)) {
+            self.status = refusal;
+            return Ok(None);
+        }
+        match self.mode {
+            Mode::NavigateFolders => match key.code {
+                KeyCode::Char('q') => return Ok(Some(Effect::EndSession { quit: true })),
+                KeyCode::Up => return Ok(self.step_folder(false)),
+                KeyCode::Down => return Ok(self.step_folder(true)),
+                KeyCode::Enter | KeyCode::Right if self.selected_sftp_root().is_some() => return Ok(Some(Effect::OpenSftp)),
+                KeyCode::Right if self.settings.tree_view && self.expandable() => return Ok(Some(Effect::Expand)),
+                KeyCode::Left if self.settings.tree_view => return Ok(self.collapse_selected()),
+                KeyCode::Right if !self.current_files.is_empty() => self.enter_files(),
+                KeyCode::Char('e') => {
+                    // Recipient folders are encrypted to public keys, which needs no key
+                    if self.key_input.is_empty() && !self.selected_dir.selected().is_some_and(|i| self.fs.has_recipients(i)) {
+                        self.status = "[!] Enter a key first (k)".to_string();
+                    } else if let Some(selected) = self.selected_dir.selected() {
+                        return Ok(Some(Effect::Encrypt(selected)));
+                    }
+                }
+                KeyCode::Char('d') => return Ok(self.selected_dir.selected().map(Effect::Decrypt)),
+                KeyCode::Char('h') => return Ok(Some(Effect::ReleaseKey)),
+                KeyCode::Char('k') => {
+                    self.mode = Mode::EnterKey;
+                    self.key_input.clear();
+                    self.status = "[Key] Enter encryption key: ".to_string();
+                }
+                KeyCode::Char('n') => self.start_create(false),
+                KeyCode::Char('f') => self.start_create(true),
+                KeyCode::Char('y') => return Ok(Some(Effect::YankPath)),
+                KeyCode::Char('p') => return Ok(self.selected_dir.selected().map(Effect::PreviewFirstFile)),
+                KeyCode::Char('t') => self.mode = Mode::Settings,
+                KeyCode::Char('r') => {
+                    self.delete_permanently = self.settings.permanent_delete;
+                    self.mode = Mode::ConfirmDeleteFolder;
+                }
+                KeyCode::Char('R') => {
+                    self.delete_permanently = true;
+                    self.mode = Mode::ConfirmDeleteFolder;
+                }
+                KeyCode::Char('i') => self.info_mode = !self.info_mode,
+                KeyCode::Char('u') => return Ok(Some(Effect::Undo)),
+                KeyCode::Char('a') => return Ok(Some(Effect::Archive)),
+                KeyCode::Char('V') => return Ok(Some(Effect::Verify)),
+                KeyCode::Char('K') => self.start_rekey(),
+                KeyCode::Char('G') => return Ok(Some(Effect::Sign)),
+                KeyCode::Char('B') => return Ok(Some(Effect::VerifyBackup)),
+                KeyCode::Char('g') => return Ok(Some(Effect::CheckSignatures)),
+                KeyCode::Char(c @ '1'..='9') => return Ok(self.jump_to(c as usize - '0' as usize, false)),
+                KeyCode::Char(':') => self.open_palette(false),
+                KeyCode::Char('/') => self.start_type_ahead(false),
+                KeyCode::Char('P') => return Ok(Some(Effect::Arrange(Arrangement::TogglePin))),
+                KeyCode::Char('[') => return Ok(Some(Effect::Arrange(Arrangement::MoveUp))),
+                KeyCode::Char(']') => return Ok(Some(Effect::Arrange(Arrangement::MoveDown))),
+                KeyCode::Char('L') => return Ok(Some(Effect::Arrange(Arrangement::CycleLabel))),
+                KeyCode::Char('N') => self.start_note(),
+                KeyCode::Char('w') => return Ok(Some(Effect::ToggleWatch)),
+                KeyCode::Char('Z') => return Ok(Some(Effect::CycleLifecycle)),
+                KeyCode::Char('W') => return Ok(Some(Effect::EndSession { quit: false })),
+                KeyCode::Char('H') => return Ok(Some(Effect::ToggleShowArchived)),
+                KeyCode::Char('O') => return Ok(Some(Effect::OpenLog)),
+                KeyCode::Char('I') => self.open_history(),
+                KeyCode::Char('b') => return Ok(self.toggle_bookmark()),
+                KeyCode::Char('C') => return Ok(Some(Effect::TogglePanes)),
+                KeyCode::Tab => return Ok(Some(Effect::SwapPanes)),
+                KeyCode::Char('T') => return Ok(self.toggle_tree_view()),
+                KeyCode::F(5) => return Ok(Some(Effect::RefreshListing)),
+                KeyCode::Char('\'') => return Ok(Some(Effect::OpenBookmarks)),
+                KeyCode::Char('!') => self.open_notifications(),
+                KeyCode::Esc => self.dismiss_toasts(),
+                KeyCode::Char('l') => return Ok(Some(Effect::LoadSavedKey)),
+                KeyCode::Char('v') => {
+                    if self.hardware.is_some() {
+                        // Saved, the bound key would open the files without the YubiKey
+                        self.status = "[!] The key is bound to your YubiKey and is not saved".to_string();
+                    } else if !self.key_input.is_empty() {
+                        return Ok(Some(Effect::SaveKey));
+                    } else {
+                        self.status = "[!] No key to save".to_string();
+                    }
+                }
+                _ => {}
+            },
+            Mode::NavigateFiles => match key.code {
+                KeyCode::Up => self.step_file(false),
+                KeyCode::Down => self.step_file(true),
+                KeyCode::Left => {
+                    self.mode = Mode::NavigateFolders;
+                    self.status = tr("status.back_to_folders");
+                    self.selected_file.select(None);
+                }
+                KeyCode::Char('q') => return Ok(Some(Effect::EndSession { quit: true })),
+                KeyCode::Char('o') => return Ok(Some(Effect::OpenInSession)),
+                KeyCode::Char('W') => return Ok(Some(Effect::EndSession { quit: false })),
+                KeyCode::Char('p') => return Ok(Some(Effect::PreviewFile)),
+                KeyCode::Char('r') => {
+                    self.delete_permanently = self.settings.permanent_delete;
+                    self.mode = Mode::ConfirmDeleteFile;
+                }
+                KeyCode::Char('R') => {
+                    self.delete_permanently = true;
+                    self.mode = Mode::ConfirmDeleteFile;
+                }
+                KeyCode::Char('s') => {
+                    self.sort_key = self.sort_key.next();
+                    self.status = format!("Sorted by {}", self.sort_key.label().to_lowercase());
+                    return Ok(Some(Effect::SortFiles));
+                }
+                KeyCode::Char('S') => {
+                    self.sort_reversed = !self.sort_reversed;
+                    self.status = format!("Sorted by {}{}", self.sort_key.label().to_lowercase(), if self.sort_reversed { ", reversed" } else { "" });
+                    return Ok(Some(Effect::SortFiles));
+                }
+                KeyCode::Enter if self.selected_file_path().is_some_and(|p| vault::is_container(&p)) => return Ok(Some(Effect::OpenContainer)),
+                KeyCode::Enter | KeyCode::F(3) => return Ok(Some(Effect::OpenDetails)),
+                KeyCode::Char(c @ '1'..='9') => return Ok(self.jump_to(c as usize - '0' as usize, true)),
+                KeyCode::Char(':') => self.open_palette(true),
+                KeyCode::Char('/') => self.start_type_ahead(true),
+                KeyCode::Char('m') | KeyCode::F(2) => {
+                    if let Some(file_idx) = self.selected_file.selected() {
+                        self.rename_input = self.current_files[file_idx].0.clone();
+                        self.mode = Mode::RenameFile;
+                    }
+                }
+                KeyCode::Char('u') => return Ok(Some(Effect::Undo)),
+                KeyCode::Char('O') => return Ok(Some(Effect::OpenLog)),
+                KeyCode::Char('I') => self.open_history(),
+                KeyCode::Char('C') => return Ok(Some(Effect::TogglePanes)),
+                KeyCode::Tab => return Ok(Some(Effect::SwapPanes)),
+                KeyCode::F(5) => return Ok(Some(Effect::RefreshListing)),
+                KeyCode::Char('c') => return Ok(Some(Effect::Transfer(Transfer::Copy))),
+                KeyCode::Char('M') => return Ok(Some(Effect::Transfer(Transfer::Move))),
+                KeyCode::Char('e') => return Ok(Some(Effect::Transfer(Transfer::Encrypt))),
+                KeyCode::Char('!') => self.open_notifications(),
+                KeyCode::Esc => self.dismiss_toasts(),
+                KeyCode::Char('f') => self.start_create(true),
+                KeyCode::Char('y') => return Ok(Some(Effect::YankPath)),
+                KeyCode::Char('Y') => return Ok(Some(Effect::YankContents)),
+                _ => {}
+            },
+            Mode::BrowseContainer => match key.code {
+                KeyCode::Up => {
+                    if let Some(selected) = self.selected_entry.selected() {
+                        self.selected_entry.select(Some(selected.saturating_sub(1)));
+                    }
+                }
+                KeyCode::Down => {
+                    let len = self.container.as_ref().map_or(0, |(_, container)| container.entries().len());
+                    if len > 0 {
+                        self.selected_entry.select(Some((self.selected_entry.selected().unwrap_or(0) + 1).min(len - 1)));
+                    }
+                }
+                KeyCode::Char('p') | KeyCode::Enter => return Ok(Some(Effect::PreviewEntry)),
+                KeyCode::Char('e') => return Ok(Some(Effect::ExtractEntry)),
+                KeyCode::Char('x') => return Ok(Some(Effect::ExtractContainer)),
+                KeyCode::Esc | KeyCode::Left => {
+                    self.container = None;
+                    self.mode = Mode::NavigateFiles;
+                }
+                _ => {}
+            },
+            Mode::BrowseSftp => match key.code {
+                KeyCode::Up => {
+                    if let Some(selected) = self.selected_sftp.selected() {
+                        self.selected_sftp.select(Some(selected.saturating_sub(1)));
+                    }
+                }
+                KeyCode::Down => {
+                    let len = self.sftp_entries.len();
+                    if len > 0 {
+                        self.selected_sftp.select(Some((self.selected_sftp.selected().unwrap_or(0) + 1).min(len - 1)));
+                    }
+                }
+                KeyCode::Enter | KeyCode::Right => return Ok(Some(Effect::EnterSftpDir)),
+                KeyCode::Left | KeyCode::Backspace => return Ok(Some(Effect::LeaveSftpDir)),
+                KeyCode::Char('e') => return Ok(Some(Effect::Sftp(Operation::Encrypt))),
+                KeyCode::Char('d') => return Ok(Some(Effect::Sftp(Operation::Decrypt))),
+                KeyCode::Esc => self.close_sftp(),
+                _ => {}
+            },
+            Mode::RenameFile => match key.code {
+                KeyCode::Enter => return Ok(Some(Effect::Rename)),
+                KeyCode::Char(c) => self.rename_input.push(c),
+                KeyCode::Backspace => {
+                    self.rename_input.pop();
+                }
+                KeyCode::Esc => self.mode = Mode::NavigateFiles,
+                _ => {}
+            },
+            Mode::EnterKey => match key.code {
+                KeyCode::Enter => {
+                    self.mode = Mode::NavigateFolders;
+                    self.totp_passed = false;
+                    if self.hardware.is_none() {
+                        return Ok(Some(Effect::SetKey));
+                    }
+                    self.start_touch(Touch::Key);
+                }
+                KeyCode::Char(c) => {
+                    self.key_input.push(c);
+                    self.status = format!("[Key] Enter encryption key: {}", "*".repeat(self.key_input.chars().count()));
+                }
+                KeyCode::Backspace => {
+                    self.key_input.pop();
+                    self.status = format!("[Key] Enter encryption key: {}", "*".repeat(self.key_input.chars().count()));
+                }
+                KeyCode::Esc => {
+                    self.mode = Mode::NavigateFolders;
+                    self.retry_decrypt = None;
+                }
+                _ => {}
+            },
+            Mode::CreateFolder | Mode::CreateFile => return Ok(self.create_input(key.code)),
+            Mode::Preview => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    self.mode = if self.container.is_some() {
+                        Mode::BrowseContainer
+                    } else if self.selected_file.selected().is_some() {
+                        Mode::NavigateFiles
+                    } else {
+                        Mode::NavigateFolders
+                    };
+                    if let Some(content) = self.preview_content.as_mut() {
+                        content.zeroize();
+                    }
+                    self.preview_content = None;
+                    self.preview_scroll = 0;
+                    self.status = tr("status.back_to_navigation");
+                }
+                KeyCode::Char('y') if self.container.is_none() => return Ok(Some(Effect::YankPath)),
+                KeyCode::Char('Y') => return Ok(Some(Effect::YankContents)),
+                code => self.scroll_preview(code, 1),
+            },
+            Mode::Settings => match key.code {
+                KeyCode::Char('1') => return Ok(Some(Effect::CycleTheme { forward: true })),
+                KeyCode::Char('2') => return Ok(Some(Effect::CycleTheme { forward: false })),
+                KeyCode::Char('3') => self.settings.key_length = 16,
+                KeyCode::Char('4') => self.settings.key_length = 32,
+                KeyCode::Char('5') => self.cycle_lock_timeout(),
+                KeyCode::Char('6') => self.cycle_in_flight(),
+                KeyCode::Char('7') => return Ok(self.cycle_vault_cipher()),
+                KeyCode::Char('8') => self.settings.permanent_delete = !self.settings.permanent_delete,
+                KeyCode::Char('9') => self.settings.relative_dates = !self.settings.relative_dates,
+                KeyCode::Char('0') => return Ok(self.toggle_vault_names()),
+                KeyCode::Char('c') => return Ok(self.toggle_vault_checksums()),
+                KeyCode::Char('f') => return Ok(self.toggle_vault_format()),
+                KeyCode::Char('w') => self.settings.auto_reencrypt = !self.settings.auto_reencrypt,
+                KeyCode::Char('a') => self.settings.ascii = !self.settings.ascii,
+                KeyCode::Char('l') => self.settings.labels = !self.settings.labels,
+                KeyCode::Char('j') => self.settings.type_ahead = !self.settings.type_ahead,
+                KeyCode::Char('g') => self.cycle_language(),
+                KeyCode::Char('e') => return Ok(Some(Effect::EditConfig)),
+                KeyCode::Char('p') => return Ok(Some(Effect::CycleProfile)),
+                KeyCode::Char('s') => self.mode = Mode::Security,
+                KeyCode::Esc => self.mode = Mode::NavigateFolders,
+                KeyCode::Char('q') => {
+                    self.mode = Mode::NavigateFolders;
+                    return Ok(Some(Effect::EndSession { quit: true }));
+                }
+                _ => {}
+            },
+            Mode::RekeyOld | Mode::RekeyNew | Mode::RekeyConfirm => return Ok(self.rekey_input(key.code)),
+            Mode::EnterDuressKey => return Ok(self.duress_input(key.code)),
+            Mode::EnterTotp => return Ok(self.totp_gate_input(key.code)),
+            Mode::Security => match key.code {
+                KeyCode::Char('e') => self.start_totp_enroll(),
+                KeyCode::Char('y') => return Ok(Some(Effect::DetectHardware)),
+                KeyCode::Char('h') => self.start_store(),
+                KeyCode::Char('f') if self.biometric_stored => return Ok(Some(Effect::ForgetStoredKey)),
+                KeyCode::Char('r') if self.totp.is_some() => {
+                    self.totp_input.clear();
+                    self.mode = Mode::SecurityRemove;
+                    self.status = "[2FA] current code to remove the second factor: ".to_string();
+                }
+                KeyCode::Esc => self.mode = Mode::Settings,
+                _ => {}
+            },
+            Mode::SecurityEnroll | Mode::SecurityRemove => return Ok(self.security_input(key.code)),
+            Mode::EnterNote => self.note_input(key.code),
+            Mode::OperationReport => return Ok(self.report_input(key.code)),
+            Mode::LogViewer => return Ok(self.log_input(key.code)),
+            Mode::HistoryBrowser => self.history_input(key.code),
+            Mode::Bookmarks => return Ok(self.bookmark_input(key.code)),
+            Mode::Finder => return Ok(self.finder_input(key.code)),
+            Mode::Palette => return Ok(self.palette_input(key.code)),
+            Mode::Notifications => self.notice_input(key.code),
+            Mode::Details => match key.code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::F(3) | KeyCode::Char('q') => {
+                    self.details_hash = None;
+                    self.mode = Mode::NavigateFiles;
+                }
+                _ => {}
+            },
+            Mode::ConfirmEncrypt => match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => return Ok(Some(Effect::StartEncrypt)),
+                KeyCode::Char('n') | KeyCode::Esc => self.confirm_encrypt(false),
+                _ => {}
+            },
+            Mode::ConfirmDrive => match key.code {
+                KeyCode::Char('y') => return Ok(Some(Effect::AnswerDrive(true))),
+                KeyCode::Char('n') | KeyCode::Esc => return Ok(Some(Effect::AnswerDrive(false))),
+                _ => {}
+            },
+            Mode::ConfirmSessionEnd => match key.code {
+                KeyCode::Char(c @ ('y' | 'n')) => {
+                    self.mode = Mode::NavigateFolders;
+                    return Ok(Some(Effect::CloseSession { keep_changes: c == 'y' }));
+                }
+                KeyCode::Esc => {
+                    self.mode = Mode::NavigateFolders;
+                    self.quit_after_session = false;
+                    self.status = "Session still open (W to close it)".to_string();
+                }
+                _ => {}
+            },
+            Mode::TypeAhead => match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.end_type_ahead(),
+                code => return Ok(self.type_ahead(code)),
+            },
+            Mode::VerifyReport => match key.code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => {
+                    self.verify_report.clear();
+                    self.mode = Mode::NavigateFolders;
+                }
+                _ => {}
+            },
+            Mode::Locked => match key.code {
+                KeyCode::Enter if self.hardware.is_some() && self.lock_verifier.is_some() => self.start_touch(Touch::Unlock),
+                KeyCode::Enter => return Ok(Some(Effect::Unlock)),
+                KeyCode::Tab => return Ok(Some(Effect::ReleaseKey)),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.should_quit = true,
+                KeyCode::Char(c) => self.unlock_input.push(c),
+                KeyCode::Backspace => {
+                    self.unlock_input.pop();
+                }
+                _ => {}
+            },
+            Mode::ConfirmDeleteFolder => match key.code {
+                KeyCode::Char('y') => {
+                    self.mode = Mode::NavigateFolders;
+                    let permanent = self.delete_permanently;
+                    return Ok(self.selected_dir.selected().map(|index| Effect::DeleteFolder { index, permanent }));
+                }
+                KeyCode::Char('n') | KeyCode::Esc => self.mode = Mode::NavigateFolders,
+                _ => {}
+            },
+            Mode::ConfirmDeleteFile => match key.code {
+                KeyCode::Char('y') => {
+                    self.mode = Mode::NavigateFiles;
+                    let permanent = self.delete_permanently;
+                    let file = self.selected_file.selected().and_then(|i| self.current_files.get(i));
+                    return Ok(self.selected_dir.selected().zip(file).map(|(dir, (name, ..))| Effect::DeleteFile { dir, name: name.clone(), permanent }));
+                }
+                KeyCode::Char('n') | KeyCode::Esc => self.mode = Mode::NavigateFiles,
+                _ => {}
+            },
+        }
+        Ok(None)
+    }
+}
+
+pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<()> {
+    let mut logged_status = String::new();
+
+    loop {
+        // Each outcome in the status bar goes into the log as well, where the next one does not overwrite it
+        if app.status != logged_status {
+            log_status(&app.status);
+            app.notify(app.status.clone());
+            logged_status.clone_from(&app.status);
+        }
+        let mut drawn = None;
+        if let Err(e) = terminal.draw(|f| drawn = Some(ui(f, &app))) {
+            eprintln!("Draw error: {}", e);
+            return Err(anyhow::Error::from(e));
+        }
+        if let Some(drawn) = drawn {
+            app.dispatch(terminal, Action::Drawn(drawn))?;
+        }
+
+        app.dispatch(terminal, Action::Tick)?;
+        let action = match app.palette_pick.take() {
+            Some((code, modifiers)) => Some(Action::Picked(code, modifiers)),
+            None if event::poll(Duration::from_millis(50))? => Action::of(event::read()?),
+            None => None,
+        };
+        if let Some(action) = action {
+            app.dispatch(terminal, action)?;
         }
 
         if app.should_quit {
@@ -462,33 +4792,68 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(
     }
 }
 
-fn main_area_height(app: &App) -> u16 {
-    app.fs.dirs.len().max(app.current_files.len()) as u16 + 2
-}
-
-fn ui(f: &mut Frame, app: &mut App) {
+// Draws the App as it is and does no I/O. What it worked out about the layout comes back for update to keep.
+fn ui(f: &mut Frame, app: &App) -> Drawn {
+    let mut drawn = Drawn::of(app);
     let (bg, fg, accent, border) = app.get_theme_styles();
+    let theme = app.settings.theme.clone();
+    let labels = app.settings.labels;
+    // With labels on, the selected row and the active list are marked in text as well as in colour
+    let ascii = app.settings.ascii;
+    let pointer = if labels { glyph(ascii, "➤ ", "> ") } else { "" };
+    let active = |on: bool| if labels && on { "[active] " } else { "" };
 
     f.render_widget(Paragraph::new("").style(Style::default().bg(bg)), f.size());
 
+    let size = f.size();
+    if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+        let message = format!("The terminal is {}x{}; GuardX needs at least {}x{}", size.width, size.height, MIN_WIDTH, MIN_HEIGHT);
+        f.render_widget(Paragraph::new(message).style(Style::default().fg(theme.warning)).wrap(Wrap { trim: true }), size);
+        return drawn;
+    }
+
+    // Lock screen hides everything else
+    if app.mode == Mode::Locked {
+        let lock_area = centered_rect(50, 30, f.size());
+        let lock_text = vec![
+            Line::from(Span::styled(i18n::visual(&app.status), Style::default().fg(theme.warning).add_modifier(Modifier::BOLD))),
+            Line::from(""),
+            Line::from(i18n::visual(&tr(if app.lock_verifier.is_some() { "lock.with_key" } else { "lock.without_key" }))),
+            Line::from("*".repeat(app.unlock_input.chars().count())),
+            Line::from(if app.biometric_stored { i18n::visual(&trf("lock.biometric", &[("name", &biometric::name())])) } else { String::new() }),
+        ];
+        let lock_widget = Paragraph::new(lock_text)
+            .alignment(Alignment::Center)
+            .style(Style::default().fg(fg))
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+                .title(format!(" {}{} ", glyph(ascii, "🔒 ", ""), i18n::visual(&tr("lock.title"))))
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)));
+        f.render_widget(lock_widget, lock_area);
+        return drawn;
+    }
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3),   // StatusBar
             Constraint::Length(2),   // ProgressBar
             Constraint::Min(10),     // Main Section
-            Constraint::Length(5),   // Help
+            Constraint::Length(if size.height < SHORT_HEIGHT { 0 } else { 5 }),   // Help
         ])
-        .split(f.size());
+        .split(size);
 
     // StatusBar
     let status_style = if app.status.starts_with("[OK]") {
-        let anim_colors = [Color::Green, Color::LightGreen, Color::Green, Color::LightGreen];
+        let anim_colors = [theme.success, theme.plain, theme.success, theme.plain];
         Style::default().fg(anim_colors[app.animation_step]).add_modifier(Modifier::BOLD)
     } else if app.status.starts_with("[X]") {
-        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD | Modifier::ITALIC)
+        Style::default().fg(theme.error).add_modifier(Modifier::BOLD | Modifier::ITALIC)
     } else if app.status.starts_with("[!]") {
-        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        Style::default().fg(theme.warning).add_modifier(Modifier::BOLD)
     } else {
         Style::default().fg(fg)
     };
@@ -496,156 +4861,326 @@ fn ui(f: &mut Frame, app: &mut App) {
         .borders(Borders::ALL)
         .border_type(BorderType::Double)
         .border_style(Style::default().fg(accent))
-        .title(" 🔒 GuardX ")
+        .title(format!(" {}GuardX ", glyph(ascii, "🔒 ", "")))
         .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD));
-    let status_widget = Paragraph::new(app.status.clone())
+    let status_widget = Paragraph::new(i18n::visual(&app.status))
+        .alignment(i18n::alignment())
         .style(status_style)
         .block(status_block);
     f.render_widget(status_widget, chunks[0]);
 
-    // ProgressBar
-    if app.in_progress {
+    // ProgressBar of the selected folder's job
+    if let Some(running) = app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i)).and_then(|d| app.jobs.get(d)) {
+        let percent = (running.done * 100).checked_div(running.total).unwrap_or(0);
         let progress_widget = Gauge::default()
-            .gauge_style(Style::default().fg(Color::Cyan).bg(bg))
-            .percent((app.progress * 100.0) as u16)
-            .label("Processing...");
+            .gauge_style(Style::default().fg(accent).bg(bg))
+            .percent(percent as u16)
+            .label(format!("{} {}/{} files", running.operation.name(), running.done, running.total));
         f.render_widget(progress_widget, chunks[1]);
     }
 
     // Main Section
+    // On narrow terminals the folder list gives way to the files table and the other right-hand views
+    let right_first = matches!(app.mode, Mode::NavigateFiles | Mode::BrowseContainer | Mode::BrowseSftp | Mode::Preview | Mode::RenameFile | Mode::ConfirmDeleteFile);
+    let widths = match (size.width < NARROW_WIDTH, right_first) {
+        (false, _) => [Constraint::Percentage(30), Constraint::Percentage(70)],
+        (true, false) => [Constraint::Percentage(100), Constraint::Percentage(0)],
+        (true, true) => [Constraint::Percentage(0), Constraint::Percentage(100)],
+    };
     let main_chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .constraints(widths)
         .split(chunks[2]);
+    drawn.folders_area = main_chunks[0].inner(&Margin { vertical: 1, horizontal: 1 });
 
     // Folder List
     let dirs: Vec<ListItem> = app.fs.dirs.iter().enumerate()
         .map(|(i, d)| {
-            let mark = if app.fs.is_encrypted(i) { "🔐 " } else { "📁 " };
-            ListItem::new(format!("{}{}", mark, d.display()))
-                .style(Style::default().fg(if app.fs.is_encrypted(i) { Color::LightCyan } else { Color::LightGreen }))
+            let mark = if app.fs.is_encrypted(i) { glyph(ascii, "🔐 ", "[E] ") } else { glyph(ascii, "📁 ", "[ ] ") };
+            let badge = match app.vault_checks.get(d).filter(|_| app.fs.is_encrypted(i)) {
+                Some(true) => Span::styled(glyph(ascii, " ✓", " ok"), Style::default().fg(theme.muted)),
+                Some(false) => Span::styled(format!(" {} check with V", glyph(ascii, "⚠", "!")), Style::default().fg(theme.warning)),
+                None => Span::raw(""),
+            };
+            let label = match app.fs.config.label(d) {
+                Some(label) if labels => Span::styled(format!("[{}] ", label_name(label)), Style::default().fg(label_color(label))),
+                Some(label) => Span::styled(glyph(ascii, "● ", "* "), Style::default().fg(label_color(label))),
+                None => Span::raw("  "),
+            };
+            let pin = if app.fs.config.pin(d).is_some() { glyph(ascii, " 📌", " [pinned]") } else { "" };
+            let bookmark = if app.fs.config.bookmarks.contains(d) { glyph(ascii, " 🔖", " [bookmark]") } else { "" };
+            // Subfolders are indented under their folder and show just their name
+            let (tree, name) = match (app.settings.tree_view, app.fs.depth(i)) {
+                (false, _) => (String::new(), d.display().to_string()),
+                (true, depth) => {
+                    let marker = if app.fs.is_expanded(i) { glyph(ascii, "▾ ", "v ") } else if app.fs.has_subfolders(i) == Some(false) { "  " } else { glyph(ascii, "▸ ", "> ") };
+                    let name = if depth == 0 { d.display().to_string() } else { d.file_name().unwrap_or_default().to_string_lossy().to_string() };
+                    (format!("{}{}", "  ".repeat(depth), marker), name)
+                }
+            };
+            let subtree = match app.fs.subtree_counts(d) {
+                (encrypted, total) if app.settings.tree_view && total > 0 => Span::styled(format!(" [{}{}/{}]", glyph(ascii, "🔐 ", "E "), encrypted, total), Style::default().fg(theme.muted)),
+                _ => Span::raw(""),
+            };
+            let number = Span::styled(if i < 9 { format!("{} ", i + 1) } else { "  ".to_string() }, Style::default().fg(theme.muted));
+            let state = match app.fs.config.lifecycle(d) {
+                Lifecycle::Active => Span::raw(""),
+                Lifecycle::Archived => Span::styled(" [archived]", Style::default().fg(theme.muted)),
+                Lifecycle::Retired => Span::styled(" [retired]", Style::default().fg(theme.error)),
+            };
+            let color = match app.fs.config.lifecycle(d) {
+                Lifecycle::Active if app.fs.is_encrypted(i) => theme.encrypted,
+                Lifecycle::Active => theme.plain,
+                _ => theme.muted,
+            };
+            let offline = if app.fs.is_offline(i) { Span::styled(" [offline]", Style::default().fg(theme.warning)) } else { Span::raw("") };
+            let busy = match app.jobs.get(d) {
+                Some(running) => Span::styled(format!(" [{} {}/{}]", running.operation.name(), running.done, running.total), Style::default().fg(theme.busy)),
+                None if app.guards.iter().any(|g| g.dir() == d) => Span::styled(" [watched]", Style::default().fg(theme.busy)),
+                None => Span::raw(""),
+            };
+            let line = Line::from(vec![number, label, Span::raw(format!("{}{}{}{}{}", tree, mark, name, pin, bookmark)), state, offline, busy, badge, subtree]);
+            match app.folder_header(i) {
+                Some(group) => ListItem::new(vec![Line::styled(format!("{} {} {}", glyph(ascii, "──", "--"), app.fs.group_name(group), glyph(ascii, "──", "--")), Style::default().fg(theme.muted)), line]),
+                None => ListItem::new(line),
+            }
+            .style(Style::default().fg(color))
         })
+        .chain(app.fs.config.sftp_roots.iter().map(|url| {
+            let open = app.sftp.as_ref().is_some_and(|root| root.url == *url);
+            ListItem::new(Line::from(vec![
+                Span::raw(glyph(ascii, "    🌐 ", "    [net] ")),
+                Span::raw(url.as_str()),
+                if open { Span::styled(" [open]", Style::default().fg(theme.busy)) } else { Span::raw("") },
+            ])).style(Style::default().fg(theme.remote))
+        }))
         .collect();
     let dirs_list = List::new(dirs)
         .block(Block::default()
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
-            .title(" Folders ")
+            .title(format!(" {}{} ", active(app.mode == Mode::NavigateFolders), i18n::visual(&tr("panel.folders"))))
             .title_alignment(Alignment::Center)
             .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
             .border_style(Style::default().fg(if app.mode == Mode::NavigateFolders { accent } else { border })))
-        .highlight_style(Style::default().fg(Color::White).bg(Color::Rgb(50, 50, 70)).add_modifier(Modifier::BOLD))
-        .highlight_symbol("➤ ");
-    f.render_stateful_widget(dirs_list, main_chunks[0], &mut app.selected_dir);
+        .highlight_style(Style::default().fg(theme.selection_text).bg(theme.selection).add_modifier(Modifier::BOLD))
+        .highlight_symbol(glyph(ascii, "➤ ", "> "));
+    let mut dirs_state = app.selected_dir.clone();
+    f.render_stateful_widget(dirs_list, main_chunks[0], &mut dirs_state);
+    drawn.folder_offset = dirs_state.offset();
 
     // Right Section
-    if app.mode == Mode::Preview {
+    if let (Mode::BrowseContainer, Some((path, container))) = (&app.mode, &app.container) {
+        let rows: Vec<Row> = container.entries().iter().enumerate().map(|(i, entry)| {
+            let style = if Some(i) == app.selected_entry.selected() {
+                Style::default().fg(theme.selection_text).bg(theme.selection).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(fg)
+            };
+            let marker = if Some(i) == app.selected_entry.selected() { pointer } else { "" };
+            Row::new(vec![
+                Cell::from(if entry.is_dir { format!("{}{}/", marker, entry.path) } else { format!("{}{}", marker, entry.path) }),
+                Cell::from(if entry.is_dir { String::new() } else { human_size(entry.size) }),
+            ]).style(style)
+        }).collect();
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let container_table = Table::new(rows, &[Constraint::Percentage(75), Constraint::Percentage(25)])
+            .header(Row::new(vec!["Path", "Size"])
+                .style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .bottom_margin(1))
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+                .title(format!(" {}{} (p preview, e extract, x extract all, Esc close) ", glyph(ascii, "📦 ", ""), name))
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)));
+        f.render_widget(container_table, main_chunks[1]);
+    } else if let (Mode::BrowseSftp, Some(root)) = (&app.mode, &app.sftp) {
+        let rows: Vec<Row> = app.sftp_entries.iter().enumerate().map(|(i, entry)| {
+            let style = if Some(i) == app.selected_sftp.selected() {
+                Style::default().fg(theme.selection_text).bg(theme.selection).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(if entry.is_dir { theme.remote } else { fg })
+            };
+            let modified = entry.modified
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .map(|t| if app.settings.relative_dates { relative_time(t) } else { absolute_date(t) })
+                .unwrap_or("N/A".to_string());
+            let marker = if Some(i) == app.selected_sftp.selected() { pointer } else { "" };
+            Row::new(vec![
+                Cell::from(if entry.is_dir { format!("{}{}/", marker, entry.name) } else { format!("{}{}", marker, entry.name) }),
+                Cell::from(if entry.is_dir { String::new() } else { human_size(entry.size) }),
+                Cell::from(modified),
+            ]).style(style)
+        }).collect();
+        let sftp_table = Table::new(rows, &[Constraint::Percentage(55), Constraint::Percentage(15), Constraint::Percentage(30)])
+            .header(Row::new(vec!["Name", "Size", "Modified"])
+                .style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .bottom_margin(1))
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+                .title(format!(" {}{} (e encrypt, d decrypt, {} up, Esc close) ", glyph(ascii, "🌐 ", ""), root.describe(&app.sftp_dir), glyph(ascii, "←", "Left")))
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)));
+        f.render_widget(sftp_table, main_chunks[1]);
+    } else if app.mode == Mode::Preview {
         let preview_text = app.preview_content.as_ref().unwrap_or(&"No content".to_string()).clone();
         let preview_widget = Paragraph::new(preview_text)
             .style(Style::default().fg(fg))
+            .scroll((app.preview_scroll, 0))
             .block(Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Thick)
-                .title(" 📄 Preview (Esc to exit) ")
+                .title(format!(" {}Preview ({} or the wheel to scroll, Esc to exit) ", glyph(ascii, "📄 ", ""), glyph(ascii, "↑/↓", "Up/Down")))
                 .title_alignment(Alignment::Center)
                 .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
-                .border_style(Style::default().fg(border).bg(Color::Rgb(30, 30, 40))));
+                .border_style(Style::default().fg(border).bg(theme.preview)));
         f.render_widget(preview_widget, main_chunks[1]);
     } else if app.info_mode && app.mode != Mode::NavigateFiles {
         let total_dirs = app.fs.dirs.len();
         let encrypted_dirs = app.fs.dirs.iter().enumerate().filter(|(i, _)| app.fs.is_encrypted(*i)).count();
-        let total_files: usize = app.fs.dirs.iter().map(|d| fs::read_dir(d).map(|dir| dir.count()).unwrap_or(0)).sum();
+        let total_files = app.file_count;
         let info_text = format!(
-            "📂 Total Folders: {}\n🔐 Encrypted: {}\n📄 Total Files: {}",
-            total_dirs, encrypted_dirs, total_files
+            "{}Total Folders: {}\n{}Encrypted: {}\n{}Total Files: {}",
+            glyph(ascii, "📂 ", ""), total_dirs, glyph(ascii, "🔐 ", ""), encrypted_dirs, glyph(ascii, "📄 ", ""), total_files
         );
         let info_widget = Paragraph::new(info_text)
             .style(Style::default().fg(fg))
             .block(Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .title(" Dashboard (i to toggle) ")
+                .title(format!(" {} ", i18n::visual(&tr("panel.dashboard"))))
                 .title_alignment(Alignment::Center)
                 .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
                 .border_style(Style::default().fg(border)));
         f.render_widget(info_widget, main_chunks[1]);
     } else {
-        let rows: Vec<Row> = if app.current_files.is_empty() && app.selected_dir.selected().map_or(false, |idx| app.fs.get_files(idx).is_err()) {
-            vec![Row::new(vec![Cell::from("⚠ No access to this folder")])
-                .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::ITALIC))]
+        // With two panes the other folder's files take the right half
+        let files_area = match &app.other_pane {
+            Some(pane) if main_chunks[1].width >= 2 * MIN_PANE_WIDTH => {
+                let halves = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(main_chunks[1]);
+                let items: Vec<ListItem> = pane.files.iter().map(|name| ListItem::new(app.revealed_names.get(name).unwrap_or(name).as_str())).collect();
+                let pane_widget = List::new(items)
+                    .style(Style::default().fg(fg))
+                    .block(Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .title(format!(" {} (Tab to switch) ", pane.dir.display()))
+                        .title_alignment(Alignment::Center)
+                        .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                        .border_style(Style::default().fg(border)));
+                f.render_widget(pane_widget, halves[1]);
+                halves[0]
+            }
+            _ => main_chunks[1],
+        };
+        drawn.files_area = files_area.inner(&Margin { vertical: 1, horizontal: 1 });
+        // Only the rows that fit are built, and only their files are stat'ed, so huge folders stay quick
+        let height = (files_area.height.saturating_sub(4) as usize).max(1);
+        (drawn.file_offset, drawn.file_rows) = (app.file_scroll(height), height);
+        let window = drawn.file_offset..(drawn.file_offset + height).min(app.current_files.len());
+        let rows: Vec<Row> = if app.current_files.is_empty() && app.files_denied {
+            vec![Row::new(vec![Cell::from(format!("{} No access to this folder", glyph(ascii, "⚠", "!")))])
+                .style(Style::default().fg(theme.warning).add_modifier(Modifier::ITALIC))]
         } else {
-            app.current_files.iter().enumerate().map(|(i, (name, meta, encrypted))| {
-                let size = format!("{} KB", meta.len() / 1024);
-                let created = meta.created()
-                    .map(|t| t.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs())
-                    .map(|s| ChronoDateTime::<Utc>::from_timestamp(s as i64, 0).unwrap().format("%Y-%m-%d").to_string())
+            let statuses = app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i)).and_then(|d| app.file_statuses.get(d));
+            app.current_files[window.clone()].iter().zip(window.clone()).map(|((name, meta, encrypted), i)| {
+                let size = meta.as_ref().map_or("N/A".to_string(), |m| human_size(m.len()));
+                let created = meta.as_ref().and_then(|m| m.created().ok())
+                    .map(|t| if app.settings.relative_dates { relative_time(t) } else { absolute_date(t) })
                     .unwrap_or("N/A".to_string());
-                let status = if *encrypted { "🔒" } else { "✔" };
-                let style = if Some(i) == app.selected_file.selected() && app.mode == Mode::NavigateFiles {
-                    Style::default().fg(Color::White).bg(Color::Rgb(50, 50, 70)).add_modifier(Modifier::BOLD)
+                let shown = app.revealed_names.get(name).unwrap_or(name);
+                let status = match statuses.and_then(|s| s.get(name).or_else(|| s.get(shown))) {
+                    Some(status) => Cell::from(Span::styled(status.label(), Style::default().fg(status.color(&theme)))),
+                    None => Cell::from(if *encrypted { glyph(ascii, "🔒", "[E]") } else { glyph(ascii, "✔", "ok") }),
+                };
+                let selected = Some(i) == app.selected_file.selected() && app.mode == Mode::NavigateFiles;
+                let style = if selected {
+                    Style::default().fg(theme.selection_text).bg(theme.selection).add_modifier(Modifier::BOLD)
                 } else {
                     Style::default().fg(fg)
                 };
+                let number = match i {
+                    _ if selected && labels => pointer.to_string(),
+                    0..=8 => format!("{} ", i + 1),
+                    _ => "  ".to_string(),
+                };
                 Row::new(vec![
-                    Cell::from(name.as_str()),
+                    Cell::from(Line::from(vec![
+                        Span::styled(number, Style::default().fg(theme.muted)),
+                        Span::raw(shown.as_str()),
+                    ])),
                     Cell::from(size),
                     Cell::from(created),
-                    Cell::from(status),
+                    status,
                 ]).style(style).height(1)
             }).collect()
         };
+        let arrow = if app.sort_reversed { glyph(ascii, " ▼", " v") } else { glyph(ascii, " ▲", " ^") };
+        let header = [SortKey::Name, SortKey::Size, SortKey::Created, SortKey::Status].map(|key| {
+            if key == app.sort_key { format!("{}{}", key.label(), arrow) } else { key.label().to_string() }
+        });
+        // Why the selected file failed in the folder's last operation
+        let failure = app.selected_file.selected().and_then(|i| app.current_files.get(i))
+            .zip(app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i)).and_then(|d| app.file_statuses.get(d)))
+            .and_then(|((name, _, _), statuses)| match statuses.get(name) {
+                Some(FileStatus::Failed(why)) => Some(why.clone()),
+                _ => None,
+            });
         let files_table = Table::new(rows, &[
             Constraint::Percentage(40),
-            Constraint::Percentage(20),
+            Constraint::Percentage(15),
             Constraint::Percentage(30),
-            Constraint::Percentage(10),
+            Constraint::Percentage(15),
         ])
-        .header(Row::new(vec!["Name", "Size", "Created", "Status"])
+        .header(Row::new(header)
             .style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
             .bottom_margin(1))
         .block(Block::default()
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
-            .title(" Files ")
+            .title(match (failure, app.session.as_ref().filter(|s| !s.is_empty())) {
+                (Some(why), _) => format!(" {}Files (failed: {}) ", active(app.mode == Mode::NavigateFiles), why),
+                (None, Some(session)) => format!(" {}Files (session: {} open, W to close) ", active(app.mode == Mode::NavigateFiles), session.copies().len()),
+                (None, None) => {
+                    let dir = app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i));
+                    let name = match (&app.other_pane, dir) {
+                        (Some(pane), Some(dir)) if files_area == main_chunks[1] => format!("{} {} {}", dir.display(), glyph(ascii, "→", "->"), pane.dir.display()),
+                        (Some(_), Some(dir)) => dir.display().to_string(),
+                        _ => i18n::visual(&tr("panel.files")),
+                    };
+                    let active = active(app.mode == Mode::NavigateFiles);
+                    match dir.and_then(|d| app.listings.get(d)).map(|l| l.read.elapsed()).filter(|age| *age >= LISTING_AGE_SHOWN) {
+                        Some(age) => format!(" {}{} (read {} min ago, F5 to refresh) ", active, name, age.as_secs() / 60),
+                        None => format!(" {}{} ", active, name),
+                    }
+                }
+            })
             .title_alignment(Alignment::Center)
             .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
             .border_style(Style::default().fg(if app.mode == Mode::NavigateFiles { accent } else { border })));
-        f.render_widget(files_table, main_chunks[1]);
+        f.render_widget(files_table, files_area);
     }
 
     // HelpBar
+    let key_style = Style::default().fg(accent).add_modifier(Modifier::BOLD);
     let help_text = vec![
-        Line::from(vec![
-            Span::styled("q", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": Quit | "),
-            Span::styled("k", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": Key | "),
-            Span::styled("n", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": New Folder"),
-        ]),
-        Line::from(vec![
-            Span::styled("e", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": Encrypt | "),
-            Span::styled("d", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": Decrypt | "),
-            Span::styled("p", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": Preview"),
-        ]),
-        Line::from(vec![
-            Span::styled("t", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": Settings | "),
-            Span::styled("r", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": Remove | "),
-            Span::styled("i", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-            Span::raw(": Info"),
-        ]),
+        key_hints(&[("q", "help.quit"), ("k", "help.key"), ("n", "help.new_folder"), (":", "help.commands")], key_style),
+        key_hints(&[("e", "help.encrypt"), ("d", "help.decrypt"), ("p", "help.preview"), ("m", "help.rename")], key_style),
+        key_hints(&[("t", "help.settings"), ("r", "help.remove"), ("u", "help.undo"), ("i", "help.info")], key_style),
     ];
     let help_widget = Paragraph::new(help_text)
         .style(Style::default().fg(fg))
         .block(Block::default()
             .borders(Borders::ALL)
             .border_type(BorderType::Double)
-            .title(" Controls ")
+            .title(format!(" {} ", i18n::visual(&tr("help.title"))))
             .title_alignment(Alignment::Center)
             .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
             .border_style(Style::default().fg(border)));
@@ -655,33 +5190,63 @@ fn ui(f: &mut Frame, app: &mut App) {
     if app.mode == Mode::Settings {
         let settings_area = centered_rect(50, 50, f.size());
         f.render_widget(Clear, settings_area);
-        f.render_widget(Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Gray).bg(Color::Rgb(20, 20, 20))), settings_area);
+        f.render_widget(Block::default().borders(Borders::ALL).border_style(Style::default().fg(border).bg(theme.popup)), settings_area);
         let settings_text = vec![
-            Line::from("⚙ Settings"),
-            Line::from(vec![
-                Span::styled("1", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-                Span::raw(": Dark Theme")
-            ]),
-            Line::from(vec![
-                Span::styled("2", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-                Span::raw(": Light Theme")
-            ]),
-            Line::from(vec![
-                Span::styled("3", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-                Span::raw(": Key Length 16")
-            ]),
-            Line::from(vec![
-                Span::styled("4", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-                Span::raw(": Key Length 32")
-            ]),
-            Line::from(vec![
-                Span::styled("Esc", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
-                Span::raw(": Exit")
-            ]),
+            i18n::line(vec![Span::raw(format!("{}{}", glyph(ascii, "⚙ ", ""), tr("settings.title")))]),
+            key_hints(&[("1", "settings.next_theme")], key_style),
+            key_hints(&[("2", "settings.previous_theme")], key_style),
+            key_hints(&[("3", "settings.key_16")], key_style),
+            key_hints(&[("4", "settings.key_32")], key_style),
+            key_hints(&[("5", "settings.auto_lock")], key_style),
+            key_hints(&[("6", "settings.in_flight")], key_style),
+            key_hints(&[("7", "settings.cipher")], key_style),
+            key_hints(&[("8", "settings.permanent_delete")], key_style),
+            key_hints(&[("9", "settings.dates")], key_style),
+            key_hints(&[("0", "settings.encrypt_names")], key_style),
+            key_hints(&[("c", "settings.checksums")], key_style),
+            key_hints(&[("f", "settings.format")], key_style),
+            key_hints(&[("w", "settings.auto_reencrypt")], key_style),
+            key_hints(&[("a", "settings.ascii")], key_style),
+            key_hints(&[("l", "settings.labels")], key_style),
+            key_hints(&[("j", "settings.type_ahead")], key_style),
+            key_hints(&[("g", "settings.language")], key_style),
+            key_hints(&[("e", "settings.edit_config")], key_style),
+            key_hints(&[("p", "settings.profile")], key_style),
+            key_hints(&[("s", "settings.security")], key_style),
+            key_hints(&[("Esc", "settings.exit")], key_style),
             Line::from(format!(
-                "Current: {} Theme, Key Length {}",
-                if app.settings.theme == Theme::Dark { "Dark" } else { "Light" },
-                app.settings.key_length
+                "Current: {} theme, Key Length {}, Auto-lock {}, Pipeline {}, Delete to {}, {} dates, session changes {}, {} symbols, colour labels {}, letters {}, {}",
+                app.settings.theme.name,
+                app.settings.key_length,
+                app.settings.lock_timeout.map_or("Off".to_string(), |d| format!("{} min", d.as_secs() / 60)),
+                if app.settings.in_flight_chunks == 0 { "Off".to_string() } else { format!("{} MiB", app.settings.in_flight_chunks) },
+                if app.settings.permanent_delete { "nowhere (permanent)" } else { "trash" },
+                if app.settings.relative_dates { "Relative" } else { "Absolute" },
+                if app.settings.auto_reencrypt { "re-encrypted automatically" } else { "confirmed" },
+                if ascii { "ASCII" } else { "Unicode" },
+                if labels { "on" } else { "off" },
+                if app.settings.type_ahead { "jump to names" } else { "run commands" },
+                i18n::current().name()
+            )),
+            Line::from(format!(
+                "Profile: {}",
+                app.fs.config.profile().map_or("none", |p| p.name.as_str())
+            )),
+            Line::from(format!(
+                "Default cipher: {} ({})",
+                app.fs.config.default_cipher().label(),
+                if aes_accelerated() { "AES acceleration detected" } else { "no AES acceleration" }
+            )),
+            Line::from(format!(
+                "Selected folder: {}, {} format, names {}, checksums {}, {} recipients",
+                app.selected_dir.selected()
+                    .and_then(|i| app.fs.dirs.get(i))
+                    .and_then(|d| app.fs.config.cipher_for(d))
+                    .map_or("Auto".to_string(), |c| c.label().to_string()),
+                app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i)).map_or(OutputFormat::GuardX, |d| app.fs.config.output_format(d)).label(),
+                if app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i)).is_some_and(|d| app.fs.config.encrypt_names(d)) { "encrypted" } else { "visible" },
+                if app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i)).is_some_and(|d| app.fs.config.checksums(d)) { "on" } else { "off" },
+                app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i)).and_then(|d| app.fs.config.vault(d)).map_or(0, |v| v.recipients.len())
             )),
         ];
         let settings_widget = Paragraph::new(settings_text)
@@ -696,20 +5261,337 @@ fn ui(f: &mut Frame, app: &mut App) {
         f.render_widget(settings_widget, settings_area);
     }
 
+    // Security screen
+    if matches!(app.mode, Mode::Security | Mode::SecurityEnroll | Mode::SecurityRemove) {
+        let security_area = centered_rect(60, 80, f.size());
+        f.render_widget(Clear, security_area);
+        let mut lines = vec![
+            Line::from(format!(
+                "Second factor: {}",
+                if app.totp.is_some() { "on, decrypting asks for a code from your authenticator" } else { "off" }
+            )),
+            Line::from(format!(
+                "{}: {}",
+                biometric::name(),
+                if app.biometric_stored { "a key is stored; h sets it, Tab unlocks" } else { "no key stored" }
+            )),
+            Line::from(match app.hardware {
+                Some(token) => format!("Hardware key: slot {} of a YubiKey, touched whenever a key is entered", token.slot),
+                None => "Hardware key: off (enroll with `guardx hardware-key --slot 1|2`)".to_string(),
+            }),
+        ];
+        match &app.totp_pending {
+            Some((pending, qr)) => {
+                lines.push(Line::from("Scan this with your authenticator app, or type in the secret below:"));
+                lines.extend(qr.lines().map(|row| Line::from(row.to_string())));
+                lines.push(Line::from(Span::styled(pending.secret_base32(), Style::default().fg(accent).add_modifier(Modifier::BOLD))));
+                lines.push(Line::from("Then type the 6-digit code the app shows and press Enter (Esc cancels)"));
+            }
+            None => {
+                lines.push(Line::from(vec![
+                    Span::styled("e", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
+                    Span::raw(": Enroll an authenticator app (TOTP)"),
+                ]));
+                lines.push(Line::from(vec![
+                    Span::styled("r", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
+                    Span::raw(": Remove the second factor (asks for a current code)"),
+                ]));
+                lines.push(Line::from(vec![
+                    Span::styled("y", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
+                    Span::raw(": List connected YubiKeys"),
+                ]));
+                lines.push(Line::from(vec![
+                    Span::styled("h", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
+                    Span::raw(format!(": Store the current key for {}", biometric::name())),
+                ]));
+                lines.push(Line::from(vec![
+                    Span::styled("f", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
+                    Span::raw(format!(": Forget the key stored for {}", biometric::name())),
+                ]));
+                lines.push(Line::from(vec![
+                    Span::styled("Esc", Style::default().fg(accent).add_modifier(Modifier::BOLD)),
+                    Span::raw(": Back to settings"),
+                ]));
+            }
+        }
+        let security_widget = Paragraph::new(lines)
+            .style(Style::default().fg(fg))
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+                .title(" Security ")
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)));
+        f.render_widget(security_widget, security_area);
+    }
+
+    // Verify Report
+    if app.mode == Mode::VerifyReport {
+        let report_area = centered_rect(60, 50, f.size());
+        f.render_widget(Clear, report_area);
+        let damaged = app.verify_report.iter().filter(|(_, error)| error.is_some()).count();
+        let mut lines = vec![Line::from(format!("Checked {} files, {} damaged", app.verify_report.len(), damaged))];
+        if let Some(note) = &app.verify_note {
+            lines.push(Line::from(Span::styled(format!("Note: {}", note), Style::default().add_modifier(Modifier::ITALIC))));
+        }
+        lines.extend(app.verify_report.iter().map(|(name, error)| match error {
+            Some(error) => Line::from(Span::styled(format!("[X] {}: {}", name, error), Style::default().fg(theme.error))),
+            None => Line::from(Span::styled(format!("[OK] {}", name), Style::default().fg(theme.success))),
+        }));
+        let border_color = if damaged == 0 { accent } else { theme.error };
+        let report_widget = Paragraph::new(lines)
+            .style(Style::default().fg(fg))
+            .wrap(Wrap { trim: false })
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(" Verify (Esc to close) ")
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(border_color).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(border_color)));
+        f.render_widget(report_widget, report_area);
+    }
+
+    // Pre-flight summary of an encryption
+    if app.mode == Mode::ConfirmEncrypt {
+        if let Some((_, plan)) = &app.preflight {
+            let plan_area = centered_rect(60, 60, f.size());
+            f.render_widget(Clear, plan_area);
+            let mut lines = vec![Line::from(format!(
+                "{} files, {}, {}",
+                plan.process.len(),
+                human_size(plan.total_size()),
+                rough_duration(plan.estimate()),
+            ))];
+            lines.extend(plan.already_encrypted.iter().map(|path| Line::from(Span::styled(
+                format!("[!] {} is already encrypted and would be encrypted again", path.file_name().unwrap_or_default().to_string_lossy()),
+                Style::default().fg(theme.error),
+            ))));
+            lines.extend(plan.process.iter().map(|(path, size)| {
+                let rename = if plan.renames { " -> random name" } else { "" };
+                Line::from(format!("  {} ({}){}", path.file_name().unwrap_or_default().to_string_lossy(), human_size(*size), rename))
+            }));
+            lines.extend(plan.skipped.iter().map(|(path, reason)| Line::from(Span::styled(
+                format!("  skip {}: {}", path.file_name().unwrap_or_default().to_string_lossy(), reason),
+                Style::default().fg(theme.warning),
+            ))));
+            let plan_widget = Paragraph::new(lines)
+                .style(Style::default().fg(fg))
+                .wrap(Wrap { trim: false })
+                .block(Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .title(" Encrypt? [y/n] ")
+                    .title_alignment(Alignment::Center)
+                    .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                    .border_style(Style::default().fg(accent)));
+            f.render_widget(plan_widget, plan_area);
+        }
+    }
+
+    // Summary of a finished encryption or decryption
+    if app.mode == Mode::OperationReport {
+        if let Some(report) = app.reports.first() {
+            let report_area = centered_rect(60, 50, f.size());
+            f.render_widget(Clear, report_area);
+            let mut lines = vec![
+                Line::from(format!("{}: {}", if report.operation == Operation::Encrypt { "Encryption" } else { "Decryption" }, report.folder.display())),
+                Line::from(format!(
+                    "{} processed ({}), {} skipped, {} failed, in {:.1} s",
+                    report.processed.len(),
+                    human_size(report.bytes),
+                    report.skipped.len(),
+                    report.failed.len(),
+                    report.elapsed_secs,
+                )),
+            ];
+            lines.extend(report.failed.iter().map(|item| Line::from(Span::styled(format!("[X] {}: {}", item.file, item.reason), Style::default().fg(theme.error)))));
+            lines.extend(report.skipped.iter().map(|item| Line::from(Span::styled(format!("[-] {}: {}", item.file, item.reason), Style::default().fg(theme.warning)))));
+            let border_color = if report.failed.is_empty() { accent } else { theme.error };
+            let report_widget = Paragraph::new(lines)
+                .style(Style::default().fg(fg))
+                .wrap(Wrap { trim: false })
+                .block(Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .title(" Report (t text, j JSON, Esc to close) ")
+                    .title_alignment(Alignment::Center)
+                    .title_style(Style::default().fg(border_color).add_modifier(Modifier::BOLD))
+                    .border_style(Style::default().fg(border_color)));
+            f.render_widget(report_widget, report_area);
+        }
+    }
+
+    // Every entry of the persistent history, newest at the bottom
+    if app.mode == Mode::HistoryBrowser {
+        let history_area = centered_rect(90, 80, f.size());
+        f.render_widget(Clear, history_area);
+        let items: Vec<ListItem> = app.shown_history().into_iter().map(|entry| {
+            let mark = if entry.ok { "[OK]" } else { "[X]" };
+            let path = entry.path.as_ref().map(|p| format!("  {}", p.display())).unwrap_or_default();
+            let mut lines = vec![Line::from(format!("{} {} {}{}", history_time(entry.time, true), mark, entry.operation, path))];
+            if let Some(note) = &entry.note {
+                lines.push(Line::from(format!("    \"{}\"", note)));
+            }
+            ListItem::new(lines).style(Style::default().fg(if entry.ok { fg } else { theme.error }))
+        }).collect();
+        let title = format!(" History{} ({} scroll, f {}, Esc to close) ", if app.history_failures_only { ": failures" } else { "" }, glyph(ascii, "↑/↓", "Up/Down"), if app.history_failures_only { "all" } else { "failures only" });
+        let history_widget = List::new(items)
+            .highlight_symbol(pointer)
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(title)
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)))
+            .highlight_style(Style::default().fg(theme.selection_text).bg(theme.selection));
+        let mut history_state = app.history_view.clone();
+        f.render_stateful_widget(history_widget, history_area, &mut history_state);
+        drawn.history_offset = history_state.offset();
+    }
+
+    // Bookmarked folders, numbered for 1-9
+    if app.mode == Mode::Bookmarks {
+        let bookmark_area = centered_rect(60, 50, f.size());
+        f.render_widget(Clear, bookmark_area);
+        let items: Vec<ListItem> = app.fs.config.bookmarks.iter().enumerate().map(|(i, dir)| {
+            let number = if i < 9 { format!("{} ", i + 1) } else { "  ".to_string() };
+            let missing = app.bookmark_missing.get(i).copied().unwrap_or(false);
+            ListItem::new(format!("{}{}{}", number, dir.display(), if missing { " [missing]" } else { "" })).style(Style::default().fg(if missing { theme.muted } else { fg }))
+        }).collect();
+        let bookmark_widget = List::new(items)
+            .highlight_symbol(pointer)
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(" Bookmarks (Enter or 1-9 to jump, d to remove, Esc to close) ")
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)))
+            .highlight_style(Style::default().fg(theme.selection_text).bg(theme.selection));
+        let mut bookmark_state = app.bookmark_view.clone();
+        f.render_stateful_widget(bookmark_widget, bookmark_area, &mut bookmark_state);
+        drawn.bookmark_offset = bookmark_state.offset();
+    }
+
+    // The query on top, the best matches below it; folders end in a slash
+    if app.mode == Mode::Finder {
+        let finder_area = centered_rect(70, 60, f.size());
+        f.render_widget(Clear, finder_area);
+        let indexed = if app.finder_rx.is_some() {
+            format!("indexing, {} so far", app.finder_entries.len())
+        } else if app.finder_truncated {
+            format!("first {} indexed", finder::MAX_ENTRIES)
+        } else {
+            format!("{} indexed", app.finder_entries.len())
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .title(format!(" Find ({}; Enter to jump, F5 to re-index, Esc to close) ", indexed))
+            .title_alignment(Alignment::Center)
+            .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+            .border_style(Style::default().fg(accent));
+        let inner = block.inner(finder_area);
+        f.render_widget(block, finder_area);
+        let rows = Layout::default().direction(Direction::Vertical).constraints([Constraint::Length(1), Constraint::Min(0)]).split(inner);
+        f.render_widget(Paragraph::new(format!("> {}{}", app.finder_query, glyph(ascii, "▏", "_"))).style(Style::default().fg(accent)), rows[0]);
+        let items: Vec<ListItem> = app.finder_matches.iter().filter_map(|&i| app.finder_entries.get(i)).map(|entry| {
+            let slash = if entry.is_dir { "/" } else { "" };
+            ListItem::new(format!("{}{}", entry.path.display(), slash)).style(Style::default().fg(if entry.is_dir { fg } else { theme.muted }))
+        }).collect();
+        let finder_widget = List::new(items).highlight_symbol(pointer).highlight_style(Style::default().fg(theme.selection_text).bg(theme.selection));
+        let mut finder_state = app.finder_view.clone();
+        f.render_stateful_widget(finder_widget, rows[1], &mut finder_state);
+        drawn.finder_offset = finder_state.offset();
+    }
+
+    // Command palette, each command with the key that does the same
+    if app.mode == Mode::Palette {
+        let palette_area = centered_rect(60, 60, f.size());
+        f.render_widget(Clear, palette_area);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .title(" Commands (type to filter or a number to jump, Enter to run, Esc to close) ")
+            .title_alignment(Alignment::Center)
+            .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+            .border_style(Style::default().fg(accent));
+        let inner = block.inner(palette_area);
+        f.render_widget(block, palette_area);
+        let rows = Layout::default().direction(Direction::Vertical).constraints([Constraint::Length(1), Constraint::Min(0)]).split(inner);
+        f.render_widget(Paragraph::new(format!(": {}{}", app.palette_query, glyph(ascii, "▏", "_"))).style(Style::default().fg(accent)), rows[0]);
+        let list = if app.palette_from_files { "file" } else { "folder" };
+        let jump = app.palette_number().map(|n| ListItem::new(format!("Go to {} {}", list, n)).style(Style::default().fg(fg)));
+        let items: Vec<ListItem> = jump.into_iter().chain(app.palette_matches.iter().map(|&i| {
+            let command = &COMMANDS[i];
+            let place = match command.scope {
+                Scope::Settings => "t, then ",
+                Scope::Folders if app.palette_from_files => glyph(ascii, "←, then ", "Left, then "),
+                _ => "",
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(command.name, Style::default().fg(fg)),
+                Span::styled(format!("  {}{}", place, key_label(command.key, command.modifiers)), Style::default().fg(theme.muted)),
+            ]))
+        })).collect();
+        let palette_widget = List::new(items).highlight_symbol(pointer).highlight_style(Style::default().fg(theme.selection_text).bg(theme.selection));
+        let mut palette_state = app.palette_view.clone();
+        f.render_stateful_widget(palette_widget, rows[1], &mut palette_state);
+        drawn.palette_offset = palette_state.offset();
+    }
+
+    // The end of the log file, newest at the bottom
+    if app.mode == Mode::LogViewer {
+        let log_area = centered_rect(90, 80, f.size());
+        f.render_widget(Clear, log_area);
+        let shown = app.shown_log_lines();
+        let height = log_area.height.saturating_sub(2) as usize;
+        let end = shown.len() - app.log_scroll.min(shown.len());
+        let lines: Vec<Line> = if shown.is_empty() {
+            vec![Line::from(if app.log_problems_only { "No warnings or errors in the log" } else { "Nothing has been logged yet" })]
+        } else {
+            shown[end.saturating_sub(height)..end].iter().map(|line| {
+                let color = if line.contains(" ERROR ") {
+                    theme.error
+                } else if line.contains(" WARN ") {
+                    theme.warning
+                } else {
+                    fg
+                };
+                Line::from(Span::styled(line.as_str(), Style::default().fg(color)))
+            }).collect()
+        };
+        let title = format!(" Log{} ({} scroll, f {}, r reload, Esc to close) ", if app.log_problems_only { ": warnings and errors" } else { "" }, glyph(ascii, "↑/↓", "Up/Down"), if app.log_problems_only { "all" } else { "problems only" });
+        let log_widget = Paragraph::new(lines)
+            .style(Style::default().fg(fg))
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(title)
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)));
+        f.render_widget(log_widget, log_area);
+    }
+
     // Confirm Folder Deletion
     if app.mode == Mode::ConfirmDeleteFolder {
         let confirm_area = centered_rect(30, 5, f.size());
         f.render_widget(Clear, confirm_area);
-        f.render_widget(Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Gray).bg(Color::Rgb(20, 20, 20))), confirm_area);
-        let confirm_widget = Paragraph::new("Delete folder? [y/n]")
+        f.render_widget(Block::default().borders(Borders::ALL).border_style(Style::default().fg(border).bg(theme.popup)), confirm_area);
+        let confirm_widget = Paragraph::new(i18n::visual(&tr(if app.delete_permanently { "confirm.delete_folder" } else { "confirm.trash_folder" })))
+            .alignment(i18n::alignment())
             .style(Style::default().fg(fg))
             .block(Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .title(" Confirm ")
+                .title(format!(" {} ", i18n::visual(&tr("confirm.title"))))
                 .title_alignment(Alignment::Center)
-                .title_style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
-                .border_style(Style::default().fg(Color::Red)));
+                .title_style(Style::default().fg(theme.error).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(theme.error)));
         f.render_widget(confirm_widget, confirm_area);
     }
 
@@ -717,47 +5599,359 @@ fn ui(f: &mut Frame, app: &mut App) {
     if app.mode == Mode::ConfirmDeleteFile {
         let confirm_area = centered_rect(30, 5, f.size());
         f.render_widget(Clear, confirm_area);
-        f.render_widget(Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Gray).bg(Color::Rgb(20, 20, 20))), confirm_area);
-        let confirm_widget = Paragraph::new("Delete file? [y/n]")
+        f.render_widget(Block::default().borders(Borders::ALL).border_style(Style::default().fg(border).bg(theme.popup)), confirm_area);
+        let confirm_widget = Paragraph::new(i18n::visual(&tr(if app.delete_permanently { "confirm.delete_file" } else { "confirm.trash_file" })))
+            .alignment(i18n::alignment())
             .style(Style::default().fg(fg))
             .block(Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .title(" Confirm ")
+                .title(format!(" {} ", i18n::visual(&tr("confirm.title"))))
                 .title_alignment(Alignment::Center)
-                .title_style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
-                .border_style(Style::default().fg(Color::Red)));
+                .title_style(Style::default().fg(theme.error).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(theme.error)));
         f.render_widget(confirm_widget, confirm_area);
     }
 
-    // History
-    if app.info_mode {
+    // Rename File
+    if app.mode == Mode::RenameFile {
+        let rename_area = centered_rect(40, 5, f.size());
+        f.render_widget(Clear, rename_area);
+        f.render_widget(Block::default().borders(Borders::ALL).border_style(Style::default().fg(border).bg(theme.popup)), rename_area);
+        let rename_widget = Paragraph::new(format!("{}_", app.rename_input))
+            .style(Style::default().fg(fg))
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(format!(" {} ", i18n::visual(&tr("confirm.rename"))))
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)));
+        f.render_widget(rename_widget, rename_area);
+    }
+
+    // File details, label and value
+    if app.mode == Mode::Details {
+        let details_area = centered_rect(80, 60, f.size());
+        f.render_widget(Clear, details_area);
+        let width = app.details.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let lines: Vec<Line> = app.details.iter().map(|(label, value)| Line::from(vec![
+            Span::styled(format!("{:width$}  ", label, width = width), Style::default().fg(accent).add_modifier(Modifier::BOLD)),
+            Span::styled(value.clone(), Style::default().fg(fg)),
+        ])).collect();
+        let details_widget = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(" Details (Esc to close) ")
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)));
+        f.render_widget(details_widget, details_area);
+    }
+
+    // Notification center, newest at the bottom
+    if app.mode == Mode::Notifications {
+        let notice_area = centered_rect(80, 70, f.size());
+        f.render_widget(Clear, notice_area);
+        let items: Vec<ListItem> = app.notices.iter().map(|notice| {
+            ListItem::new(format!("{} {}", history_time(notice.time, true), notice.text)).style(Style::default().fg(notice.severity.color(&theme)))
+        }).collect();
+        let notice_widget = List::new(items)
+            .highlight_symbol(pointer)
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(format!(" Notifications ({} scroll, c to clear, Esc to close) ", glyph(ascii, "↑/↓", "Up/Down")))
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+                .border_style(Style::default().fg(accent)))
+            .highlight_style(Style::default().fg(theme.selection_text).bg(theme.selection));
+        let mut notice_state = app.notice_view.clone();
+        f.render_stateful_widget(notice_widget, notice_area, &mut notice_state);
+        drawn.notice_offset = notice_state.offset();
+    }
+
+    // History; the full-screen views cover it
+    if app.info_mode && size.width >= HISTORY_MIN_WIDTH && !matches!(app.mode, Mode::HistoryBrowser | Mode::LogViewer | Mode::Bookmarks | Mode::Finder | Mode::Palette | Mode::Notifications) {
         let history_area = Rect {
-            x: f.size().width - 35,
-            y: 4,
-            width: 35,
-            height: (app.history.len() + 2).min(10) as u16,
-        };
-        let history_items: Vec<ListItem> = app.history.iter().rev().take(8)
-            .map(|(msg, time, success)| {
-                let time_str = format!("{:?}s", time.elapsed().as_secs());
-                ListItem::new(format!("{} ({})", msg, time_str))
-                    .style(Style::default().fg(if *success { Color::Green } else { Color::Red }))
+            x: chunks[2].right() - HISTORY_WIDTH,
+            y: chunks[2].y,
+            width: HISTORY_WIDTH,
+            height: ((app.history.len() + 2).min(10) as u16).min(chunks[2].height),
+        };
+        let history_items: Vec<ListItem> = app.history.iter().enumerate().rev().take(8)
+            .map(|(i, entry)| {
+                // Entries that `u` can still revert carry a marker
+                let undoable = if app.undo_stack.iter().any(|(undo, _)| *undo == i) { "[u] " } else { "" };
+                let outcome = match (labels, entry.ok) {
+                    (false, _) => "",
+                    (true, true) => "[OK] ",
+                    (true, false) => "[X] ",
+                };
+                ListItem::new(format!("{}{}{} ({})", outcome, undoable, history_text(entry), history_time(entry.time, false)))
+                    .style(Style::default().fg(if entry.ok { theme.success } else { theme.error }))
             }).collect();
         let history_widget = List::new(history_items)
             .block(Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .title(" History ")
+                .title(format!(" {} ", i18n::visual(&tr("panel.history"))))
                 .title_alignment(Alignment::Center)
                 .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
                 .border_style(Style::default().fg(border)))
-            .highlight_style(Style::default().fg(Color::White).bg(Color::DarkGray));
+            .highlight_style(Style::default().fg(theme.selection_text).bg(theme.selection));
         f.render_widget(history_widget, history_area);
     }
+
+    // Toasts, stacked up from the bottom right of the lists with the newest lowest; popups that take typed
+    // input stay uncovered
+    if !matches!(app.mode, Mode::Notifications | Mode::Palette | Mode::Finder | Mode::RenameFile) {
+        let width = chunks[2].width.saturating_sub(2).min(50);
+        let mut bottom = chunks[2].bottom();
+        for notice in app.toasts().into_iter().rev() {
+            let text = i18n::visual(&notice.text);
+            let lines = (text.chars().count() as u16).div_ceil(width.saturating_sub(2).max(1)).clamp(1, 3);
+            if width < 10 || bottom < chunks[2].y + lines + 2 {
+                break;
+            }
+            let area = Rect { x: chunks[2].right() - width - 1, y: bottom - lines - 2, width, height: lines + 2 };
+            let color = notice.severity.color(&theme);
+            f.render_widget(Clear, area);
+            f.render_widget(Paragraph::new(text)
+                .wrap(Wrap { trim: true })
+                .style(Style::default().fg(fg).bg(theme.popup))
+                .block(Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .border_style(Style::default().fg(color))), area);
+            bottom = area.y;
+        }
+    }
+    drawn
+}
+
+// $VISUAL or $EDITOR gets this terminal until it exits; without one the system's default app opens the file
+// and the session stays open until W. Returns whether the program was waited for.
+fn open_external<B: Backend>(terminal: &mut Terminal<B>, path: &Path) -> Result<bool> {
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).unwrap_or_default();
+    let mut words = editor.split_whitespace();
+    if let Some(program) = words.next() {
+        disable_raw_mode()?;
+        let status = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste).map_err(anyhow::Error::from)
+            .and_then(|_| Command::new(program).args(words).arg(path).status().with_context(|| format!("Could not start {}", program)));
+        // The TUI comes back whether or not the editor could run
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
+        terminal.clear()?;
+        status?;
+        return Ok(true);
+    }
+    // `start` is built into cmd; its first quoted argument is the window title
+    let (opener, args): (&str, &[&str]) = if cfg!(target_os = "macos") { ("open", &[]) } else if cfg!(windows) { ("cmd", &["/C", "start", ""]) } else { ("xdg-open", &[]) };
+    Command::new(opener).args(args).arg(path).stdout(Stdio::null()).stderr(Stdio::null()).spawn()
+        .with_context(|| format!("Could not start {}", opener))?;
+    Ok(false)
+}
+
+// "-rw-r--r-- (644)" on Unix; elsewhere only whether the file is read-only
+#[cfg(unix)]
+fn permissions(meta: &Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = meta.permissions().mode();
+    let kind = if meta.is_dir() { 'd' } else if meta.file_type().is_symlink() { 'l' } else { '-' };
+    let bits: String = (0..9).map(|i| if mode & (0o400 >> i) != 0 { ['r', 'w', 'x'][i % 3] } else { '-' }).collect();
+    format!("{}{} ({:o})", kind, bits, mode & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn permissions(meta: &Metadata) -> String {
+    if meta.permissions().readonly() { "read-only" } else { "writable" }.to_string()
+}
+
+// User and group by name where /etc/passwd and /etc/group have them, by number otherwise
+#[cfg(unix)]
+fn owner(meta: &Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let name = |file: &str, id: u32| {
+        fs::read_to_string(file).ok()
+            .and_then(|text| text.lines().map(|line| line.split(':').collect::<Vec<_>>()).find(|fields| fields.get(2) == Some(&id.to_string().as_str())).map(|fields| fields[0].to_string()))
+            .map_or_else(|| id.to_string(), |name| format!("{} ({})", name, id))
+    };
+    Some(format!("{}, group {}", name("/etc/passwd", meta.uid()), name("/etc/group", meta.gid())))
+}
+
+#[cfg(not(unix))]
+fn owner(_meta: &Metadata) -> Option<String> {
+    None
+}
+
+// A command's key as the palette shows it
+fn key_label(code: KeyCode, modifiers: KeyModifiers) -> String {
+    let key = match code {
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        _ => "?".to_string(),
+    };
+    if modifiers.contains(KeyModifiers::CONTROL) { format!("Ctrl+{}", key.to_uppercase()) } else { key }
+}
+
+// "k: Message | k: Message" from (key, catalog key) pairs, laid out from the right in a right-to-left language
+fn key_hints(hints: &[(&'static str, &str)], key_style: Style) -> Line<'static> {
+    let mut spans = Vec::new();
+    for (i, (key, message)) in hints.iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw(" | "));
+        }
+        spans.push(Span::styled(*key, key_style));
+        spans.push(Span::raw(format!(": {}", tr(message))));
+    }
+    i18n::line(spans)
+}
+
+// Box-drawing lines stay either way; console fonts have those
+fn glyph(ascii: bool, unicode: &'static str, plain: &'static str) -> &'static str {
+    if ascii { plain } else { unicode }
+}
+
+// Emoji need a UTF-8 locale and a font that has them; the Linux console's fonts do not, whatever the locale
+fn unicode_likely() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter().find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()));
+    let utf8 = locale.map_or(cfg!(any(windows, target_os = "macos")), |l| l.to_uppercase().replace('-', "").contains("UTF8"));
+    utf8 && std::env::var("TERM").map_or(true, |term| term != "linux" && term != "vt100" && term != "vt220")
+}
+
+fn label_name(label: Label) -> &'static str {
+    match label {
+        Label::Red => "red",
+        Label::Yellow => "yellow",
+        Label::Green => "green",
+        Label::Blue => "blue",
+        Label::Magenta => "magenta",
+    }
+}
+
+fn label_color(label: Label) -> Color {
+    match label {
+        Label::Red => Color::Red,
+        Label::Yellow => Color::Yellow,
+        Label::Green => Color::Green,
+        Label::Blue => Color::Blue,
+        Label::Magenta => Color::Magenta,
+    }
+}
+
+// An entry in the history panel: the folder or file it was about, if any, then what happened and its note
+fn history_text(entry: &LogEntry) -> String {
+    let mut text = match entry.path.as_deref().and_then(Path::file_name) {
+        Some(name) => format!("{}: {}", name.to_string_lossy(), entry.operation),
+        None => entry.operation.clone(),
+    };
+    if let Some(note) = &entry.note {
+        text.push_str(&format!(" – \"{}\"", note));
+    }
+    text
+}
+
+// The time of day for today's entries and the date for older ones, or both with `full`
+fn history_time(time: i64, full: bool) -> String {
+    let Some(at) = ChronoDateTime::from_timestamp(time, 0).map(|t| t.with_timezone(&chrono::Local)) else { return String::new() };
+    if full {
+        at.format("%Y-%m-%d %H:%M").to_string()
+    } else if at.date_naive() == chrono::Local::now().date_naive() {
+        at.format("%H:%M").to_string()
+    } else {
+        at.format("%b %d").to_string()
+    }
+}
+
+// Lines of the log file the log viewer reads; older ones are in the file
+const LOG_VIEWER_LINES: usize = 2000;
+
+// Outcomes only: prompts show what is being typed, such as second-factor codes, and stay out of the log
+fn log_status(status: &str) {
+    if status.starts_with("[X]") {
+        tracing::error!("{}", status);
+    } else if status.starts_with("[!]") {
+        tracing::warn!("{}", status);
+    } else if status.starts_with("[OK]") {
+        tracing::info!("{}", status);
+    }
+}
+
+// What went wrong, worded by cause where GuardX knows it; other errors are shown as they are
+// Added to a copy's status when it went out as an escape sequence, which the terminal may not pass on
+fn via_note(via: Via) -> &'static str {
+    match via {
+        Via::System => "",
+        Via::Terminal => " (via the terminal, OSC 52)",
+    }
+}
+
+fn failure_message(e: &anyhow::Error) -> String {
+    let named = |path: &Path| if path.as_os_str().is_empty() { "a file".to_string() } else { path.display().to_string() };
+    match GuardXError::of(e) {
+        Some(GuardXError::Authentication) => "wrong key, or the data was altered".to_string(),
+        Some(GuardXError::PermissionDenied(path)) => format!("GuardX may not change {}; check its permissions", named(&path)),
+        Some(GuardXError::DiskFull(path)) => format!("the disk is full while writing {}", named(&path)),
+        Some(GuardXError::NotFound(path)) => format!("{} disappeared; another program may have moved or deleted it", named(&path)),
+        Some(GuardXError::Format(_)) | None => format!("{:#}", e),
+    }
+}
+
+pub(crate) fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+// For estimates, which are rough anyway
+pub(crate) fn rough_duration(duration: Duration) -> String {
+    match duration.as_secs() {
+        0 => "under a second".to_string(),
+        secs @ 1..=89 => format!("about {} s", secs),
+        secs @ 90..=5399 => format!("about {} min", (secs + 30) / 60),
+        secs => format!("about {} h", (secs + 1800) / 3600),
+    }
+}
+
+// Clock skew can put a file's time slightly in the future; that still reads as "just now"
+fn relative_time(time: SystemTime) -> String {
+    let secs = SystemTime::now().duration_since(time).map(|d| d.as_secs()).unwrap_or(0);
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86_399 => format!("{} h ago", secs / 3600),
+        86_400..=2_591_999 => format!("{} d ago", secs / 86_400),
+        _ => absolute_date(time),
+    }
+}
+
+fn absolute_date(time: SystemTime) -> String {
+    ChronoDateTime::<Utc>::from(time).format("%Y-%m-%d").to_string()
+}
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    (area.x..area.x + area.width).contains(&column) && (area.y..area.y + area.height).contains(&row)
 }
 
+// Never shorter than three rows, so a bordered line of text still shows on small terminals
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    if (r.height as u32 * percent_y as u32) / 100 < 3 && r.height > 3 {
+        return centered_rect(percent_x, 100, Rect { y: r.y + (r.height - 3) / 2, height: 3, ..r });
+    }
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -774,4 +5968,235 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
             Constraint::Percentage((100 - percent_x) / 2),
         ])
         .split(popup_layout[1])[1]
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use std::sync::OnceLock;
+
+    // An App against a home of its own, whose one root holds the folder "alpha" with one file. Tests share
+    // it, so none of them performs an Effect that changes it.
+    fn app() -> App {
+        static HOME: OnceLock<PathBuf> = OnceLock::new();
+        HOME.get_or_init(|| {
+            let home = std::env::temp_dir().join(format!("guardx-ui-test-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&home);
+            let root = home.join("vaults");
+            fs::create_dir_all(root.join("alpha")).unwrap();
+            fs::write(root.join("alpha").join("notes.txt"), "meet at noon").unwrap();
+            std::env::set_var("HOME", &home);
+            std::env::set_var("XDG_CONFIG_HOME", home.join("config"));
+            std::env::set_var("XDG_DATA_HOME", home.join("data"));
+            std::env::set_var("LC_ALL", "C");
+            let config = Config { roots: vec![root], ..Default::default() };
+            config.save().unwrap();
+            home
+        });
+        App::new().unwrap()
+    }
+
+    // As picked in the palette, which skips the repeat guard, so keys can follow each other at test speed
+    fn press(app: &mut App, code: KeyCode) -> Vec<Effect> {
+        app.update(Action::Picked(code, KeyModifiers::NONE)).unwrap()
+    }
+
+    // Draws a frame and hands what it laid out to update, as run_app does
+    fn draw(terminal: &mut Terminal<TestBackend>, app: &mut App) -> Vec<Effect> {
+        let mut drawn = None;
+        terminal.draw(|f| drawn = Some(ui(f, app))).unwrap();
+        app.update(Action::Drawn(drawn.unwrap())).unwrap()
+    }
+
+    fn screen(terminal: &Terminal<TestBackend>) -> String {
+        terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    fn selected_dir(app: &App) -> Option<&PathBuf> {
+        app.selected_dir.selected().and_then(|i| app.fs.dirs.get(i))
+    }
+
+    // Draws the TUI and steps through a few screens
+    #[test]
+    fn draws_the_folder_list_and_follows_keys() {
+        let mut app = app();
+        let mut terminal = Terminal::new(TestBackend::new(120, 32)).unwrap();
+        draw(&mut terminal, &mut app);
+        let drawn = screen(&terminal);
+        assert!(drawn.contains("GuardX"), "{}", drawn);
+        // Folder rows show the whole path, which the pane may cut short, so the folder is checked in the App
+        assert!(selected_dir(&app).is_some_and(|d| d.ends_with("alpha")));
+
+        app.update(Action::Key(KeyEvent::new(KeyCode::Right, KeyModifiers::NONE))).unwrap();
+        assert!(app.mode == Mode::NavigateFiles);
+        draw(&mut terminal, &mut app);
+        assert!(screen(&terminal).contains("notes.txt"));
+
+        app.update(Action::Key(KeyEvent::new(KeyCode::Left, KeyModifiers::NONE))).unwrap();
+        app.update(Action::Key(KeyEvent::new(KeyCode::Char('t'), KeyModifiers::NONE))).unwrap();
+        assert!(app.mode == Mode::Settings);
+        draw(&mut terminal, &mut app);
+
+        // Too small for most of the layout, which must still not panic
+        let mut tiny = Terminal::new(TestBackend::new(20, 6)).unwrap();
+        draw(&mut tiny, &mut app);
+    }
+
+    // Drawing reads nothing; the rows it showed without sizes and dates are stat'ed by perform
+    #[test]
+    fn rows_drawn_without_metadata_are_left_to_perform() {
+        let mut app = app();
+        let mut terminal = Terminal::new(TestBackend::new(120, 32)).unwrap();
+        app.current_files.iter_mut().for_each(|(_, meta, _)| *meta = None);
+        let effects = draw(&mut terminal, &mut app);
+        assert_eq!(effects, vec![Effect::StatFiles(0..1)]);
+        assert!(app.current_files[0].1.is_none());
+        for effect in effects {
+            app.perform(&mut terminal, effect).unwrap();
+        }
+        assert!(app.current_files[0].1.is_some());
+        assert_eq!(draw(&mut terminal, &mut app), vec![]);
+    }
+
+    #[test]
+    fn deleting_a_folder_asks_first_and_leaves_the_deleting_to_perform() {
+        let mut app = app();
+        let index = app.selected_dir.selected().unwrap();
+        assert_eq!(press(&mut app, KeyCode::Char('r')), vec![]);
+        assert!(app.mode == Mode::ConfirmDeleteFolder);
+        assert_eq!(press(&mut app, KeyCode::Char('y')), vec![Effect::DeleteFolder { index, permanent: false }]);
+        assert!(app.mode == Mode::NavigateFolders);
+        assert!(selected_dir(&app).is_some_and(|d| d.is_dir()));
+
+        press(&mut app, KeyCode::Char('R'));
+        assert!(app.mode == Mode::ConfirmDeleteFolder && app.delete_permanently);
+        assert_eq!(press(&mut app, KeyCode::Char('n')), vec![]);
+        assert!(app.mode == Mode::NavigateFolders);
+    }
+
+    #[test]
+    fn only_a_destructive_key_right_after_another_is_ignored() {
+        let mut app = app();
+        let key = |code| Action::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        // An arrow just before does not hold back the destructive key after it
+        app.update(key(KeyCode::Down)).unwrap();
+        let index = app.selected_dir.selected().unwrap();
+        app.update(key(KeyCode::Char('r'))).unwrap();
+        assert!(app.mode == Mode::ConfirmDeleteFolder);
+
+        assert_eq!(app.update(key(KeyCode::Char('y'))).unwrap(), vec![]);
+        assert!(app.mode == Mode::ConfirmDeleteFolder);
+        assert!(app.status.contains("Ignored"), "{}", app.status);
+
+        app.last_destructive = app.last_destructive.map(|at| at - REPEAT_GUARD);
+        assert_eq!(app.update(key(KeyCode::Char('y'))).unwrap(), vec![Effect::DeleteFolder { index, permanent: false }]);
+    }
+
+    #[test]
+    fn with_type_ahead_on_letters_jump_instead_of_running_commands() {
+        let mut app = app();
+        app.settings.type_ahead = true;
+        let key = |code| Action::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let index = app.selected_dir.selected().unwrap();
+        // d would decrypt the folder
+        assert_eq!(app.update(key(KeyCode::Char('d'))).unwrap(), vec![]);
+        assert!(app.mode == Mode::TypeAhead);
+        assert!(app.status.contains("no match"), "{}", app.status);
+        app.update(key(KeyCode::Esc)).unwrap();
+        app.update(key(KeyCode::Char('A'))).unwrap();
+        assert!(selected_dir(&app).is_some_and(|d| d.ends_with("alpha")));
+        assert_eq!(app.status, "/A");
+        app.update(key(KeyCode::Enter)).unwrap();
+
+        app.update(key(KeyCode::Right)).unwrap();
+        app.update(key(KeyCode::Char('n'))).unwrap();
+        assert_eq!(app.selected_file.selected(), Some(0));
+        app.update(key(KeyCode::Esc)).unwrap();
+        assert!(app.mode == Mode::NavigateFiles);
+        // The palette still runs the letters' commands
+        press(&mut app, KeyCode::Left);
+        assert_eq!(press(&mut app, KeyCode::Char('p')), vec![Effect::PreviewFirstFile(index)]);
+    }
+
+    #[test]
+    fn deleting_a_file_names_it_in_the_effect() {
+        let mut app = app();
+        let dir = app.selected_dir.selected().unwrap();
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('R'));
+        assert!(app.mode == Mode::ConfirmDeleteFile);
+        assert_eq!(press(&mut app, KeyCode::Char('y')), vec![Effect::DeleteFile { dir, name: "notes.txt".to_string(), permanent: true }]);
+        assert!(app.mode == Mode::NavigateFiles);
+    }
+
+    #[test]
+    fn a_failed_delete_is_reported_and_keeps_the_selection() {
+        let mut app = app();
+        let selected = app.selected_dir.selected();
+        let result = Err(anyhow::anyhow!("Permission denied"));
+        // The failure goes into the history, which perform writes to the log file
+        assert_eq!(app.update(Action::FolderDeleted { index: 0, dir: None, permanent: false, result }).unwrap(), vec![Effect::WriteHistory]);
+        assert!(app.status.starts_with("[X] Delete failed"), "{}", app.status);
+        assert_eq!(app.selected_dir.selected(), selected);
+    }
+
+    #[test]
+    fn a_typed_key_is_masked_and_esc_leaves_it() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('k'));
+        assert!(app.mode == Mode::EnterKey);
+        for c in "abc".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        assert_eq!(app.key_input.as_str(), "abc");
+        assert!(app.status.ends_with("***") && !app.status.contains("abc"), "{}", app.status);
+        press(&mut app, KeyCode::Esc);
+        assert!(app.mode == Mode::NavigateFolders);
+    }
+
+    #[test]
+    fn keys_that_need_the_terminal_or_the_disk_become_effects() {
+        let mut app = app();
+        let index = app.selected_dir.selected().unwrap();
+        assert_eq!(press(&mut app, KeyCode::Char('p')), vec![Effect::PreviewFirstFile(index)]);
+        assert_eq!(press(&mut app, KeyCode::Char('l')), vec![Effect::LoadSavedKey]);
+        assert_eq!(app.update(Action::Resize).unwrap(), vec![Effect::Relayout]);
+        assert_eq!(press(&mut app, KeyCode::Char('V')), vec![Effect::Verify]);
+        assert_eq!(press(&mut app, KeyCode::Char('a')), vec![Effect::Archive]);
+        assert_eq!(press(&mut app, KeyCode::Char('G')), vec![Effect::Sign]);
+        assert_eq!(press(&mut app, KeyCode::Char('b')), vec![Effect::SaveConfig]);
+        assert!(app.fs.config.bookmarks.iter().any(|d| d.ends_with("alpha")));
+        // m only renames, in the files list
+        assert_eq!(press(&mut app, KeyCode::Char('m')), vec![]);
+        assert!(app.mode == Mode::NavigateFolders);
+        assert_eq!(press(&mut app, KeyCode::Down), vec![Effect::ListFiles { fresh: false }]);
+        press(&mut app, KeyCode::Right);
+        assert_eq!(press(&mut app, KeyCode::Char('o')), vec![Effect::OpenInSession]);
+        assert_eq!(press(&mut app, KeyCode::Char('s')), vec![Effect::SortFiles]);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('m'));
+        assert!(app.mode == Mode::RenameFile);
+        assert_eq!(press(&mut app, KeyCode::Enter), vec![Effect::Rename]);
+    }
+
+    #[test]
+    fn what_effects_found_comes_back_through_update() {
+        let mut app = app();
+        app.update(Action::FolderPreviewed(Ok(Some("meet at noon".to_string())))).unwrap();
+        assert!(app.mode == Mode::Preview);
+        assert_eq!(app.preview_content.as_deref(), Some("meet at noon"));
+        press(&mut app, KeyCode::Esc);
+        assert!(app.mode == Mode::NavigateFolders && app.preview_content.is_none());
+
+        app.update(Action::FolderPreviewed(Ok(None))).unwrap();
+        assert_eq!(app.status, "[!] No files to preview");
+
+        app.update(Action::KeyLoaded(None)).unwrap();
+        assert_eq!(app.status, "[X] No saved key found");
+        app.update(Action::KeyLoaded(Some(Zeroizing::new("secret\n".to_string())))).unwrap();
+        assert_eq!(app.key_input.as_str(), "secret");
+        assert_eq!(app.status, "[OK] Key loaded!");
+    }
+}
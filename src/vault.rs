@@ -0,0 +1,455 @@
+use crate::crypto::{encrypt_stream, replace_with, temp_path, Cipher, DecryptingReader};
+use crate::entropy;
+use crate::error::GuardXError;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+// A .gxvault container is an ordinary GuardX encrypted file. Its plaintext is every file of one folder
+// as a separate zstd frame, followed by a JSON index of the entries and a fixed trailer:
+//   frames | index | index_len u64 | INDEX_MAGIC
+// Because the index sits at the end and chunks decrypt independently, listing a container or reading
+// one entry only decrypts the chunks involved. Containers written before the index existed are a zstd
+// tar of the folder; they can still be listed and extracted, but only by decrypting all of them.
+//
+// A container with a decoy holds two such streams back to back: the folder under the key and a decoy
+// folder under the duress key. The shorter plaintext is padded with zeros between its frames and its
+// index, so both streams have the same length and record layout, and the two are written in random
+// order. Opening always tries both, so neither the file nor the time it takes to open shows which slot
+// a key belongs to, or which one holds the real data.
+pub const VAULT_EXTENSION: &str = "gxvault";
+const ZSTD_LEVEL: i32 = 3;
+const INDEX_MAGIC: &[u8; 8] = b"GXINDEX1";
+const TRAILER_LEN: u64 = 8 + INDEX_MAGIC.len() as u64;
+
+#[derive(Serialize, Deserialize)]
+pub struct ContainerEntry {
+    pub path: String,
+    pub size: u64,
+    pub is_dir: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<i64>,
+    // Where the entry's zstd frame starts in the plaintext and how long it is
+    #[serde(default)]
+    offset: u64,
+    #[serde(default)]
+    stored: u64,
+}
+
+pub fn is_container(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == VAULT_EXTENSION)
+}
+
+// `<parent>/<name>.gxvault` for a folder, and the folder a container extracts to by default
+pub fn container_path(dir: &Path) -> PathBuf {
+    let name = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "vault".to_string());
+    dir.with_file_name(format!("{}.{}", name, VAULT_EXTENSION))
+}
+
+pub fn extract_path(container: &Path) -> PathBuf {
+    container.with_extension("")
+}
+
+// Counts what goes into the pipe so every frame's offset is known when the index is written
+struct Counter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Entry paths use '/' on every platform and are sorted so the same folder always packs the same way
+fn pack<W: Write>(root: &Path, dir: &Path, out: &mut Counter<W>, entries: &mut Vec<ContainerEntry>) -> Result<()> {
+    let mut children: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    children.sort_by_key(|c| c.file_name());
+    for child in children {
+        let path = child.path();
+        let meta = fs::symlink_metadata(&path)?;
+        let name = path.strip_prefix(root)?.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        let mut entry = ContainerEntry {
+            path: name,
+            size: 0,
+            is_dir: meta.is_dir(),
+            link: None,
+            mode: file_mode(&meta),
+            modified: meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs() as i64),
+            offset: out.written,
+            stored: 0,
+        };
+        if meta.is_symlink() {
+            entry.link = Some(fs::read_link(&path)?.to_string_lossy().to_string());
+        } else if meta.is_file() {
+            zstd::stream::copy_encode(File::open(&path).with_context(|| format!("Failed to archive {:?}", path))?, &mut *out, ZSTD_LEVEL)?;
+            entry.size = meta.len();
+            entry.stored = out.written - entry.offset;
+        } else if !meta.is_dir() {
+            return Err(anyhow::anyhow!("Cannot archive {:?}: not a file, folder or symlink", path));
+        }
+        let is_dir = entry.is_dir;
+        entries.push(entry);
+        if is_dir {
+            pack(root, &path, out, entries)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+pub fn create(dir: &Path, dest: &Path, key: &str, cipher: Cipher) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(anyhow::anyhow!("Not a folder: {:?}", dir));
+    }
+    if dest.starts_with(dir) {
+        return Err(anyhow::anyhow!("The container cannot be written inside the folder it archives"));
+    }
+    let (mut plain, pipe) = io::pipe()?;
+    thread::scope(|scope| {
+        // The packer feeds frames and the index into the pipe while this thread encrypts whatever comes out
+        let packer = scope.spawn(move || -> Result<()> {
+            let mut out = Counter { inner: io::BufWriter::new(pipe), written: 0 };
+            let mut entries = Vec::new();
+            pack(dir, dir, &mut out, &mut entries)?;
+            let index = serde_json::to_vec(&entries)?;
+            out.write_all(&index)?;
+            out.write_all(&(index.len() as u64).to_le_bytes())?;
+            out.write_all(INDEX_MAGIC)?;
+            out.flush()?;
+            Ok(())
+        });
+        // A packer that fails closes the pipe early, which looks like a complete (short) container to
+        // the encryptor, so its result decides whether the container replaces `dest`
+        replace_with(dest, move |w| {
+            let sealed = encrypt_stream(&mut plain, w, key, cipher);
+            drop(plain);
+            let packed = packer.join().map_err(|_| anyhow::anyhow!("Archiving thread panicked"))?;
+            sealed.and(packed)
+        })
+    })
+}
+
+pub fn create_with_decoy(dir: &Path, decoy: &Path, dest: &Path, key: &str, duress_key: &str, cipher: Cipher) -> Result<()> {
+    if key == duress_key {
+        return Err(anyhow::anyhow!("The duress key must differ from the key"));
+    }
+    if dest.starts_with(decoy) {
+        return Err(anyhow::anyhow!("The container cannot be written inside the decoy folder"));
+    }
+    let slots = [slot_path(dest, 0), slot_path(dest, 1)];
+    let keys = [key, duress_key];
+    let result = (|| {
+        create(dir, &slots[0], key, cipher)?;
+        create(decoy, &slots[1], duress_key, cipher)?;
+        let len = DecryptingReader::open(&slots[0], key)?.size().max(DecryptingReader::open(&slots[1], duress_key)?.size());
+        let mut coin = [0u8; 1];
+        entropy::fill(&mut coin)?;
+        let order = if coin[0] & 1 == 0 { [0, 1] } else { [1, 0] };
+        replace_with(dest, |w| {
+            for i in order {
+                write_padded(&slots[i], keys[i], cipher, len, w)?;
+            }
+            Ok(())
+        })
+    })();
+    for slot in &slots {
+        let _ = fs::remove_file(slot);
+    }
+    result
+}
+
+// Where one slot is built before the two are joined
+fn slot_path(dest: &Path, slot: usize) -> PathBuf {
+    let name = dest.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    dest.with_file_name(format!(".{}.slot{}", name, slot))
+}
+
+// Copies a slot as it is, or re-encrypts it with its plaintext grown to `len`. The zeros go between the
+// last frame and the index, where no entry offset points.
+fn write_padded<W: Write>(slot: &Path, key: &str, cipher: Cipher, len: u64, out: &mut W) -> Result<()> {
+    let mut frames = DecryptingReader::open(slot, key)?;
+    let size = frames.size();
+    if size >= len {
+        io::copy(&mut File::open(slot)?, out)?;
+        return Ok(());
+    }
+    let mut index = DecryptingReader::open(slot, key)?;
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    index.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+    index.read_exact(&mut trailer)?;
+    let index_start = size - TRAILER_LEN - u64::from_le_bytes(trailer[..8].try_into()?);
+    frames.rewind()?;
+    index.seek(SeekFrom::Start(index_start))?;
+    let mut padded = frames.take(index_start).chain(io::repeat(0).take(len - size)).chain(index);
+    encrypt_stream(&mut padded, out, key, cipher)
+}
+
+// An opened container: the index is decrypted up front, entry contents only when they are read
+pub struct Container {
+    reader: DecryptingReader,
+    entries: Vec<ContainerEntry>,
+    indexed: bool,
+}
+
+impl Container {
+    pub fn open(path: &Path, key: &str) -> Result<Self> {
+        let reader = DecryptingReader::open(path, key).context("Container could not be decrypted")?;
+        // Anything after the first stream is the second slot
+        let second = (reader.end() < fs::metadata(path)?.len()).then(|| {
+            DecryptingReader::open_at(path, key, reader.end()).context("Container could not be decrypted").and_then(Self::from_reader)
+        });
+        match (Self::from_reader(reader), second) {
+            (Err(_), Some(Ok(container))) => Ok(container),
+            (first, _) => first,
+        }
+    }
+
+    fn from_reader(mut reader: DecryptingReader) -> Result<Self> {
+        let size = reader.size();
+        let mut trailer = [0u8; TRAILER_LEN as usize];
+        if size >= TRAILER_LEN {
+            reader.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+            reader.read_exact(&mut trailer).context("Container could not be decrypted")?;
+        }
+        if &trailer[8..] != INDEX_MAGIC {
+            let entries = legacy_list(&mut reader)?;
+            return Ok(Container { reader, entries, indexed: false });
+        }
+        let index_len = u64::from_le_bytes(trailer[..8].try_into()?);
+        if index_len > size - TRAILER_LEN {
+            return Err(GuardXError::Format("Container index is corrupted".to_string()).into());
+        }
+        reader.seek(SeekFrom::Start(size - TRAILER_LEN - index_len))?;
+        let mut index = Zeroizing::new(vec![0u8; index_len as usize]);
+        reader.read_exact(&mut index).context("Container could not be decrypted")?;
+        let entries: Vec<ContainerEntry> = serde_json::from_slice(&index).context(GuardXError::Format("Container index is corrupted".to_string()))?;
+        let data_len = size - TRAILER_LEN - index_len;
+        if entries.iter().any(|e| e.offset.checked_add(e.stored).is_none_or(|end| end > data_len)) {
+            return Err(GuardXError::Format("Container index is corrupted".to_string()).into());
+        }
+        Ok(Container { reader, entries, indexed: true })
+    }
+
+    pub fn entries(&self) -> &[ContainerEntry] {
+        &self.entries
+    }
+
+    fn find(&self, path: &str) -> Result<usize> {
+        if !self.indexed {
+            return Err(anyhow::anyhow!("This container has no index; extract it fully or archive the folder again"));
+        }
+        self.entries.iter().position(|e| e.path == path).with_context(|| format!("No entry {:?} in the container", path))
+    }
+
+    // Up to `limit` bytes of one file, for previews
+    pub fn read_entry(&mut self, path: &str, limit: u64) -> Result<Zeroizing<Vec<u8>>> {
+        let entry = &self.entries[self.find(path)?];
+        if entry.is_dir || entry.link.is_some() {
+            return Err(anyhow::anyhow!("{:?} is not a file", path));
+        }
+        let mut out = Zeroizing::new(Vec::new());
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        zstd::Decoder::new((&mut self.reader).take(entry.stored))?.take(limit).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    // Extracts one file to `dest`, or a folder entry with everything below it
+    pub fn extract_entry(&mut self, path: &str, dest: &Path) -> Result<()> {
+        let index = self.find(path)?;
+        if dest.exists() {
+            return Err(anyhow::anyhow!("{:?} already exists", dest));
+        }
+        if self.entries[index].is_dir {
+            let prefix = format!("{}/", path);
+            return self.unpack(dest, |p| p.strip_prefix(&prefix));
+        }
+        let Container { reader, entries, .. } = self;
+        let entry = &entries[index];
+        replace_with(dest, |w| copy_entry(reader, entry, w))?;
+        restore_metadata(dest, entry)
+    }
+
+    // Unpacks into a temporary sibling first, so a tampered or truncated container never leaves a half-written folder
+    pub fn extract(&mut self, dest: &Path) -> Result<()> {
+        if dest.exists() {
+            return Err(anyhow::anyhow!("{:?} already exists", dest));
+        }
+        if !self.indexed {
+            return self.unpack_legacy(dest);
+        }
+        self.unpack(dest, |p| Some(p))
+    }
+
+    fn unpack<F: Fn(&str) -> Option<&str>>(&mut self, dest: &Path, select: F) -> Result<()> {
+        let tmp = fresh_temp_dir(dest)?;
+        let result = (|| {
+            let Container { reader, entries, .. } = self;
+            for entry in entries.iter() {
+                let Some(relative) = select(&entry.path) else { continue };
+                let target = tmp.join(safe_relative(relative)?);
+                if entry.is_dir {
+                    fs::create_dir_all(&target)?;
+                    continue;
+                }
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if let Some(link) = &entry.link {
+                    make_symlink(link, &target)?;
+                    continue;
+                }
+                copy_entry(reader, entry, &mut io::BufWriter::new(File::create(&target)?))?;
+                restore_metadata(&target, entry)?;
+            }
+            // Decrypting the final chunk proves nothing was cut off, even when no entry reached it
+            reader.seek(SeekFrom::End(-1))?;
+            reader.read_exact(&mut [0u8; 1])?;
+            Ok(())
+        })();
+        finish_temp_dir(tmp, dest, result)
+    }
+
+    fn unpack_legacy(&mut self, dest: &Path) -> Result<()> {
+        let tmp = fresh_temp_dir(dest)?;
+        let result = (|| {
+            self.reader.rewind()?;
+            let mut archive = tar::Archive::new(zstd::Decoder::new(&mut self.reader)?);
+            archive.unpack(&tmp)?;
+            // Drain the rest so the final chunk is decrypted and checked
+            io::copy(&mut archive.into_inner(), &mut io::sink())?;
+            Ok(())
+        })();
+        finish_temp_dir(tmp, dest, result)
+    }
+}
+
+fn copy_entry(reader: &mut DecryptingReader, entry: &ContainerEntry, out: &mut dyn Write) -> Result<()> {
+    reader.seek(SeekFrom::Start(entry.offset))?;
+    let written = io::copy(&mut zstd::Decoder::new(reader.take(entry.stored))?, out)?;
+    out.flush()?;
+    if written != entry.size {
+        return Err(anyhow::anyhow!("Entry {:?} is damaged", entry.path));
+    }
+    Ok(())
+}
+
+// Index paths come from the container, so anything that could climb out of the destination is refused
+fn safe_relative(path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    if path.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(anyhow::anyhow!("Unsafe path in container: {:?}", path));
+    }
+    Ok(relative.to_path_buf())
+}
+
+fn restore_metadata(path: &Path, entry: &ContainerEntry) -> Result<()> {
+    if let Some(secs) = entry.modified.and_then(|s| u64::try_from(s).ok()) {
+        File::options().write(true).open(path)?.set_modified(UNIX_EPOCH + Duration::from_secs(secs))?;
+    }
+    #[cfg(unix)]
+    if let Some(mode) = entry.mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn make_symlink(link: &str, target: &Path) -> Result<()> {
+    Ok(std::os::unix::fs::symlink(link, target)?)
+}
+
+// Creating symlinks needs extra privileges on Windows, so they are left out there
+#[cfg(not(unix))]
+fn make_symlink(_link: &str, _target: &Path) -> Result<()> {
+    Ok(())
+}
+
+fn fresh_temp_dir(dest: &Path) -> Result<PathBuf> {
+    let tmp = temp_path(dest);
+    if tmp.exists() {
+        fs::remove_dir_all(&tmp)?;
+    }
+    fs::create_dir_all(&tmp)?;
+    Ok(tmp)
+}
+
+fn finish_temp_dir(tmp: PathBuf, dest: &Path, result: Result<()>) -> Result<()> {
+    match result {
+        Ok(()) => {
+            fs::rename(&tmp, dest)?;
+            Ok(())
+        }
+        Err(e) => {
+            let _ = fs::remove_dir_all(&tmp);
+            Err(e)
+        }
+    }
+}
+
+fn legacy_list(reader: &mut DecryptingReader) -> Result<Vec<ContainerEntry>> {
+    reader.rewind()?;
+    let mut entries = Vec::new();
+    let mut archive = tar::Archive::new(zstd::Decoder::new(&mut *reader)?);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.to_string_lossy().trim_start_matches("./").trim_end_matches('/').to_string();
+        if path.is_empty() || path == "." {
+            continue;
+        }
+        let header = entry.header();
+        entries.push(ContainerEntry {
+            path,
+            size: header.size()?,
+            is_dir: header.entry_type().is_dir(),
+            link: None,
+            mode: None,
+            modified: None,
+            offset: 0,
+            stored: 0,
+        });
+    }
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(entries)
+}
+
+pub fn list(container: &Path, key: &str) -> Result<Vec<ContainerEntry>> {
+    Ok(Container::open(container, key)?.entries)
+}
+
+pub fn extract(container: &Path, dest: &Path, key: &str) -> Result<()> {
+    Container::open(container, key)?.extract(dest)
+}
+
+// File count and unpacked size for the browser's title line
+pub fn summary(entries: &[ContainerEntry]) -> (usize, u64) {
+    let files = entries.iter().filter(|e| !e.is_dir).count();
+    (files, entries.iter().map(|e| e.size).sum())
+}
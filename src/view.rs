@@ -0,0 +1,67 @@
+use crate::crypto::{is_chunked, original_name, DecryptingReader};
+use crate::error::GuardXError;
+use crate::filesystem::files_in;
+use crate::manifest::Manifest;
+use anyhow::Result;
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// What `guardx mount` and `guardx serve --webdav` show of an encrypted folder: each file under its real
+// name with its plaintext size. The folder is listed once; files added later do not show up until it is
+// opened again. Only chunked GuardX files can be read at an offset, so age and legacy files are left out.
+pub struct View {
+    pub entries: Vec<Entry>,
+    // Files that cannot be shown, with why
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+pub struct Entry {
+    pub name: OsString,
+    pub path: PathBuf,
+    pub size: u64,
+    pub mtime: SystemTime,
+}
+
+impl View {
+    // Fails with GuardXError::Authentication when the key does not open the folder
+    pub fn open(dir: &Path, key: &str) -> Result<Self> {
+        Manifest::load(dir)?.check_key(key)?;
+        let mut view = View { entries: Vec::new(), skipped: Vec::new() };
+        let mut files = files_in(dir)?;
+        files.sort();
+        for path in files {
+            if !is_chunked(&path)? {
+                view.skipped.push((path, "not a chunked GuardX file (age or an older format)".to_string()));
+                continue;
+            }
+            let mut reader = match DecryptingReader::open(&path, key) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    view.skipped.push((path, format!("{:#}", e)));
+                    continue;
+                }
+            };
+            // Manifests without keyed hashes cannot check the key, so the first file's first chunk does
+            if view.entries.is_empty() && reader.size() > 0 {
+                if let Err(e) = reader.read(&mut [0u8; 1]) {
+                    if e.get_ref().and_then(|inner| inner.downcast_ref::<GuardXError>()).is_some_and(|f| matches!(f, GuardXError::Authentication)) {
+                        return Err(GuardXError::Authentication.into());
+                    }
+                }
+            }
+            let name = match original_name(&path, key)? {
+                Some(name) => OsString::from(name),
+                None => path.file_name().unwrap_or_default().to_os_string(),
+            };
+            if view.entries.iter().any(|e| e.name == name) {
+                view.skipped.push((path, format!("another file is also named {:?}", name)));
+                continue;
+            }
+            let mtime = std::fs::metadata(&path)?.modified()?;
+            view.entries.push(Entry { name, path, size: reader.size(), mtime });
+        }
+        Ok(view)
+    }
+}
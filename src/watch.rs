@@ -0,0 +1,214 @@
+use crate::age::{self, Recipient};
+use crate::config::{Config, Lifecycle};
+use crate::crypto::{check_cancelled, is_chunked, Cipher, OutputFormat};
+use crate::filesystem::encrypt_one;
+use crate::filter;
+use crate::manifest::{Checksums, Manifest, CHECKSUMS_NAME, MANIFEST_NAME, SIGNATURES_NAME};
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+// Keeps an encrypted folder encrypted: plaintext files created or changed directly inside it are encrypted
+// with the folder's settings once nothing has written to them for SETTLE, so files that are still being
+// copied in are left alone until they are complete. Hidden files (editors' swap and lock files) and
+// GuardX's own temporaries are ignored, and so is anything already encrypted, which includes the files
+// the guard just wrote.
+const SETTLE: Duration = Duration::from_secs(2);
+
+enum Target {
+    Key { key: Zeroizing<String>, cipher: Cipher, format: OutputFormat, hide_names: bool, checksums: bool },
+    Recipients(Vec<Recipient>),
+}
+
+pub struct Activity {
+    // The file as it was dropped in; with hidden names it is stored under another one
+    pub path: PathBuf,
+    pub result: Result<()>,
+}
+
+pub struct Guard {
+    dir: PathBuf,
+    target: Target,
+    events: mpsc::Receiver<notify::Result<Event>>,
+    // Dropping it stops the events
+    _watcher: RecommendedWatcher,
+    // Files with writes that have not settled yet, by their last write
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl Guard {
+    // Only active encrypted folders can be guarded: archived and retired ones are not meant to take new
+    // files. Recipient folders need no key. Fails with GuardXError::Authentication when the key does not
+    // match the folder's manifest.
+    pub fn new(dir: &Path, key: &str, config: &Config) -> Result<Self> {
+        let manifest = Manifest::load(dir).with_context(|| format!("{:?} is not an encrypted folder", dir))?;
+        let state = config.lifecycle(dir);
+        if state != Lifecycle::Active {
+            return Err(anyhow::anyhow!("{:?} is {}; make it active again to watch it", dir, state.name()));
+        }
+        let recipients = config.recipients(dir)?;
+        let target = if !recipients.is_empty() {
+            Target::Recipients(recipients)
+        } else if key.is_empty() {
+            return Err(anyhow::anyhow!("Enter a key first"));
+        } else {
+            manifest.check_key(key)?;
+            let format = config.output_format(dir);
+            let hide_names = config.encrypt_names(dir);
+            if format == OutputFormat::Age && hide_names {
+                return Err(anyhow::anyhow!("File names cannot be encrypted in age files; turn off name encryption or use the GuardX format"));
+            }
+            Target::Key {
+                key: Zeroizing::new(key.to_string()),
+                cipher: config.cipher(dir),
+                format,
+                hide_names,
+                checksums: config.checksums(dir),
+            }
+        };
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).context("Could not start watching for files")?;
+        watcher.watch(dir, RecursiveMode::NonRecursive).with_context(|| format!("Could not watch {:?}", dir))?;
+        Ok(Guard { dir: dir.to_path_buf(), target, events, _watcher: watcher, pending: HashMap::new() })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Waits up to `wait` for something to happen, then encrypts the files that have settled
+    pub fn poll(&mut self, wait: Duration) -> Result<Vec<Activity>> {
+        check_cancelled()?;
+        let deadline = Instant::now() + wait;
+        loop {
+            let event = match self.events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Err(anyhow::anyhow!("Stopped receiving changes to {:?}", self.dir)),
+            };
+            let event = event.with_context(|| format!("Watching {:?} failed", self.dir))?;
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                let now = Instant::now();
+                // The watch is not recursive, so these are files directly inside, or the folder itself
+                let filter = filter::for_dir(&self.dir);
+                for path in event.paths.into_iter().filter(|p| !ignored(p) && !filter.excludes_file(&self.dir, p)) {
+                    self.pending.insert(path, now);
+                }
+            }
+        }
+        let settled: Vec<PathBuf> = self.pending.iter().filter(|(_, at)| at.elapsed() >= SETTLE).map(|(path, _)| path.clone()).collect();
+        let mut activity = Vec::new();
+        for path in settled {
+            self.pending.remove(&path);
+            // Gone already, or written by the guard itself
+            if !path.is_file() || matches!(is_encrypted(&path), Ok(true)) {
+                continue;
+            }
+            let result = self.encrypt(&path);
+            activity.push(Activity { path, result });
+        }
+        if activity.iter().any(|a| a.result.is_ok()) {
+            let manifest = match &self.target {
+                Target::Key { key, .. } => Manifest::build_hashed(&self.dir, key),
+                Target::Recipients(_) => Manifest::build(&self.dir),
+            };
+            manifest.and_then(|m| m.save(&self.dir)).context("Could not update the manifest")?;
+        }
+        Ok(activity)
+    }
+
+    fn encrypt(&self, path: &Path) -> Result<()> {
+        match &self.target {
+            Target::Recipients(recipients) => age::encrypt_file_to(path, recipients),
+            Target::Key { key, cipher, format, hide_names, checksums } => {
+                if *checksums {
+                    let mut sums = match Checksums::load(&self.dir, key)? {
+                        Some(sums) => sums,
+                        None => Checksums::build(&[])?,
+                    };
+                    sums.files.extend(Checksums::build(&[path.to_path_buf()])?.files);
+                    sums.save(&self.dir, key, *cipher)?;
+                }
+                encrypt_one(path, key, *cipher, *format, *hide_names)
+            }
+        }
+    }
+}
+
+fn ignored(path: &Path) -> bool {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else { return true };
+    name.starts_with('.') || name.ends_with(".gxtmp") || [MANIFEST_NAME, CHECKSUMS_NAME, SIGNATURES_NAME].contains(&&*name)
+}
+
+fn is_encrypted(path: &Path) -> Result<bool> {
+    Ok(is_chunked(path)? || age::is_age(path)?)
+}
+
+// Says when anything directly inside a folder was added, removed, renamed or changed, so a listing of it can
+// be kept current. A burst of changes (a copy, an operation on the folder) is reported at most once per
+// REFRESH_INTERVAL.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(300);
+
+pub struct Changes {
+    dir: PathBuf,
+    events: mpsc::Receiver<notify::Result<Event>>,
+    _watcher: RecommendedWatcher,
+    pending: bool,
+    reported: Option<Instant>,
+}
+
+impl Changes {
+    pub fn new(dir: &Path) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).context("Could not start watching for files")?;
+        watcher.watch(dir, RecursiveMode::NonRecursive).with_context(|| format!("Could not watch {:?}", dir))?;
+        Ok(Changes { dir: dir.to_path_buf(), events, _watcher: watcher, pending: false, reported: None })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Never blocks
+    pub fn changed(&mut self) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            // Reading a file is not a change; an error may have hidden one
+            self.pending |= event.map_or(true, |e| !matches!(e.kind, EventKind::Access(_)));
+        }
+        if !self.pending || self.reported.is_some_and(|at| at.elapsed() < REFRESH_INTERVAL) {
+            return false;
+        }
+        self.pending = false;
+        self.reported = Some(Instant::now());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn only_active_folders_are_watched() {
+        let dir = std::env::temp_dir().join(format!("guardx-watch-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Manifest::build(&dir).unwrap().save(&dir).unwrap();
+        let mut config = Config::default();
+
+        // An active folder gets as far as asking for the key
+        let err = Guard::new(&dir, "", &config).err().unwrap();
+        assert!(err.to_string().contains("Enter a key first"), "{}", err);
+        for state in [Lifecycle::Archived, Lifecycle::Retired] {
+            config.set_lifecycle(&dir, state, 0);
+            let err = Guard::new(&dir, "", &config).err().unwrap();
+            assert!(err.to_string().contains(&format!("is {}; make it active again to watch it", state.name())), "{}", err);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
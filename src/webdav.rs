@@ -0,0 +1,241 @@
+use crate::crypto::DecryptingReader;
+use crate::entropy;
+use crate::view::{Entry, View};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::io::{Read, Seek, SeekFrom};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tiny_http::{Header, Method, Request, Response, StatusCode};
+use zeroize::Zeroizing;
+
+// A read-only View of an encrypted folder over WebDAV on 127.0.0.1, which Finder, Explorer and most
+// file managers mount without extra drivers. Reads decrypt just the chunks they touch into memory. The
+// share lives under a random path that is only printed to whoever started it, so other users and web
+// pages on the machine cannot guess their way in, and requests naming another host are refused.
+pub struct Share {
+    server: tiny_http::Server,
+    files: Arc<Files>,
+    // "/<random>/", the share's root
+    base: String,
+}
+
+struct Files {
+    key: Zeroizing<String>,
+    entries: Vec<Entry>,
+    mtime: SystemTime,
+}
+
+impl Share {
+    // Also returns the files left out, with why. Fails with GuardXError::Authentication when the key does not
+    // open the folder. Port 0 picks a free one.
+    pub fn open(dir: &Path, key: &str, port: u16) -> Result<(Self, Vec<(PathBuf, String)>)> {
+        let view = View::open(dir, key)?;
+        let mtime = std::fs::metadata(dir).with_context(|| format!("Could not open {:?}", dir))?.modified()?;
+        let server = tiny_http::Server::http(("127.0.0.1", port)).map_err(|e| anyhow::anyhow!("Could not listen on 127.0.0.1:{}: {}", port, e))?;
+        let mut token = [0u8; 16];
+        entropy::fill(&mut token)?;
+        let base = format!("/{}/", token.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        let files = Arc::new(Files { key: Zeroizing::new(key.to_string()), entries: view.entries, mtime });
+        Ok((Share { server, files, base }, view.skipped))
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.entries.len()
+    }
+
+    // What to mount, e.g. http://127.0.0.1:49152/3f.../
+    pub fn url(&self) -> String {
+        let addr = self.server.server_addr().to_ip().unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], 0)));
+        format!("http://{}{}", addr, self.base)
+    }
+
+    // Blocks until the process is stopped; each request gets its own thread, so a long read does not hold up the listing
+    pub fn serve(self) -> Result<()> {
+        for request in self.server.incoming_requests() {
+            let files = Arc::clone(&self.files);
+            let base = self.base.clone();
+            std::thread::spawn(move || {
+                let _ = handle(&files, &base, request);
+            });
+        }
+        Ok(())
+    }
+}
+
+fn handle(files: &Files, base: &str, request: Request) -> std::io::Result<()> {
+    // A page on some other site can point its own host name at 127.0.0.1; its requests carry that name
+    let local = header(&request, "Host").is_some_and(|host| {
+        let name = host.rsplit_once(':').map_or(host, |(name, _)| name);
+        matches!(name, "127.0.0.1" | "localhost" | "[::1]")
+    });
+    if !local {
+        return request.respond(status(403));
+    }
+    let method = request.method().clone();
+    // Explorer asks the server root what it supports before it goes to the share; that gives nothing away
+    if method == Method::Options {
+        let mut response = status(200);
+        for (name, value) in [("DAV", "1"), ("Allow", ALLOWED), ("MS-Author-Via", "DAV")] {
+            response.add_header(make_header(name, value));
+        }
+        return request.respond(response);
+    }
+    let url = request.url().split('?').next().unwrap_or_default().to_string();
+    let Some(rest) = url.strip_prefix(base).or_else(|| (url == base.trim_end_matches('/')).then_some("")) else {
+        return request.respond(status(404));
+    };
+    let target = if rest.is_empty() { Target::Root } else {
+        match decode(rest).and_then(|name| files.entries.iter().find(|e| e.name.to_string_lossy() == name)) {
+            Some(entry) => Target::File(entry),
+            None => return request.respond(status(404)),
+        }
+    };
+    match (&method, &target) {
+        (Method::NonStandard(method), _) if method.as_str() == "PROPFIND" => {
+            // The folder is flat, so "infinity" lists no more than 1 does
+            let depth_zero = header(&request, "Depth") == Some("0");
+            let mut body = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n");
+            match target {
+                Target::Root => {
+                    body.push_str(&propstat(base, "", true, 0, files.mtime));
+                    if !depth_zero {
+                        for entry in &files.entries {
+                            let name = entry.name.to_string_lossy();
+                            body.push_str(&propstat(&format!("{}{}", base, encode(&name)), &name, false, entry.size, entry.mtime));
+                        }
+                    }
+                }
+                Target::File(entry) => {
+                    let name = entry.name.to_string_lossy();
+                    body.push_str(&propstat(&format!("{}{}", base, encode(&name)), &name, false, entry.size, entry.mtime));
+                }
+            }
+            body.push_str("</D:multistatus>\n");
+            let mut response = Response::from_string(body).with_status_code(207);
+            response.add_header(make_header("Content-Type", "application/xml; charset=utf-8"));
+            request.respond(response)
+        }
+        (Method::Get | Method::Head, Target::Root) => {
+            let names: Vec<String> = files.entries.iter().map(|e| e.name.to_string_lossy().to_string()).collect();
+            request.respond(Response::from_string(names.join("\n")))
+        }
+        (Method::Get | Method::Head, Target::File(entry)) => respond_file(files, entry, request),
+        _ => {
+            let mut response = status(405);
+            response.add_header(make_header("Allow", ALLOWED));
+            request.respond(response)
+        }
+    }
+}
+
+const ALLOWED: &str = "OPTIONS, PROPFIND, GET, HEAD";
+
+enum Target<'a> {
+    Root,
+    File(&'a Entry),
+}
+
+// A single byte range is honoured, so players and viewers can seek without decrypting the whole file. A
+// chunk that fails to authenticate ends the response early rather than handing out unauthenticated bytes.
+fn respond_file(files: &Files, entry: &Entry, request: Request) -> std::io::Result<()> {
+    let mut reader = match DecryptingReader::open(&entry.path, &files.key) {
+        Ok(reader) => reader,
+        Err(_) => return request.respond(status(500)),
+    };
+    let size = reader.size();
+    let range = header(&request, "Range").and_then(|range| parse_range(range, size));
+    if header(&request, "Range").is_some() && range.is_none() && size > 0 {
+        let mut response = status(416);
+        response.add_header(make_header("Content-Range", &format!("bytes */{}", size)));
+        return request.respond(response);
+    }
+    let (start, end) = range.unwrap_or((0, size));
+    reader.seek(SeekFrom::Start(start))?;
+    let mut headers = vec![
+        make_header("Content-Type", "application/octet-stream"),
+        make_header("Accept-Ranges", "bytes"),
+        make_header("Last-Modified", &http_date(entry.mtime)),
+    ];
+    if range.is_some() {
+        headers.push(make_header("Content-Range", &format!("bytes {}-{}/{}", start, end.saturating_sub(1), size)));
+    }
+    let code = if range.is_some() { 206 } else { 200 };
+    let len = end - start;
+    request.respond(Response::new(StatusCode(code), headers, reader.take(len), Some(len as usize), None))
+}
+
+// "bytes=a-b", "bytes=a-" or "bytes=-n" as a half-open range within `size`
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = range.strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (from, to) = spec.split_once('-')?;
+    let (start, end) = match (from.trim(), to.trim()) {
+        ("", n) => (size.saturating_sub(n.parse().ok()?), size),
+        (a, "") => (a.parse().ok()?, size),
+        (a, b) => (a.parse().ok()?, b.parse::<u64>().ok()?.saturating_add(1).min(size)),
+    };
+    (start < end).then_some((start, end))
+}
+
+fn propstat(href: &str, name: &str, collection: bool, size: u64, mtime: SystemTime) -> String {
+    let kind = if collection { "<D:collection/>" } else { "" };
+    let length = if collection { String::new() } else { format!("<D:getcontentlength>{}</D:getcontentlength><D:getcontenttype>application/octet-stream</D:getcontenttype>", size) };
+    format!(
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop><D:displayname>{}</D:displayname><D:resourcetype>{}</D:resourcetype>{}<D:getlastmodified>{}</D:getlastmodified></D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n",
+        escape(href), escape(name), kind, length, http_date(mtime),
+    )
+}
+
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request.headers().iter().find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name)).map(|h| h.value.as_str())
+}
+
+fn make_header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header names and values here are ASCII")
+}
+
+fn status(code: u16) -> Response<std::io::Empty> {
+    Response::empty(code)
+}
+
+fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+// Percent-encodes everything but unreserved characters
+fn encode(name: &str) -> String {
+    let mut out = String::new();
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}